@@ -20,6 +20,9 @@ pub fn build(b: *std.Build) void {
     // of this build script using `b.option()`. All defined flags (including
     // target and optimize options) will be listed when running `zig build --help`
     // in this directory.
+    const enable_kubernetes = b.option(bool, "kubernetes", "Enable Kubernetes EndpointSlice upstream discovery") orelse false;
+    const options = b.addOptions();
+    options.addOption(bool, "kubernetes", enable_kubernetes);
 
     // This creates a module, which represents a collection of source files alongside
     // some compilation options, such as optimization mode and linked system libraries.
@@ -82,6 +85,7 @@ pub fn build(b: *std.Build) void {
         // which requires us to specify a target.
         .target = target,
     });
+    mod.addOptions("build_options", options);
 
     // Here we define an executable. An executable needs to have a root module
     // which needs to expose a `main` function. While we could add a main function
@@ -132,6 +136,7 @@ pub fn build(b: *std.Build) void {
                 .{ .name = "zpack", .module = zpack.module("zpack") },
                 .{ .name = "ztime", .module = ztime.module("ztime") },
                 .{ .name = "zigzag", .module = zigzag.module("zigzag") },
+                .{ .name = "build_options", .module = options.createModule() },
             },
         }),
     });
@@ -195,6 +200,31 @@ pub fn build(b: *std.Build) void {
     test_step.dependOn(&run_mod_tests.step);
     test_step.dependOn(&run_exe_tests.step);
 
+    // Benchmarks run optimized against the library module. `bench` runs
+    // each with its defaults; the bench-<name> steps take arguments.
+    const bench_step = b.step("bench", "Run benchmarks");
+    const benches = [_]struct { name: []const u8, path: []const u8, description: []const u8 }{
+        .{ .name = "bench-static-io", .path = "tests/bench/static_io.zig", .description = "Benchmark static files under server.static_io" },
+        .{ .name = "bench-forwarder", .path = "tests/bench/forwarder.zig", .description = "Benchmark proxying with and without upstream pooling" },
+    };
+    for (benches) |bench| {
+        const bench_exe = b.addExecutable(.{
+            .name = bench.name,
+            .root_module = b.createModule(.{
+                .root_source_file = b.path(bench.path),
+                .target = target,
+                .optimize = .ReleaseFast,
+                .imports = &.{
+                    .{ .name = "wraith", .module = mod },
+                },
+            }),
+        });
+        bench_step.dependOn(&b.addRunArtifact(bench_exe).step);
+        const run_bench = b.addRunArtifact(bench_exe);
+        if (b.args) |args| run_bench.addArgs(args);
+        b.step(bench.name, bench.description).dependOn(&run_bench.step);
+    }
+
     // Just like flags, top level steps are also listed in the `--help` menu.
     //
     // The Zig build system is entirely implemented in userland, which means
@@ -1,5 +1,6 @@
 const std = @import("std");
 const flash = @import("flash");
+const replay = @import("replay.zig");
 
 pub const Command = enum {
     serve,
@@ -9,16 +10,67 @@ pub const Command = enum {
     quit,
     status,
     version,
+    replay,
 };
 
 pub fn parseArgs(allocator: std.mem.Allocator) !struct {
     command: Command,
     config_path: []const u8,
+    /// Set for `replay`
+    replay: ?replay.Options = null,
 } {
     _ = allocator; // TODO: Use allocator when implementing flash CLI parsing
+    const argv = std.os.argv;
+    if (argv.len > 1 and std.mem.eql(u8, std.mem.span(argv[1]), "replay")) {
+        return .{
+            .command = .replay,
+            .config_path = "wraith.toml",
+            .replay = try parseReplay(argv[2..]),
+        };
+    }
     // For MVP, return default values
     return .{
         .command = .serve,
         .config_path = "wraith.toml",
     };
 }
+
+/// `wraith replay <access.log> --target <url> [--rate 2x|50rps]
+/// [--concurrency N] [--host name] [--all-methods]`
+fn parseReplay(args: []const [*:0]u8) !replay.Options {
+    var log_path: ?[]const u8 = null;
+    var target: ?[]const u8 = null;
+    var options = replay.Options{ .log_path = "", .target = "" };
+    var i: usize = 0;
+    while (i < args.len) : (i += 1) {
+        const arg = std.mem.span(args[i]);
+        if (std.mem.eql(u8, arg, "--all-methods")) {
+            options.all_methods = true;
+            continue;
+        }
+        if (!std.mem.startsWith(u8, arg, "--")) {
+            if (log_path != null) return error.UnexpectedArgument;
+            log_path = arg;
+            continue;
+        }
+        i += 1;
+        if (i == args.len) return error.MissingArgumentValue;
+        const value = std.mem.span(args[i]);
+        if (std.mem.eql(u8, arg, "--target")) {
+            target = value;
+        } else if (std.mem.eql(u8, arg, "--rate")) {
+            options.pace = try replay.parsePace(value);
+        } else if (std.mem.eql(u8, arg, "--concurrency")) {
+            options.concurrency = std.fmt.parseInt(u32, value, 10) catch return error.InvalidConcurrency;
+            if (options.concurrency == 0) return error.InvalidConcurrency;
+        } else if (std.mem.eql(u8, arg, "--host")) {
+            options.host = value;
+        } else {
+            std.debug.print("✗ Unknown replay option {s}\n", .{arg});
+            return error.UnknownOption;
+        }
+    }
+    options.log_path = log_path orelse return error.MissingAccessLog;
+    options.target = target orelse return error.MissingReplayTarget;
+    return options;
+}
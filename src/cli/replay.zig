@@ -0,0 +1,352 @@
+const std = @import("std");
+
+/// Longest log line considered; longer lines are skipped
+const MAX_LINE = 16 * 1024;
+
+/// Marks replayed requests, so the target's logs can tell them apart
+const REPLAY_HEADER = "X-Wraith-Replay";
+
+pub const Pace = union(enum) {
+    /// Multiple of the logged pace: 2 replays an hour of traffic in 30
+    /// minutes
+    speedup: f64,
+    /// Fixed requests per second, ignoring logged timing
+    per_second: f64,
+};
+
+pub const Options = struct {
+    log_path: []const u8,
+    /// Base URL requests are sent to, like "http://staging:8080"
+    target: []const u8,
+    pace: Pace = .{ .speedup = 1 },
+    /// Requests in flight at once
+    concurrency: u32 = 32,
+    /// Replay methods other than GET and HEAD too. Bodies aren't logged,
+    /// so they are sent empty.
+    all_methods: bool = false,
+    /// Host header for every request; empty keeps each request's logged
+    /// host, where the log has one
+    host: []const u8 = "",
+};
+
+/// "2x" is a speedup, "50" or "50rps" a fixed rate
+pub fn parsePace(text: []const u8) !Pace {
+    if (std.mem.endsWith(u8, text, "x")) {
+        const factor = std.fmt.parseFloat(f64, text[0 .. text.len - 1]) catch return error.InvalidRate;
+        if (!(factor > 0)) return error.InvalidRate;
+        return .{ .speedup = factor };
+    }
+    const number = if (std.mem.endsWith(u8, text, "rps")) text[0 .. text.len - 3] else text;
+    const rate = std.fmt.parseFloat(f64, number) catch return error.InvalidRate;
+    if (!(rate > 0)) return error.InvalidRate;
+    return .{ .per_second = rate };
+}
+
+/// What is replayed from one access log line
+pub const Entry = struct {
+    time_ms: ?i64 = null,
+    method: []const u8,
+    uri: []const u8,
+    host: ?[]const u8 = null,
+    referer: ?[]const u8 = null,
+    user_agent: ?[]const u8 = null,
+};
+
+/// Parse a line written by any access log format: json, combined or
+/// common. Null for lines that aren't requests.
+pub fn parseLine(arena: std.mem.Allocator, line: []const u8) ?Entry {
+    const trimmed = std.mem.trim(u8, line, " \t\r\n");
+    if (trimmed.len == 0) return null;
+    if (trimmed[0] == '{') return parseJson(arena, trimmed);
+    return parseClf(trimmed);
+}
+
+fn parseJson(arena: std.mem.Allocator, line: []const u8) ?Entry {
+    const Logged = struct {
+        time_ms: ?i64 = null,
+        host: ?[]const u8 = null,
+        method: []const u8,
+        uri: []const u8,
+        referer: ?[]const u8 = null,
+        user_agent: ?[]const u8 = null,
+    };
+    const logged = std.json.parseFromSliceLeaky(Logged, arena, line, .{ .ignore_unknown_fields = true }) catch return null;
+    return .{
+        .time_ms = logged.time_ms,
+        .method = logged.method,
+        .uri = logged.uri,
+        .host = if (logged.host) |h| (if (h.len > 0) h else null) else null,
+        .referer = logged.referer,
+        .user_agent = logged.user_agent,
+    };
+}
+
+/// `client - - [10/Oct/2000:13:55:36 +0000] "GET /a HTTP/1.1" 200 - "ref" "ua"`
+fn parseClf(line: []const u8) ?Entry {
+    const time_start = (std.mem.indexOfScalar(u8, line, '[') orelse return null) + 1;
+    const time_end = std.mem.indexOfScalarPos(u8, line, time_start, ']') orelse return null;
+    var fields = QuotedFields{ .rest = line[time_end + 1 ..] };
+
+    const request_line = fields.next() orelse return null;
+    var parts = std.mem.tokenizeScalar(u8, request_line, ' ');
+    const method = parts.next() orelse return null;
+    const uri = parts.next() orelse return null;
+    if (uri.len == 0 or (uri[0] != '/' and !std.mem.eql(u8, uri, "*"))) return null;
+
+    const referer = fields.next();
+    const user_agent = fields.next();
+    return .{
+        .time_ms = clfTimeMs(line[time_start..time_end]),
+        .method = method,
+        .uri = uri,
+        .referer = if (referer) |r| (if (std.mem.eql(u8, r, "-")) null else r) else null,
+        .user_agent = if (user_agent) |ua| (if (std.mem.eql(u8, ua, "-")) null else ua) else null,
+    };
+}
+
+/// Double-quoted fields of a log line, in order. Logged values aren't
+/// escaped, so a field ends at the next quote followed by a space or the
+/// end of the line.
+const QuotedFields = struct {
+    rest: []const u8,
+
+    fn next(self: *QuotedFields) ?[]const u8 {
+        const open = std.mem.indexOfScalar(u8, self.rest, '"') orelse return null;
+        var close = open + 1;
+        while (std.mem.indexOfScalarPos(u8, self.rest, close, '"')) |quote| {
+            if (quote + 1 == self.rest.len or self.rest[quote + 1] == ' ') {
+                const field = self.rest[open + 1 .. quote];
+                self.rest = self.rest[quote + 1 ..];
+                return field;
+            }
+            close = quote + 1;
+        }
+        return null;
+    }
+};
+
+const month_names = [_][]const u8{ "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec" };
+
+/// "10/Oct/2000:13:55:36 +0000" as milliseconds since the epoch. Access
+/// logs are always written in UTC.
+fn clfTimeMs(text: []const u8) ?i64 {
+    if (text.len < 20) return null;
+    const day = std.fmt.parseInt(u8, text[0..2], 10) catch return null;
+    const month: u8 = for (month_names, 1..) |name, i| {
+        if (std.mem.eql(u8, name, text[3..6])) break @intCast(i);
+    } else return null;
+    const year = std.fmt.parseInt(u16, text[7..11], 10) catch return null;
+    const hour = std.fmt.parseInt(i64, text[12..14], 10) catch return null;
+    const minute = std.fmt.parseInt(i64, text[15..17], 10) catch return null;
+    const second = std.fmt.parseInt(i64, text[18..20], 10) catch return null;
+    if (year < 1970 or day == 0) return null;
+
+    var days: i64 = 0;
+    var y: u16 = 1970;
+    while (y < year) : (y += 1) days += std.time.epoch.getDaysInYear(y);
+    var m: u8 = 1;
+    while (m < month) : (m += 1) days += std.time.epoch.getDaysInMonth(year, @enumFromInt(m));
+    days += day - 1;
+    return ((days * 24 + hour) * 60 + minute) * std.time.ms_per_min + second * std.time.ms_per_s;
+}
+
+/// Outcomes across every replayed request
+const Tally = struct {
+    mutex: std.Thread.Mutex = .{},
+    sent: u64 = 0,
+    failed: u64 = 0,
+    /// Responses by status class, 1xx through 5xx
+    classes: [5]u64 = [_]u64{0} ** 5,
+    latencies_ms: std.ArrayList(u32) = .empty,
+
+    fn record(self: *Tally, allocator: std.mem.Allocator, status: ?u16, latency_ms: u32) void {
+        self.mutex.lock();
+        defer self.mutex.unlock();
+        self.sent += 1;
+        const code = status orelse {
+            self.failed += 1;
+            return;
+        };
+        if (code >= 100 and code < 600) self.classes[code / 100 - 1] += 1;
+        self.latencies_ms.append(allocator, latency_ms) catch {};
+    }
+
+    fn percentile(sorted: []const u32, p: usize) u32 {
+        if (sorted.len == 0) return 0;
+        return sorted[@min(sorted.len - 1, sorted.len * p / 100)];
+    }
+};
+
+const Shared = struct {
+    allocator: std.mem.Allocator,
+    options: *const Options,
+    tally: Tally = .{},
+    in_flight: std.atomic.Value(u32) = std.atomic.Value(u32).init(0),
+    wait_group: std.Thread.WaitGroup = .{},
+};
+
+/// Re-issue the requests in an access log against `options.target`, at
+/// the logged pace scaled by the speedup or at a fixed rate, then print a
+/// summary
+pub fn run(allocator: std.mem.Allocator, options: *const Options) !void {
+    const target = std.mem.trimRight(u8, options.target, "/");
+    if (!std.mem.startsWith(u8, target, "http://") and !std.mem.startsWith(u8, target, "https://")) {
+        return error.InvalidReplayTarget;
+    }
+    _ = std.Uri.parse(target) catch return error.InvalidReplayTarget;
+
+    const file = std.fs.cwd().openFile(options.log_path, .{}) catch |err| {
+        std.debug.print("✗ Access log {s}: {any}\n", .{ options.log_path, err });
+        return err;
+    };
+    defer file.close();
+    var read_buf: [MAX_LINE]u8 = undefined;
+    var reader = file.reader(&read_buf);
+
+    var shared = Shared{ .allocator = allocator, .options = options };
+    defer shared.tally.latencies_ms.deinit(allocator);
+
+    std.debug.print("✓ Replaying {s} against {s}\n", .{ options.log_path, target });
+    const started_ms = std.time.milliTimestamp();
+    var first_logged_ms: ?i64 = null;
+    var scheduled: u64 = 0;
+    var skipped: u64 = 0;
+    while (true) {
+        const line = reader.interface.takeDelimiterExclusive('\n') catch |err| switch (err) {
+            error.EndOfStream => break,
+            error.StreamTooLong => {
+                skipped += 1;
+                _ = reader.interface.discardDelimiterInclusive('\n') catch break;
+                continue;
+            },
+            else => return err,
+        };
+        var line_arena = std.heap.ArenaAllocator.init(allocator);
+        defer line_arena.deinit();
+        const entry = parseLine(line_arena.allocator(), line) orelse {
+            skipped += 1;
+            continue;
+        };
+        if (!options.all_methods and !std.mem.eql(u8, entry.method, "GET") and !std.mem.eql(u8, entry.method, "HEAD")) {
+            skipped += 1;
+            continue;
+        }
+
+        // Wait until the request is due
+        const due_ms: i64 = switch (options.pace) {
+            .per_second => |rate| started_ms + @as(i64, @intFromFloat(@as(f64, @floatFromInt(scheduled)) * 1000 / rate)),
+            .speedup => |factor| blk: {
+                const logged = entry.time_ms orelse break :blk std.time.milliTimestamp();
+                const first = first_logged_ms orelse first: {
+                    first_logged_ms = logged;
+                    break :first logged;
+                };
+                break :blk started_ms + @as(i64, @intFromFloat(@as(f64, @floatFromInt(@max(logged - first, 0))) / factor));
+            },
+        };
+        const wait_ms = due_ms - std.time.milliTimestamp();
+        if (wait_ms > 0) std.Thread.sleep(@as(u64, @intCast(wait_ms)) * std.time.ns_per_ms);
+
+        // Past the concurrency limit, the pace slips rather than the limit
+        while (shared.in_flight.load(.acquire) >= options.concurrency) std.Thread.sleep(std.time.ns_per_ms);
+
+        const owned = try ownedEntry(allocator, entry);
+        _ = shared.in_flight.fetchAdd(1, .acq_rel);
+        shared.wait_group.start();
+        const thread = std.Thread.spawn(.{}, send, .{ &shared, target, owned }) catch |err| {
+            freeEntry(allocator, owned);
+            shared.wait_group.finish();
+            _ = shared.in_flight.fetchSub(1, .acq_rel);
+            return err;
+        };
+        thread.detach();
+        scheduled += 1;
+    }
+    shared.wait_group.wait();
+
+    const tally = &shared.tally;
+    std.mem.sort(u32, tally.latencies_ms.items, {}, std.sort.asc(u32));
+    const elapsed_s = @as(f64, @floatFromInt(std.time.milliTimestamp() - started_ms)) / 1000;
+    std.debug.print("✓ Replayed {d} requests in {d:.1}s ({d} lines skipped)\n", .{ tally.sent, elapsed_s, skipped });
+    std.debug.print("  1xx {d}  2xx {d}  3xx {d}  4xx {d}  5xx {d}  failed {d}\n", .{
+        tally.classes[0], tally.classes[1], tally.classes[2], tally.classes[3], tally.classes[4], tally.failed,
+    });
+    std.debug.print("  latency p50 {d}ms  p90 {d}ms  p99 {d}ms\n", .{
+        Tally.percentile(tally.latencies_ms.items, 50),
+        Tally.percentile(tally.latencies_ms.items, 90),
+        Tally.percentile(tally.latencies_ms.items, 99),
+    });
+}
+
+/// Copy of an entry that outlives its line
+fn ownedEntry(allocator: std.mem.Allocator, entry: Entry) !*Entry {
+    const owned = try allocator.create(Entry);
+    errdefer allocator.destroy(owned);
+    owned.* = .{
+        .time_ms = entry.time_ms,
+        .method = try allocator.dupe(u8, entry.method),
+        .uri = try allocator.dupe(u8, entry.uri),
+        .host = if (entry.host) |h| try allocator.dupe(u8, h) else null,
+        .referer = if (entry.referer) |r| try allocator.dupe(u8, r) else null,
+        .user_agent = if (entry.user_agent) |ua| try allocator.dupe(u8, ua) else null,
+    };
+    return owned;
+}
+
+fn freeEntry(allocator: std.mem.Allocator, entry: *Entry) void {
+    allocator.free(entry.method);
+    allocator.free(entry.uri);
+    if (entry.host) |h| allocator.free(h);
+    if (entry.referer) |r| allocator.free(r);
+    if (entry.user_agent) |ua| allocator.free(ua);
+    allocator.destroy(entry);
+}
+
+fn send(shared: *Shared, target: []const u8, entry: *Entry) void {
+    const allocator = shared.allocator;
+    defer {
+        freeEntry(allocator, entry);
+        _ = shared.in_flight.fetchSub(1, .acq_rel);
+        shared.wait_group.finish();
+    }
+
+    const started = std.time.milliTimestamp();
+    const status = fetch(allocator, shared.options, target, entry) catch |err| blk: {
+        std.debug.print("✗ {s} {s}: {any}\n", .{ entry.method, entry.uri, err });
+        break :blk null;
+    };
+    const latency: u32 = @intCast(std.math.clamp(std.time.milliTimestamp() - started, 0, std.math.maxInt(u32)));
+    shared.tally.record(allocator, status, latency);
+}
+
+fn fetch(allocator: std.mem.Allocator, options: *const Options, target: []const u8, entry: *const Entry) !u16 {
+    const method = std.meta.stringToEnum(std.http.Method, entry.method) orelse return error.UnsupportedMethod;
+    const url = try std.fmt.allocPrint(allocator, "{s}{s}", .{ target, entry.uri });
+    defer allocator.free(url);
+
+    var extra: [3]std.http.Header = undefined;
+    var count: usize = 0;
+    extra[count] = .{ .name = REPLAY_HEADER, .value = "1" };
+    count += 1;
+    if (entry.referer) |referer| {
+        extra[count] = .{ .name = "Referer", .value = referer };
+        count += 1;
+    }
+    if (entry.user_agent) |user_agent| {
+        extra[count] = .{ .name = "User-Agent", .value = user_agent };
+        count += 1;
+    }
+    const host = if (options.host.len > 0) options.host else entry.host;
+
+    var client: std.http.Client = .{ .allocator = allocator };
+    defer client.deinit();
+    const result = try client.fetch(.{
+        .location = .{ .url = url },
+        .method = method,
+        .payload = if (method.requestHasBody()) "" else null,
+        .headers = .{ .host = if (host) |h| .{ .override = h } else .default },
+        .extra_headers = extra[0..count],
+        .keep_alive = false,
+    });
+    return @intFromEnum(result.status);
+}
@@ -1,109 +1,1869 @@
 const std = @import("std");
 const flare = @import("flare");
+const acl = @import("../security/acl.zig");
+const request = @import("../server/request.zig");
+const cache_headers = @import("../proxy/cache_headers.zig");
+const when = @import("../routing/when.zig");
+const path_rewrite = @import("../proxy/rewrite.zig");
+const header_rules = @import("../proxy/header_rules.zig");
+const alt_svc = @import("../proxy/alt_svc.zig");
+const acme = @import("../server/acme.zig");
+const schedule_mod = @import("../server/schedule.zig");
+const fingerprint = @import("../server/fingerprint.zig");
+const memory = @import("../server/memory.zig");
+const secret_mod = @import("secret.zig");
+const toml = @import("toml.zig");
+
+/// Largest configuration file read
+const MAX_CONFIG_SIZE = 4 * 1024 * 1024;
+
+/// How closely requests are held to HTTP/1.1 framing rules. Requests that
+/// parsers could read differently are how smuggling gets past a proxy.
+pub const FramingStrictness = enum {
+    /// Only the checks configured separately
+    lenient,
+    /// Also reject Transfer-Encoding other than a single "chunked",
+    /// Transfer-Encoding on HTTP/1.0, control characters in header values
+    /// or the target, and header names that aren't tokens
+    standard,
+    /// Also reject repeated Content-Length headers even when they agree,
+    /// both framing headers whatever `reject_conflicting_framing` says,
+    /// and bodies on GET, HEAD, OPTIONS and DELETE
+    strict,
+};
+
+/// Request head limits enforced before any routing or proxying
+pub const RequestLimitsConfig = struct {
+    /// Maximum number of request headers (431 when exceeded)
+    max_header_count: usize = 100,
+    /// Maximum size of a single header field in bytes (431 when exceeded)
+    max_header_size: usize = 8 * 1024,
+    /// Maximum size of the whole request head in bytes (431 when exceeded)
+    max_head_size: usize = 16 * 1024,
+    /// Maximum request-target length in bytes (414 when exceeded)
+    max_uri_length: usize = 8 * 1024,
+    /// Reject obsolete header line folding with 400 instead of unfolding it
+    reject_obsolete_folding: bool = true,
+    /// Reject requests carrying both Content-Length and Transfer-Encoding with 400
+    reject_conflicting_framing: bool = true,
+    strictness: FramingStrictness = .standard,
+};
+
+/// Header limits for requests routed to one upstream group or route.
+/// Each non-zero field replaces the server-wide value, so a backend that
+/// needs large headers (e.g. Kerberos tokens) doesn't loosen the rest.
+pub const HeaderLimitsConfig = struct {
+    /// Maximum number of request headers (431 when exceeded)
+    max_header_count: usize = 0,
+    /// Maximum size of a single request header field in bytes (431 when exceeded)
+    max_header_size: usize = 0,
+    /// Maximum size of the whole request head in bytes, at most 64 KiB (431 when exceeded)
+    max_head_size: usize = 0,
+    /// Maximum size of the upstream response head in bytes, at most 64 KiB
+    /// (502 when exceeded; 0 = 64 KiB)
+    max_response_head_size: usize = 0,
+    /// Maximum number of upstream response headers, at most 256
+    /// (502 when exceeded; 0 = 256)
+    max_response_header_count: usize = 0,
+
+    /// Layer `other` over these limits
+    fn merge(self: *HeaderLimitsConfig, other: *const HeaderLimitsConfig) void {
+        inline for (std.meta.fields(HeaderLimitsConfig)) |field| {
+            if (@field(other, field.name) != 0) @field(self, field.name) = @field(other, field.name);
+        }
+    }
+};
+
+/// Header hygiene for responses leaving wraith
+pub const ResponseHeadersConfig = struct {
+    /// Server header on wraith-generated responses (empty = omit)
+    server: []const u8 = "Wraith/0.0.0",
+    /// Replace the upstream Server header with `server` (dropped when empty)
+    override_upstream_server: bool = false,
+    /// Upstream response headers removed before reaching the client
+    hide: []const []const u8 = &[_][]const u8{},
+};
+
+/// An alternative endpoint clients may switch to, like HTTP/3 on UDP 443
+pub const AltServiceConfig = struct {
+    /// ALPN protocol id, such as "h3"
+    protocol: []const u8 = "h3",
+    /// Alternative host (empty = the host the client asked for)
+    host: []const u8 = "",
+    port: u16 = 443,
+};
+
+/// Alt-Svc advertisement added to proxied responses, replacing any the
+/// upstream sent
+pub const AltSvcConfig = struct {
+    enabled: bool = false,
+    services: []const AltServiceConfig = &[_]AltServiceConfig{},
+    /// How long clients may remember the advertisement, in seconds
+    max_age: u32 = 86_400,
+    /// Send "Alt-Svc: clear" so clients forget earlier advertisements
+    clear: bool = false,
+};
+
+/// Canonical spellings applied to request URLs before routing, ACLs and
+/// caching, so one resource can't be reached under several names
+pub const NormalizeConfig = struct {
+    /// Collapse runs of slashes in the path ("//a///b" -> "/a/b")
+    merge_slashes: bool = false,
+    /// Decode escaped unreserved characters and uppercase the hex digits of
+    /// other escapes ("%7euser%2f" -> "~user%2F"); malformed escapes get 400
+    percent_encoding: bool = false,
+    /// Drop a "#fragment", which clients shouldn't send
+    strip_fragment: bool = false,
+    /// Lowercase the Host header
+    lowercase_host: bool = false,
+};
+
+/// Frontend connection timeouts
+pub const TimeoutsConfig = struct {
+    /// Time allowed to receive a complete request head, in milliseconds (0 = none)
+    header_timeout_ms: u32 = 10_000,
+    /// Close idle keep-alive connections after this long (0 = disable keep-alive)
+    keepalive_timeout_ms: u32 = 75_000,
+    /// Requests served on one connection before it is closed (0 = unlimited)
+    max_requests_per_connection: usize = 100,
+};
+
+/// Passthrough route on the demux listener
+pub const DemuxRouteConfig = struct {
+    /// Protocol detected from the first bytes: "tls", "ssh", "http" or "unknown"
+    protocol: []const u8 = "tls",
+    /// TLS server name: "*", "*.example.com" or an exact name
+    sni: []const u8 = "*",
+    /// ALPN protocol the TLS client must offer (empty = any)
+    alpn: []const u8 = "",
+    /// Backend as ip:port; bytes are relayed untouched
+    backend: []const u8,
+};
+
+/// Protocol multiplexing on a single port (e.g. SSH and HTTPS on 443)
+pub const DemuxConfig = struct {
+    enabled: bool = false,
+    listen: []const u8 = "0.0.0.0:443",
+    /// Checked in order
+    routes: []const DemuxRouteConfig = &[_]DemuxRouteConfig{},
+    /// Backend for connections matching no route (empty = close)
+    default_backend: []const u8 = "",
+    /// How long to wait for the client's first bytes, in milliseconds
+    peek_timeout_ms: u32 = 3000,
+    /// Close passthrough connections idle this long (0 = never), in milliseconds
+    idle_timeout_ms: u32 = 600_000,
+};
+
+/// Where the client address of a request from a trusted proxy comes from
+pub const RealIpSource = enum {
+    /// Always the TCP peer (or PROXY protocol source)
+    peer,
+    /// Rightmost X-Forwarded-For entry that is not a trusted proxy
+    x_forwarded_for,
+    /// Cloudflare's CF-Connecting-IP
+    cf_connecting_ip,
+    /// X-Real-IP, as set by another reverse proxy
+    x_real_ip,
+};
+
+/// Client address resolution behind load balancers and CDNs. The result
+/// is what rate limits, ACLs, templates and logs see.
+pub const RealIpConfig = struct {
+    /// Peers allowed to report the client address, in CIDR notation
+    trusted_proxies: []const []const u8 = &[_][]const u8{},
+    source: RealIpSource = .x_forwarded_for,
+    /// Trusted peers start each connection with a PROXY protocol v1 or v2
+    /// header carrying the client address
+    proxy_protocol: bool = false,
+};
+
+/// JA3/JA4 fingerprints of clients on the `listen_tls` listeners, worked
+/// out from their ClientHello. Rules take JA3 hashes or JA4 strings and,
+/// like `listen_tls`, are refused until the TLS handshake is implemented.
+pub const FingerprintConfig = struct {
+    /// Request headers carrying the fingerprints to upstreams (empty =
+    /// not sent). Copies sent by clients are always dropped.
+    ja3_header: []const u8 = "X-JA3-Fingerprint",
+    ja4_header: []const u8 = "X-JA4-Fingerprint",
+    /// Only clients with these fingerprints complete the handshake; a
+    /// ClientHello that can't be read matches none (empty = any)
+    allow: []const []const u8 = &[_][]const u8{},
+    /// Clients with these fingerprints are disconnected before the handshake
+    deny: []const []const u8 = &[_][]const u8{},
+};
+
+/// Caps on memory held by requests: buffered bodies, body filters and the
+/// per-connection scratch space. Request heads are bounded by `limits`.
+pub const MemoryConfig = struct {
+    /// Bytes one connection may hold; requests needing more get 413
+    /// (0 = unlimited)
+    connection_limit: usize = 0,
+    /// Bytes all connections together may hold; requests needing more get
+    /// 503 (0 = unlimited)
+    global_limit: usize = 0,
+    /// Fraction of `global_limit` past which caches are shrunk and new
+    /// requests are refused with 503 until usage falls
+    high_watermark: f64 = 0.9,
+};
+
+/// Bound on static mounts' disk work: opening directories and files,
+/// stat'ing them and hashing strong ETags. Sending file data is paced by
+/// the client and limited per client by a mount's `downloads` instead.
+pub const StaticIoConfig = struct {
+    /// Requests doing disk work at once, across all mounts (0 = unlimited)
+    max_concurrent: u32 = 0,
+    /// How long a request waits for a turn before it is refused with 503
+    queue_timeout_ms: u32 = 1000,
+};
 
 pub const ServerConfig = struct {
+    /// Every address is bound; IPv6 addresses such as "[::]:80" accept
+    /// IPv6 clients only, so they can share a port with an IPv4 listener
     listen: []const []const u8,
-    listen_tls: []const []const u8,
-    worker_threads: usize,
+    /// Like `listen`, terminating TLS with the [tls] certificate. Refused
+    /// at validation until the TLS handshake is implemented
+    listen_tls: []const []const u8 = &[_][]const u8{},
+    /// Also listen on [::] for every 0.0.0.0 address in `listen` and
+    /// `listen_tls`
+    dual_stack: bool = false,
+    /// Host names requests may address, like "example.com" or
+    /// "*.example.com"; others are refused before routing (empty = any)
+    allowed_hosts: []const []const u8 = &[_][]const u8{},
+    worker_threads: usize = 0,
+    /// Concurrent client connections; more are refused with 503. Preflight
+    /// checks the open file limit against it (0 = unlimited)
+    max_connections: usize = 10_000,
+    /// Concurrent connections from one client address (0 = unlimited)
+    max_connections_per_client: u32 = 0,
+    /// Check files, certificates, upstream names, ports and limits before
+    /// binding, and refuse to start when any check fails
+    preflight: bool = true,
+    limits: RequestLimitsConfig = .{},
+    response_headers: ResponseHeadersConfig = .{},
+    timeouts: TimeoutsConfig = .{},
+    demux: DemuxConfig = .{},
+    real_ip: RealIpConfig = .{},
+    alt_svc: AltSvcConfig = .{},
+    normalize: NormalizeConfig = .{},
+    fingerprint: FingerprintConfig = .{},
+    memory: MemoryConfig = .{},
+    static_io: StaticIoConfig = .{},
+};
+
+/// External account binding issued by the CA, ties a new ACME account to an
+/// existing one (ZeroSSL, step-ca and most internal CAs)
+pub const AcmeEabConfig = struct {
+    key_id: []const u8,
+    /// Base64url-encoded HMAC key, "env:NAME" or "file:/path"
+    hmac_key: []const u8,
+
+    pub const secret_fields = [_][]const u8{"hmac_key"};
+};
+
+/// Automatic certificates from an ACME CA
+pub const AcmeConfig = struct {
+    enabled: bool = false,
+    /// Contact for expiry and revocation notices
+    email: ?[]const u8 = null,
+    /// Directory of the CA; Let's Encrypt production unless set
+    directory_url: []const u8 = acme.LETS_ENCRYPT_PRODUCTION,
+    eab: ?AcmeEabConfig = null,
 };
 
 pub const TlsConfig = struct {
-    cert_dir: []const u8,
-    acme_enabled: bool,
-    acme_email: ?[]const u8,
+    cert_dir: []const u8 = "/etc/wraith/certs",
+    /// Certificate and key served on `server.listen_tls`; default to
+    /// server.crt/server.key in `cert_dir`
+    cert: ?[]const u8 = null,
+    key: ?[]const u8 = null,
+    acme: AcmeConfig = .{},
+};
+
+pub const AccessLogFormat = enum {
+    /// NCSA common log format
+    common,
+    /// Common format plus Referer and User-Agent, as nginx and Apache log
+    combined,
+    /// One JSON object per line
+    json,
+};
+
+/// Where and how requests are logged
+pub const AccessLogConfig = struct {
+    /// File appended to (empty = no access log)
+    path: []const u8 = "",
+    format: AccessLogFormat = .combined,
 };
 
 pub const LoggingConfig = struct {
-    level: []const u8,
-    format: []const u8,
-    output: []const u8,
+    level: []const u8 = "info",
+    format: []const u8 = "json",
+    output: []const u8 = "stdout",
+    access_log: AccessLogConfig = .{},
 };
 
 pub const UpstreamServer = struct {
     host: []const u8,
-    weight: u32,
+    weight: u32 = 1,
+    /// Receives traffic only while every primary server is unhealthy
+    backup: bool = false,
+
+    /// A server written as its address alone
+    pub fn fromString(host: []const u8) UpstreamServer {
+        return .{ .host = host };
+    }
+};
+
+/// Upstream servers discovered from DNS SRV records, as served by Consul or
+/// for Kubernetes headless Services
+pub const DnsDiscoveryConfig = struct {
+    enabled: bool = false,
+    /// SRV name, e.g. "_http._tcp.api.service.consul"
+    name: []const u8 = "",
+    /// Resolvers as ip:port, tried in order (empty = those in /etc/resolv.conf)
+    nameservers: []const []const u8 = &[_][]const u8{},
+    /// Per-resolver query timeout in milliseconds
+    timeout_ms: u32 = 2000,
+    /// How often the records are looked up again, in milliseconds
+    refresh_interval_ms: u32 = 30_000,
+};
+
+/// Upstream servers discovered from a Service's EndpointSlices
+/// (requires building with -Dkubernetes=true)
+pub const KubernetesDiscoveryConfig = struct {
+    enabled: bool = false,
+    namespace: []const u8 = "default",
+    service: []const u8 = "",
+    /// Named service port to use (empty = first port)
+    port_name: []const u8 = "",
+    /// API server URL (null = in-cluster service from the pod environment)
+    api_server: ?[]const u8 = null,
+    token_path: []const u8 = "/var/run/secrets/kubernetes.io/serviceaccount/token",
+    ca_path: []const u8 = "/var/run/secrets/kubernetes.io/serviceaccount/ca.crt",
+    /// How often the endpoints are re-listed, in milliseconds
+    refresh_interval_ms: u32 = 5000,
+};
+
+pub const HeaderConfig = struct {
+    name: []const u8,
+    value: []const u8,
+};
+
+/// How health check probes are sent and judged
+pub const HealthCheckConfig = struct {
+    method: []const u8 = "GET",
+    /// Host header (empty = the server's address)
+    host: []const u8 = "",
+    headers: []const HeaderConfig = &[_]HeaderConfig{},
+    /// Required status (0 = any 2xx or 3xx)
+    expected_status: u16 = 0,
+    /// Text the response body must contain (empty = not checked)
+    body_contains: []const u8 = "",
+    /// Dotted path of a JSON body field that must be present, e.g. "checks.db.status"
+    json_field: []const u8 = "",
+    /// Required value of `json_field` (empty = any value except null and false)
+    json_value: []const u8 = "",
+    /// Largest body read for matching, in bytes
+    max_body_size: usize = 64 * 1024,
+    /// Consecutive passing checks before an unhealthy server is used again
+    rise: u32 = 2,
+    /// Consecutive failing checks before a healthy server is taken out
+    fall: u32 = 3,
+    /// Random delay up to this long added to each interval, in milliseconds
+    jitter_ms: u32 = 0,
+};
+
+pub const EgressProxyKind = enum {
+    /// HTTP proxy tunnelling with CONNECT
+    http_connect,
+    socks5,
+};
+
+/// Proxy for wraith's own outbound connections: proxied requests, stream
+/// proxying and health checks
+pub const EgressProxyConfig = struct {
+    enabled: bool = false,
+    kind: EgressProxyKind = .http_connect,
+    /// Proxy address as ip:port
+    address: []const u8 = "",
+    /// Basic credentials for CONNECT, or RFC 1929 credentials for SOCKS5
+    username: []const u8 = "",
+    /// The value itself, "env:NAME" or "file:/path"
+    password: []const u8 = "",
+    /// Addresses and CIDR ranges connected to directly
+    no_proxy: []const []const u8 = &[_][]const u8{},
+    /// Time allowed to set up the tunnel, in milliseconds
+    handshake_timeout_ms: u32 = 10_000,
+
+    pub const secret_fields = [_][]const u8{"password"};
+};
+
+pub const ConcurrencyAlgorithm = enum {
+    /// Additive increase, multiplicative decrease on failures or slow responses
+    aimd,
+    /// Scale by the ratio of long-term to current latency
+    gradient,
+};
+
+/// Adaptive limit on requests in flight to an upstream group, learned
+/// from response latency. Requests over the limit get 503.
+pub const AdaptiveConcurrencyConfig = struct {
+    enabled: bool = false,
+    algorithm: ConcurrencyAlgorithm = .gradient,
+    initial_limit: u32 = 20,
+    min_limit: u32 = 1,
+    max_limit: u32 = 1000,
+    /// aimd: responses slower than this count as overload
+    latency_threshold_ms: u32 = 1000,
+    /// aimd: factor applied to the limit on overload
+    backoff_ratio: f64 = 0.9,
+    /// gradient: weight given to each new estimate, between 0 and 1
+    smoothing: f64 = 0.2,
+    /// How long a request over the limit waits for a slot (0 = rejected at
+    /// once). Waiting requests are admitted highest priority first.
+    queue_timeout_ms: u32 = 0,
+    /// Waiting requests; when full, a new request pushes out a waiting one
+    /// of lower priority or is rejected
+    max_queue: u32 = 100,
+    /// Share of the limit that low-priority requests may use
+    low_priority_share: f64 = 0.5,
+};
+
+/// Admission order when an upstream group's concurrency limit is reached
+pub const RequestPriority = enum {
+    /// Health checks, checkout flows
+    critical,
+    high,
+    normal,
+    /// Batch and background work; shed first
+    low,
+};
+
+/// How a group spreads requests over its servers. Every method honours
+/// server weights and skips servers with weight 0.
+pub const LoadBalancing = enum {
+    /// Smooth weighted round-robin
+    round_robin,
+    /// Fewest requests in flight relative to weight; ties in round-robin order
+    least_conn,
+    /// The same client address keeps reaching the same server
+    ip_hash,
+    random,
+};
+
+/// How requests to an upstream group are signed
+pub const SigningKind = enum {
+    none,
+    /// HMAC-SHA256 of the method, target, host, timestamp and body hash,
+    /// sent in a header
+    hmac,
+    /// AWS Signature Version 4, for S3 and other AWS-compatible APIs
+    aws_sigv4,
+};
+
+/// Signs every request forwarded to an upstream group. Credentials are the
+/// value itself, "env:NAME" or "file:/path"; files are re-read when they
+/// change, so keys rotate without a reload.
+pub const SigningConfig = struct {
+    kind: SigningKind = .none,
+    /// Host header sent upstream and signed (empty = the client's Host)
+    host: []const u8 = "",
+    /// HMAC key or AWS secret access key
+    secret: []const u8 = "",
+    /// hmac: identifies the key to the upstream (empty = no key id header)
+    key_id: []const u8 = "",
+    signature_header: []const u8 = "X-Signature",
+    timestamp_header: []const u8 = "X-Signature-Timestamp",
+    key_id_header: []const u8 = "X-Signature-Key-Id",
+    /// aws_sigv4
+    access_key_id: []const u8 = "",
+    /// aws_sigv4: for temporary credentials
+    session_token: []const u8 = "",
+    region: []const u8 = "us-east-1",
+    service: []const u8 = "s3",
+
+    pub const secret_fields = [_][]const u8{ "secret", "key_id", "access_key_id", "session_token" };
+};
+
+/// TLS to a group's servers, for backends that only speak HTTPS
+pub const UpstreamTlsConfig = struct {
+    /// Also turned on by an "https://" prefix on any of the group's servers
+    enabled: bool = false,
+    /// Sent as SNI and matched against the certificates (empty = the host
+    /// name of the first server given by name)
+    server_name: []const u8 = "",
+    /// PEM file of the CAs trusted to sign the servers' certificates, e.g.
+    /// a self-signed one (empty = the system's roots)
+    ca_file: []const u8 = "",
+    /// Accept any certificate, for development only
+    insecure_skip_verify: bool = false,
+    /// Give up on a handshake after this long, in milliseconds
+    handshake_timeout_ms: u32 = 10_000,
+};
+
+/// Connections kept open to a group's servers between requests
+pub const UpstreamPoolConfig = struct {
+    /// Idle keep-alive connections kept across the group's servers
+    /// (0 = a new connection per request, closed after the response)
+    max_idle: u32 = 32,
+    /// Close idle connections after this long (0 = never), in milliseconds
+    idle_timeout_ms: u32 = 60_000,
+    /// Connections open to the group at once, idle or in use; requests
+    /// beyond it get 503 (0 = unlimited)
+    max_connections: u32 = 0,
 };
 
 pub const UpstreamConfig = struct {
     name: []const u8,
     servers: []const UpstreamServer,
-    load_balancing: []const u8,
-    health_check_interval: u64,
-    health_check_timeout: u64,
-    health_check_path: []const u8,
+    load_balancing: LoadBalancing = .round_robin,
+    /// Seconds between health checks (0 = disabled)
+    health_check_interval: u64 = 10,
+    health_check_timeout: u64 = 5,
+    health_check_path: []const u8 = "/",
+    health_check: HealthCheckConfig = .{},
+    kubernetes: KubernetesDiscoveryConfig = .{},
+    dns: DnsDiscoveryConfig = .{},
+    adaptive_concurrency: AdaptiveConcurrencyConfig = .{},
+    /// Overrides server.limits for requests routed to this group
+    header_limits: HeaderLimitsConfig = .{},
+    signing: SigningConfig = .{},
+    /// Source IP of connections to the group's servers, for multi-homed
+    /// hosts and backends that allowlist addresses (empty = chosen by the
+    /// kernel)
+    local_address: []const u8 = "",
+    tls: UpstreamTlsConfig = .{},
+    pool: UpstreamPoolConfig = .{},
+    /// Retries for routes to this group that don't set their own
+    retry: RetryConfig = .{},
+    retry_budget: RetryBudgetConfig = .{},
+
+    /// Whether connections to the group's servers use TLS
+    pub fn usesTls(self: *const UpstreamConfig) bool {
+        if (self.tls.enabled) return true;
+        for (self.servers) |server| {
+            if (std.mem.startsWith(u8, server.host, "https://")) return true;
+        }
+        return false;
+    }
+
+    /// Name the servers' certificates are checked against: `tls.server_name`,
+    /// or else the host of the first server given by name rather than by IP
+    pub fn tlsServerName(self: *const UpstreamConfig) ?[]const u8 {
+        if (self.tls.server_name.len > 0) return self.tls.server_name;
+        for (self.servers) |server| {
+            const host = serverHostName(server.host);
+            if (std.net.Address.parseIp(host, 0)) |_| continue else |_| return host;
+        }
+        return null;
+    }
+};
+
+/// Host part of an upstream server's "scheme://host:port"
+pub fn serverHostName(host: []const u8) []const u8 {
+    const start = if (std.mem.indexOf(u8, host, "://")) |i| i + 3 else 0;
+    const without_scheme = host[start..];
+    const end = std.mem.lastIndexOfScalar(u8, without_scheme, ':') orelse without_scheme.len;
+    return std.mem.trim(u8, without_scheme[0..end], "[]");
+}
+
+pub const CorsConfig = struct {
+    enabled: bool = false,
+    /// Allowed origins; supports "*" and wildcards like "https://*.example.com"
+    allowed_origins: []const []const u8 = &[_][]const u8{},
+    allowed_methods: []const []const u8 = &[_][]const u8{ "GET", "HEAD", "POST" },
+    /// Allowed request headers (empty or "*" reflects the preflight request)
+    allowed_headers: []const []const u8 = &[_][]const u8{},
+    expose_headers: []const []const u8 = &[_][]const u8{},
+    allow_credentials: bool = false,
+    /// Preflight cache lifetime in seconds (0 = not sent)
+    max_age: u32 = 0,
+};
+
+/// IP access control list; entries are addresses or CIDR ranges
+pub const AclConfig = struct {
+    allow: []const []const u8 = &[_][]const u8{},
+    deny: []const []const u8 = &[_][]const u8{},
+};
+
+/// Token bucket per client address
+pub const PerIpRateLimitConfig = struct {
+    enabled: bool = false,
+    requests_per_second: f64 = 100,
+    /// Requests a client may make at once (0 = requests_per_second)
+    burst: u32 = 200,
+    /// Clients tracked at once; beyond this the least recently seen is dropped
+    max_clients: usize = 100_000,
+    /// Forget clients idle this long (0 = once their bucket has refilled), in milliseconds
+    idle_timeout_ms: u32 = 0,
+};
+
+/// Requests over a limit get 429 Too Many Requests
+pub const RateLimitConfig = struct {
+    enabled: bool = false,
+    /// Across all clients (0 = unlimited)
+    requests_per_second: f64 = 0,
+    /// 0 = requests_per_second
+    burst: u32 = 0,
+    per_ip: PerIpRateLimitConfig = .{},
+};
+
+/// Per-client limit for a single route, applied on top of the global ones
+pub const RouteRateLimitConfig = struct {
+    /// 0 = no route limit
+    requests_per_second: f64 = 0,
+    /// 0 = requests_per_second
+    burst: u32 = 0,
+    max_clients: usize = 10_000,
+};
+
+pub const SubFilterRule = struct {
+    search: []const u8,
+    replace: []const u8,
+};
+
+/// Find/replace on textual response bodies
+pub const SubFilterConfig = struct {
+    /// Applied in order at each position; empty disables the filter
+    rules: []const SubFilterRule = &[_]SubFilterRule{},
+    /// Content types filtered; supports "text/*" and "*"
+    types: []const []const u8 = &[_][]const u8{"text/html"},
+    /// Replace only the first occurrence of each rule
+    once: bool = false,
+};
+
+pub const CompressionLevel = enum {
+    fast,
+    default,
+    best,
+};
+
+/// Gzip compression of proxied responses for clients that accept it
+pub const CompressionConfig = struct {
+    enabled: bool = false,
+    level: CompressionLevel = .default,
+    /// Responses known to be smaller than this are sent as they are, in bytes
+    min_size: usize = 1024,
+    /// Content types compressed; supports "text/*" and "*"
+    types: []const []const u8 = &[_][]const u8{
+        "text/*",
+        "application/javascript",
+        "application/json",
+        "application/xml",
+        "image/svg+xml",
+    },
+    /// Content types never compressed, even when `types` matches
+    exclude_types: []const []const u8 = &[_][]const u8{},
+    /// Leave responses with Cache-Control: no-transform untouched
+    respect_no_transform: bool = true,
+};
+
+pub const HtmlInjectPosition = enum {
+    head,
+    body,
+};
+
+/// HTML fragment inserted into text/html responses
+pub const HtmlInjectConfig = struct {
+    /// Markup to insert; empty disables injection
+    fragment: []const u8 = "",
+    /// Insert before the closing </head> or </body> tag
+    position: HtmlInjectPosition = .body,
+    /// Gzip-encoded documents up to this size are inflated for injection
+    max_decompress_size: usize = 4 * 1024 * 1024,
+};
+
+/// Where buffered request bodies are kept
+pub const RequestBufferConfig = struct {
+    /// Bodies larger than this spill to a temporary file, in bytes
+    memory_threshold: usize = 1024 * 1024,
+    temp_dir: []const u8 = "/tmp",
+    /// Largest accepted request body (0 = unlimited); larger requests get 413
+    max_body_size: usize = 0,
+};
+
+/// Which filenames multipart file parts may carry
+pub const FilenamePolicy = enum {
+    any,
+    /// No path separators, control characters or leading dots
+    safe,
+    /// Only letters, digits, spaces, '.', '-' and '_', without a leading dot
+    strict,
+};
+
+/// Limits on multipart/form-data request bodies, checked as the body is
+/// read. Violations get 413 (too many or too large parts) or 400.
+pub const MultipartLimitsConfig = struct {
+    /// Most parts in one body (0 = unlimited)
+    max_parts: usize = 0,
+    /// Largest part content in bytes (0 = unlimited)
+    max_part_size: usize = 0,
+    filenames: FilenamePolicy = .any,
+
+    pub fn enabled(self: *const MultipartLimitsConfig) bool {
+        return self.max_parts > 0 or self.max_part_size > 0 or self.filenames != .any;
+    }
+};
+
+pub const VariantConfig = struct {
+    /// Stored in the variant cookie
+    name: []const u8,
+    upstream: []const u8,
+    /// Share of new visitors assigned to this variant
+    percent: u8,
+};
+
+/// Cookie-based A/B test splitting a route across upstream groups
+pub const AbTestConfig = struct {
+    /// Percentages must add up to 100; empty disables the experiment
+    variants: []const VariantConfig = &[_]VariantConfig{},
+    cookie: []const u8 = "wraith_variant",
+    /// Lifetime of the variant cookie in seconds
+    cookie_max_age: u32 = 30 * 24 * 60 * 60,
+};
+
+/// Duplicate slow idempotent requests to a second upstream server
+pub const HedgeConfig = struct {
+    enabled: bool = false,
+    /// Hedge once the first attempt is slower than this percentile of recent responses
+    percentile: f64 = 95,
+    /// Delay used until `min_samples` responses have been seen, in milliseconds
+    initial_delay_ms: u32 = 100,
+    /// Lower bound on the hedge delay, in milliseconds
+    min_delay_ms: u32 = 5,
+    min_samples: usize = 20,
+};
+
+/// Send a failed request to another server in the upstream group. Requests
+/// that may have reached the upstream are only resent when repeating them
+/// is harmless.
+pub const RetryConfig = struct {
+    /// Additional servers to try (0 = no retries)
+    attempts: u32 = 0,
+    /// Requests carrying this header are treated as idempotent whatever
+    /// their method (empty = method only)
+    idempotency_key_header: []const u8 = "Idempotency-Key",
+    /// Without request buffering, bodies up to this size are read into
+    /// memory first so they can be resent; larger ones are streamed and
+    /// can't be retried once sent (0 = stream every body)
+    max_buffered_body: usize = 64 * 1024,
+    /// Methods resent once the request may have reached the upstream
+    /// (empty = the idempotent methods)
+    methods: []const []const u8 = &[_][]const u8{},
+    /// Upstream statuses retried on another server like a failed
+    /// connection, e.g. 502, 503 and 504 (empty = failures only)
+    statuses: []const u16 = &[_]u16{},
+    /// Delay before the first retry, doubled for each one after and
+    /// jittered (0 = retry at once), in milliseconds
+    backoff_ms: u32 = 0,
+    /// Cap on the doubled delay, in milliseconds
+    max_backoff_ms: u32 = 1000,
+};
+
+/// Limit on retries to a group relative to its traffic, so retries can't
+/// multiply the load on servers that are already failing
+pub const RetryBudgetConfig = struct {
+    /// Retries allowed per request sent to the group over the last ten
+    /// seconds (0 = no budget)
+    ratio: f64 = 0,
+    /// Retries allowed each second whatever the traffic
+    min_per_second: u32 = 10,
+};
+
+/// Fixed response served by a route instead of proxying, for health
+/// endpoints, robots.txt or placeholder APIs
+pub const RespondConfig = struct {
+    /// 0 = proxy to the route's upstream
+    status: u16 = 0,
+    headers: []const HeaderConfig = &[_]HeaderConfig{},
+    body: []const u8 = "",
+    /// Read the body from this file instead of `body`
+    file: []const u8 = "",
+    /// Expand request variables like {host} and {path} in the body
+    template: bool = false,
+};
+
+pub const DeadlineFormat = enum {
+    /// Whole milliseconds: "1500"
+    milliseconds,
+    /// Seconds with millisecond precision: "1.500"
+    seconds,
+    /// gRPC timeout syntax: "1500m"
+    grpc,
+};
+
+/// Limits on how long the upstream may go quiet. Each is restarted by
+/// progress rather than bounding the whole exchange, so long downloads and
+/// streams run as long as bytes keep coming.
+pub const ResponseTimeoutConfig = struct {
+    /// From the request being sent to the first byte of the response
+    /// (0 = only the deadline applies), in milliseconds
+    first_byte_ms: u32 = 0,
+    /// Longest pause between reads from or writes to the upstream, request
+    /// body and response alike (0 = none), in milliseconds
+    idle_ms: u32 = 0,
+};
+
+/// Time allowed for the upstream to start answering, counted from when the
+/// request reached the forwarder
+pub const DeadlineConfig = struct {
+    /// 0 = no deadline
+    timeout_ms: u32 = 0,
+    /// Header carrying the remaining budget to the upstream, e.g.
+    /// "X-Request-Timeout" or "grpc-timeout" (empty = not sent)
+    header: []const u8 = "",
+    format: DeadlineFormat = .milliseconds,
+};
+
+/// What replaces an upstream error when `intercept_errors` is on
+pub const ErrorInterceptConfig = struct {
+    /// Intercept 404 responses as well as 5xx
+    not_found: bool = false,
+    /// Upstream group retried for requests without a body; its errors
+    /// get the error page too
+    fallback: []const u8 = "",
+};
+
+/// Page served for the listed statuses, like nginx's error_page
+pub const ErrorPageConfig = struct {
+    statuses: []const u16,
+    file: []const u8,
+};
+
+/// Body format for errors wraith generates itself
+pub const ErrorFormat = enum {
+    /// Plain text, or the configured error page
+    text,
+    /// RFC 9457 application/problem+json
+    problem_json,
+};
+
+/// What a cache header rule does to the upstream's header
+pub const CacheHeaderAction = enum {
+    /// Replace the upstream's value, or add it when missing
+    set,
+    /// Add to the upstream's comma-separated list
+    append,
+    /// Only when the upstream sent none
+    default,
+    remove,
+};
+
+/// Rewrites a caching header on responses proxied for a route, for
+/// upstreams that get their caching headers wrong
+pub const CacheHeaderRule = struct {
+    /// Cache-Control, Expires, Vary or Surrogate-Control
+    header: []const u8,
+    action: CacheHeaderAction = .set,
+    value: []const u8 = "",
+    /// Only responses with these statuses (empty = all)
+    statuses: []const u16 = &[_]u16{},
+};
+
+/// Changes to the headers of one direction of a proxied exchange. Removals
+/// and replacements apply first, then `set` and `add` go in.
+pub const HeaderActionsConfig = struct {
+    /// Added alongside any of the same name
+    add: []const HeaderConfig = &[_]HeaderConfig{},
+    /// Replace any of the same name, or added when missing
+    set: []const HeaderConfig = &[_]HeaderConfig{},
+    /// Names of headers dropped
+    remove: []const []const u8 = &[_][]const u8{},
+};
+
+/// Header manipulation for a route, like injecting credentials for the
+/// upstream or hiding what it runs on from clients
+pub const HeaderRulesConfig = struct {
+    /// Applied to requests sent upstream
+    request: HeaderActionsConfig = .{},
+    /// Applied to upstream responses sent to the client
+    response: HeaderActionsConfig = .{},
+};
+
+pub const Weekday = enum { mon, tue, wed, thu, fri, sat, sun };
+
+/// When a route matches; at other times requests go to the next most
+/// specific route, like a daytime one under a night-only batch route
+pub const RouteWhenConfig = struct {
+    /// "HH:MM" local time the daily window opens and closes; it may cross
+    /// midnight (both empty = all day)
+    start: []const u8 = "",
+    end: []const u8 = "",
+    /// Days the window opens on (empty = every day)
+    days: []const Weekday = &[_]Weekday{},
+    /// First and last days, "YYYY-MM-DD", inclusive (empty = unbounded)
+    from: []const u8 = "",
+    until: []const u8 = "",
+    /// Offset of the local time from UTC, like "+02:00"; daylight saving
+    /// changes aren't followed
+    utc_offset: []const u8 = "+00:00",
+};
+
+/// Regex replacement of the path sent upstream
+pub const PathRewriteConfig = struct {
+    pattern: []const u8,
+    /// $1-$9 stand for the pattern's groups and $0 for the whole match
+    replacement: []const u8,
 };
 
 pub const RouteConfig = struct {
-    host: []const u8,
+    /// Name used by the admin API (empty = position among all routes)
+    id: []const u8 = "",
+    /// Within a vhost, narrows the vhost's hostnames
+    host: []const u8 = "*",
     path: []const u8,
+    /// Not needed when the route responds itself
+    upstream: []const u8 = "",
+    /// Removed from the start of the path sent upstream, by whole segments
+    strip_prefix: []const u8 = "",
+    /// Applied to the path sent upstream after strip_prefix
+    rewrite: ?PathRewriteConfig = null,
+    /// Put in front of the path sent upstream, after the rewrite
+    add_prefix: []const u8 = "",
+    cors: CorsConfig = .{},
+    acl: AclConfig = .{},
+    rate_limit: RouteRateLimitConfig = .{},
+    sub_filter: SubFilterConfig = .{},
+    html_inject: HtmlInjectConfig = .{},
+    compression: CompressionConfig = .{},
+    /// Access log for this route's requests, in place of the vhost's
+    access_log: ?AccessLogConfig = null,
+    /// Read the whole request body before contacting the upstream, so a
+    /// slow client ties up only wraith; off streams uploads as they arrive
+    request_buffering: bool = false,
+    request_buffer: RequestBufferConfig = .{},
+    multipart: MultipartLimitsConfig = .{},
+    /// Off relays responses untouched as they arrive, for Server-Sent Events
+    /// and other streams; body filters are skipped. text/event-stream
+    /// responses are always streamed.
+    response_buffering: bool = true,
+    /// Streamed responses: hold bytes up to this long to coalesce small
+    /// writes (0 = write each read immediately)
+    flush_interval_ms: u32 = 0,
+    /// Pass WebSocket upgrades through to the upstream and relay the
+    /// upgraded connection; otherwise the upgrade headers are dropped
+    websocket: bool = false,
+    /// Close WebSocket connections idle this long (0 = never), in milliseconds
+    websocket_idle_timeout_ms: u32 = 600_000,
+    ab_test: AbTestConfig = .{},
+    hedge: HedgeConfig = .{},
+    retry: RetryConfig = .{},
+    respond: RespondConfig = .{},
+    /// Answer 304 when the upstream sends a full 200 although its ETag or
+    /// Last-Modified shows the client's cached copy is current
+    not_modified: bool = false,
+    /// Replace upstream 5xx responses with wraith's error pages
+    intercept_errors: bool = false,
+    error_intercept: ErrorInterceptConfig = .{},
+    deadline: DeadlineConfig = .{},
+    response_timeout: ResponseTimeoutConfig = .{},
+    priority: RequestPriority = .normal,
+    error_format: ErrorFormat = .text,
+    /// Overrides server.limits and the upstream group's header_limits
+    header_limits: HeaderLimitsConfig = .{},
+    /// Applied in order to upstream responses
+    cache_headers: []const CacheHeaderRule = &[_]CacheHeaderRule{},
+    headers: HeaderRulesConfig = .{},
+    /// Match only at certain times (null = always)
+    when: ?RouteWhenConfig = null,
+};
+
+/// How static files are tagged for revalidation
+pub const EtagMode = enum {
+    off,
+    /// W/"<mtime>-<size>": free to compute, but changes whenever the file
+    /// is touched even if its contents don't
+    weak,
+    /// Hash of the contents, computed once per file version and cached
+    strong,
+};
+
+/// Content hash behind strong ETags
+pub const EtagHash = enum {
+    sha256,
+    blake3,
+    /// Fastest, but not collision resistant
+    wyhash,
+};
+
+pub const S3CacheCompression = enum {
+    none,
+    gzip,
+};
+
+/// Local disk copies of whole objects from an S3 origin, served without
+/// asking the bucket until they expire
+pub const S3CacheConfig = struct {
+    /// Directory holding the copies (empty = no cache)
+    dir: []const u8 = "",
+    /// Oldest copies are evicted past this many bytes
+    max_size: u64 = 1024 * 1024 * 1024,
+    /// Larger objects are always streamed from the bucket
+    max_object_size: u64 = 16 * 1024 * 1024,
+    /// Seconds a copy is served before the object is fetched again
+    ttl_s: u32 = 300,
+    /// Store copies of `compress_types` objects compressed; clients that
+    /// don't accept the coding get them decompressed
+    compression: S3CacheCompression = .none,
+    compression_level: CompressionLevel = .default,
+    /// Content types stored compressed; supports "text/*" and "*"
+    compress_types: []const []const u8 = &[_][]const u8{
+        "text/*",
+        "application/javascript",
+        "application/json",
+        "application/xml",
+        "image/svg+xml",
+    },
+    /// URLs in each list of the top report (0 = not kept)
+    top_urls: u32 = 20,
+    /// Seconds of traffic each top report covers
+    top_interval_s: u32 = 60,
+    /// Distinct URLs counted per interval; requests for more are left out
+    top_max_tracked: u32 = 10_000,
+};
+
+/// Serves a static mount from an S3-compatible bucket instead of a local
+/// root. Credentials take the same forms as upstream signing.
+pub const S3OriginConfig = struct {
+    /// "host:port" of the S3 API, spoken over plain HTTP (empty = serve `root`)
+    endpoint: []const u8 = "",
+    bucket: []const u8 = "",
+    /// Prepended to the request path to form the object key
+    prefix: []const u8 = "",
+    region: []const u8 = "us-east-1",
+    access_key_id: []const u8 = "",
+    secret: []const u8 = "",
+    session_token: []const u8 = "",
+    /// Address the bucket as "<endpoint>/<bucket>" rather than
+    /// "<bucket>.<endpoint>"; MinIO and most self-hosted stores need this
+    path_style: bool = true,
+    /// Limit on each read from and write to the bucket
+    timeout_ms: u32 = 30_000,
+    cache: S3CacheConfig = .{},
+
+    pub const secret_fields = [_][]const u8{ "access_key_id", "secret", "session_token" };
+};
+
+/// Concurrent downloads allowed to one client address, so download
+/// managers opening many parallel segments can't monopolize disk and network
+pub const DownloadLimitConfig = struct {
+    /// Downloads in progress per client (0 = unlimited)
+    max_per_client: u32 = 0,
+    /// Smaller responses aren't counted, so pages and their assets load freely
+    min_size: u64 = 0,
+    /// How long an extra download waits for a slot before it is refused
+    /// with 429 (0 = refused at once)
+    queue_timeout_ms: u32 = 0,
+};
+
+/// Directory served under a URL path prefix
+pub const StaticMountConfig = struct {
+    path: []const u8 = "/",
+    /// Directory served; unused when `s3` is set
+    root: []const u8 = "",
+    /// File served for directory requests
+    index: []const u8 = "index.html",
+    /// Copy file data to the socket inside the kernel with sendfile(2)
+    /// instead of through a userspace buffer
+    sendfile: bool = true,
+    etag: EtagMode = .weak,
+    etag_hash: EtagHash = .sha256,
+    s3: S3OriginConfig = .{},
+    downloads: DownloadLimitConfig = .{},
+};
+
+/// Certificate presented for a vhost's hostnames; defaults to
+/// server.crt/server.key in tls.cert_dir
+pub const VhostTlsConfig = struct {
+    cert_path: ?[]const u8 = null,
+    key_path: ?[]const u8 = null,
+};
+
+pub const CanonicalHost = enum {
+    /// Leave the host as requested
+    keep,
+    /// Redirect www.example.com to example.com
+    apex,
+    /// Redirect example.com to www.example.com
+    www,
+};
+
+/// Redirects to a site's canonical URL, sent before any routing. Path and
+/// query are kept.
+pub const CanonicalConfig = struct {
+    host: CanonicalHost = .keep,
+    /// Redirect plain HTTP requests to https://. Requests a trusted proxy
+    /// marks with "X-Forwarded-Proto: https" already count as HTTPS.
+    https: bool = false,
+    /// Port of the https:// URL; omitted from it when 443
+    https_port: u16 = 443,
+    /// 301, 302, 307 or 308
+    status: u16 = 301,
+};
+
+/// A site served by wraith, like an nginx server block
+pub const VhostConfig = struct {
+    /// Exact names or wildcards like "*.example.com"
+    hostnames: []const []const u8,
+    tls: VhostTlsConfig = .{},
+    /// Checked after `routes`; the longest matching path wins
+    static: []const StaticMountConfig = &[_]StaticMountConfig{},
+    routes: []const RouteConfig = &[_]RouteConfig{},
+    /// Overrides the global [logging] section for this site
+    logging: ?LoggingConfig = null,
+    /// Format of the 404 sent when no route or static mount matches
+    error_format: ErrorFormat = .text,
+    /// Overrides server.alt_svc for this site
+    alt_svc: ?AltSvcConfig = null,
+    /// Access log for this site's requests, unless a route has its own
+    access_log: ?AccessLogConfig = null,
+    canonical: CanonicalConfig = .{},
+    /// Checked before this site's routes and static mounts; a route's own
+    /// ACL applies on top
+    acl: AclConfig = .{},
+};
+
+pub const DefaultVhostAction = enum {
+    /// Use the top-level [[routes]]
+    routes,
+    /// Serve the vhost named by `vhost`
+    vhost,
+    /// Respond 421 Misdirected Request
+    misdirected,
+    /// Close the connection without a response, like nginx's 444; TLS
+    /// handshakes with an unknown SNI are refused
+    close,
+    /// Serve the file at `page` with `status`
+    page,
+};
+
+/// Handling of requests whose Host matches no vhost
+pub const DefaultVhostConfig = struct {
+    action: DefaultVhostAction = .routes,
+    /// Hostname of the vhost to serve when action = vhost
+    vhost: []const u8 = "",
+    page: []const u8 = "",
+    status: u16 = 404,
+};
+
+/// TCP listener proxied to an upstream group
+pub const StreamServerConfig = struct {
+    listen: []const u8,
+    /// Name of the upstream group connections are balanced across
     upstream: []const u8,
+    /// Close connections with no traffic in either direction (0 = never), in milliseconds
+    idle_timeout_ms: u32 = 600_000,
+};
+
+/// Layer-4 stream proxying, like nginx's stream module
+pub const StreamConfig = struct {
+    servers: []const StreamServerConfig = &[_]StreamServerConfig{},
+};
+
+/// Routes generated from container labels (wraith.host, wraith.port, wraith.path)
+pub const DockerConfig = struct {
+    enabled: bool = false,
+    socket_path: []const u8 = "/var/run/docker.sock",
+    /// Network whose container address is used (empty = first attached network)
+    network: []const u8 = "",
+    /// How often running containers are re-listed, in milliseconds
+    refresh_interval_ms: u32 = 5000,
+};
+
+/// Traffic capture started through the admin API
+pub const CaptureConfig = struct {
+    /// Upper bound on the count a capture may request
+    max_entries: usize = 1000,
+    /// Headers whose values are masked in captured entries
+    redact_headers: []const []const u8 = &[_][]const u8{
+        "Authorization",
+        "Proxy-Authorization",
+        "Cookie",
+        "Set-Cookie",
+        "X-Api-Key",
+    },
+};
+
+/// Share upstream health between wraith instances over UDP, so a server one
+/// instance sees failing is avoided by all of them
+pub const ClusterConfig = struct {
+    enabled: bool = false,
+    /// UDP address peers send state to
+    listen: []const u8 = "0.0.0.0:7946",
+    /// The other instances' cluster addresses, as ip:port
+    peers: []const []const u8 = &[_][]const u8{},
+    /// Name of this instance in messages (empty = the listen address)
+    node_id: []const u8 = "",
+    /// Shared key that authenticates messages; required. The value itself,
+    /// "env:NAME" or "file:/path".
+    secret: []const u8 = "",
+    /// How often state is sent to every peer, in milliseconds
+    gossip_interval_ms: u32 = 1000,
+    /// A peer's report is trusted for this long, in milliseconds
+    peer_timeout_ms: u32 = 5000,
+
+    pub const secret_fields = [_][]const u8{"secret"};
+};
+
+/// Limits on the admin listener itself, per client address
+pub const AdminProtectionConfig = struct {
+    /// Requests a client may make per minute (0 = unlimited)
+    requests_per_minute: u32 = 60,
+    /// Failed authentications within `failure_window_s` that lock a client
+    /// out (0 = never)
+    max_auth_failures: u32 = 5,
+    failure_window_s: u32 = 300,
+    lockout_s: u32 = 900,
+    /// File audit events are appended to as JSON lines (empty = stderr)
+    audit_log: []const u8 = "",
+};
+
+pub const AdminRole = enum {
+    /// GET endpoints: stats, routes, upstreams, metrics, redacted config
+    read_only,
+    /// Also endpoints that change state: captures and cache flushes
+    operator,
+    /// Also credentials, through /admin/config?include_secrets=true
+    admin,
+
+    pub fn allows(self: AdminRole, needed: AdminRole) bool {
+        return @intFromEnum(self) >= @intFromEnum(needed);
+    }
+};
+
+/// A bearer token for the admin API
+pub const AdminTokenConfig = struct {
+    /// Shown in logs instead of the token
+    name: []const u8,
+    /// The value itself, "env:NAME" or "file:/path"
+    token: []const u8,
+    role: AdminRole = .read_only,
+
+    pub const secret_fields = [_][]const u8{"token"};
+};
+
+/// Delays and errors injected into routes through the admin API, for
+/// testing how clients cope
+pub const FaultInjectionConfig = struct {
+    enabled: bool = false,
+    /// How long a fault lasts when the request doesn't say, in seconds
+    default_duration_s: u32 = 300,
+    /// Longest a fault may be set for, in seconds
+    max_duration_s: u32 = 3600,
+    /// Longest delay a fault may inject, in milliseconds
+    max_delay_ms: u32 = 30_000,
+};
+
+/// Admin API listener; bind it to a private address
+pub const AdminConfig = struct {
+    enabled: bool = false,
+    listen: []const u8 = "127.0.0.1:9090",
+    capture: CaptureConfig = .{},
+    faults: FaultInjectionConfig = .{},
+    protection: AdminProtectionConfig = .{},
+    /// Allow /admin/config?include_secrets=true to show credentials, to
+    /// tokens with the admin role
+    expose_secrets: bool = false,
+    /// Requests must carry one of these as "Authorization: Bearer <token>"
+    /// (empty = no authentication)
+    tokens: []const AdminTokenConfig = &[_]AdminTokenConfig{},
+};
+
+pub const DotConfig = struct {
+    enabled: bool = false,
+    /// Single listener, used when dns.listen_dot is empty
+    listen: []const u8 = "0.0.0.0:853",
+    /// Dedicated certificate and key; default to server.crt/server.key in tls.cert_dir
+    cert_path: ?[]const u8 = null,
+    key_path: ?[]const u8 = null,
+};
+
+pub const DnsCacheConfig = struct {
+    enabled: bool = true,
+    max_entries: usize = 10_000,
+    /// Lower and upper clamps applied to record TTLs, in seconds
+    min_ttl: u32 = 0,
+    max_ttl: u32 = 86_400,
+    /// Upper bound for caching NXDOMAIN and empty answers, in seconds
+    negative_ttl: u32 = 300,
+};
+
+/// Answers for vhost hostnames pointing clients at wraith itself
+pub const LocalDnsConfig = struct {
+    enabled: bool = false,
+    /// Addresses returned in A and AAAA answers
+    addresses: []const []const u8 = &[_][]const u8{},
+    /// TTL of local answers in seconds; short, so failover takes effect quickly
+    ttl: u32 = 30,
+    /// Answer SERVFAIL for vhosts whose upstream groups are all down, so
+    /// clients move on to their next resolver
+    health_checked: bool = true,
+};
+
+pub const DnsConfig = struct {
+    enabled: bool = false,
+    /// Path of the DNS-over-HTTPS endpoint on the main server
+    doh_path: []const u8 = "/dns-query",
+    /// Upstream resolvers as ip:port, tried in order
+    upstreams: []const []const u8 = &[_][]const u8{ "1.1.1.1:53", "9.9.9.9:53" },
+    /// Per-upstream query timeout in milliseconds
+    timeout_ms: u32 = 2000,
+    cache: DnsCacheConfig = .{},
+    dot: DotConfig = .{},
+    local: LocalDnsConfig = .{},
+    /// Plain DNS over UDP, for resolvers pointed at port 53
+    udp_enabled: bool = false,
+    listen_udp: []const []const u8 = &[_][]const u8{"0.0.0.0:53"},
+    /// Plain DNS over TCP, for clients retrying truncated UDP answers
+    tcp_enabled: bool = false,
+    listen_tcp: []const []const u8 = &[_][]const u8{"0.0.0.0:53"},
+    /// DNS-over-TLS listener addresses (empty = dot.listen)
+    listen_dot: []const []const u8 = &[_][]const u8{},
+
+    /// Addresses the DoT listener binds
+    pub fn dotAddresses(self: *const DnsConfig) []const []const u8 {
+        if (self.listen_dot.len > 0) return self.listen_dot;
+        const single: *const [1][]const u8 = &self.dot.listen;
+        return single;
+    }
+};
+
+/// Liveness, readiness and startup endpoints for orchestrators, answered on
+/// the main listener before any routing
+pub const ProbesConfig = struct {
+    enabled: bool = true,
+    livez_path: []const u8 = "/livez",
+    readyz_path: []const u8 = "/readyz",
+    startupz_path: []const u8 = "/startupz",
+    /// Groups that need a healthy server for readiness (empty = every group)
+    required_upstreams: []const []const u8 = &[_][]const u8{},
+};
+
+/// Who may scrape Prometheus metrics, and whether the main listener serves
+/// them too
+pub const MetricsConfig = struct {
+    /// Also serve metrics on the main listener at `path`, ahead of every vhost
+    main_listener: bool = false,
+    path: []const u8 = "/metrics",
+    /// Client addresses allowed to scrape, in CIDR notation (empty = any)
+    allow: []const []const u8 = &[_][]const u8{},
+    /// Bearer tokens for scrapers: the value itself, "env:NAME" or
+    /// "file:/path". With any set, the main listener requires one; the
+    /// admin listener accepts one in place of an admin token.
+    tokens: []const []const u8 = &[_][]const u8{},
+
+    pub const secret_fields = [_][]const u8{"tokens"};
+};
+
+/// Bytes in and out per vhost, route and optionally tenant, for billing and
+/// capping tenants by transfer volume
+pub const UsageConfig = struct {
+    enabled: bool = false,
+    /// Request header identifying the tenant, like "X-API-Key". Values are
+    /// recorded as the first 16 hex digits of their SHA-256, never as sent.
+    key_header: []const u8 = "",
+    /// Days of daily rollups kept for /admin/usage
+    retention_days: u32 = 31,
+    /// Most vhost, route and key combinations tracked at once; requests
+    /// beyond that are counted under the key "other"
+    max_series: usize = 10_000,
+};
+
+/// Requests answered with an error page while a maintenance window is open
+pub const ScheduledMaintenanceConfig = struct {
+    /// Host names affected, including wildcards (empty = every host)
+    hosts: []const []const u8 = &[_][]const u8{},
+    /// Path prefix affected
+    path: []const u8 = "/",
+    status: u16 = 503,
+    message: []const u8 = "Down for scheduled maintenance",
+};
+
+/// Overrides applied between `start` and `end`, in UTC. Times are "HH:MM"
+/// for a daily window (which may cross midnight) or "YYYY-MM-DDTHH:MM" for
+/// a single one.
+pub const ScheduledChangeConfig = struct {
+    /// Identifies the change in the admin API
+    name: []const u8,
+    start: []const u8,
+    end: []const u8,
+    maintenance: ?ScheduledMaintenanceConfig = null,
+    /// Multiplies the global, per-client and route rate limits, like 0.5
+    /// to halve them during backups
+    rate_limit_scale: f64 = 1,
+};
+
+/// Traffic history for the admin API, kept per second and downsampled to
+/// minute, five-minute and hour tiers
+pub const HistoryConfig = struct {
+    enabled: bool = false,
+    /// File the history is saved to and restored from at startup; empty
+    /// keeps it in memory only
+    path: []const u8 = "",
+    /// Seconds between saves; the history is also saved at shutdown
+    save_interval_s: u32 = 60,
+};
+
+/// State changes published on the event bus
+pub const EventKind = enum {
+    /// A reload signal (SIGHUP) was handled
+    config_reloaded,
+    /// An upstream server failed enough health checks to be taken out
+    upstream_down,
+    /// An unhealthy upstream server passed enough checks to return
+    upstream_up,
+    /// A client address was locked out of the admin API
+    ip_blocked,
+    /// A container route appeared through Docker discovery
+    route_added,
+    /// A container route disappeared
+    route_removed,
+    /// Rate-limit denials over the last minute passed `events.rate_limit_spike`
+    rate_limit_spike,
+};
+
+/// Body of a webhook POST
+pub const WebhookFormat = enum {
+    /// {"event", "subject", "message", "time_ms"}
+    json,
+    /// Slack incoming webhook: {"text"}
+    slack,
+    /// Discord webhook: {"content"}
+    discord,
+};
+
+pub const WebhookConfig = struct {
+    /// http:// or https:// endpoint; may be an "env:" or "file:" reference,
+    /// since chat webhook URLs carry their own credentials
+    url: []const u8,
+    format: WebhookFormat = .json,
+    /// Events sent; empty sends every event
+    events: []const EventKind = &.{},
+
+    pub const secret_fields = [_][]const u8{"url"};
+};
+
+/// Chat and HTTP webhooks for people, with repeats held back
+pub const NotificationsConfig = struct {
+    webhooks: []const WebhookConfig = &.{},
+    /// The same event about the same subject is sent at most once per
+    /// cooldown
+    cooldown_s: u32 = 300,
+};
+
+pub const EventSinkKind = enum {
+    /// One JSON line per event
+    log,
+    /// JSON POST per event
+    webhook,
+    /// PUB to a NATS server
+    nats,
+    /// Produce through a Kafka REST Proxy
+    kafka,
+};
+
+/// Where events are delivered for automation. Unlike notifications, every
+/// event is delivered.
+pub const EventSinkConfig = struct {
+    kind: EventSinkKind,
+    /// Events delivered; empty delivers every event
+    events: []const EventKind = &.{},
+    /// log: file appended to; empty writes to stderr
+    path: []const u8 = "",
+    /// webhook: endpoint POSTed to; kafka: REST Proxy base URL. May be an
+    /// "env:" or "file:" reference.
+    url: []const u8 = "",
+    /// nats: server host:port
+    address: []const u8 = "",
+    /// nats: subject prefix; the event name is appended
+    subject: []const u8 = "wraith.events",
+    /// nats: auth token, or an "env:" or "file:" reference
+    token: []const u8 = "",
+    /// kafka: topic produced to
+    topic: []const u8 = "wraith-events",
+
+    pub const secret_fields = [_][]const u8{ "url", "token" };
+};
+
+pub const EventsConfig = struct {
+    sinks: []const EventSinkConfig = &.{},
+    /// Rate-limit denials within a minute that count as a spike; 0 disables
+    rate_limit_spike: u64 = 0,
 };
 
 pub const Config = struct {
     server: ServerConfig,
-    tls: TlsConfig,
-    logging: LoggingConfig,
-    upstreams: []const UpstreamConfig,
-    routes: []const RouteConfig,
+    tls: TlsConfig = .{},
+    logging: LoggingConfig = .{},
+    upstreams: []const UpstreamConfig = &[_]UpstreamConfig{},
+    /// Routes for requests matching no vhost
+    routes: []const RouteConfig = &[_]RouteConfig{},
+    vhosts: []const VhostConfig = &[_]VhostConfig{},
+    default_vhost: DefaultVhostConfig = .{},
+    error_pages: []const ErrorPageConfig = &[_]ErrorPageConfig{},
+    dns: DnsConfig = .{},
+    docker: DockerConfig = .{},
+    stream: StreamConfig = .{},
+    admin: AdminConfig = .{},
+    rate_limit: RateLimitConfig = .{},
+    probes: ProbesConfig = .{},
+    egress_proxy: EgressProxyConfig = .{},
+    cluster: ClusterConfig = .{},
+    usage: UsageConfig = .{},
+    metrics: MetricsConfig = .{},
+    history: HistoryConfig = .{},
+    notifications: NotificationsConfig = .{},
+    events: EventsConfig = .{},
+    /// Maintenance windows and other timed overrides
+    schedule: []const ScheduledChangeConfig = &[_]ScheduledChangeConfig{},
 
+    /// Read, parse and validate a TOML configuration file. Everything the
+    /// config refers to is allocated with `allocator`, which should be an
+    /// arena that lives as long as the config.
     pub fn loadFromFile(allocator: std.mem.Allocator, path: []const u8) !Config {
-        _ = allocator;
-        _ = path;
-        // TODO: Implement flare TOML parsing
-        // For MVP, return default config
-        const listen_addrs = [_][]const u8{"0.0.0.0:9000"};
-        const listen_tls_addrs = [_][]const u8{};
-
-        const upstream_servers = [_]UpstreamServer{
-            UpstreamServer{
-                .host = "http://127.0.0.1:8080",
-                .weight = 1,
-            },
+        const text = std.fs.cwd().readFileAlloc(allocator, path, MAX_CONFIG_SIZE) catch |err| {
+            std.debug.print("✗ Cannot read {s}: {any}\n", .{ path, err });
+            return err;
         };
-
-        const upstreams = [_]UpstreamConfig{
-            UpstreamConfig{
-                .name = "localhost",
-                .servers = &upstream_servers,
-                .load_balancing = "round_robin",
-                .health_check_interval = 10,
-                .health_check_timeout = 5,
-                .health_check_path = "/",
-            },
+        var diag: toml.Diagnostics = .{};
+        const root = toml.parse(allocator, text, &diag) catch |err| {
+            std.debug.print("✗ {s}:{d}: {any}\n", .{ path, diag.line, err });
+            return err;
         };
-
-        const routes = [_]RouteConfig{
-            RouteConfig{
-                .host = "*",
-                .path = "/",
-                .upstream = "localhost",
-            },
+        const cfg = toml.decode(Config, allocator, root, &diag) catch |err| {
+            std.debug.print("✗ {s}: {s}: {any}\n", .{ path, diag.path.text(), err });
+            return err;
         };
-
-        return Config{
-            .server = ServerConfig{
-                .listen = &listen_addrs,
-                .listen_tls = &listen_tls_addrs,
-                .worker_threads = 0,
-            },
-            .tls = TlsConfig{
-                .cert_dir = "/etc/wraith/certs",
-                .acme_enabled = false,
-                .acme_email = null,
-            },
-            .logging = LoggingConfig{
-                .level = "info",
-                .format = "json",
-                .output = "stdout",
-            },
-            .upstreams = &upstreams,
-            .routes = &routes,
+        cfg.validate() catch |err| {
+            std.debug.print("✗ {s} is invalid: {any}\n", .{ path, err });
+            return err;
         };
+        // Named by field, never by value
+        var secret_field: []const u8 = "";
+        secret_mod.checkFields(cfg, &secret_field) catch |err| {
+            std.debug.print("✗ {s}: {s} does not resolve: {any}\n", .{ path, secret_field, err });
+            return err;
+        };
+        return cfg;
     }
 
     pub fn validate(self: *const Config) !void {
-        if (self.server.listen.len == 0 and self.server.listen_tls.len == 0) {
-            return error.NoListenAddresses;
+        // The TLS frontend can't complete a handshake yet, so a TLS listener
+        // would accept connections only to drop them
+        if (self.server.listen_tls.len > 0) return error.TlsListenersUnavailable;
+        if (self.server.listen.len == 0) return error.NoListenAddresses;
+        for (self.server.listen) |addr| {
+            _ = std.net.Address.parseIpAndPort(addr) catch return error.InvalidListenAddress;
+        }
+        acl.validateList(self.server.real_ip.trusted_proxies) catch return error.InvalidTrustedProxies;
+        try alt_svc.validate(&self.server.alt_svc);
+        try fingerprint.validate(&self.server.fingerprint);
+        // Fingerprints come from the TLS listeners refused above, so rules
+        // would never be checked
+        const fingerprint_cfg = &self.server.fingerprint;
+        if (fingerprint_cfg.allow.len > 0 or fingerprint_cfg.deny.len > 0) return error.FingerprintRulesUnavailable;
+        try memory.validate(&self.server.memory);
+        try acme.validate(&self.tls.acme);
+        try schedule_mod.validate(self.schedule);
+
+        if (self.dns.cache.min_ttl > self.dns.cache.max_ttl) {
+            return error.InvalidDnsCacheTtl;
+        }
+        if (self.dns.enabled) {
+            if (self.dns.udp_enabled) try validateDnsListeners(self.dns.listen_udp);
+            if (self.dns.tcp_enabled) try validateDnsListeners(self.dns.listen_tcp);
+            // DoT sessions run over the TLS frontend, which can't complete a
+            // handshake yet
+            if (self.dns.dot.enabled) return error.DnsOverTlsUnavailable;
+        }
+        if (self.dns.local.enabled) {
+            if (self.dns.local.addresses.len == 0) return error.MissingDnsLocalAddresses;
+            for (self.dns.local.addresses) |addr| {
+                _ = std.net.Address.parseIp(addr, 0) catch return error.InvalidDnsLocalAddress;
+            }
+        }
+
+        for (self.upstreams) |upstream| {
+            try validateHeaderLimits(&upstream.header_limits);
+            try validateRetry(&upstream.retry);
+            if (upstream.local_address.len > 0) {
+                _ = std.net.Address.parseIp(upstream.local_address, 0) catch return error.InvalidLocalAddress;
+            }
+            if (upstream.usesTls()) {
+                if (!upstream.tls.insecure_skip_verify and upstream.tlsServerName() == null) return error.MissingTlsServerName;
+                if (upstream.tls.handshake_timeout_ms == 0) return error.InvalidTlsHandshakeTimeout;
+            }
+            if (upstream.retry_budget.ratio < 0) return error.InvalidRetryBudget;
+            if (upstream.kubernetes.enabled and upstream.kubernetes.service.len == 0) {
+                return error.MissingKubernetesService;
+            }
+            if (upstream.dns.enabled) {
+                if (upstream.dns.name.len == 0) return error.MissingDnsDiscoveryName;
+                if (upstream.kubernetes.enabled) return error.ConflictingDiscovery;
+                for (upstream.dns.nameservers) |nameserver| {
+                    _ = std.net.Address.parseIpAndPort(nameserver) catch return error.InvalidDnsNameserver;
+                }
+            }
+            const concurrency = &upstream.adaptive_concurrency;
+            if (concurrency.enabled) {
+                if (concurrency.min_limit == 0 or concurrency.min_limit > concurrency.initial_limit or
+                    concurrency.initial_limit > concurrency.max_limit) return error.InvalidConcurrencyLimits;
+                if (!(concurrency.backoff_ratio > 0 and concurrency.backoff_ratio < 1) or
+                    !(concurrency.smoothing > 0 and concurrency.smoothing <= 1) or
+                    !(concurrency.low_priority_share > 0 and concurrency.low_priority_share <= 1)) return error.InvalidConcurrencyLimits;
+            }
+            switch (upstream.signing.kind) {
+                .none => {},
+                .hmac => if (upstream.signing.secret.len == 0) return error.MissingSigningCredentials,
+                .aws_sigv4 => if (upstream.signing.secret.len == 0 or upstream.signing.access_key_id.len == 0) {
+                    return error.MissingSigningCredentials;
+                },
+            }
+            if (upstream.health_check.rise == 0 or upstream.health_check.fall == 0) {
+                return error.InvalidHealthCheckThreshold;
+            }
+            if (upstream.servers.len > 0) {
+                for (upstream.servers) |server| {
+                    if (!server.backup) break;
+                } else return error.NoPrimaryServers;
+            }
+        }
+
+        if (self.egress_proxy.enabled) {
+            _ = std.net.Address.parseIpAndPort(self.egress_proxy.address) catch return error.InvalidEgressProxy;
+            try acl.validateList(self.egress_proxy.no_proxy);
+        }
+
+        if (self.cluster.enabled) {
+            _ = std.net.Address.parseIpAndPort(self.cluster.listen) catch return error.InvalidClusterAddress;
+            for (self.cluster.peers) |peer| {
+                _ = std.net.Address.parseIpAndPort(peer) catch return error.InvalidClusterAddress;
+            }
+            if (self.cluster.secret.len == 0) return error.MissingClusterSecret;
+            if (self.cluster.gossip_interval_ms == 0 or self.cluster.peer_timeout_ms <= self.cluster.gossip_interval_ms) {
+                return error.InvalidClusterTimers;
+            }
+        }
+
+        if (self.admin.enabled) {
+            for (self.admin.tokens, 0..) |token, i| {
+                if (token.name.len == 0 or token.token.len == 0) return error.InvalidAdminToken;
+                for (self.admin.tokens[0..i]) |other| {
+                    if (std.mem.eql(u8, token.name, other.name)) return error.DuplicateAdminToken;
+                }
+            }
+            const protection = &self.admin.protection;
+            if (protection.max_auth_failures > 0 and (protection.failure_window_s == 0 or protection.lockout_s == 0)) {
+                return error.InvalidAdminLockout;
+            }
+            const faults = &self.admin.faults;
+            if (faults.enabled and (faults.default_duration_s == 0 or faults.default_duration_s > faults.max_duration_s)) {
+                return error.InvalidFaultDuration;
+            }
+        }
+
+        if (self.rate_limit.enabled) {
+            if (self.rate_limit.requests_per_second < 0) return error.InvalidRateLimit;
+            if (self.rate_limit.per_ip.enabled and self.rate_limit.per_ip.requests_per_second <= 0) return error.InvalidRateLimit;
+        }
+
+        acl.validateList(self.metrics.allow) catch return error.InvalidMetricsAllow;
+        if (self.metrics.main_listener and (self.metrics.path.len == 0 or self.metrics.path[0] != '/')) {
+            return error.InvalidMetricsPath;
+        }
+
+        if (self.usage.enabled) {
+            if (self.usage.retention_days == 0 or self.usage.max_series == 0) return error.InvalidUsageLimits;
+        }
+
+        if (self.history.enabled and self.history.path.len > 0 and self.history.save_interval_s == 0) {
+            return error.InvalidHistoryInterval;
+        }
+
+        for (self.notifications.webhooks) |webhook| try validateWebhookUrl(webhook.url);
+        for (self.events.sinks) |sink| {
+            switch (sink.kind) {
+                .log => {},
+                .webhook => try validateWebhookUrl(sink.url),
+                .kafka => {
+                    try validateWebhookUrl(sink.url);
+                    if (sink.topic.len == 0) return error.InvalidEventSink;
+                },
+                .nats => {
+                    if (sink.subject.len == 0 or std.mem.indexOfAny(u8, sink.subject, " \t\r\n") != null) return error.InvalidEventSink;
+                    _ = std.net.Address.parseIpAndPort(sink.address) catch return error.InvalidEventSink;
+                },
+            }
+        }
+
+        for (self.probes.required_upstreams) |name| {
+            if (!self.hasUpstream(name)) return error.UnknownRequiredUpstream;
+        }
+
+        for (self.stream.servers) |server| {
+            if (!self.hasUpstream(server.upstream)) return error.UnknownStreamUpstream;
+        }
+
+        for (self.routes) |*route| try self.validateRoute(route);
+
+        for (self.vhosts) |vhost| {
+            if (vhost.hostnames.len == 0) return error.MissingVhostHostnames;
+            if (vhost.alt_svc) |*vhost_alt_svc| try alt_svc.validate(vhost_alt_svc);
+            switch (vhost.canonical.status) {
+                301, 302, 307, 308 => {},
+                else => return error.InvalidCanonicalStatus,
+            }
+            if (vhost.canonical.https_port == 0) return error.InvalidCanonicalPort;
+            try acl.validateList(vhost.acl.allow);
+            try acl.validateList(vhost.acl.deny);
+            for (vhost.static) |mount| {
+                if (mount.s3.endpoint.len > 0) {
+                    if (mount.s3.bucket.len == 0) return error.MissingS3Bucket;
+                    if (mount.s3.access_key_id.len == 0 or mount.s3.secret.len == 0) return error.MissingSigningCredentials;
+                } else if (mount.root.len == 0) return error.MissingStaticRoot;
+            }
+            for (vhost.routes) |*route| try self.validateRoute(route);
+        }
+
+        switch (self.default_vhost.action) {
+            .vhost => if (!self.hasVhost(self.default_vhost.vhost)) return error.UnknownDefaultVhost,
+            .page => if (self.default_vhost.page.len == 0) return error.MissingDefaultPage,
+            else => {},
         }
     }
+
+    fn validateRoute(self: *const Config, route: *const RouteConfig) !void {
+        try acl.validateList(route.acl.allow);
+        try acl.validateList(route.acl.deny);
+        if (route.rate_limit.requests_per_second < 0) return error.InvalidRateLimit;
+        try validateHeaderLimits(&route.header_limits);
+        for (route.cache_headers) |*rule| try cache_headers.validate(rule);
+        if (route.when) |*when_cfg| try when.validate(when_cfg);
+        try path_rewrite.validate(route);
+        try header_rules.validate(route);
+
+        if (route.respond.status != 0) {
+            if (route.respond.status < 100 or route.respond.status > 599) return error.InvalidRespondStatus;
+            if (route.respond.body.len > 0 and route.respond.file.len > 0) return error.ConflictingRespondBody;
+        }
+
+        if (route.error_intercept.fallback.len > 0 and !self.hasUpstream(route.error_intercept.fallback)) {
+            return error.UnknownFallbackUpstream;
+        }
+
+        try validateRetry(&route.retry);
+
+        if (route.hedge.enabled and !(route.hedge.percentile > 0 and route.hedge.percentile < 100)) {
+            return error.InvalidHedgePercentile;
+        }
+
+        if (route.ab_test.variants.len > 0) {
+            var total: u32 = 0;
+            for (route.ab_test.variants) |variant| {
+                if (variant.name.len == 0 or std.mem.indexOfAny(u8, variant.name, " ;,=\"") != null) {
+                    return error.InvalidVariantName;
+                }
+                if (!self.hasUpstream(variant.upstream)) return error.UnknownVariantUpstream;
+                total += variant.percent;
+            }
+            if (total != 100) return error.InvalidVariantPercentages;
+        }
+    }
+
+    fn validateRetry(retry: *const RetryConfig) !void {
+        for (retry.statuses) |status| {
+            if (status < 100 or status > 599) return error.InvalidRetryStatus;
+        }
+        if (retry.max_backoff_ms < retry.backoff_ms) return error.InvalidRetryBackoff;
+    }
+
+    /// Limits can't exceed the buffers heads are read into
+    fn validateHeaderLimits(limits: *const HeaderLimitsConfig) !void {
+        if (limits.max_head_size > request.MAX_HEAD_SIZE or
+            limits.max_response_head_size > request.MAX_HEAD_SIZE or
+            limits.max_header_count > request.MAX_HEADERS or
+            limits.max_response_header_count > request.MAX_HEADERS) return error.InvalidHeaderLimits;
+    }
+
+    fn validateDnsListeners(addresses: []const []const u8) !void {
+        if (addresses.len == 0) return error.NoDnsListenAddresses;
+        for (addresses) |addr| {
+            _ = std.net.Address.parseIpAndPort(addr) catch return error.InvalidDnsListenAddress;
+        }
+    }
+
+    pub fn hasVhost(self: *const Config, hostname: []const u8) bool {
+        for (self.vhosts) |vhost| {
+            for (vhost.hostnames) |name| {
+                if (std.mem.eql(u8, name, hostname)) return true;
+            }
+        }
+        return false;
+    }
+
+    pub fn hasUpstream(self: *const Config, name: []const u8) bool {
+        for (self.upstreams) |upstream| {
+            if (std.mem.eql(u8, upstream.name, name)) return true;
+        }
+        return false;
+    }
+
+    /// Header limits of a route's upstream group, overridden by the route's own
+    pub fn headerLimits(self: *const Config, route: *const RouteConfig) HeaderLimitsConfig {
+        var limits = HeaderLimitsConfig{};
+        for (self.upstreams) |*upstream| {
+            if (std.mem.eql(u8, upstream.name, route.upstream)) limits.merge(&upstream.header_limits);
+        }
+        limits.merge(&route.header_limits);
+        return limits;
+    }
+
+    /// server.limits with a route's header limit overrides applied
+    pub fn requestLimits(self: *const Config, route: *const RouteConfig) RequestLimitsConfig {
+        const overrides = self.headerLimits(route);
+        var limits = self.server.limits;
+        if (overrides.max_header_count != 0) limits.max_header_count = overrides.max_header_count;
+        if (overrides.max_header_size != 0) limits.max_header_size = overrides.max_header_size;
+        if (overrides.max_head_size != 0) limits.max_head_size = overrides.max_head_size;
+        return limits;
+    }
+
+    /// Whether any upstream group or route overrides the header limits
+    pub fn hasHeaderLimits(self: *const Config) bool {
+        const none = HeaderLimitsConfig{};
+        for (self.upstreams) |upstream| {
+            if (!std.meta.eql(upstream.header_limits, none)) return true;
+        }
+        for (self.routes) |route| {
+            if (!std.meta.eql(route.header_limits, none)) return true;
+        }
+        for (self.vhosts) |vhost| {
+            for (vhost.routes) |route| {
+                if (!std.meta.eql(route.header_limits, none)) return true;
+            }
+        }
+        return false;
+    }
+
+    /// Largest request head any route accepts, which is how much is read
+    /// before the route is known
+    pub fn maxHeadSize(self: *const Config) usize {
+        var max = self.server.limits.max_head_size;
+        for (self.upstreams) |upstream| max = @max(max, upstream.header_limits.max_head_size);
+        for (self.routes) |route| max = @max(max, route.header_limits.max_head_size);
+        for (self.vhosts) |vhost| {
+            for (vhost.routes) |route| max = @max(max, route.header_limits.max_head_size);
+        }
+        return @min(max, request.MAX_HEAD_SIZE);
+    }
 };
+
+/// An http:// or https:// URL, or a reference resolved at send time
+fn validateWebhookUrl(url: []const u8) !void {
+    // References are resolved, and checked, by preflight
+    if (secret_mod.isReference(url)) return;
+    if (!std.mem.startsWith(u8, url, "http://") and !std.mem.startsWith(u8, url, "https://")) {
+        return error.InvalidWebhookUrl;
+    }
+    _ = std.Uri.parse(url) catch return error.InvalidWebhookUrl;
+}
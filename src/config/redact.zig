@@ -0,0 +1,73 @@
+const std = @import("std");
+const config = @import("config.zig");
+const secret = @import("secret.zig");
+
+pub const REDACTED = "[redacted]";
+
+/// The config as JSON. Unless `include_secrets` is set, the fields each
+/// config struct lists in `secret_fields` are masked; "env:" and "file:"
+/// references are shown, since they only say where a secret lives.
+pub fn json(allocator: std.mem.Allocator, cfg: *const config.Config, include_secrets: bool) ![]u8 {
+    var out: std.Io.Writer.Allocating = .init(allocator);
+    errdefer out.deinit();
+    var jws: std.json.Stringify = .{ .writer = &out.writer, .options = .{} };
+    try writeValue(&jws, cfg.*, include_secrets);
+    return out.toOwnedSlice();
+}
+
+fn writeValue(jws: *std.json.Stringify, value: anytype, include_secrets: bool) !void {
+    switch (@typeInfo(@TypeOf(value))) {
+        .@"struct" => |info| {
+            const T = @TypeOf(value);
+            try jws.beginObject();
+            inline for (info.fields) |field| {
+                try jws.objectField(field.name);
+                if (!include_secrets and comptime secret.isSecretField(T, field.name)) {
+                    try writeSecret(jws, @field(value, field.name));
+                } else {
+                    try writeValue(jws, @field(value, field.name), include_secrets);
+                }
+            }
+            try jws.endObject();
+        },
+        .optional => if (value) |inner| try writeValue(jws, inner, include_secrets) else try jws.write(null),
+        .pointer => |ptr| if (ptr.size == .slice and ptr.child != u8) {
+            try jws.beginArray();
+            for (value) |item| try writeValue(jws, item, include_secrets);
+            try jws.endArray();
+        } else try jws.write(value),
+        else => try jws.write(value),
+    }
+}
+
+fn writeSecret(jws: *std.json.Stringify, value: anytype) !void {
+    switch (@typeInfo(@TypeOf(value))) {
+        .optional => if (value) |inner| try writeSecret(jws, inner) else try jws.write(null),
+        .pointer => |ptr| if (ptr.child == u8) {
+            try jws.write(if (value.len == 0 or secret.isReference(value)) value else REDACTED);
+        } else {
+            try jws.beginArray();
+            for (value) |item| try writeSecret(jws, item);
+            try jws.endArray();
+        },
+        else => try jws.write(REDACTED),
+    }
+}
+
+test "only declared secret fields are masked" {
+    const cfg = config.Config{
+        .server = .{ .listen = &.{"127.0.0.1:8080"} },
+        .notifications = .{ .webhooks = &.{.{ .url = "https://hooks.example.com/T0/B0/abc" }} },
+        .events = .{ .sinks = &.{.{ .kind = .kafka, .url = "http://kafka-rest:8082", .token = "env:NATS_TOKEN" }} },
+        .admin = .{ .tokens = &.{.{ .name = "ops", .token = "hunter2" }} },
+    };
+    const text = try json(std.testing.allocator, &cfg, false);
+    defer std.testing.allocator.free(text);
+
+    try std.testing.expect(std.mem.indexOf(u8, text, "hooks.example.com") == null);
+    try std.testing.expect(std.mem.indexOf(u8, text, "hunter2") == null);
+    // Names and references stay readable
+    try std.testing.expect(std.mem.indexOf(u8, text, "\"ops\"") != null);
+    try std.testing.expect(std.mem.indexOf(u8, text, "env:NATS_TOKEN") != null);
+    try std.testing.expect(std.mem.indexOf(u8, text, cfg.tls.acme.directory_url) != null);
+}
@@ -0,0 +1,160 @@
+const std = @import("std");
+
+/// How often file secrets are checked for changes
+const REFRESH_MS = 10_000;
+
+/// Largest secret file read
+const MAX_FILE_SIZE = 64 * 1024;
+
+/// Whether a secret-bearing field names where the value lives rather than
+/// holding it
+pub fn isReference(source: []const u8) bool {
+    return std.mem.startsWith(u8, source, "env:") or std.mem.startsWith(u8, source, "file:");
+}
+
+/// A secret given as "env:NAME", "file:/path" or the value itself. Files
+/// are re-read when they change, so keys rotate without a restart. Not
+/// thread-safe; holders lock around `get` and their use of the value.
+pub const Secret = struct {
+    source: []const u8,
+    value: []u8 = &.{},
+    mtime: i128 = 0,
+    checked_ms: i64 = 0,
+
+    pub fn get(self: *Secret, allocator: std.mem.Allocator, now_ms: i64) ![]const u8 {
+        if (std.mem.startsWith(u8, self.source, "env:")) {
+            return std.posix.getenv(self.source["env:".len..]) orelse error.MissingCredential;
+        }
+        if (!std.mem.startsWith(u8, self.source, "file:")) return self.source;
+
+        if (self.value.len > 0 and now_ms - self.checked_ms < REFRESH_MS) return self.value;
+        self.checked_ms = now_ms;
+
+        const path = self.source["file:".len..];
+        const file = try std.fs.cwd().openFile(path, .{});
+        defer file.close();
+        const stat = try file.stat();
+        if (self.value.len > 0 and stat.mtime == self.mtime) return self.value;
+
+        const contents = try file.readToEndAlloc(allocator, MAX_FILE_SIZE);
+        defer allocator.free(contents);
+        const value = try allocator.dupe(u8, std.mem.trim(u8, contents, " \t\r\n"));
+        allocator.free(self.value);
+        self.value = value;
+        self.mtime = stat.mtime;
+        return self.value;
+    }
+
+    pub fn deinit(self: *Secret, allocator: std.mem.Allocator) void {
+        allocator.free(self.value);
+    }
+};
+
+/// Resolve a secret into `buf`, for short secrets read where no state is
+/// kept. Files are read on every call.
+pub fn resolveInto(source: []const u8, buf: []u8) ![]const u8 {
+    const value = if (std.mem.startsWith(u8, source, "env:"))
+        std.posix.getenv(source["env:".len..]) orelse return error.MissingCredential
+    else if (std.mem.startsWith(u8, source, "file:")) blk: {
+        const contents = try std.fs.cwd().readFile(source["file:".len..], buf);
+        if (contents.len == buf.len) return error.SecretTooLong;
+        break :blk std.mem.trim(u8, contents, " \t\r\n");
+    } else source;
+
+    if (value.len > buf.len) return error.SecretTooLong;
+    // Overlapping copies from file contents move the value to the front
+    std.mem.copyForwards(u8, buf[0..value.len], value);
+    return buf[0..value.len];
+}
+
+/// Whether a secret can be resolved now: the variable is set or the file
+/// is readable
+pub fn check(source: []const u8) !void {
+    if (std.mem.startsWith(u8, source, "env:")) {
+        if (std.posix.getenv(source["env:".len..]) == null) return error.MissingCredential;
+    } else if (std.mem.startsWith(u8, source, "file:")) {
+        const file = try std.fs.cwd().openFile(source["file:".len..], .{});
+        defer file.close();
+        const stat = try file.stat();
+        if (stat.size > MAX_FILE_SIZE) return error.SecretTooLong;
+    }
+}
+
+/// Whether `T.name` holds a secret. Config structs list their secret
+/// fields in a `secret_fields` declaration; nothing is a secret by name.
+pub fn isSecretField(comptime T: type, comptime name: []const u8) bool {
+    if (!@hasDecl(T, "secret_fields")) return false;
+    inline for (T.secret_fields) |field| {
+        if (!@hasField(T, field)) @compileError(@typeName(T) ++ " has no secret field " ++ field);
+    }
+    for (T.secret_fields) |field| {
+        if (std.mem.eql(u8, name, field)) return true;
+    }
+    return false;
+}
+
+/// Check every "env:" and "file:" reference in the secret fields of
+/// `value`, skipping sections that are disabled. `field` is set to the
+/// field of the first reference that doesn't resolve.
+pub fn checkFields(value: anytype, field: *[]const u8) anyerror!void {
+    const T = @TypeOf(value);
+    switch (@typeInfo(T)) {
+        .@"struct" => |info| {
+            if (@hasField(T, "enabled")) {
+                if (!value.enabled) return;
+            }
+            inline for (info.fields) |f| {
+                if (comptime isSecretField(T, f.name)) {
+                    field.* = comptime shortName(T) ++ "." ++ f.name;
+                    try checkSources(@field(value, f.name));
+                } else {
+                    try checkFields(@field(value, f.name), field);
+                }
+            }
+        },
+        .optional => if (value) |inner| try checkFields(inner, field),
+        .pointer => |ptr| if (ptr.size == .slice and ptr.child != u8) {
+            for (value) |item| try checkFields(item, field);
+        },
+        else => {},
+    }
+}
+
+fn checkSources(value: anytype) !void {
+    switch (@typeInfo(@TypeOf(value))) {
+        .optional => if (value) |inner| try checkSources(inner),
+        .pointer => |ptr| if (ptr.child == u8) {
+            try check(value);
+        } else {
+            for (value) |item| try checkSources(item);
+        },
+        else => {},
+    }
+}
+
+/// "ClusterConfig" for "config.config.ClusterConfig"
+fn shortName(comptime T: type) []const u8 {
+    const name = @typeName(T);
+    const dot = comptime std.mem.lastIndexOfScalar(u8, name, '.');
+    return if (dot) |i| name[i + 1 ..] else name;
+}
+
+test "secret fields are checked where they are declared" {
+    const Sink = struct {
+        url: []const u8 = "",
+        token: []const u8 = "",
+        pub const secret_fields = [_][]const u8{"token"};
+    };
+    const Section = struct {
+        enabled: bool = true,
+        sinks: []const Sink = &.{},
+    };
+
+    var field: []const u8 = "";
+    // A reference in a field that isn't a secret is just a value
+    try checkFields(Section{ .sinks = &.{.{ .url = "env:WRAITH_TEST_UNSET" }} }, &field);
+    try std.testing.expectError(error.MissingCredential, checkFields(Section{ .sinks = &.{.{ .token = "env:WRAITH_TEST_UNSET" }} }, &field));
+    try std.testing.expect(std.mem.endsWith(u8, field, ".token"));
+    try checkFields(Section{ .enabled = false, .sinks = &.{.{ .token = "env:WRAITH_TEST_UNSET" }} }, &field);
+    try std.testing.expectError(error.FileNotFound, checkFields(Section{ .sinks = &.{.{ .token = "file:/nonexistent/wraith-test" }} }, &field));
+}
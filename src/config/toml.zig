@@ -0,0 +1,658 @@
+const std = @import("std");
+
+/// A parsed TOML value. Dates and times aren't supported, since nothing
+/// in the configuration uses them.
+pub const Value = union(enum) {
+    string: []const u8,
+    integer: i64,
+    float: f64,
+    boolean: bool,
+    array: *Array,
+    table: *Table,
+};
+
+pub const Array = struct {
+    items: std.ArrayList(Value) = .empty,
+    /// Built from [[header]] sections, which append to it; inline arrays
+    /// can't be extended
+    of_tables: bool = false,
+};
+
+pub const Table = struct {
+    entries: std.StringArrayHashMapUnmanaged(Value) = .empty,
+    /// Given by a [header] or dotted keys, so another header for it is a
+    /// duplicate
+    defined: bool = false,
+    /// Inline tables are complete as written
+    closed: bool = false,
+};
+
+pub const ParseError = error{
+    UnexpectedEnd,
+    UnexpectedCharacter,
+    ExpectedNewline,
+    InvalidKey,
+    DuplicateKey,
+    DuplicateTable,
+    UnterminatedString,
+    InvalidEscape,
+    InvalidNumber,
+    UnsupportedDateTime,
+} || std.mem.Allocator.Error;
+
+pub const DecodeError = error{
+    InvalidConfigType,
+    InvalidConfigValue,
+    ConfigValueOutOfRange,
+    MissingConfigKey,
+} || std.mem.Allocator.Error;
+
+/// Where parsing or decoding failed
+pub const Diagnostics = struct {
+    /// Line of a syntax error
+    line: usize = 0,
+    /// Key being decoded, like "routes[2].retry.attempts"
+    path: Path = .{},
+};
+
+pub const Path = struct {
+    buf: [256]u8 = undefined,
+    len: usize = 0,
+
+    pub fn text(self: *const Path) []const u8 {
+        return self.buf[0..self.len];
+    }
+
+    /// Descend into `name`; returns what `restore` takes to come back up
+    fn field(self: *Path, name: []const u8) usize {
+        const saved = self.len;
+        const separator: []const u8 = if (self.len == 0) "" else ".";
+        self.len += (std.fmt.bufPrint(self.buf[self.len..], "{s}{s}", .{ separator, name }) catch "").len;
+        return saved;
+    }
+
+    fn index(self: *Path, i: usize) usize {
+        const saved = self.len;
+        self.len += (std.fmt.bufPrint(self.buf[self.len..], "[{d}]", .{i}) catch "").len;
+        return saved;
+    }
+
+    fn restore(self: *Path, saved: usize) void {
+        self.len = saved;
+    }
+};
+
+/// Parse a TOML document. Everything is allocated with `allocator` and
+/// may refer to `text`; an arena suits, since nothing is freed on its own.
+pub fn parse(allocator: std.mem.Allocator, text: []const u8, diag: *Diagnostics) ParseError!*Table {
+    var p = Parser{ .allocator = allocator, .src = text };
+    return p.document() catch |err| {
+        diag.line = std.mem.count(u8, text[0..@min(p.pos, text.len)], "\n") + 1;
+        return err;
+    };
+}
+
+/// Fill a `T` from `table`. Keys map to fields of the same name, and
+/// fields without a key keep their defaults. Unknown keys are reported
+/// and skipped, so a misspelt key doesn't pass unnoticed. Enums are
+/// written as their tag names.
+pub fn decode(comptime T: type, allocator: std.mem.Allocator, table: *const Table, diag: *Diagnostics) DecodeError!T {
+    return decodeStruct(T, allocator, table, diag);
+}
+
+fn decodeStruct(comptime T: type, allocator: std.mem.Allocator, table: *const Table, diag: *Diagnostics) DecodeError!T {
+    var result: T = undefined;
+    inline for (std.meta.fields(T)) |field| {
+        const saved = diag.path.field(field.name);
+        if (table.entries.get(field.name)) |value| {
+            @field(result, field.name) = try decodeValue(field.type, allocator, value, diag);
+        } else if (field.defaultValue()) |default| {
+            @field(result, field.name) = default;
+        } else {
+            return error.MissingConfigKey;
+        }
+        diag.path.restore(saved);
+    }
+
+    for (table.entries.keys()) |name| {
+        if (hasField(T, name)) continue;
+        const separator: []const u8 = if (diag.path.len == 0) "" else ".";
+        std.debug.print("✗ Unknown config key {s}{s}{s} ignored\n", .{ diag.path.text(), separator, name });
+    }
+    return result;
+}
+
+fn decodeValue(comptime T: type, allocator: std.mem.Allocator, value: Value, diag: *Diagnostics) DecodeError!T {
+    switch (@typeInfo(T)) {
+        .bool => return switch (value) {
+            .boolean => |b| b,
+            else => error.InvalidConfigType,
+        },
+        .int => return switch (value) {
+            .integer => |i| std.math.cast(T, i) orelse error.ConfigValueOutOfRange,
+            else => error.InvalidConfigType,
+        },
+        .float => return switch (value) {
+            .float => |f| @floatCast(f),
+            .integer => |i| @floatFromInt(i),
+            else => error.InvalidConfigType,
+        },
+        .@"enum" => return switch (value) {
+            .string => |s| std.meta.stringToEnum(T, s) orelse error.InvalidConfigValue,
+            else => error.InvalidConfigType,
+        },
+        .optional => |info| return try decodeValue(info.child, allocator, value, diag),
+        .pointer => |info| {
+            if (info.size != .slice) @compileError("unsupported config field type " ++ @typeName(T));
+            if (info.child == u8) return switch (value) {
+                .string => |s| s,
+                else => error.InvalidConfigType,
+            };
+            const array = switch (value) {
+                .array => |a| a,
+                else => return error.InvalidConfigType,
+            };
+            const items = try allocator.alloc(info.child, array.items.items.len);
+            for (array.items.items, items, 0..) |item, *out, i| {
+                const saved = diag.path.index(i);
+                out.* = try decodeValue(info.child, allocator, item, diag);
+                diag.path.restore(saved);
+            }
+            return items;
+        },
+        .@"struct" => return switch (value) {
+            .table => |t| try decodeStruct(T, allocator, t, diag),
+            // Structs with a `fromString` may be written as a string, like
+            // an upstream server given by its address alone
+            .string => |text| if (@hasDecl(T, "fromString")) T.fromString(text) else error.InvalidConfigType,
+            else => error.InvalidConfigType,
+        },
+        else => @compileError("unsupported config field type " ++ @typeName(T)),
+    }
+}
+
+fn hasField(comptime T: type, name: []const u8) bool {
+    inline for (std.meta.fields(T)) |field| {
+        if (std.mem.eql(u8, field.name, name)) return true;
+    }
+    return false;
+}
+
+const Parser = struct {
+    allocator: std.mem.Allocator,
+    src: []const u8,
+    pos: usize = 0,
+
+    fn document(p: *Parser) ParseError!*Table {
+        const root = try p.newTable();
+        var current = root;
+        while (true) {
+            p.skipBlank();
+            if (p.pos >= p.src.len) break;
+            if (p.startsWith("[[")) {
+                current = try p.arrayTableHeader(root);
+            } else if (p.src[p.pos] == '[') {
+                current = try p.tableHeader(root);
+            } else {
+                try p.keyValue(current);
+            }
+            try p.endOfLine();
+        }
+        return root;
+    }
+
+    fn newTable(p: *Parser) ParseError!*Table {
+        const table = try p.allocator.create(Table);
+        table.* = .{};
+        return table;
+    }
+
+    fn tableHeader(p: *Parser, root: *Table) ParseError!*Table {
+        p.pos += 1;
+        const keys = try p.key();
+        p.skipSpace();
+        try p.expect(']');
+
+        var table = root;
+        for (keys[0 .. keys.len - 1]) |name| table = try p.headerTable(table, name);
+        const entry = try table.entries.getOrPut(p.allocator, keys[keys.len - 1]);
+        if (entry.found_existing) {
+            switch (entry.value_ptr.*) {
+                .table => |existing| {
+                    if (existing.defined or existing.closed) return error.DuplicateTable;
+                    existing.defined = true;
+                    return existing;
+                },
+                else => return error.DuplicateTable,
+            }
+        }
+        const created = try p.newTable();
+        created.defined = true;
+        entry.value_ptr.* = .{ .table = created };
+        return created;
+    }
+
+    fn arrayTableHeader(p: *Parser, root: *Table) ParseError!*Table {
+        p.pos += 2;
+        const keys = try p.key();
+        p.skipSpace();
+        if (!p.startsWith("]]")) return error.UnexpectedCharacter;
+        p.pos += 2;
+
+        var table = root;
+        for (keys[0 .. keys.len - 1]) |name| table = try p.headerTable(table, name);
+        const entry = try table.entries.getOrPut(p.allocator, keys[keys.len - 1]);
+        if (!entry.found_existing) {
+            const array = try p.allocator.create(Array);
+            array.* = .{ .of_tables = true };
+            entry.value_ptr.* = .{ .array = array };
+        }
+        const array = switch (entry.value_ptr.*) {
+            .array => |a| if (a.of_tables) a else return error.DuplicateKey,
+            else => return error.DuplicateKey,
+        };
+        const created = try p.newTable();
+        created.defined = true;
+        try array.items.append(p.allocator, .{ .table = created });
+        return created;
+    }
+
+    /// The table `name` names within `table` on the way to a header's
+    /// own, created when missing; arrays of tables lead to their last
+    fn headerTable(p: *Parser, table: *Table, name: []const u8) ParseError!*Table {
+        const entry = try table.entries.getOrPut(p.allocator, name);
+        if (!entry.found_existing) {
+            const created = try p.newTable();
+            entry.value_ptr.* = .{ .table = created };
+            return created;
+        }
+        switch (entry.value_ptr.*) {
+            .table => |existing| return if (existing.closed) error.DuplicateKey else existing,
+            .array => |array| {
+                if (!array.of_tables or array.items.items.len == 0) return error.DuplicateKey;
+                return array.items.items[array.items.items.len - 1].table;
+            },
+            else => return error.DuplicateKey,
+        }
+    }
+
+    fn keyValue(p: *Parser, table: *Table) ParseError!void {
+        const keys = try p.key();
+        p.skipSpace();
+        try p.expect('=');
+        p.skipSpace();
+        const value = try p.parseValue();
+
+        var target = table;
+        for (keys[0 .. keys.len - 1]) |name| {
+            const entry = try target.entries.getOrPut(p.allocator, name);
+            if (entry.found_existing) {
+                target = switch (entry.value_ptr.*) {
+                    .table => |existing| if (existing.closed) return error.DuplicateKey else existing,
+                    else => return error.DuplicateKey,
+                };
+            } else {
+                const created = try p.newTable();
+                created.defined = true;
+                entry.value_ptr.* = .{ .table = created };
+                target = created;
+            }
+        }
+        const entry = try target.entries.getOrPut(p.allocator, keys[keys.len - 1]);
+        if (entry.found_existing) return error.DuplicateKey;
+        entry.value_ptr.* = value;
+    }
+
+    /// A dotted key, one element per part
+    fn key(p: *Parser) ParseError![]const []const u8 {
+        var parts: std.ArrayList([]const u8) = .empty;
+        while (true) {
+            p.skipSpace();
+            try parts.append(p.allocator, try p.simpleKey());
+            p.skipSpace();
+            if (p.peek() != '.') break;
+            p.pos += 1;
+        }
+        return parts.items;
+    }
+
+    fn simpleKey(p: *Parser) ParseError![]const u8 {
+        switch (p.peek() orelse return error.UnexpectedEnd) {
+            '"' => return p.basicString(),
+            '\'' => return p.literalString(),
+            else => {},
+        }
+        const start = p.pos;
+        while (p.peek()) |c| switch (c) {
+            'A'...'Z', 'a'...'z', '0'...'9', '_', '-' => p.pos += 1,
+            else => break,
+        };
+        if (p.pos == start) return error.InvalidKey;
+        return p.src[start..p.pos];
+    }
+
+    fn parseValue(p: *Parser) ParseError!Value {
+        switch (p.peek() orelse return error.UnexpectedEnd) {
+            '"' => return .{ .string = if (p.startsWith("\"\"\"")) try p.multilineString('"') else try p.basicString() },
+            '\'' => return .{ .string = if (p.startsWith("'''")) try p.multilineString('\'') else try p.literalString() },
+            '[' => return p.parseArray(),
+            '{' => return p.inlineTable(),
+            else => return p.scalar(),
+        }
+    }
+
+    fn parseArray(p: *Parser) ParseError!Value {
+        p.pos += 1;
+        const result = try p.allocator.create(Array);
+        result.* = .{};
+        while (true) {
+            p.skipBlank();
+            if (p.peek() == ']') break;
+            try result.items.append(p.allocator, try p.parseValue());
+            p.skipBlank();
+            if (p.peek() != ',') break;
+            p.pos += 1;
+        }
+        p.skipBlank();
+        try p.expect(']');
+        return .{ .array = result };
+    }
+
+    fn inlineTable(p: *Parser) ParseError!Value {
+        p.pos += 1;
+        const table = try p.newTable();
+        p.skipSpace();
+        if (p.peek() == '}') {
+            p.pos += 1;
+        } else {
+            while (true) {
+                try p.keyValue(table);
+                p.skipSpace();
+                if (p.peek() != ',') break;
+                p.pos += 1;
+            }
+            try p.expect('}');
+        }
+        table.defined = true;
+        table.closed = true;
+        return .{ .table = table };
+    }
+
+    /// Booleans and numbers, which run up to the next delimiter
+    fn scalar(p: *Parser) ParseError!Value {
+        const start = p.pos;
+        while (p.peek()) |c| switch (c) {
+            ' ', '\t', '\r', '\n', ',', ']', '}', '#' => break,
+            else => p.pos += 1,
+        };
+        const token = p.src[start..p.pos];
+        if (token.len == 0) return error.UnexpectedCharacter;
+        if (std.mem.eql(u8, token, "true")) return .{ .boolean = true };
+        if (std.mem.eql(u8, token, "false")) return .{ .boolean = false };
+        return parseNumber(token);
+    }
+
+    fn basicString(p: *Parser) ParseError![]const u8 {
+        p.pos += 1;
+        var out: std.ArrayList(u8) = .empty;
+        while (true) {
+            const c = p.peek() orelse return error.UnterminatedString;
+            p.pos += 1;
+            switch (c) {
+                '"' => return out.items,
+                '\\' => try p.escape(&out),
+                '\n' => return error.UnterminatedString,
+                else => try out.append(p.allocator, c),
+            }
+        }
+    }
+
+    fn literalString(p: *Parser) ParseError![]const u8 {
+        p.pos += 1;
+        const start = p.pos;
+        while (true) {
+            const c = p.peek() orelse return error.UnterminatedString;
+            if (c == '\n') return error.UnterminatedString;
+            p.pos += 1;
+            if (c == '\'') return p.src[start .. p.pos - 1];
+        }
+    }
+
+    /// """ or ''' strings; `quote` says which, and only """ ones have
+    /// escapes
+    fn multilineString(p: *Parser, quote: u8) ParseError![]const u8 {
+        p.pos += 3;
+        // A newline right after the opening quotes isn't part of the string
+        if (p.startsWith("\r\n")) p.pos += 2 else if (p.peek() == '\n') p.pos += 1;
+
+        var out: std.ArrayList(u8) = .empty;
+        while (true) {
+            const c = p.peek() orelse return error.UnterminatedString;
+            if (c == quote) {
+                // Up to two quotes may come right before the closing three
+                var run: usize = 0;
+                while (p.pos + run < p.src.len and p.src[p.pos + run] == quote) run += 1;
+                if (run >= 3) {
+                    if (run > 5) return error.UnexpectedCharacter;
+                    try out.appendNTimes(p.allocator, quote, run - 3);
+                    p.pos += run;
+                    return out.items;
+                }
+                try out.appendNTimes(p.allocator, quote, run);
+                p.pos += run;
+                continue;
+            }
+            p.pos += 1;
+            if (c == '\\' and quote == '"') {
+                // A backslash ending a line trims the line break and any
+                // whitespace after it
+                const rest = p.src[p.pos..];
+                const trimmed = std.mem.trimLeft(u8, rest, " \t");
+                if (trimmed.len > 0 and (trimmed[0] == '\n' or std.mem.startsWith(u8, trimmed, "\r\n"))) {
+                    p.pos += rest.len - trimmed.len;
+                    p.skipWhitespace();
+                    continue;
+                }
+                try p.escape(&out);
+                continue;
+            }
+            try out.append(p.allocator, c);
+        }
+    }
+
+    /// The escape after a backslash in a basic string
+    fn escape(p: *Parser, out: *std.ArrayList(u8)) ParseError!void {
+        const c = p.peek() orelse return error.UnterminatedString;
+        p.pos += 1;
+        const byte: u8 = switch (c) {
+            'b' => 0x08,
+            't' => '\t',
+            'n' => '\n',
+            'f' => 0x0c,
+            'r' => '\r',
+            '"' => '"',
+            '\\' => '\\',
+            'u', 'U' => {
+                const digits: usize = if (c == 'u') 4 else 8;
+                if (p.pos + digits > p.src.len) return error.InvalidEscape;
+                const code = std.fmt.parseInt(u21, p.src[p.pos..][0..digits], 16) catch return error.InvalidEscape;
+                p.pos += digits;
+                var buf: [4]u8 = undefined;
+                const len = std.unicode.utf8Encode(code, &buf) catch return error.InvalidEscape;
+                try out.appendSlice(p.allocator, buf[0..len]);
+                return;
+            },
+            else => return error.InvalidEscape,
+        };
+        try out.append(p.allocator, byte);
+    }
+
+    fn endOfLine(p: *Parser) ParseError!void {
+        p.skipSpace();
+        p.skipComment();
+        if (p.pos >= p.src.len) return;
+        if (p.startsWith("\r\n")) {
+            p.pos += 2;
+        } else if (p.src[p.pos] == '\n') {
+            p.pos += 1;
+        } else return error.ExpectedNewline;
+    }
+
+    /// Spaces, comments and line breaks
+    fn skipBlank(p: *Parser) void {
+        while (true) {
+            p.skipWhitespace();
+            if (p.peek() != '#') return;
+            p.skipComment();
+        }
+    }
+
+    fn skipWhitespace(p: *Parser) void {
+        while (p.peek()) |c| switch (c) {
+            ' ', '\t', '\r', '\n' => p.pos += 1,
+            else => return,
+        };
+    }
+
+    fn skipSpace(p: *Parser) void {
+        while (p.peek()) |c| switch (c) {
+            ' ', '\t' => p.pos += 1,
+            else => return,
+        };
+    }
+
+    fn skipComment(p: *Parser) void {
+        if (p.peek() != '#') return;
+        p.pos = std.mem.indexOfScalarPos(u8, p.src, p.pos, '\n') orelse p.src.len;
+    }
+
+    fn expect(p: *Parser, c: u8) ParseError!void {
+        if (p.peek() != c) return if (p.pos >= p.src.len) error.UnexpectedEnd else error.UnexpectedCharacter;
+        p.pos += 1;
+    }
+
+    fn peek(p: *const Parser) ?u8 {
+        return if (p.pos < p.src.len) p.src[p.pos] else null;
+    }
+
+    fn startsWith(p: *const Parser, prefix: []const u8) bool {
+        return std.mem.startsWith(u8, p.src[p.pos..], prefix);
+    }
+};
+
+fn parseNumber(token: []const u8) ParseError!Value {
+    const unsigned = if (token[0] == '+' or token[0] == '-') token[1..] else token;
+    if (std.mem.eql(u8, unsigned, "inf")) return .{ .float = if (token[0] == '-') -std.math.inf(f64) else std.math.inf(f64) };
+    if (std.mem.eql(u8, unsigned, "nan")) return .{ .float = std.math.nan(f64) };
+    if (isDateTime(token)) return error.UnsupportedDateTime;
+
+    // Underscores may only sit between digits
+    var buf: [64]u8 = undefined;
+    var len: usize = 0;
+    for (token, 0..) |c, i| {
+        if (c == '_') {
+            if (i == 0 or i + 1 == token.len or !std.ascii.isAlphanumeric(token[i - 1]) or !std.ascii.isAlphanumeric(token[i + 1])) return error.InvalidNumber;
+            continue;
+        }
+        if (len == buf.len) return error.InvalidNumber;
+        buf[len] = c;
+        len += 1;
+    }
+    const text = buf[0..len];
+
+    if (text.len > 2 and text[0] == '0' and std.ascii.isAlphabetic(text[1])) {
+        const base: u8 = switch (text[1]) {
+            'x' => 16,
+            'o' => 8,
+            'b' => 2,
+            else => return error.InvalidNumber,
+        };
+        return .{ .integer = std.fmt.parseInt(i64, text[2..], base) catch return error.InvalidNumber };
+    }
+
+    const digits = if (text[0] == '+' or text[0] == '-') text[1..] else text;
+    if (digits.len == 0 or !std.ascii.isDigit(digits[0])) return error.InvalidNumber;
+    // Leading zeros aren't allowed, so "010" isn't mistaken for octal
+    if (digits.len > 1 and digits[0] == '0' and std.ascii.isDigit(digits[1])) return error.InvalidNumber;
+    if (std.mem.indexOfAny(u8, digits, ".eE") != null) {
+        return .{ .float = std.fmt.parseFloat(f64, text) catch return error.InvalidNumber };
+    }
+    return .{ .integer = std.fmt.parseInt(i64, text, 10) catch return error.InvalidNumber };
+}
+
+/// Offset dates, local dates and local times: 1979-05-27, 07:32:00
+fn isDateTime(token: []const u8) bool {
+    if (token.len >= 5 and std.ascii.isDigit(token[0]) and token[4] == '-') return true;
+    return token.len >= 3 and std.ascii.isDigit(token[0]) and token[2] == ':';
+}
+
+test "parse and decode" {
+    var arena = std.heap.ArenaAllocator.init(std.testing.allocator);
+    defer arena.deinit();
+    const allocator = arena.allocator();
+
+    const Server = struct { host: []const u8, weight: u32 = 1 };
+    const Mode = enum { fast, safe };
+    const Doc = struct {
+        name: []const u8,
+        ratio: f64 = 0,
+        mode: Mode = .safe,
+        tags: []const []const u8 = &.{},
+        limits: struct { max: u16 = 0, strict: bool = false } = .{},
+        servers: []const Server = &.{},
+        note: ?[]const u8 = null,
+    };
+
+    const text =
+        \\# comment
+        \\name = "wraith é" # trailing
+        \\ratio = 1_000.5
+        \\mode = 'fast'
+        \\tags = [
+        \\  "a", # first
+        \\  "b",
+        \\]
+        \\limits.max = 0x10
+        \\
+        \\[[servers]]
+        \\host = "10.0.0.1:80"
+        \\
+        \\[[servers]]
+        \\host = "10.0.0.2:80"
+        \\weight = 3
+    ;
+    var diag: Diagnostics = .{};
+    const root = try parse(allocator, text, &diag);
+    const doc = try decode(Doc, allocator, root, &diag);
+    try std.testing.expectEqualStrings("wraith é", doc.name);
+    try std.testing.expectEqual(@as(f64, 1000.5), doc.ratio);
+    try std.testing.expectEqual(Mode.fast, doc.mode);
+    try std.testing.expectEqual(@as(usize, 2), doc.tags.len);
+    try std.testing.expectEqual(@as(u16, 16), doc.limits.max);
+    try std.testing.expectEqual(@as(usize, 2), doc.servers.len);
+    try std.testing.expectEqual(@as(u32, 1), doc.servers[0].weight);
+    try std.testing.expectEqual(@as(u32, 3), doc.servers[1].weight);
+    try std.testing.expect(doc.note == null);
+}
+
+test "syntax errors carry the line" {
+    var arena = std.heap.ArenaAllocator.init(std.testing.allocator);
+    defer arena.deinit();
+
+    var diag: Diagnostics = .{};
+    try std.testing.expectError(error.DuplicateKey, parse(arena.allocator(), "a = 1\nb = 2\na = 3\n", &diag));
+    try std.testing.expectEqual(@as(usize, 3), diag.line);
+    try std.testing.expectError(error.ExpectedNewline, parse(arena.allocator(), "a = 1 b = 2\n", &diag));
+    try std.testing.expectError(error.UnsupportedDateTime, parse(arena.allocator(), "d = 1979-05-27\n", &diag));
+}
+
+test "decode errors carry the key" {
+    var arena = std.heap.ArenaAllocator.init(std.testing.allocator);
+    defer arena.deinit();
+
+    const Doc = struct { items: []const struct { port: u16 } = &.{} };
+    var diag: Diagnostics = .{};
+    const root = try parse(arena.allocator(), "items = [{ port = 80 }, { port = 70000 }]\n", &diag);
+    try std.testing.expectError(error.ConfigValueOutOfRange, decode(Doc, arena.allocator(), root, &diag));
+    try std.testing.expectEqualStrings("items[1].port", diag.path.text());
+}
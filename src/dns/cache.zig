@@ -0,0 +1,187 @@
+const std = @import("std");
+const config = @import("../config/config.zig");
+const message = @import("message.zig");
+
+pub const CacheStats = struct {
+    hits: std.atomic.Value(u64) = std.atomic.Value(u64).init(0),
+    misses: std.atomic.Value(u64) = std.atomic.Value(u64).init(0),
+    inserts: std.atomic.Value(u64) = std.atomic.Value(u64).init(0),
+    negative_inserts: std.atomic.Value(u64) = std.atomic.Value(u64).init(0),
+    evictions: std.atomic.Value(u64) = std.atomic.Value(u64).init(0),
+};
+
+const Entry = struct {
+    answer: []u8,
+    stored_at_ms: i64,
+    /// Cache lifetime after min/max clamping, in seconds
+    ttl: u32,
+};
+
+/// In-memory DNS answer cache keyed by (name, type, class)
+pub const DnsCache = struct {
+    allocator: std.mem.Allocator,
+    config: *const config.DnsCacheConfig,
+    mutex: std.Thread.Mutex,
+    entries: std.StringHashMapUnmanaged(Entry),
+    stats: CacheStats,
+
+    pub fn init(allocator: std.mem.Allocator, cfg: *const config.DnsCacheConfig) DnsCache {
+        return .{
+            .allocator = allocator,
+            .config = cfg,
+            .mutex = .{},
+            .entries = .empty,
+            .stats = .{},
+        };
+    }
+
+    pub fn deinit(self: *DnsCache) void {
+        var it = self.entries.iterator();
+        while (it.next()) |kv| {
+            self.allocator.free(kv.key_ptr.*);
+            self.allocator.free(kv.value_ptr.answer);
+        }
+        self.entries.deinit(self.allocator);
+    }
+
+    pub fn count(self: *DnsCache) usize {
+        self.mutex.lock();
+        defer self.mutex.unlock();
+        return self.entries.count();
+    }
+
+    /// Return a copy of the cached answer for a query, carrying the query's
+    /// ID and TTLs reduced by the time spent in the cache
+    pub fn lookup(self: *DnsCache, allocator: std.mem.Allocator, query: []const u8) !?[]u8 {
+        if (!self.config.enabled) return null;
+
+        var key_buf: [message.MAX_NAME_LEN + 16]u8 = undefined;
+        const key = cacheKey(query, &key_buf) catch return null;
+        const now = std.time.milliTimestamp();
+
+        self.mutex.lock();
+        defer self.mutex.unlock();
+
+        const entry = self.entries.get(key) orelse {
+            _ = self.stats.misses.fetchAdd(1, .monotonic);
+            return null;
+        };
+        const age: u32 = @intCast(@max(0, @divFloor(now - entry.stored_at_ms, 1000)));
+        if (age >= entry.ttl) {
+            _ = self.stats.misses.fetchAdd(1, .monotonic);
+            self.removeLocked(key);
+            return null;
+        }
+
+        const answer = try allocator.dupe(u8, entry.answer);
+        @memcpy(answer[0..2], query[0..2]);
+        try ageTtls(answer, entry.ttl, age);
+
+        _ = self.stats.hits.fetchAdd(1, .monotonic);
+        return answer;
+    }
+
+    /// Cache an upstream answer. NOERROR answers use the smallest record TTL
+    /// clamped to [min_ttl, max_ttl]; NXDOMAIN and empty answers are cached
+    /// for at most negative_ttl. Other response codes are never cached.
+    pub fn store(self: *DnsCache, query: []const u8, answer: []const u8) !void {
+        if (!self.config.enabled or self.config.max_entries == 0) return;
+
+        const header = try message.Header.parse(answer);
+        if (header.isTruncated()) return;
+        const rcode = header.rcode();
+        if (rcode != message.RCODE_NOERROR and rcode != message.RCODE_NXDOMAIN) return;
+
+        const negative = rcode == message.RCODE_NXDOMAIN or header.ancount == 0;
+        const record_ttl = try message.minTtl(answer);
+        const ttl = if (negative)
+            @min(record_ttl orelse self.config.negative_ttl, self.config.negative_ttl)
+        else
+            @max(@min(record_ttl orelse return, self.config.max_ttl), self.config.min_ttl);
+        if (ttl == 0) return;
+
+        var key_buf: [message.MAX_NAME_LEN + 16]u8 = undefined;
+        const key = try cacheKey(query, &key_buf);
+        const now = std.time.milliTimestamp();
+
+        self.mutex.lock();
+        defer self.mutex.unlock();
+
+        if (self.entries.count() >= self.config.max_entries and !self.entries.contains(key)) {
+            self.evictOneLocked(now);
+        }
+
+        const owned_answer = try self.allocator.dupe(u8, answer);
+        errdefer self.allocator.free(owned_answer);
+
+        const gop = try self.entries.getOrPut(self.allocator, key);
+        if (gop.found_existing) {
+            self.allocator.free(gop.value_ptr.answer);
+        } else {
+            gop.key_ptr.* = self.allocator.dupe(u8, key) catch |err| {
+                self.entries.removeByPtr(gop.key_ptr);
+                return err;
+            };
+        }
+        gop.value_ptr.* = .{
+            .answer = owned_answer,
+            .stored_at_ms = now,
+            .ttl = ttl,
+        };
+
+        _ = self.stats.inserts.fetchAdd(1, .monotonic);
+        if (negative) _ = self.stats.negative_inserts.fetchAdd(1, .monotonic);
+    }
+
+    /// Drop an expired entry if there is one, otherwise an arbitrary entry
+    fn evictOneLocked(self: *DnsCache, now: i64) void {
+        var victim: ?[]const u8 = null;
+        var it = self.entries.iterator();
+        while (it.next()) |kv| {
+            if (victim == null) victim = kv.key_ptr.*;
+            const age = @divFloor(now - kv.value_ptr.stored_at_ms, 1000);
+            if (age >= kv.value_ptr.ttl) {
+                victim = kv.key_ptr.*;
+                break;
+            }
+        }
+        if (victim) |key| {
+            self.removeLocked(key);
+            _ = self.stats.evictions.fetchAdd(1, .monotonic);
+        }
+    }
+
+    /// Evict entries until at most `keep` remain, expired ones first, to
+    /// give memory back under pressure
+    pub fn shrink(self: *DnsCache, keep: usize) void {
+        const now = std.time.milliTimestamp();
+        self.mutex.lock();
+        defer self.mutex.unlock();
+        while (self.entries.count() > keep) self.evictOneLocked(now);
+    }
+
+    fn removeLocked(self: *DnsCache, key: []const u8) void {
+        if (self.entries.fetchRemove(key)) |kv| {
+            self.allocator.free(kv.key);
+            self.allocator.free(kv.value.answer);
+        }
+    }
+};
+
+/// Build the "name|type|class" cache key of a query's first question
+fn cacheKey(query: []const u8, buf: []u8) ![]const u8 {
+    var name_buf: [message.MAX_NAME_LEN]u8 = undefined;
+    const question = try message.firstQuestion(query, &name_buf);
+    return std.fmt.bufPrint(buf, "{s}|{d}|{d}", .{ question.name, question.qtype, question.qclass });
+}
+
+/// Rewrite record TTLs in place to reflect time spent in the cache
+fn ageTtls(answer: []u8, ttl: u32, age: u32) !void {
+    var it = try message.RecordIterator.init(answer);
+    while (try it.next()) |record| {
+        // The OPT pseudo-record uses the TTL field for EDNS flags
+        if (record.rtype == @intFromEnum(message.RecordType.OPT)) continue;
+        const remaining = @min(record.ttl, ttl) -| age;
+        std.mem.writeInt(u32, answer[record.ttl_offset..][0..4], remaining, .big);
+    }
+}
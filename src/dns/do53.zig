@@ -0,0 +1,193 @@
+const std = @import("std");
+const config = @import("../config/config.zig");
+const message = @import("message.zig");
+const resolver_mod = @import("resolver.zig");
+const signals = @import("../server/signals.zig");
+const socket = @import("../server/socket.zig");
+
+/// Largest UDP answer for clients that don't advertise EDNS (RFC 1035)
+const CLASSIC_UDP_SIZE = 512;
+
+/// Largest UDP query accepted, and the cap on advertised EDNS sizes
+const MAX_UDP_SIZE = 4096;
+
+/// How often a blocked UDP listener checks for shutdown
+const SHUTDOWN_POLL_MS = 1000;
+
+/// Idle time allowed between queries on a TCP connection
+const TCP_IDLE_TIMEOUT_MS = 10_000;
+
+pub const Do53Stats = struct {
+    queries: std.atomic.Value(u64) = std.atomic.Value(u64).init(0),
+    query_failures: std.atomic.Value(u64) = std.atomic.Value(u64).init(0),
+    truncated: std.atomic.Value(u64) = std.atomic.Value(u64).init(0),
+};
+
+/// Plain DNS listeners on UDP and TCP, sharing the resolver with DoH and DoT
+pub const Do53Server = struct {
+    allocator: std.mem.Allocator,
+    config: *const config.DnsConfig,
+    resolver: *resolver_mod.Resolver,
+    stats: Do53Stats,
+
+    pub fn init(
+        allocator: std.mem.Allocator,
+        cfg: *const config.DnsConfig,
+        resolver: *resolver_mod.Resolver,
+    ) Do53Server {
+        return .{
+            .allocator = allocator,
+            .config = cfg,
+            .resolver = resolver,
+            .stats = .{},
+        };
+    }
+
+    /// Bind every enabled listener, each served on its own thread
+    pub fn start(self: *Do53Server) !void {
+        if (self.config.udp_enabled) {
+            for (self.config.listen_udp) |text| {
+                const handle = try socket.bindUdp(try std.net.Address.parseIpAndPort(text));
+                std.debug.print("✓ DNS listening on udp {s}\n", .{text});
+                const thread = try std.Thread.spawn(.{}, runUdp, .{ self, handle });
+                thread.detach();
+            }
+        }
+        if (self.config.tcp_enabled) {
+            for (self.config.listen_tcp) |text| {
+                const server = try self.allocator.create(std.net.Server);
+                server.* = try socket.listen(try std.net.Address.parseIpAndPort(text));
+                std.debug.print("✓ DNS listening on tcp {s}\n", .{text});
+                const thread = try std.Thread.spawn(.{}, runTcp, .{ self, server });
+                thread.detach();
+            }
+        }
+    }
+
+    /// Thread entry point for one UDP socket
+    fn runUdp(self: *Do53Server, handle: std.posix.socket_t) void {
+        defer std.posix.close(handle);
+        self.serveUdp(handle) catch |err| {
+            std.debug.print("✗ DNS UDP listener failed: {any}\n", .{err});
+        };
+    }
+
+    fn serveUdp(self: *Do53Server, handle: std.posix.socket_t) !void {
+        // The receive timeout lets the loop notice shutdown
+        try socket.setTimeouts(handle, SHUTDOWN_POLL_MS);
+
+        var arena = std.heap.ArenaAllocator.init(self.allocator);
+        defer arena.deinit();
+
+        var buf: [MAX_UDP_SIZE]u8 = undefined;
+        while (!signals.shouldShutdown()) {
+            var peer: std.net.Address = undefined;
+            var peer_len: std.posix.socklen_t = @sizeOf(std.net.Address);
+            const n = std.posix.recvfrom(handle, &buf, 0, &peer.any, &peer_len) catch |err| switch (err) {
+                error.WouldBlock => continue,
+                else => return err,
+            };
+            const query = buf[0..n];
+            if (n < message.HEADER_LEN) continue;
+            _ = self.stats.queries.fetchAdd(1, .monotonic);
+
+            _ = arena.reset(.retain_capacity);
+            const answer = self.answerUdp(arena.allocator(), query) catch |err| {
+                std.debug.print("✗ DNS query from {f} failed: {any}\n", .{ peer, err });
+                continue;
+            };
+            _ = std.posix.sendto(handle, answer, 0, &peer.any, peer_len) catch |err| {
+                std.debug.print("✗ DNS reply to {f} failed: {any}\n", .{ peer, err });
+            };
+        }
+    }
+
+    /// Resolve a UDP query, falling back to SERVFAIL, and truncate answers
+    /// larger than the client can receive so it retries over TCP
+    fn answerUdp(self: *Do53Server, allocator: std.mem.Allocator, query: []const u8) ![]const u8 {
+        const answer = self.resolver.resolve(allocator, query) catch |err| {
+            std.debug.print("✗ DNS resolution failed: {any}\n", .{err});
+            _ = self.stats.query_failures.fetchAdd(1, .monotonic);
+            return message.errorReply(allocator, query, message.RCODE_SERVFAIL);
+        };
+
+        const advertised = (message.ednsPayloadSize(query) catch null) orelse CLASSIC_UDP_SIZE;
+        const limit = std.math.clamp(advertised, CLASSIC_UDP_SIZE, MAX_UDP_SIZE);
+        if (answer.len <= limit) return answer;
+
+        _ = self.stats.truncated.fetchAdd(1, .monotonic);
+        return truncate(answer);
+    }
+
+    /// Thread entry point for one TCP listener
+    fn runTcp(self: *Do53Server, server: *std.net.Server) void {
+        defer {
+            server.deinit();
+            self.allocator.destroy(server);
+        }
+        while (!signals.shouldShutdown()) {
+            const conn = server.accept() catch |err| {
+                if (signals.shouldShutdown()) break;
+                std.debug.print("✗ DNS TCP accept failed: {any}\n", .{err});
+                continue;
+            };
+            const thread = std.Thread.spawn(.{}, handleTcp, .{ self, conn.stream }) catch |err| {
+                std.debug.print("✗ DNS TCP connection dropped: {any}\n", .{err});
+                conn.stream.close();
+                continue;
+            };
+            thread.detach();
+        }
+    }
+
+    fn handleTcp(self: *Do53Server, stream: std.net.Stream) void {
+        defer stream.close();
+        self.serveTcp(stream) catch |err| switch (err) {
+            error.EndOfStream, error.WouldBlock => {},
+            else => std.debug.print("✗ DNS TCP session error: {any}\n", .{err}),
+        };
+    }
+
+    /// Serve length-prefixed DNS messages until the client closes the
+    /// connection or goes idle
+    fn serveTcp(self: *Do53Server, stream: std.net.Stream) !void {
+        try socket.setTimeouts(stream.handle, TCP_IDLE_TIMEOUT_MS);
+
+        var arena = std.heap.ArenaAllocator.init(self.allocator);
+        defer arena.deinit();
+
+        var len_buf: [2]u8 = undefined;
+        while (true) {
+            _ = arena.reset(.retain_capacity);
+            const allocator = arena.allocator();
+
+            try socket.readExact(stream, &len_buf);
+            const len = std.mem.readInt(u16, &len_buf, .big);
+            if (len < message.HEADER_LEN) return error.InvalidMessage;
+
+            const query = try allocator.alloc(u8, len);
+            try socket.readExact(stream, query);
+            _ = self.stats.queries.fetchAdd(1, .monotonic);
+
+            const answer = self.resolver.resolve(allocator, query) catch |err| blk: {
+                std.debug.print("✗ DNS resolution failed: {any}\n", .{err});
+                _ = self.stats.query_failures.fetchAdd(1, .monotonic);
+                break :blk try message.errorReply(allocator, query, message.RCODE_SERVFAIL);
+            };
+            std.mem.writeInt(u16, &len_buf, @intCast(answer.len), .big);
+            try stream.writeAll(&len_buf);
+            try stream.writeAll(answer);
+        }
+    }
+};
+
+/// Cut an answer down to its header and question with the TC bit set
+fn truncate(answer: []u8) ![]u8 {
+    var name_buf: [message.MAX_NAME_LEN + 1]u8 = undefined;
+    const question = try message.firstQuestion(answer, &name_buf);
+    const flags = std.mem.readInt(u16, answer[2..4], .big);
+    std.mem.writeInt(u16, answer[2..4], flags | 0x0200, .big);
+    std.mem.writeInt(u16, answer[4..6], 1, .big);
+    @memset(answer[6..12], 0);
+    return answer[0..question.end];
+}
@@ -0,0 +1,61 @@
+const std = @import("std");
+const message = @import("message.zig");
+const resolver_mod = @import("resolver.zig");
+const request = @import("../server/request.zig");
+const response = @import("../server/response.zig");
+
+const Header = request.Header;
+
+pub const CONTENT_TYPE = "application/dns-message";
+
+/// Serve an RFC 8484 DNS-over-HTTPS request (GET ?dns= or POST application/dns-message)
+pub fn handle(
+    resolver: *resolver_mod.Resolver,
+    allocator: std.mem.Allocator,
+    stream: std.net.Stream,
+    req: *const request.Request,
+    buffered: []const u8,
+) !void {
+    const decoder = std.base64.url_safe_no_pad.Decoder;
+
+    const query = if (std.mem.eql(u8, req.method, "GET")) blk: {
+        const param = req.queryParam("dns") orelse return response.writeError(stream, 400);
+        const size = decoder.calcSizeForSlice(param) catch return response.writeError(stream, 400);
+        if (size > message.MAX_MESSAGE_SIZE) return response.writeError(stream, 414);
+        const decoded = try allocator.alloc(u8, size);
+        decoder.decode(decoded, param) catch return response.writeError(stream, 400);
+        break :blk decoded;
+    } else if (std.mem.eql(u8, req.method, "POST")) blk: {
+        const content_type = req.header("Content-Type") orelse "";
+        if (!std.ascii.eqlIgnoreCase(content_type, CONTENT_TYPE)) {
+            return response.writeError(stream, 415);
+        }
+        break :blk request.readBody(allocator, stream, req, buffered, message.MAX_MESSAGE_SIZE) catch |err| switch (err) {
+            error.BodyTooLarge => return response.writeError(stream, 413),
+            else => return response.writeError(stream, 400),
+        };
+    } else {
+        return response.write(stream, 405, &[_]Header{
+            .{ .name = "Allow", .value = "GET, POST" },
+        }, "");
+    };
+
+    if (query.len < message.HEADER_LEN) return response.writeError(stream, 400);
+
+    const answer = resolver.resolve(allocator, query) catch |err| {
+        std.debug.print("✗ DoH resolution failed: {any}\n", .{err});
+        return response.writeError(stream, 502);
+    };
+
+    var headers: std.ArrayList(Header) = .empty;
+    try headers.append(allocator, .{ .name = "Content-Type", .value = CONTENT_TYPE });
+    // Freshness follows the smallest record TTL (RFC 8484 section 5.1)
+    if (message.minTtl(answer) catch null) |ttl| {
+        try headers.append(allocator, .{
+            .name = "Cache-Control",
+            .value = try std.fmt.allocPrint(allocator, "max-age={d}", .{ttl}),
+        });
+    }
+
+    try response.write(stream, 200, headers.items, answer);
+}
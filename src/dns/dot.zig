@@ -0,0 +1,169 @@
+const std = @import("std");
+const config = @import("../config/config.zig");
+const message = @import("message.zig");
+const resolver_mod = @import("resolver.zig");
+const signals = @import("../server/signals.zig");
+const socket = @import("../server/socket.zig");
+const tls = @import("../server/tls.zig");
+
+/// ALPN protocol identifier for DNS-over-TLS (RFC 7858)
+pub const ALPN = "dot";
+
+const alpn_protocols = [_][]const u8{ALPN};
+
+pub const DotStats = struct {
+    sessions_total: std.atomic.Value(u64) = std.atomic.Value(u64).init(0),
+    queries: std.atomic.Value(u64) = std.atomic.Value(u64).init(0),
+    query_failures: std.atomic.Value(u64) = std.atomic.Value(u64).init(0),
+};
+
+/// DNS-over-TLS listener sharing the resolver with the DoH endpoint
+pub const DotServer = struct {
+    allocator: std.mem.Allocator,
+    config: *const config.Config,
+    resolver: *resolver_mod.Resolver,
+    tls_server: tls.TlsServer,
+    stats: DotStats,
+
+    pub fn init(
+        allocator: std.mem.Allocator,
+        cfg: *const config.Config,
+        resolver: *resolver_mod.Resolver,
+    ) DotServer {
+        return .{
+            .allocator = allocator,
+            .config = cfg,
+            .resolver = resolver,
+            .tls_server = tls.TlsServer.initWithAlpn(allocator, "", "", &alpn_protocols),
+            .stats = .{},
+        };
+    }
+
+    /// Thread entry point
+    pub fn run(self: *DotServer) void {
+        self.start() catch |err| {
+            std.debug.print("✗ DNS-over-TLS listener failed: {any}\n", .{err});
+        };
+    }
+
+    pub fn start(self: *DotServer) !void {
+        const dot = &self.config.dns.dot;
+
+        // Dedicated DoT certificates, or the server certificate from tls.cert_dir
+        var owned_paths: [2]?[]u8 = .{ null, null };
+        defer {
+            for (owned_paths) |p| {
+                if (p) |path| self.allocator.free(path);
+            }
+        }
+        if (dot.cert_path) |path| {
+            self.tls_server.cert_path = path;
+        } else {
+            owned_paths[0] = try std.fs.path.join(self.allocator, &[_][]const u8{ self.config.tls.cert_dir, "server.crt" });
+            self.tls_server.cert_path = owned_paths[0].?;
+        }
+        if (dot.key_path) |path| {
+            self.tls_server.key_path = path;
+        } else {
+            owned_paths[1] = try std.fs.path.join(self.allocator, &[_][]const u8{ self.config.tls.cert_dir, "server.key" });
+            self.tls_server.key_path = owned_paths[1].?;
+        }
+        try self.tls_server.loadCertificate();
+
+        const addresses = self.config.dns.dotAddresses();
+        const servers = try self.allocator.alloc(std.net.Server, addresses.len);
+        defer self.allocator.free(servers);
+        var bound: usize = 0;
+        defer for (servers[0..bound]) |*server| server.deinit();
+        for (addresses, servers) |text, *server| {
+            server.* = try socket.listen(try std.net.Address.parseIpAndPort(text));
+            bound += 1;
+            std.debug.print("✓ DNS-over-TLS listening on {s}\n", .{text});
+        }
+
+        // The first listener is served on this thread, the rest on their own
+        for (servers[1..]) |*server| {
+            const thread = try std.Thread.spawn(.{}, runListener, .{ self, server });
+            thread.detach();
+        }
+        try self.acceptLoop(&servers[0]);
+    }
+
+    /// Thread entry point for an additional listener
+    fn runListener(self: *DotServer, server: *std.net.Server) void {
+        self.acceptLoop(server) catch |err| {
+            std.debug.print("✗ DNS-over-TLS listener on {f} failed: {any}\n", .{ server.listen_address, err });
+        };
+    }
+
+    fn acceptLoop(self: *DotServer, server: *std.net.Server) !void {
+        while (!signals.shouldShutdown()) {
+            const conn = server.accept() catch |err| {
+                if (signals.shouldShutdown()) break;
+                return err;
+            };
+            // A session stays open between queries, so each one gets its
+            // own thread
+            const thread = std.Thread.spawn(.{}, handleConnection, .{ self, conn.stream }) catch |err| {
+                std.debug.print("✗ DoT connection dropped: {any}\n", .{err});
+                conn.stream.close();
+                continue;
+            };
+            thread.detach();
+        }
+    }
+
+    fn handleConnection(self: *DotServer, stream: std.net.Stream) void {
+        defer stream.close();
+        var session = self.tls_server.acceptTls(stream) catch |err| {
+            std.debug.print("✗ DoT handshake failed: {any}\n", .{err});
+            return;
+        };
+        defer session.close();
+        _ = self.stats.sessions_total.fetchAdd(1, .monotonic);
+
+        self.serveQueries(&session) catch |err| {
+            std.debug.print("✗ DoT session error: {any}\n", .{err});
+        };
+    }
+
+    /// Serve length-prefixed DNS messages until the client closes the session
+    fn serveQueries(self: *DotServer, session: *tls.TlsSession) !void {
+        var arena = std.heap.ArenaAllocator.init(self.allocator);
+        defer arena.deinit();
+
+        var len_buf: [2]u8 = undefined;
+        while (true) {
+            _ = arena.reset(.retain_capacity);
+            const allocator = arena.allocator();
+
+            readExact(session, &len_buf) catch |err| switch (err) {
+                error.EndOfStream => return,
+                else => return err,
+            };
+            const len = std.mem.readInt(u16, &len_buf, .big);
+            if (len < message.HEADER_LEN) return error.InvalidMessage;
+
+            const query = try allocator.alloc(u8, len);
+            try readExact(session, query);
+            _ = self.stats.queries.fetchAdd(1, .monotonic);
+
+            const answer = self.resolver.resolve(allocator, query) catch |err| {
+                _ = self.stats.query_failures.fetchAdd(1, .monotonic);
+                return err;
+            };
+            std.mem.writeInt(u16, &len_buf, @intCast(answer.len), .big);
+            try session.writeAll(&len_buf);
+            try session.writeAll(answer);
+        }
+    }
+};
+
+fn readExact(session: *tls.TlsSession, buf: []u8) !void {
+    var filled: usize = 0;
+    while (filled < buf.len) {
+        const n = try session.read(buf[filled..]);
+        if (n == 0) return error.EndOfStream;
+        filled += n;
+    }
+}
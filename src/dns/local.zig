@@ -0,0 +1,116 @@
+const std = @import("std");
+const config = @import("../config/config.zig");
+const message = @import("message.zig");
+const router = @import("../routing/router.zig");
+const upstream_mod = @import("../upstream/manager.zig");
+
+const CLASS_IN = 1;
+
+/// Answers queries for vhost hostnames with wraith's own addresses instead
+/// of forwarding them, withholding them while the vhost's backends are down
+pub const LocalAnswers = struct {
+    allocator: std.mem.Allocator,
+    config: *const config.LocalDnsConfig,
+    vhosts: []const config.VhostConfig,
+    upstreams: *upstream_mod.UpstreamManager,
+    addresses: []std.net.Address,
+
+    pub fn init(
+        allocator: std.mem.Allocator,
+        cfg: *const config.LocalDnsConfig,
+        vhosts: []const config.VhostConfig,
+        upstreams: *upstream_mod.UpstreamManager,
+    ) !LocalAnswers {
+        const addresses = try allocator.alloc(std.net.Address, cfg.addresses.len);
+        errdefer allocator.free(addresses);
+        for (cfg.addresses, addresses) |text, *address| {
+            address.* = try std.net.Address.parseIp(text, 0);
+        }
+        return .{
+            .allocator = allocator,
+            .config = cfg,
+            .vhosts = vhosts,
+            .upstreams = upstreams,
+            .addresses = addresses,
+        };
+    }
+
+    pub fn deinit(self: *LocalAnswers) void {
+        self.allocator.free(self.addresses);
+    }
+
+    /// Reply for a query naming a vhost, or null to forward it upstream.
+    /// Matching names are answered for every type; only A and AAAA carry
+    /// records.
+    pub fn answer(self: *LocalAnswers, allocator: std.mem.Allocator, query: []const u8) !?[]u8 {
+        var name_buf: [message.MAX_NAME_LEN + 1]u8 = undefined;
+        const question = try message.firstQuestion(query, &name_buf);
+        if (question.qclass != CLASS_IN) return null;
+        const vhost = self.findVhost(question.name) orelse return null;
+
+        if (self.config.health_checked and self.vhostDown(vhost)) {
+            return try message.errorReply(allocator, query, message.RCODE_SERVFAIL);
+        }
+
+        var reply = try message.replyHead(allocator, query, message.RCODE_NOERROR);
+        errdefer reply.deinit(allocator);
+        // Authoritative answer
+        reply.items[2] |= 0x04;
+
+        var count: u16 = 0;
+        for (self.addresses) |address| {
+            const rdata: []const u8 = switch (address.any.family) {
+                std.posix.AF.INET => if (question.qtype == @intFromEnum(message.RecordType.A))
+                    std.mem.asBytes(&address.in.sa.addr)
+                else
+                    continue,
+                std.posix.AF.INET6 => if (question.qtype == @intFromEnum(message.RecordType.AAAA))
+                    &address.in6.sa.addr
+                else
+                    continue,
+                else => continue,
+            };
+            var record: [12]u8 = undefined;
+            // Name as a pointer to the question at offset 12
+            std.mem.writeInt(u16, record[0..2], 0xc000 | message.HEADER_LEN, .big);
+            std.mem.writeInt(u16, record[2..4], question.qtype, .big);
+            std.mem.writeInt(u16, record[4..6], CLASS_IN, .big);
+            std.mem.writeInt(u32, record[6..10], self.config.ttl, .big);
+            std.mem.writeInt(u16, record[10..12], @intCast(rdata.len), .big);
+            try reply.appendSlice(allocator, &record);
+            try reply.appendSlice(allocator, rdata);
+            count += 1;
+        }
+        std.mem.writeInt(u16, reply.items[6..8], count, .big);
+        return try reply.toOwnedSlice(allocator);
+    }
+
+    /// Vhost serving `name`, preferring exact hostnames over wildcards.
+    /// The catch-all "*" is ignored so unrelated names still resolve.
+    fn findVhost(self: *const LocalAnswers, name: []const u8) ?*const config.VhostConfig {
+        for (self.vhosts) |*vhost| {
+            for (vhost.hostnames) |hostname| {
+                if (std.ascii.eqlIgnoreCase(hostname, name)) return vhost;
+            }
+        }
+        for (self.vhosts) |*vhost| {
+            for (vhost.hostnames) |hostname| {
+                if (!std.mem.startsWith(u8, hostname, "*.")) continue;
+                if (router.hostMatches(hostname, name)) return vhost;
+            }
+        }
+        return null;
+    }
+
+    /// A vhost is down when it proxies every request and none of its
+    /// upstream groups has a healthy server. Static mounts and routes
+    /// without an upstream keep it up.
+    fn vhostDown(self: *const LocalAnswers, vhost: *const config.VhostConfig) bool {
+        if (vhost.static.len > 0 or vhost.routes.len == 0) return false;
+        for (vhost.routes) |route| {
+            const group = self.upstreams.get(route.upstream) orelse return false;
+            if (group.hasHealthy()) return false;
+        }
+        return true;
+    }
+};
@@ -0,0 +1,283 @@
+const std = @import("std");
+
+/// Size of the fixed DNS message header
+pub const HEADER_LEN = 12;
+
+/// Largest DNS message (TCP length prefix limit)
+pub const MAX_MESSAGE_SIZE = 65535;
+
+/// Longest textual domain name
+pub const MAX_NAME_LEN = 253;
+
+pub const RCODE_NOERROR = 0;
+pub const RCODE_SERVFAIL = 2;
+pub const RCODE_NXDOMAIN = 3;
+
+pub const RecordType = enum(u16) {
+    A = 1,
+    NS = 2,
+    CNAME = 5,
+    SOA = 6,
+    PTR = 12,
+    MX = 15,
+    TXT = 16,
+    AAAA = 28,
+    SRV = 33,
+    OPT = 41,
+    HTTPS = 65,
+    _,
+};
+
+pub const Header = struct {
+    id: u16,
+    flags: u16,
+    qdcount: u16,
+    ancount: u16,
+    nscount: u16,
+    arcount: u16,
+
+    pub fn parse(msg: []const u8) !Header {
+        if (msg.len < HEADER_LEN) return error.MessageTooShort;
+        return .{
+            .id = std.mem.readInt(u16, msg[0..2], .big),
+            .flags = std.mem.readInt(u16, msg[2..4], .big),
+            .qdcount = std.mem.readInt(u16, msg[4..6], .big),
+            .ancount = std.mem.readInt(u16, msg[6..8], .big),
+            .nscount = std.mem.readInt(u16, msg[8..10], .big),
+            .arcount = std.mem.readInt(u16, msg[10..12], .big),
+        };
+    }
+
+    pub fn isResponse(self: Header) bool {
+        return self.flags & 0x8000 != 0;
+    }
+
+    pub fn isTruncated(self: Header) bool {
+        return self.flags & 0x0200 != 0;
+    }
+
+    pub fn rcode(self: Header) u4 {
+        return @truncate(self.flags & 0x000f);
+    }
+};
+
+pub const Question = struct {
+    /// Lowercase dotted name, stored in the caller's buffer
+    name: []const u8,
+    qtype: u16,
+    qclass: u16,
+    /// Offset just past the question in the message
+    end: usize,
+};
+
+pub const Name = struct {
+    text: []const u8,
+    /// Offset just past the encoded name in the message
+    end: usize,
+};
+
+/// Decode a possibly compressed name at `start` as lowercase dotted text
+pub fn readName(msg: []const u8, start: usize, out: []u8) !Name {
+    var pos = start;
+    var len: usize = 0;
+    var end: ?usize = null;
+    var jumps: usize = 0;
+
+    while (true) {
+        if (pos >= msg.len) return error.InvalidName;
+        const label_len = msg[pos];
+        if (label_len == 0) {
+            if (end == null) end = pos + 1;
+            break;
+        }
+        if (label_len & 0xc0 == 0xc0) {
+            if (pos + 1 >= msg.len) return error.InvalidName;
+            if (end == null) end = pos + 2;
+            jumps += 1;
+            if (jumps > 16) return error.InvalidName;
+            pos = (@as(usize, label_len & 0x3f) << 8) | msg[pos + 1];
+            continue;
+        }
+        if (label_len > 63 or pos + 1 + label_len > msg.len) return error.InvalidName;
+
+        if (len > 0) {
+            if (len >= out.len) return error.NameTooLong;
+            out[len] = '.';
+            len += 1;
+        }
+        if (len + label_len > out.len) return error.NameTooLong;
+        for (msg[pos + 1 .. pos + 1 + label_len], 0..) |c, i| {
+            out[len + i] = std.ascii.toLower(c);
+        }
+        len += label_len;
+        pos += 1 + label_len;
+    }
+
+    return .{ .text = out[0..len], .end = end.? };
+}
+
+/// Return the offset just past a possibly compressed name
+pub fn skipName(msg: []const u8, start: usize) !usize {
+    var pos = start;
+    while (true) {
+        if (pos >= msg.len) return error.InvalidName;
+        const label_len = msg[pos];
+        if (label_len == 0) return pos + 1;
+        if (label_len & 0xc0 == 0xc0) {
+            if (pos + 1 >= msg.len) return error.InvalidName;
+            return pos + 2;
+        }
+        pos += 1 + @as(usize, label_len);
+    }
+}
+
+/// Parse the first question of a message
+pub fn firstQuestion(msg: []const u8, name_buf: []u8) !Question {
+    const header = try Header.parse(msg);
+    if (header.qdcount == 0) return error.NoQuestion;
+
+    const name = try readName(msg, HEADER_LEN, name_buf);
+    if (name.end + 4 > msg.len) return error.MessageTooShort;
+    return .{
+        .name = name.text,
+        .qtype = std.mem.readInt(u16, msg[name.end..][0..2], .big),
+        .qclass = std.mem.readInt(u16, msg[name.end + 2 ..][0..2], .big),
+        .end = name.end + 4,
+    };
+}
+
+/// Visits every resource record in the answer, authority and additional sections
+pub const RecordIterator = struct {
+    msg: []const u8,
+    pos: usize,
+    remaining: usize,
+    /// Records left before the additional section starts
+    remaining_non_additional: usize,
+
+    pub const Record = struct {
+        /// Offset of the owner name, for `readName`
+        name_offset: usize,
+        rtype: u16,
+        ttl: u32,
+        /// Offset of the TTL field, for in-place rewriting
+        ttl_offset: usize,
+        rdata: []const u8,
+        rdata_offset: usize,
+        additional: bool,
+    };
+
+    pub fn init(msg: []const u8) !RecordIterator {
+        const header = try Header.parse(msg);
+        var pos: usize = HEADER_LEN;
+        var i: usize = 0;
+        while (i < header.qdcount) : (i += 1) {
+            pos = try skipName(msg, pos) + 4;
+        }
+        const non_additional = @as(usize, header.ancount) + header.nscount;
+        return .{
+            .msg = msg,
+            .pos = pos,
+            .remaining = non_additional + header.arcount,
+            .remaining_non_additional = non_additional,
+        };
+    }
+
+    pub fn next(self: *RecordIterator) !?Record {
+        if (self.remaining == 0) return null;
+        const additional = self.remaining_non_additional == 0;
+        self.remaining -= 1;
+        if (!additional) self.remaining_non_additional -= 1;
+
+        const name_offset = self.pos;
+        const pos = try skipName(self.msg, name_offset);
+        if (pos + 10 > self.msg.len) return error.MessageTooShort;
+        const rdlen = std.mem.readInt(u16, self.msg[pos + 8 ..][0..2], .big);
+        const rdata_offset = pos + 10;
+        if (rdata_offset + rdlen > self.msg.len) return error.MessageTooShort;
+        self.pos = rdata_offset + rdlen;
+
+        return .{
+            .name_offset = name_offset,
+            .rtype = std.mem.readInt(u16, self.msg[pos..][0..2], .big),
+            .ttl = std.mem.readInt(u32, self.msg[pos + 4 ..][0..4], .big),
+            .ttl_offset = pos + 4,
+            .rdata = self.msg[rdata_offset..self.pos],
+            .rdata_offset = rdata_offset,
+            .additional = additional,
+        };
+    }
+};
+
+/// A recursive query for `name`, allocated with `allocator`
+pub fn buildQuery(allocator: std.mem.Allocator, name: []const u8, rtype: RecordType) ![]u8 {
+    const trimmed = std.mem.trimRight(u8, name, ".");
+    if (trimmed.len == 0 or trimmed.len > MAX_NAME_LEN) return error.InvalidName;
+
+    var out: std.ArrayList(u8) = .empty;
+    errdefer out.deinit(allocator);
+    var header = [_]u8{0} ** HEADER_LEN;
+    std.mem.writeInt(u16, header[0..2], std.crypto.random.int(u16), .big);
+    // Recursion desired, one question
+    header[2] = 0x01;
+    header[5] = 1;
+    try out.appendSlice(allocator, &header);
+
+    var labels = std.mem.splitScalar(u8, trimmed, '.');
+    while (labels.next()) |label| {
+        if (label.len == 0 or label.len > 63) return error.InvalidName;
+        try out.append(allocator, @intCast(label.len));
+        try out.appendSlice(allocator, label);
+    }
+    try out.append(allocator, 0);
+
+    var question: [4]u8 = undefined;
+    std.mem.writeInt(u16, question[0..2], @intFromEnum(rtype), .big);
+    // Class IN
+    std.mem.writeInt(u16, question[2..4], 1, .big);
+    try out.appendSlice(allocator, &question);
+    return out.toOwnedSlice(allocator);
+}
+
+/// Smallest TTL across answer and authority records, if any
+pub fn minTtl(msg: []const u8) !?u32 {
+    var it = try RecordIterator.init(msg);
+    var min: ?u32 = null;
+    while (try it.next()) |record| {
+        if (record.additional) break;
+        min = if (min) |m| @min(m, record.ttl) else record.ttl;
+    }
+    return min;
+}
+
+/// UDP payload size advertised by the query's EDNS OPT record, if any
+pub fn ednsPayloadSize(msg: []const u8) !?u16 {
+    var it = try RecordIterator.init(msg);
+    while (try it.next()) |record| {
+        if (!record.additional or record.rtype != @intFromEnum(RecordType.OPT)) continue;
+        // OPT carries the size in the CLASS field, just before the TTL
+        return std.mem.readInt(u16, msg[record.ttl_offset - 2 ..][0..2], .big);
+    }
+    return null;
+}
+
+/// Header and first question of a reply to `query`: QR and RA set, the
+/// opcode and RD copied, and every count but the question zeroed
+pub fn replyHead(allocator: std.mem.Allocator, query: []const u8, rcode: u4) !std.ArrayList(u8) {
+    var name_buf: [MAX_NAME_LEN + 1]u8 = undefined;
+    const question = try firstQuestion(query, &name_buf);
+
+    var reply: std.ArrayList(u8) = .empty;
+    errdefer reply.deinit(allocator);
+    try reply.appendSlice(allocator, query[0..question.end]);
+    const flags = std.mem.readInt(u16, reply.items[2..4], .big);
+    std.mem.writeInt(u16, reply.items[2..4], 0x8080 | (flags & 0x7900) | rcode, .big);
+    std.mem.writeInt(u16, reply.items[4..6], 1, .big);
+    @memset(reply.items[6..12], 0);
+    return reply;
+}
+
+/// Reply to `query` with no records and the given response code
+pub fn errorReply(allocator: std.mem.Allocator, query: []const u8, rcode: u4) ![]u8 {
+    var reply = try replyHead(allocator, query, rcode);
+    return reply.toOwnedSlice(allocator);
+}
@@ -0,0 +1,103 @@
+const std = @import("std");
+const config = @import("../config/config.zig");
+const message = @import("message.zig");
+const cache_mod = @import("cache.zig");
+const local_mod = @import("local.zig");
+const socket = @import("../server/socket.zig");
+
+/// Largest UDP response accepted from an upstream resolver
+const MAX_UDP_SIZE = 4096;
+
+/// Forwards wire-format DNS queries to the configured upstream resolvers,
+/// answering from the cache when possible
+pub const Resolver = struct {
+    config: *const config.DnsConfig,
+    cache: cache_mod.DnsCache,
+    /// Answers for vhost hostnames, set up once the upstream groups exist
+    local: ?local_mod.LocalAnswers = null,
+
+    pub fn init(allocator: std.mem.Allocator, cfg: *const config.DnsConfig) Resolver {
+        return .{
+            .config = cfg,
+            .cache = cache_mod.DnsCache.init(allocator, &cfg.cache),
+        };
+    }
+
+    pub fn deinit(self: *Resolver) void {
+        if (self.local) |*local| local.deinit();
+        self.cache.deinit();
+    }
+
+    /// Resolve a wire-format query, trying each upstream in order.
+    /// The returned message is allocated with `allocator`.
+    pub fn resolve(self: *Resolver, allocator: std.mem.Allocator, query: []const u8) ![]u8 {
+        _ = try message.Header.parse(query);
+
+        if (self.local) |*local| {
+            if (try local.answer(allocator, query)) |reply| return reply;
+        }
+        if (try self.cache.lookup(allocator, query)) |cached| return cached;
+
+        for (self.config.upstreams) |upstream| {
+            const addr = std.net.Address.parseIpAndPort(upstream) catch {
+                std.debug.print("✗ Invalid DNS upstream: {s}\n", .{upstream});
+                continue;
+            };
+            const answer = self.exchange(allocator, addr, query) catch |err| {
+                std.debug.print("✗ DNS upstream {s} failed: {any}\n", .{ upstream, err });
+                continue;
+            };
+            self.cache.store(query, answer) catch |err| {
+                std.debug.print("✗ DNS cache store failed: {any}\n", .{err});
+            };
+            return answer;
+        }
+        return error.UpstreamUnavailable;
+    }
+
+    /// Query over UDP, retrying over TCP when the answer is truncated
+    fn exchange(self: *Resolver, allocator: std.mem.Allocator, addr: std.net.Address, query: []const u8) ![]u8 {
+        var buf: [MAX_UDP_SIZE]u8 = undefined;
+        const n = try self.exchangeUdp(addr, query, &buf);
+        const header = try message.Header.parse(buf[0..n]);
+        if (header.isTruncated()) return self.exchangeTcp(allocator, addr, query);
+        return allocator.dupe(u8, buf[0..n]);
+    }
+
+    fn exchangeUdp(self: *Resolver, addr: std.net.Address, query: []const u8, buf: []u8) !usize {
+        const sock = try std.posix.socket(
+            addr.any.family,
+            std.posix.SOCK.DGRAM | std.posix.SOCK.CLOEXEC,
+            std.posix.IPPROTO.UDP,
+        );
+        defer std.posix.close(sock);
+
+        try socket.setTimeouts(sock, self.config.timeout_ms);
+        try std.posix.connect(sock, &addr.any, addr.getOsSockLen());
+        _ = try std.posix.send(sock, query, 0);
+
+        while (true) {
+            const n = try std.posix.recv(sock, buf, 0);
+            // Ignore stray datagrams that don't answer this query
+            if (n >= message.HEADER_LEN and std.mem.eql(u8, buf[0..2], query[0..2])) return n;
+        }
+    }
+
+    fn exchangeTcp(self: *Resolver, allocator: std.mem.Allocator, addr: std.net.Address, query: []const u8) ![]u8 {
+        const stream = try std.net.tcpConnectToAddress(addr);
+        defer stream.close();
+        try socket.setTimeouts(stream.handle, self.config.timeout_ms);
+
+        var len_buf: [2]u8 = undefined;
+        std.mem.writeInt(u16, &len_buf, @intCast(query.len), .big);
+        try stream.writeAll(&len_buf);
+        try stream.writeAll(query);
+
+        try socket.readExact(stream, &len_buf);
+        const len = std.mem.readInt(u16, &len_buf, .big);
+        const answer = try allocator.alloc(u8, len);
+        errdefer allocator.free(answer);
+        try socket.readExact(stream, answer);
+        return answer;
+    }
+};
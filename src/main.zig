@@ -2,6 +2,8 @@ const std = @import("std");
 const cli = @import("cli/commands.zig");
 const config_mod = @import("config/config.zig");
 const server_mod = @import("server/http_server.zig");
+const preflight_mod = @import("server/preflight.zig");
+const replay = @import("cli/replay.zig");
 
 pub fn main() !void {
     var gpa = std.heap.GeneralPurposeAllocator(.{}){};
@@ -17,9 +19,15 @@ pub fn main() !void {
 
     switch (args.command) {
         .serve => {
-            // Load configuration
-            const cfg = try config_mod.Config.loadFromFile(allocator, args.config_path);
-            try cfg.validate();
+            // Load configuration; it lives until the server stops
+            var config_arena = std.heap.ArenaAllocator.init(allocator);
+            defer config_arena.deinit();
+            const cfg = try config_mod.Config.loadFromFile(config_arena.allocator(), args.config_path);
+
+            if (cfg.server.preflight) {
+                var preflight = preflight_mod.Preflight.init(allocator, &cfg);
+                try preflight.run();
+            }
 
             std.debug.print("Loaded configuration:\n", .{});
             std.debug.print("  Listen addresses: {d}\n", .{cfg.server.listen.len});
@@ -27,40 +35,11 @@ pub fn main() !void {
             std.debug.print("  Log level: {s}\n", .{cfg.logging.level});
             std.debug.print("  Log format: {s}\n\n", .{cfg.logging.format});
 
-            // Parse first listen address
+            // Listen addresses are bound by the server; at least one is required
             if (cfg.server.listen.len > 0) {
-                const listen_addr = cfg.server.listen[0];
-                std.debug.print("Parsing address: {s}\n", .{listen_addr});
-
-                // Parse host:port format
-                const colon_idx = std.mem.indexOf(u8, listen_addr, ":") orelse return error.InvalidListenAddress;
-                const host = listen_addr[0..colon_idx];
-                const port_str = listen_addr[colon_idx + 1 ..];
-                const port = try std.fmt.parseInt(u16, port_str, 10);
-
-                const addr = try std.net.Address.parseIp(host, port);
-
-                // Parse upstream address if configured
-                var upstream_addr: ?std.net.Address = null;
-                if (cfg.upstreams.len > 0 and cfg.upstreams[0].servers.len > 0) {
-                    const upstream_url = cfg.upstreams[0].servers[0].host;
-                    std.debug.print("Upstream: {s}\n", .{upstream_url});
-
-                    // Simple parsing for http://host:port format
-                    if (std.mem.startsWith(u8, upstream_url, "http://")) {
-                        const without_proto = upstream_url[7..];
-                        const upstream_colon = std.mem.indexOf(u8, without_proto, ":") orelse return error.InvalidUpstreamAddress;
-                        const upstream_host = without_proto[0..upstream_colon];
-                        const upstream_port_str = without_proto[upstream_colon + 1 ..];
-                        const upstream_port = try std.fmt.parseInt(u16, upstream_port_str, 10);
-
-                        upstream_addr = try std.net.Address.parseIp(upstream_host, upstream_port);
-                        std.debug.print("Parsed upstream: {s}:{}\n", .{ upstream_host, upstream_port });
-                    }
-                }
-
                 // Start HTTP server
-                var http_server = server_mod.HttpServer.init(allocator, addr, upstream_addr);
+                var http_server = try server_mod.HttpServer.init(allocator, &cfg);
+                defer http_server.deinit();
                 try http_server.start();
             } else {
                 std.debug.print("No listen addresses configured\n", .{});
@@ -75,10 +54,12 @@ pub fn main() !void {
             std.debug.print("Next-Gen Web Server & Reverse Proxy\n", .{});
         },
         .test_config => {
-            const cfg = try config_mod.Config.loadFromFile(allocator, args.config_path);
-            try cfg.validate();
+            var config_arena = std.heap.ArenaAllocator.init(allocator);
+            defer config_arena.deinit();
+            _ = try config_mod.Config.loadFromFile(config_arena.allocator(), args.config_path);
             std.debug.print("Configuration is valid!\n", .{});
         },
+        .replay => try replay.run(allocator, &args.replay.?),
         else => {
             std.debug.print("Command not yet implemented: {s}\n", .{@tagName(args.command)});
             return error.NotImplemented;
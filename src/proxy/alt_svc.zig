@@ -0,0 +1,73 @@
+const std = @import("std");
+const config = @import("../config/config.zig");
+
+pub const HEADER = "Alt-Svc";
+
+pub fn validate(cfg: *const config.AltSvcConfig) !void {
+    if (!cfg.enabled or cfg.clear) return;
+    if (cfg.services.len == 0) return error.MissingAltSvcServices;
+    for (cfg.services) |service| {
+        if (service.protocol.len == 0 or service.port == 0) return error.InvalidAltService;
+        for (service.protocol) |c| {
+            if (!std.ascii.isAlphanumeric(c) and c != '-' and c != '.' and c != '_') return error.InvalidAltService;
+        }
+        if (std.mem.indexOfAny(u8, service.host, "\":, ") != null) return error.InvalidAltService;
+    }
+}
+
+/// Alt-Svc values rendered once at startup: the server-wide one and each
+/// vhost override
+pub const AltSvc = struct {
+    allocator: std.mem.Allocator,
+    server: ?[]u8,
+    vhosts: std.AutoHashMapUnmanaged(*const config.VhostConfig, ?[]u8),
+
+    pub fn init(allocator: std.mem.Allocator, cfg: *const config.Config) !AltSvc {
+        var self = AltSvc{
+            .allocator = allocator,
+            .server = null,
+            .vhosts = .empty,
+        };
+        errdefer self.deinit();
+
+        self.server = try render(allocator, &cfg.server.alt_svc);
+        for (cfg.vhosts) |*vhost| {
+            if (vhost.alt_svc) |*vhost_cfg| {
+                try self.vhosts.put(allocator, vhost, try render(allocator, vhost_cfg));
+            }
+        }
+        return self;
+    }
+
+    pub fn deinit(self: *AltSvc) void {
+        if (self.server) |rendered| self.allocator.free(rendered);
+        var it = self.vhosts.valueIterator();
+        while (it.next()) |entry| {
+            if (entry.*) |rendered| self.allocator.free(rendered);
+        }
+        self.vhosts.deinit(self.allocator);
+    }
+
+    /// Value to advertise on responses for `vhost`, if any
+    pub fn value(self: *const AltSvc, vhost: ?*const config.VhostConfig) ?[]const u8 {
+        if (vhost) |v| {
+            if (self.vhosts.get(v)) |override| return override;
+        }
+        return self.server;
+    }
+};
+
+/// `h3=":443"; ma=86400, h3="alt.example.com:8443"; ma=86400`, or null
+/// when the advertisement is disabled
+fn render(allocator: std.mem.Allocator, cfg: *const config.AltSvcConfig) !?[]u8 {
+    if (!cfg.enabled) return null;
+    if (cfg.clear) return try allocator.dupe(u8, "clear");
+
+    var out: std.ArrayList(u8) = .empty;
+    errdefer out.deinit(allocator);
+    for (cfg.services, 0..) |service, i| {
+        if (i > 0) try out.appendSlice(allocator, ", ");
+        try out.print(allocator, "{s}=\"{s}:{d}\"; ma={d}", .{ service.protocol, service.host, service.port, cfg.max_age });
+    }
+    return try out.toOwnedSlice(allocator);
+}
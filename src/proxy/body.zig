@@ -0,0 +1,185 @@
+const std = @import("std");
+const request = @import("../server/request.zig");
+const response = @import("../server/response.zig");
+const multipart = @import("multipart.zig");
+
+/// How a message body is delimited on the wire
+pub const Framing = union(enum) {
+    length: usize,
+    chunked,
+    /// Delimited by the peer closing the connection
+    close,
+};
+
+/// Framing of a response body from its headers
+pub fn responseFraming(res: *const response.ResponseHead) Framing {
+    if (res.header("Transfer-Encoding")) |te| {
+        if (std.ascii.indexOfIgnoreCase(te, "chunked") != null) return .chunked;
+    }
+    if (res.contentLength()) |len| return .{ .length = len };
+    return .close;
+}
+
+/// Framing of a request body; requests without either header have none
+pub fn requestFraming(req: *const request.Request) Framing {
+    if (req.header("Transfer-Encoding")) |te| {
+        if (std.ascii.indexOfIgnoreCase(te, "chunked") != null) return .chunked;
+    }
+    return .{ .length = req.contentLength() orelse 0 };
+}
+
+/// Reads a message body with its transfer coding removed
+pub const BodyReader = struct {
+    stream: std.net.Stream,
+    framing: Framing,
+    /// Body bytes not yet consumed; initially those read along with the head
+    pending: []const u8,
+    buf: [8192]u8 = undefined,
+    /// Bytes left in the body (length framing) or the current chunk
+    remaining: usize,
+    state: State,
+    /// Sees every decoded byte, enforcing the route's multipart limits
+    inspector: ?*multipart.Inspector = null,
+
+    const State = enum { body, chunk_size, chunk_data, chunk_end, trailers, done };
+
+    pub fn init(stream: std.net.Stream, framing: Framing, buffered: []const u8) BodyReader {
+        return .{
+            .stream = stream,
+            .framing = framing,
+            .pending = buffered,
+            .remaining = switch (framing) {
+                .length => |len| len,
+                else => 0,
+            },
+            .state = switch (framing) {
+                .length => |len| if (len == 0) .done else .body,
+                .chunked => .chunk_size,
+                .close => .body,
+            },
+        };
+    }
+
+    /// Read decoded body bytes; returns 0 at the end of the body
+    pub fn read(self: *BodyReader, out: []u8) !usize {
+        const n = try self.readDecoded(out);
+        if (self.inspector) |inspector| try inspector.feed(out[0..n]);
+        return n;
+    }
+
+    fn readDecoded(self: *BodyReader, out: []u8) !usize {
+        while (true) switch (self.state) {
+            .done => return 0,
+            .body => {
+                const want = if (self.framing == .length) @min(out.len, self.remaining) else out.len;
+                const n = try self.rawRead(out[0..want]);
+                if (n == 0) {
+                    if (self.framing == .length) return error.UnexpectedEndOfBody;
+                    self.state = .done;
+                    return 0;
+                }
+                if (self.framing == .length) {
+                    self.remaining -= n;
+                    if (self.remaining == 0) self.state = .done;
+                }
+                return n;
+            },
+            .chunk_size => {
+                var line_buf: [128]u8 = undefined;
+                const line = try self.readLine(&line_buf);
+                // Chunk extensions after ';' are ignored
+                const size = line[0 .. std.mem.indexOfScalar(u8, line, ';') orelse line.len];
+                self.remaining = try parseChunkSize(std.mem.trimRight(u8, size, " \t"));
+                self.state = if (self.remaining == 0) .trailers else .chunk_data;
+            },
+            .chunk_data => {
+                const n = try self.rawRead(out[0..@min(out.len, self.remaining)]);
+                if (n == 0) return error.UnexpectedEndOfBody;
+                self.remaining -= n;
+                if (self.remaining == 0) self.state = .chunk_end;
+                return n;
+            },
+            .chunk_end => {
+                var line_buf: [2]u8 = undefined;
+                if ((try self.readLine(&line_buf)).len != 0) return error.InvalidChunk;
+                self.state = .chunk_size;
+            },
+            .trailers => {
+                // Trailer fields are dropped
+                var line_buf: [8192]u8 = undefined;
+                if ((try self.readLine(&line_buf)).len == 0) self.state = .done;
+            },
+        };
+    }
+
+    fn rawRead(self: *BodyReader, out: []u8) !usize {
+        if (self.pending.len == 0) {
+            const n = try self.stream.read(&self.buf);
+            self.pending = self.buf[0..n];
+        }
+        const n = @min(out.len, self.pending.len);
+        @memcpy(out[0..n], self.pending[0..n]);
+        self.pending = self.pending[n..];
+        return n;
+    }
+
+    fn readLine(self: *BodyReader, line: []u8) ![]const u8 {
+        var len: usize = 0;
+        while (true) {
+            var c: [1]u8 = undefined;
+            if (try self.rawRead(&c) == 0) return error.UnexpectedEndOfBody;
+            if (c[0] == '\n') break;
+            if (len == line.len) return error.LineTooLong;
+            line[len] = c[0];
+            len += 1;
+        }
+        return std.mem.trimRight(u8, line[0..len], "\r");
+    }
+};
+
+/// Writes a body whose final length is unknown up front: chunked for
+/// HTTP/1.1 clients, close-delimited otherwise
+pub const BodyWriter = struct {
+    stream: std.net.Stream,
+    chunked: bool,
+
+    pub fn write(self: *BodyWriter, bytes: []const u8) !void {
+        if (bytes.len == 0) return;
+        if (self.chunked) {
+            var size_buf: [20]u8 = undefined;
+            try self.stream.writeAll(try std.fmt.bufPrint(&size_buf, "{x}\r\n", .{bytes.len}));
+            try self.stream.writeAll(bytes);
+            try self.stream.writeAll("\r\n");
+        } else {
+            try self.stream.writeAll(bytes);
+        }
+    }
+
+    pub fn finish(self: *BodyWriter) !void {
+        if (self.chunked) try self.stream.writeAll("0\r\n\r\n");
+    }
+};
+
+/// A chunk size: hex digits only. std.fmt.parseInt would also take a sign
+/// and underscores, which a next hop may read differently.
+fn parseChunkSize(text: []const u8) !usize {
+    if (text.len == 0) return error.InvalidChunk;
+    var size: usize = 0;
+    for (text) |c| {
+        const digit = std.fmt.charToDigit(c, 16) catch return error.InvalidChunk;
+        size = std.math.mul(usize, size, 16) catch return error.InvalidChunk;
+        size = std.math.add(usize, size, digit) catch return error.InvalidChunk;
+    }
+    return size;
+}
+
+test "chunk sizes are hex digits only" {
+    try std.testing.expectEqual(@as(usize, 0), try parseChunkSize("0"));
+    try std.testing.expectEqual(@as(usize, 0x1a), try parseChunkSize("1A"));
+    try std.testing.expectEqual(@as(usize, 0xff), try parseChunkSize("00ff"));
+
+    const invalid = [_][]const u8{ "", "+1a", "-0", "1_0", "0x10", " 10", "1 0", "g", "1ffffffffffffffff" };
+    for (invalid) |text| {
+        try std.testing.expectError(error.InvalidChunk, parseChunkSize(text));
+    }
+}
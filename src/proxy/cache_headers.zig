@@ -0,0 +1,71 @@
+const std = @import("std");
+const config = @import("../config/config.zig");
+const response = @import("../server/response.zig");
+
+/// Headers cache header rules may change
+const managed = [_][]const u8{ "Cache-Control", "Expires", "Vary", "Surrogate-Control" };
+
+/// Of those, the ones holding comma-separated lists, which may be appended to
+const lists = [_][]const u8{ "Cache-Control", "Vary", "Surrogate-Control" };
+
+pub fn validate(rule: *const config.CacheHeaderRule) !void {
+    if (!isListed(&managed, rule.header)) return error.InvalidCacheHeaderRule;
+    switch (rule.action) {
+        .remove => {},
+        .append => if (!isListed(&lists, rule.header) or rule.value.len == 0) return error.InvalidCacheHeaderRule,
+        .set, .default => if (rule.value.len == 0) return error.InvalidCacheHeaderRule,
+    }
+}
+
+/// Whether the rules replace or remove the upstream's `name` header
+pub fn overrides(rules: []const config.CacheHeaderRule, status: u16, name: []const u8) bool {
+    for (rules) |*rule| {
+        if (applies(rule, status, name) and (rule.action == .set or rule.action == .remove)) return true;
+    }
+    return false;
+}
+
+/// Write the headers the rules add to `res`. Appended values go on a
+/// field line of their own, which is equivalent to extending the list.
+pub fn write(head: *response.HeadWriter, rules: []const config.CacheHeaderRule, res: *const response.ResponseHead) !void {
+    for (managed) |name| {
+        // Rules before the last set or remove are overridden by it
+        var start: usize = 0;
+        var present = res.header(name) != null;
+        for (rules, 0..) |*rule, i| {
+            if (!applies(rule, res.status, name)) continue;
+            if (rule.action == .set or rule.action == .remove) {
+                start = i;
+                present = false;
+            }
+        }
+
+        for (rules[start..]) |*rule| {
+            if (!applies(rule, res.status, name)) continue;
+            switch (rule.action) {
+                .remove => {},
+                .set, .append => {
+                    try head.header(name, rule.value);
+                    present = true;
+                },
+                .default => if (!present) {
+                    try head.header(name, rule.value);
+                    present = true;
+                },
+            }
+        }
+    }
+}
+
+fn applies(rule: *const config.CacheHeaderRule, status: u16, name: []const u8) bool {
+    if (!std.ascii.eqlIgnoreCase(rule.header, name)) return false;
+    if (rule.statuses.len == 0) return true;
+    return std.mem.indexOfScalar(u16, rule.statuses, status) != null;
+}
+
+fn isListed(names: []const []const u8, name: []const u8) bool {
+    for (names) |n| {
+        if (std.ascii.eqlIgnoreCase(n, name)) return true;
+    }
+    return false;
+}
@@ -0,0 +1,105 @@
+const std = @import("std");
+const config = @import("../config/config.zig");
+const request = @import("../server/request.zig");
+const response = @import("../server/response.zig");
+const sub_filter = @import("sub_filter.zig");
+
+const flate = std.compress.flate;
+
+/// Whether a route compresses this response for this client. Partial,
+/// already encoded and no-transform responses are left alone, as are
+/// responses known to be smaller than `min_size`.
+pub fn applies(cfg: *const config.CompressionConfig, req: *const request.Request, res: *const response.ResponseHead) bool {
+    if (!cfg.enabled or res.status != 200 or !res.hasBody(req.method)) return false;
+    if (res.header("Content-Encoding")) |encoding| {
+        if (!std.ascii.eqlIgnoreCase(encoding, "identity")) return false;
+    }
+    if (res.header("Content-Range") != null) return false;
+    if (cfg.respect_no_transform) {
+        if (res.header("Cache-Control")) |cache_control| {
+            if (std.ascii.indexOfIgnoreCase(cache_control, "no-transform") != null) return false;
+        }
+    }
+    if (res.contentLength()) |len| {
+        if (len < cfg.min_size) return false;
+    }
+    const content_type = res.header("Content-Type") orelse return false;
+    if (sub_filter.contentTypeListed(cfg.exclude_types, content_type)) return false;
+    if (!sub_filter.contentTypeListed(cfg.types, content_type)) return false;
+    return acceptsGzip(req.header("Accept-Encoding") orelse return false);
+}
+
+/// Whether an Accept-Encoding value allows gzip, directly or through "*",
+/// with a non-zero quality
+pub fn acceptsGzip(accept_encoding: []const u8) bool {
+    var codings = std.mem.splitScalar(u8, accept_encoding, ',');
+    while (codings.next()) |entry| {
+        var params = std.mem.splitScalar(u8, entry, ';');
+        const coding = std.mem.trim(u8, params.next().?, " \t");
+        if (!std.ascii.eqlIgnoreCase(coding, "gzip") and !std.mem.eql(u8, coding, "*")) continue;
+        while (params.next()) |p| {
+            const param = std.mem.trim(u8, p, " \t");
+            if (!std.ascii.startsWithIgnoreCase(param, "q=")) continue;
+            const q = std.fmt.parseFloat(f32, param[2..]) catch 1;
+            if (q == 0) break;
+        } else return true;
+    }
+    return false;
+}
+
+/// Gzip stage at the end of a response filter chain. Compressed bytes are
+/// passed on to `out` as the compressor emits them.
+pub const Gzip = struct {
+    allocator: std.mem.Allocator,
+    output: std.Io.Writer.Allocating,
+    window: []u8,
+    compress: flate.Compress,
+
+    /// The compressor keeps a pointer to `output`, so the stage lives on
+    /// the heap
+    pub fn create(allocator: std.mem.Allocator, level: config.CompressionLevel) !*Gzip {
+        const self = try allocator.create(Gzip);
+        errdefer allocator.destroy(self);
+        const window = try allocator.alloc(u8, flate.max_window_len);
+        errdefer allocator.free(window);
+
+        self.* = .{
+            .allocator = allocator,
+            .output = .init(allocator),
+            .window = window,
+            .compress = undefined,
+        };
+        self.compress = .init(&self.output.writer, window, .{
+            .container = .gzip,
+            .level = switch (level) {
+                .fast => .fast,
+                .default => .default,
+                .best => .best,
+            },
+        });
+        return self;
+    }
+
+    pub fn destroy(self: *Gzip) void {
+        self.output.deinit();
+        self.allocator.free(self.window);
+        self.allocator.destroy(self);
+    }
+
+    pub fn write(self: *Gzip, bytes: []const u8, out: anytype) !void {
+        try self.compress.writer.writeAll(bytes);
+        try self.drain(out);
+    }
+
+    pub fn finish(self: *Gzip, out: anytype) !void {
+        try self.compress.end();
+        try self.drain(out);
+    }
+
+    fn drain(self: *Gzip, out: anytype) !void {
+        const compressed = self.output.written();
+        if (compressed.len == 0) return;
+        try out.write(compressed);
+        self.output.clearRetainingCapacity();
+    }
+};
@@ -0,0 +1,132 @@
+const std = @import("std");
+const config = @import("../config/config.zig");
+const request = @import("../server/request.zig");
+const response = @import("../server/response.zig");
+
+const Header = request.Header;
+
+/// Per-route CORS policy layer
+pub const Cors = struct {
+    policy: *const config.CorsConfig,
+
+    pub fn init(policy: *const config.CorsConfig) Cors {
+        return .{ .policy = policy };
+    }
+
+    /// Preflight requests are answered locally and never forwarded upstream
+    pub fn isPreflight(self: Cors, req: *const request.Request) bool {
+        _ = self;
+        return std.mem.eql(u8, req.method, "OPTIONS") and
+            req.header("Origin") != null and
+            req.header("Access-Control-Request-Method") != null;
+    }
+
+    pub fn originAllowed(self: Cors, origin: []const u8) bool {
+        for (self.policy.allowed_origins) |pattern| {
+            if (matchWildcard(pattern, origin)) return true;
+        }
+        return false;
+    }
+
+    pub fn methodAllowed(self: Cors, method: []const u8) bool {
+        for (self.policy.allowed_methods) |allowed| {
+            if (std.ascii.eqlIgnoreCase(allowed, method)) return true;
+        }
+        return false;
+    }
+
+    /// Answer a preflight request with 204, or 403 if the policy rejects it
+    pub fn writePreflight(
+        self: Cors,
+        allocator: std.mem.Allocator,
+        stream: std.net.Stream,
+        req: *const request.Request,
+    ) !void {
+        const origin = req.header("Origin").?;
+        const method = req.header("Access-Control-Request-Method").?;
+        if (!self.originAllowed(origin) or !self.methodAllowed(method)) {
+            return response.writeError(stream, 403);
+        }
+
+        var headers: std.ArrayList(Header) = .empty;
+        try self.appendOriginHeaders(allocator, origin, &headers);
+        try headers.append(allocator, .{
+            .name = "Access-Control-Allow-Methods",
+            .value = try std.mem.join(allocator, ", ", self.policy.allowed_methods),
+        });
+
+        const allow_headers: ?[]const u8 = if (self.reflectsRequestHeaders())
+            req.header("Access-Control-Request-Headers")
+        else
+            try std.mem.join(allocator, ", ", self.policy.allowed_headers);
+        if (allow_headers) |value| {
+            if (value.len > 0) {
+                try headers.append(allocator, .{ .name = "Access-Control-Allow-Headers", .value = value });
+            }
+        }
+
+        if (self.policy.max_age > 0) {
+            try headers.append(allocator, .{
+                .name = "Access-Control-Max-Age",
+                .value = try std.fmt.allocPrint(allocator, "{d}", .{self.policy.max_age}),
+            });
+        }
+
+        try response.write(stream, 204, headers.items, "");
+    }
+
+    /// Append headers for an actual (non-preflight) cross-origin response
+    pub fn appendHeaders(
+        self: Cors,
+        allocator: std.mem.Allocator,
+        req: *const request.Request,
+        headers: *std.ArrayList(Header),
+    ) !void {
+        const origin = req.header("Origin") orelse return;
+        if (!self.originAllowed(origin)) return;
+
+        try self.appendOriginHeaders(allocator, origin, headers);
+        if (self.policy.expose_headers.len > 0) {
+            try headers.append(allocator, .{
+                .name = "Access-Control-Expose-Headers",
+                .value = try std.mem.join(allocator, ", ", self.policy.expose_headers),
+            });
+        }
+    }
+
+    fn appendOriginHeaders(
+        self: Cors,
+        allocator: std.mem.Allocator,
+        origin: []const u8,
+        headers: *std.ArrayList(Header),
+    ) !void {
+        // A literal "*" cannot be combined with credentials, so echo the origin instead
+        const any_origin = self.policy.allowed_origins.len == 1 and
+            std.mem.eql(u8, self.policy.allowed_origins[0], "*");
+        if (any_origin and !self.policy.allow_credentials) {
+            try headers.append(allocator, .{ .name = "Access-Control-Allow-Origin", .value = "*" });
+        } else {
+            try headers.append(allocator, .{ .name = "Access-Control-Allow-Origin", .value = origin });
+            try headers.append(allocator, .{ .name = "Vary", .value = "Origin" });
+        }
+        if (self.policy.allow_credentials) {
+            try headers.append(allocator, .{ .name = "Access-Control-Allow-Credentials", .value = "true" });
+        }
+    }
+
+    fn reflectsRequestHeaders(self: Cors) bool {
+        const allowed = self.policy.allowed_headers;
+        return allowed.len == 0 or (allowed.len == 1 and std.mem.eql(u8, allowed[0], "*"));
+    }
+};
+
+/// Match a value against a pattern containing at most one `*` wildcard
+pub fn matchWildcard(pattern: []const u8, value: []const u8) bool {
+    const star = std.mem.indexOfScalar(u8, pattern, '*') orelse
+        return std.ascii.eqlIgnoreCase(pattern, value);
+    const prefix = pattern[0..star];
+    const suffix = pattern[star + 1 ..];
+    return value.len >= prefix.len + suffix.len and
+        std.ascii.startsWithIgnoreCase(value, prefix) and
+        std.ascii.endsWithIgnoreCase(value, suffix);
+}
@@ -1,59 +1,717 @@
 const std = @import("std");
 const zhttp = @import("zhttp");
+const config_mod = @import("../config/config.zig");
+const request = @import("../server/request.zig");
+const response = @import("../server/response.zig");
+const body = @import("body.zig");
+const sub_filter = @import("sub_filter.zig");
+const html_inject = @import("html_inject.zig");
+const request_buffer = @import("request_buffer.zig");
+const multipart = @import("multipart.zig");
+const compression = @import("compression.zig");
+const hedge_mod = @import("hedge.zig");
+const retry_mod = @import("retry.zig");
+const conditional = @import("../server/conditional.zig");
+const cache_headers = @import("cache_headers.zig");
+const header_rules = @import("header_rules.zig");
+const alt_svc = @import("alt_svc.zig");
+const egress = @import("../upstream/egress.zig");
+const signing = @import("../upstream/signing.zig");
+const socket = @import("../server/socket.zig");
+const capture = @import("../server/capture.zig");
+const latency = @import("../upstream/latency.zig");
+const websocket = @import("websocket.zig");
+const stream_mod = @import("../stream/proxy.zig");
+const pool_mod = @import("../upstream/pool.zig");
+
+const Header = request.Header;
+
+/// Hop-by-hop headers that are never forwarded as-is
+const hop_by_hop = [_][]const u8{
+    "Connection",
+    "Keep-Alive",
+    "Proxy-Connection",
+    "TE",
+    "Trailer",
+    "Upgrade",
+};
+
+/// For requests without a route
+const no_header_actions = config_mod.HeaderActionsConfig{};
+
+/// A second server raced against the first attempt once `delay_ms` passes
+pub const Hedge = struct {
+    address: std.net.Address,
+    /// Opens the second connection, outside the pool
+    pool: ?*pool_mod.ConnectionPool = null,
+    delay_ms: u32,
+    window: *hedge_mod.LatencyWindow,
+};
+
+/// Signs each request sent upstream. `payload_hash` is the body's SHA-256
+/// when the body is known before sending.
+const Signing = struct {
+    signer: *signing.Signer,
+    payload_hash: ?[std.crypto.hash.sha2.Sha256.digest_length]u8,
+};
+
+/// What happened to a forwarded request
+pub const Outcome = struct {
+    /// Status relayed to the client
+    status: u16,
+    /// Whether the client connection can carry another request
+    reusable: bool,
+    /// Connections opened to upstream servers
+    attempts: u32,
+    /// Connect time and time to first byte of the attempt that answered
+    timing: latency.Timing = .{},
+    /// The upstream's error response was discarded; nothing has been sent
+    /// to the client yet
+    intercepted: bool = false,
+};
 
 /// HTTP request forwarder
 pub const Forwarder = struct {
     allocator: std.mem.Allocator,
+    config: *const config_mod.Config,
+    /// Upgraded WebSocket connections
+    websocket_stats: stream_mod.StreamStats = .{},
 
-    pub fn init(allocator: std.mem.Allocator) Forwarder {
-        return .{ .allocator = allocator };
+    pub fn init(allocator: std.mem.Allocator, config: *const config_mod.Config) Forwarder {
+        return .{
+            .allocator = allocator,
+            .config = config,
+        };
     }
 
-    /// Forward an HTTP request to an upstream server
+    /// Forward a parsed HTTP request to an upstream server. Bodies and filter
+    /// state held for the request come from `allocator`.
+    /// `buffered` holds everything read from the client so far (head plus any body bytes).
+    /// `extra_headers` are appended to the upstream response before it reaches the client.
+    /// `route` supplies response body filters, if any, `hedge` a second server
+    /// for slow responses and `retry` the servers tried when the first attempt
+    /// fails. `signer` signs each request sent upstream. `pool` supplies
+    /// connections to the group's servers, kept open between requests when
+    /// it is enabled. `response_record` receives the upstream response head
+    /// when the request is being captured. Errors intercepted by the route are
+    /// not relayed; see `Outcome.intercepted`. The connection is only reusable
+    /// with `keep_alive` and a response whose end is known without closing.
     pub fn forward(
         self: *Forwarder,
+        allocator: std.mem.Allocator,
         client_stream: std.net.Stream,
         upstream_addr: std.net.Address,
-    ) !void {
-        _ = self;
-
+        req: *const request.Request,
+        buffered: []const u8,
+        extra_headers: []const Header,
+        route: ?*const config_mod.RouteConfig,
+        hedge: ?Hedge,
+        retry: ?retry_mod.Retry,
+        signer: ?*signing.Signer,
+        pool: ?*pool_mod.ConnectionPool,
+        response_record: ?*capture.ResponseRecord,
+        keep_alive: bool,
+    ) !Outcome {
         // TODO: Implement with zhttp
-        // For MVP, establish connection and forward raw bytes
-
-        // Connect to upstream
-        const upstream_stream = try std.net.tcpConnectToAddress(upstream_addr);
-        defer upstream_stream.close();
 
-        std.debug.print("✓ Connected to upstream {any}\n", .{upstream_addr});
+        // WebSocket handshakes keep the upgrade headers and, once accepted,
+        // turn the connection into a tunnel
+        if (route) |r| {
+            if (r.websocket and websocket.isUpgrade(req)) {
+                const upstream = self.dial(pool, upstream_addr) catch |err| {
+                    _ = self.websocket_stats.upstream_failures.fetchAdd(1, .monotonic);
+                    return err;
+                };
+                const status = websocket.tunnel(client_stream, upstream, req, buffered, r, &self.websocket_stats) catch |err| {
+                    _ = self.websocket_stats.upstream_failures.fetchAdd(1, .monotonic);
+                    return err;
+                };
+                return .{ .status = status, .reusable = false, .attempts = 1 };
+            }
+        }
 
-        // Read request from client
-        var buf: [4096]u8 = undefined;
-        const n = try client_stream.read(&buf);
+        const framing = body.requestFraming(req);
+        const buffer_cfg = if (route) |r| &r.request_buffer else null;
+        if (buffer_cfg) |cfg| {
+            if (cfg.max_body_size > 0 and framing == .length and framing.length > cfg.max_body_size) {
+                return error.BodyTooLarge;
+            }
+        }
+        var body_reader = body.BodyReader.init(client_stream, framing, buffered[req.head_len..]);
+        var inspector: ?multipart.Inspector = null;
+        if (route) |r| {
+            if (r.multipart.enabled()) inspector = try multipart.Inspector.forRequest(&r.multipart, req);
+        }
+        if (inspector) |*i| body_reader.inspector = i;
 
-        if (n == 0) {
-            return error.ClientClosedConnection;
+        // Collect the body first when buffering, so slow uploads don't tie up
+        // the upstream. Otherwise it is streamed, unless it is small enough
+        // to keep for a retry.
+        var buffered_body: ?request_buffer.BufferedBody = null;
+        defer if (buffered_body) |*b| b.deinit();
+        if (route) |r| {
+            if (r.request_buffering and !(framing == .length and framing.length == 0)) {
+                buffered_body = try request_buffer.BufferedBody.read(allocator, &r.request_buffer, &body_reader);
+            } else if (retry) |rt| {
+                if (framing == .length and framing.length > 0 and framing.length <= rt.config.max_buffered_body) {
+                    const in_memory = config_mod.RequestBufferConfig{ .memory_threshold = rt.config.max_buffered_body };
+                    buffered_body = try request_buffer.BufferedBody.read(allocator, &in_memory, &body_reader);
+                }
+            }
         }
 
-        std.debug.print("✓ Received {d} bytes from client\n", .{n});
+        const deadline_ms: ?i64 = if (route) |r|
+            (if (r.deadline.timeout_ms > 0) std.time.milliTimestamp() + r.deadline.timeout_ms else null)
+        else
+            null;
 
-        // Forward request to upstream
-        _ = try upstream_stream.writeAll(buf[0..n]);
+        // A body read from the client as it is sent can't be sent again
+        const replayable = buffered_body != null or (framing == .length and framing.length == 0);
 
-        std.debug.print("✓ Forwarded request to upstream\n", .{});
+        // Only bodies known up front are covered by the signature
+        const sign: ?Signing = if (signer) |s| .{
+            .signer = s,
+            .payload_hash = if (buffered_body) |*b|
+                try b.sha256()
+            else if (framing == .length and framing.length == 0)
+                emptyHash()
+            else
+                null,
+        } else null;
+
+        // Response heads beyond the route's limits are treated as invalid
+        const header_limits = if (route) |r| self.config.headerLimits(r) else config_mod.HeaderLimitsConfig{};
+        const head_size = if (header_limits.max_response_head_size > 0) header_limits.max_response_head_size else request.MAX_HEAD_SIZE;
+        const header_count = if (header_limits.max_response_header_count > 0) header_limits.max_response_header_count else request.MAX_HEADERS;
+
+        var address = upstream_addr;
+        var attempts: u32 = 0;
+        var retries: u32 = 0;
+        var upstream_stream: std.net.Stream = undefined;
+        // Pool the connection came from, which gets it back once the
+        // exchange is over
+        var lease: ?*pool_mod.ConnectionPool = null;
+        var fresh = false;
+        var buf: [request.MAX_HEAD_SIZE]u8 = undefined;
+        var timing = latency.Timing{};
+        var connected_ms: i64 = 0;
+        const n = while (true) {
+            attempts += 1;
+            const connect_started = std.time.milliTimestamp();
+            const attempt_hedge = if (attempts == 1) hedge else null;
+            // The losing side of a hedge is closed, so hedged attempts
+            // don't use the pool
+            const attempt_pool = if (attempt_hedge == null) pool else null;
+            const conn = self.connectUpstream(pool, attempt_pool != null, address, fresh) catch |err| {
+                // The limit is the group's, so no other server has room either
+                if (err == error.UpstreamPoolExhausted) return err;
+                // Nothing reached the upstream, so any request can go elsewhere
+                address = nextServer(retry, address, &retries, deadline_ms) orelse return err;
+                std.debug.print("✗ Upstream unreachable ({any}), retrying on {any}\n", .{ err, address });
+                continue;
+            };
+            upstream_stream = conn.stream;
+            lease = attempt_pool;
+            fresh = false;
+            if (conn.reused) {
+                std.debug.print("✓ Reusing connection to upstream {any}\n", .{address});
+            } else {
+                std.debug.print("✓ Connected to upstream {any}\n", .{address});
+            }
+            timing.connect_ms = elapsedSince(connect_started);
+            connected_ms = std.time.milliTimestamp();
+
+            const attempt_body = if (buffered_body) |*b| b else null;
+            const pooled = if (lease) |p| p.enabled() else false;
+            const got = self.exchange(&upstream_stream, client_stream, req, route, &body_reader, attempt_body, framing == .chunked, attempt_hedge, sign, deadline_ms, pooled, &attempts, buf[0..head_size]) catch |err| {
+                if (lease) |p| p.release(address, upstream_stream, false) else upstream_stream.close();
+                // An idle connection may be closed by the upstream just as
+                // the request goes out, before it could act on it. A slow
+                // upstream is not that.
+                if (conn.reused and replayable and retry_mod.upstreamFailure(err) and err != error.FirstByteTimeout) {
+                    fresh = true;
+                    continue;
+                }
+                // The upstream may have acted on the request, so only resend
+                // what is safe to repeat
+                const resendable = replayable and retry_mod.upstreamFailure(err) and
+                    (if (retry) |r| retry_mod.retryable(req, r.config) else false);
+                if (!resendable) return err;
+                address = nextServer(retry, address, &retries, deadline_ms) orelse return err;
+                std.debug.print("✗ Upstream failed ({any}), retrying on {any}\n", .{ err, address });
+                continue;
+            };
+
+            // Statuses the route retries are treated like a failure after
+            // the request was sent; when no retry is left the response is
+            // relayed as it is
+            if (retry) |r| {
+                if (retry_mod.retriedStatus(r.config, buf[0..got])) |status| {
+                    if (replayable and retry_mod.retryable(req, r.config)) {
+                        if (nextServer(retry, address, &retries, deadline_ms)) |next| {
+                            if (lease) |p| p.release(address, upstream_stream, false) else upstream_stream.close();
+                            std.debug.print("✗ Upstream answered {d}, retrying on {any}\n", .{ status, next });
+                            address = next;
+                            continue;
+                        }
+                    }
+                }
+            }
+            break got;
+        };
+        // Set once the response has been read to its end over a connection
+        // the upstream keeps open
+        var upstream_reusable = false;
+        defer if (lease) |p| p.release(address, upstream_stream, upstream_reusable) else upstream_stream.close();
+        timing.ttfb_ms = elapsedSince(connected_ms);
 
-        // Read response from upstream
-        const response_n = try upstream_stream.read(&buf);
+        var headers: [request.MAX_HEADERS]Header = undefined;
+        const res = try response.parse(buf[0..n], headers[0..header_count]);
+        const upstream_keep_alive = if (lease) |p| p.enabled() and keepsAlive(&res) else false;
+        const response_framing = body.responseFraming(&res);
 
-        if (response_n == 0) {
-            return error.UpstreamClosedConnection;
+        std.debug.print("✓ Received {d} response from upstream\n", .{res.status});
+        if (response_record) |record| try record.set(&res);
+
+        const body_start = buf[res.head_len..n];
+
+        // Bytes beyond this request's body mean the client is pipelining,
+        // which isn't supported; close after this response instead
+        const reusable = keep_alive and body_reader.pending.len == 0;
+
+        if (route) |r| {
+            if (intercepts(r, res.status)) {
+                return .{ .status = res.status, .reusable = reusable, .attempts = attempts, .timing = timing, .intercepted = true };
+            }
+            // The upstream ignored the client's conditional; spare the
+            // client a body it already has
+            if (r.not_modified and conditional.notModified(req, &res)) {
+                try self.writeNotModified(client_stream, &res, extra_headers, r, reusable);
+                return .{ .status = 304, .reusable = reusable, .attempts = attempts, .timing = timing };
+            }
+        }
+
+        // Streamed responses skip every body filter and go out as they arrive
+        const streaming = isEventStream(&res) or (if (route) |r| !r.response_buffering else false);
+        const flush_interval_ms = if (route) |r| r.flush_interval_ms else 0;
+
+        if (route) |r| {
+            const filtered = sub_filter.applies(&r.sub_filter, &res) or html_inject.applies(&r.html_inject, &res) or
+                compression.applies(&r.compression, req, &res);
+            if (res.hasBody(req.method) and filtered and !streaming) {
+                const filtered_reusable = try self.forwardFiltered(allocator, client_stream, upstream_stream, req, &res, body_start, extra_headers, r, reusable);
+                upstream_reusable = upstream_keep_alive and response_framing != .close;
+                return .{ .status = res.status, .reusable = filtered_reusable, .attempts = attempts, .timing = timing };
+            }
         }
 
-        std.debug.print("✓ Received {d} bytes from upstream\n", .{response_n});
+        const delimited = !res.hasBody(req.method) or response_framing != .close;
+
+        // Small events must not wait for Nagle's algorithm
+        if (streaming) try socket.setNoDelay(client_stream.handle);
+
+        // A streamed response may go quiet for long stretches, during which
+        // only watching the client shows it has left
+        var client_watch = ClientWatch{ .client = client_stream, .idle_ms = responseTimeouts(route).idle_ms };
+        const watch = if (streaming) &client_watch else null;
 
         // Forward response to client
-        _ = try client_stream.writeAll(buf[0..response_n]);
+        try self.writeResponseHead(client_stream, &res, extra_headers, route, null, reusable and delimited);
+        if (upstream_keep_alive and res.hasBody(req.method) and response_framing == .chunked) {
+            // The upstream won't close after the body, so its end is found
+            // by reading the chunks
+            try relayChunked(upstream_stream, client_stream, body_start, watch);
+        } else if (res.hasBody(req.method) and streaming and flush_interval_ms > 0) {
+            try client_stream.writeAll(body_start);
+            const remaining: ?usize = if (res.contentLength()) |len| len -| body_start.len else null;
+            try relayCoalesced(upstream_stream, client_stream, remaining, flush_interval_ms, watch);
+        } else if (res.hasBody(req.method)) {
+            try client_stream.writeAll(body_start);
+            if (res.contentLength()) |len| {
+                if (len > body_start.len) {
+                    try self.copyBody(upstream_stream, client_stream, len - body_start.len);
+                }
+            } else {
+                // Chunked or close-delimited: upstream closes after the response
+                try relayUntilClose(upstream_stream, client_stream, watch);
+            }
+        }
 
         std.debug.print("✓ Forwarded response to client\n", .{});
+        upstream_reusable = upstream_keep_alive and delimited;
+        return .{ .status = res.status, .reusable = reusable and delimited, .attempts = attempts, .timing = timing };
+    }
+
+    /// A connection from `pool` when `leased`, otherwise one outside it
+    fn connectUpstream(self: *Forwarder, pool: ?*pool_mod.ConnectionPool, leased: bool, address: std.net.Address, fresh: bool) !pool_mod.Connection {
+        if (pool) |p| {
+            if (leased) return p.acquire(address, fresh);
+        }
+        return .{ .stream = try self.dial(pool, address), .reused = false };
+    }
+
+    /// A new connection to `address`, set up as `pool`'s group asks when
+    /// the request has one
+    fn dial(self: *Forwarder, pool: ?*pool_mod.ConnectionPool, address: std.net.Address) !std.net.Stream {
+        if (pool) |p| return p.dial(address);
+        return egress.connect(&self.config.egress_proxy, address, null);
+    }
+
+    /// Send the request over a connected upstream stream and read the
+    /// response head into `buf`, returning its length. A hedge may replace
+    /// the stream with a second connection.
+    fn exchange(
+        self: *Forwarder,
+        upstream_stream: *std.net.Stream,
+        client_stream: std.net.Stream,
+        req: *const request.Request,
+        route: ?*const config_mod.RouteConfig,
+        body_reader: *body.BodyReader,
+        buffered_body: ?*request_buffer.BufferedBody,
+        chunked: bool,
+        hedge: ?Hedge,
+        sign: ?Signing,
+        deadline_ms: ?i64,
+        keep_alive: bool,
+        attempts: *u32,
+        buf: []u8,
+    ) !usize {
+        const timeouts = responseTimeouts(route);
+        // Stalls in either direction count against the idle timeout; set
+        // every time, as pooled connections serve other routes too
+        try socket.setTimeouts(upstream_stream.handle, timeouts.idle_ms);
+
+        // Forward request head and body to upstream
+        if (buffered_body) |b| {
+            try self.writeRequestHead(upstream_stream.*, req, route, b.len, sign, deadline_ms, keep_alive);
+            try b.writeTo(upstream_stream.*);
+        } else {
+            try self.writeRequestHead(upstream_stream.*, req, route, null, sign, deadline_ms, keep_alive);
+            try streamRequestBody(body_reader, upstream_stream.*, chunked);
+        }
+
+        std.debug.print("✓ Forwarded request to upstream\n", .{});
+
+        // The first-byte timer runs from here, through any hedge race
+        const wait = ResponseWait.init(deadline_ms, timeouts.first_byte_ms);
+        if (hedge) |h| upstream_stream.* = try self.race(upstream_stream.*, client_stream, req, route, h, sign, deadline_ms, &wait, attempts);
+        try awaitResponse(upstream_stream.*, client_stream, &wait);
+
+        // Read response head from upstream
+        return request.readHead(upstream_stream.*, buf) catch |err| switch (err) {
+            error.ConnectionClosed => return error.UpstreamClosedConnection,
+            error.WouldBlock => return error.UpstreamIdleTimeout,
+            else => return err,
+        };
+    }
+
+    /// Relay a response through the route's body filters. The new length
+    /// is unknown, so the body is re-framed for the client.
+    fn forwardFiltered(
+        self: *Forwarder,
+        allocator: std.mem.Allocator,
+        client_stream: std.net.Stream,
+        upstream_stream: std.net.Stream,
+        req: *const request.Request,
+        res: *const response.ResponseHead,
+        body_start: []const u8,
+        extra_headers: []const Header,
+        route: *const config_mod.RouteConfig,
+        keep_alive: bool,
+    ) !bool {
+        const chunked = std.mem.eql(u8, req.version, "HTTP/1.1");
+        var writer = body.BodyWriter{ .stream = client_stream, .chunked = chunked };
+        var reader = body.BodyReader.init(upstream_stream, body.responseFraming(res), body_start);
+        // Close-delimited bodies end the connection
+        const reusable = keep_alive and chunked;
+
+        if (html_inject.isGzip(res)) {
+            try self.forwardGzipHtml(allocator, client_stream, &reader, &writer, res, extra_headers, route, reusable);
+            return reusable;
+        }
+
+        var gzip: ?*compression.Gzip = null;
+        if (compression.applies(&route.compression, req, res)) gzip = try compression.Gzip.create(allocator, route.compression.level);
+        defer if (gzip) |g| g.destroy();
+
+        try self.writeResponseHead(client_stream, res, extra_headers, route, .{ .chunked = chunked, .encoded = gzip != null }, reusable);
+
+        var substitutions: ?sub_filter.SubFilter = null;
+        if (sub_filter.applies(&route.sub_filter, res)) {
+            substitutions = try sub_filter.SubFilter.init(allocator, &route.sub_filter);
+        }
+        defer if (substitutions) |*s| s.deinit();
+
+        var injector: ?html_inject.HtmlInjector = null;
+        if (html_inject.applies(&route.html_inject, res)) {
+            injector = html_inject.HtmlInjector.init(allocator, &route.html_inject);
+        }
+        defer if (injector) |*i| i.deinit();
+
+        var chain = FilterChain{
+            .substitutions = if (substitutions) |*s| s else null,
+            .injector = if (injector) |*i| i else null,
+            .gzip = gzip,
+            .writer = &writer,
+        };
+        var chunk: [8192]u8 = undefined;
+        while (true) {
+            const n = try reader.read(&chunk);
+            if (n == 0) break;
+            try chain.write(chunk[0..n]);
+        }
+        try chain.finish();
+
+        std.debug.print("✓ Forwarded filtered response to client\n", .{});
+        return reusable;
+    }
+
+    /// Compressed HTML is inflated in memory so the fragment can be placed.
+    /// Bodies over the limit are relayed still compressed and unmodified.
+    fn forwardGzipHtml(
+        self: *Forwarder,
+        allocator: std.mem.Allocator,
+        client_stream: std.net.Stream,
+        reader: *body.BodyReader,
+        writer: *body.BodyWriter,
+        res: *const response.ResponseHead,
+        extra_headers: []const Header,
+        route: *const config_mod.RouteConfig,
+        keep_alive: bool,
+    ) !void {
+        const limit = route.html_inject.max_decompress_size;
+
+        var compressed: std.ArrayList(u8) = .empty;
+        defer compressed.deinit(allocator);
+        var chunk: [8192]u8 = undefined;
+        var complete = false;
+        while (compressed.items.len <= limit) {
+            const n = try reader.read(&chunk);
+            if (n == 0) {
+                complete = true;
+                break;
+            }
+            try compressed.appendSlice(allocator, chunk[0..n]);
+        }
+
+        const decoded: ?[]u8 = if (complete)
+            html_inject.gunzip(allocator, compressed.items, limit) catch null
+        else
+            null;
+
+        if (decoded) |html| {
+            defer allocator.free(html);
+            const injected = try html_inject.inject(allocator, &route.html_inject, html);
+            defer allocator.free(injected);
+
+            try self.writeResponseHead(client_stream, res, extra_headers, route, .{ .chunked = writer.chunked, .decoded = true }, keep_alive);
+            try writer.write(injected);
+        } else {
+            try self.writeResponseHead(client_stream, res, extra_headers, route, .{ .chunked = writer.chunked }, keep_alive);
+            try writer.write(compressed.items);
+            while (true) {
+                const n = try reader.read(&chunk);
+                if (n == 0) break;
+                try writer.write(chunk[0..n]);
+            }
+        }
+        try writer.finish();
+    }
+
+    /// Wait for the first attempt to start answering. Past the hedge delay, send
+    /// the same bodyless request to the second server and keep whichever answers
+    /// first; the other connection is closed, abandoning its request. Neither
+    /// wait runs past the deadline or the first-byte timeout, and both end
+    /// when the client hangs up.
+    fn race(
+        self: *Forwarder,
+        first: std.net.Stream,
+        client: std.net.Stream,
+        req: *const request.Request,
+        route: ?*const config_mod.RouteConfig,
+        h: Hedge,
+        sign: ?Signing,
+        deadline_ms: ?i64,
+        wait: *const ResponseWait,
+        attempts: *u32,
+    ) !std.net.Stream {
+        const started = std.time.milliTimestamp();
+        const hedge_at = started + h.delay_ms;
+        const first_until = if (wait.limit_ms) |limit| @min(limit, hedge_at) else hedge_at;
+        if (try awaitReadable(&[_]std.posix.socket_t{first.handle}, client, first_until) != null) {
+            h.window.record(elapsedSince(started));
+            return first;
+        }
+        // A hedge sent now would have no time left to answer
+        if (first_until < hedge_at) return wait.timeout_error;
+
+        const second = self.dial(h.pool, h.address) catch return first;
+        errdefer second.close();
+        try socket.setTimeouts(second.handle, responseTimeouts(route).idle_ms);
+        try self.writeRequestHead(second, req, route, null, sign, deadline_ms, false);
+        attempts.* += 1;
+        _ = h.window.hedged.fetchAdd(1, .monotonic);
+        std.debug.print("✓ Hedged request to {any} after {d}ms\n", .{ h.address, h.delay_ms });
+
+        const hedged_at = std.time.milliTimestamp();
+        const winner = try awaitReadable(&[_]std.posix.socket_t{ first.handle, second.handle }, client, wait.limit_ms) orelse
+            return wait.timeout_error;
+        if (winner == 1) {
+            _ = h.window.hedge_wins.fetchAdd(1, .monotonic);
+            h.window.record(elapsedSince(hedged_at));
+            first.close();
+            return second;
+        }
+        h.window.record(elapsedSince(started));
+        second.close();
+        return first;
+    }
+
+    /// Re-serialize the request head for the upstream. Without `keep_alive`
+    /// the upstream is asked to close the connection after its response.
+    /// `body_length` replaces the client's framing for a buffered body.
+    /// With a deadline, the remaining budget goes in the route's deadline header.
+    /// With signing, the signer's headers replace any the client sent.
+    /// The route's request header rules apply before either.
+    fn writeRequestHead(
+        self: *Forwarder,
+        upstream_stream: std.net.Stream,
+        req: *const request.Request,
+        route: ?*const config_mod.RouteConfig,
+        body_length: ?usize,
+        sign: ?Signing,
+        deadline_ms: ?i64,
+        keep_alive: bool,
+    ) !void {
+        // Body filters need an uncompressed response
+        const identity_only = if (route) |r| r.response_buffering and (r.sub_filter.rules.len > 0 or r.html_inject.fragment.len > 0) else false;
+        const deadline_header = if (route) |r| r.deadline.header else "";
+        const header_actions = if (route) |r| &r.headers.request else &no_header_actions;
+
+        var buf: [request.MAX_HEAD_SIZE]u8 = undefined;
+        var head = response.HeadWriter{ .buf = &buf };
+
+        try head.print("{s} {s} {s}\r\n", .{ req.method, req.target, req.version });
+        for (req.headers) |h| {
+            if (isHopByHop(h.name) or !header_rules.keeps(header_actions, h.name)) continue;
+            // Framing is written from what was parsed below, never copied,
+            // so the upstream can't read the body differently
+            if (std.ascii.eqlIgnoreCase(h.name, "Content-Length") or std.ascii.eqlIgnoreCase(h.name, "Transfer-Encoding")) continue;
+            if (identity_only and std.ascii.eqlIgnoreCase(h.name, "Accept-Encoding")) continue;
+            if (deadline_header.len > 0 and std.ascii.eqlIgnoreCase(h.name, deadline_header)) continue;
+            if (sign) |s| {
+                if (s.signer.replaces(h.name)) continue;
+            }
+            try head.header(h.name, h.value);
+        }
+        if (body_length) |len| {
+            try head.print("Content-Length: {d}\r\n", .{len});
+        } else switch (body.requestFraming(req)) {
+            // Only lenient framing lets other codings through, and they
+            // are the client's to declare
+            .chunked => try head.header("Transfer-Encoding", if (self.config.server.limits.strictness == .lenient)
+                req.header("Transfer-Encoding").?
+            else
+                "chunked"),
+            .length => |len| if (req.header("Content-Length") != null) try head.print("Content-Length: {d}\r\n", .{len}),
+            .close => {},
+        }
+        try header_rules.write(&head, header_actions);
+        if (sign) |s| {
+            var arena = std.heap.ArenaAllocator.init(self.allocator);
+            defer arena.deinit();
+            const signed = try s.signer.sign(arena.allocator(), req.method, req.target, req.header("Host") orelse "", s.payload_hash);
+            for (signed) |h| try head.header(h.name, h.value);
+        }
+        if (deadline_ms) |deadline| {
+            const remaining = deadline - std.time.milliTimestamp();
+            if (remaining <= 0) return error.DeadlineExceeded;
+            if (deadline_header.len > 0) try writeBudget(&head, &route.?.deadline, remaining);
+        }
+        if (!keep_alive) try head.print("Connection: close\r\n", .{});
+        try head.print("\r\n", .{});
+
+        try upstream_stream.writeAll(head.slice());
+    }
+
+    /// `rewrite` is set when the body is re-framed and replaces the upstream
+    /// framing. The route's cache header and response header rules are
+    /// applied here.
+    fn writeResponseHead(
+        self: *Forwarder,
+        client_stream: std.net.Stream,
+        res: *const response.ResponseHead,
+        extra_headers: []const Header,
+        route: ?*const config_mod.RouteConfig,
+        rewrite: ?Rewrite,
+        keep_alive: bool,
+    ) !void {
+        const policy = &self.config.server.response_headers;
+        const rules = if (route) |r| r.cache_headers else &[_]config_mod.CacheHeaderRule{};
+        const header_actions = if (route) |r| &r.headers.response else &no_header_actions;
+        var buf: [request.MAX_HEAD_SIZE]u8 = undefined;
+        var head = response.HeadWriter{ .buf = &buf };
+
+        response.noteStatus(res.status);
+        try head.print("{s} {d} {s}\r\n", .{ res.version, res.status, res.reason });
+        for (res.headers) |h| {
+            if (isHopByHop(h.name) or isListed(policy.hide, h.name)) continue;
+            if (!header_rules.keeps(header_actions, h.name)) continue;
+            if (policy.override_upstream_server and std.ascii.eqlIgnoreCase(h.name, "Server")) continue;
+            if (cache_headers.overrides(rules, res.status, h.name)) continue;
+            // Wraith's own advertisement replaces the upstream's
+            if (std.ascii.eqlIgnoreCase(h.name, alt_svc.HEADER) and hasHeader(extra_headers, alt_svc.HEADER)) continue;
+            if (rewrite) |rw| {
+                if (describesBody(h.name)) continue;
+                if (rw.decoded and std.ascii.eqlIgnoreCase(h.name, "Content-Encoding")) continue;
+                // Ranges of the upstream bytes don't apply to the gzipped body
+                if (rw.encoded and std.ascii.eqlIgnoreCase(h.name, "Accept-Ranges")) continue;
+            }
+            try head.header(h.name, h.value);
+        }
+        if (rewrite) |rw| {
+            if (rw.chunked) try head.header("Transfer-Encoding", "chunked");
+            if (rw.encoded) {
+                try head.header("Content-Encoding", "gzip");
+                try head.header("Vary", "Accept-Encoding");
+            }
+        }
+        if (policy.override_upstream_server and policy.server.len > 0 and header_rules.keeps(header_actions, "Server")) {
+            try head.header("Server", policy.server);
+        }
+        try cache_headers.write(&head, rules, res);
+        for (extra_headers) |h| {
+            if (header_rules.keeps(header_actions, h.name)) try head.header(h.name, h.value);
+        }
+        try header_rules.write(&head, header_actions);
+        try head.header("Connection", if (keep_alive) "keep-alive" else "close");
+        try head.print("\r\n", .{});
+
+        try client_stream.writeAll(head.slice());
+    }
+
+    /// Answer 304 in place of an upstream 200, keeping the headers a 304
+    /// must repeat (RFC 9110 15.4.5)
+    fn writeNotModified(
+        self: *Forwarder,
+        client_stream: std.net.Stream,
+        res: *const response.ResponseHead,
+        extra_headers: []const Header,
+        route: *const config_mod.RouteConfig,
+        keep_alive: bool,
+    ) !void {
+        var kept: [not_modified_headers.len * 2]Header = undefined;
+        var count: usize = 0;
+        for (res.headers) |h| {
+            if (!isListed(&not_modified_headers, h.name) or count == kept.len) continue;
+            kept[count] = h;
+            count += 1;
+        }
+        var not_modified = res.*;
+        not_modified.status = 304;
+        not_modified.reason = "Not Modified";
+        not_modified.headers = kept[0..count];
+        try self.writeResponseHead(client_stream, &not_modified, extra_headers, route, null, keep_alive);
     }
 
     /// Copy headers from request to upstream request
@@ -62,10 +720,9 @@ pub const Forwarder = struct {
         source_headers: []const u8,
         dest_headers: *std.ArrayList(u8),
     ) !void {
-        _ = self;
         // TODO: Parse and copy headers
         // For now, just copy everything
-        try dest_headers.appendSlice(source_headers);
+        try dest_headers.appendSlice(self.allocator, source_headers);
     }
 
     /// Copy body from request to upstream request
@@ -84,10 +741,325 @@ pub const Forwarder = struct {
             const to_read = @min(remaining, buf.len);
             const n = try source_stream.read(buf[0..to_read]);
 
-            if (n == 0) break;
+            if (n == 0) return error.UnexpectedEndOfBody;
 
             _ = try dest_stream.writeAll(buf[0..n]);
             remaining -= n;
         }
     }
 };
+
+/// How a response body is re-framed after filtering
+const Rewrite = struct {
+    /// Chunked for HTTP/1.1 clients, close-delimited otherwise
+    chunked: bool,
+    /// Content coding was removed
+    decoded: bool = false,
+    /// Body is gzipped on the way out
+    encoded: bool = false,
+};
+
+/// Response body filters applied in order: substitutions, HTML injection,
+/// then compression
+const FilterChain = struct {
+    substitutions: ?*sub_filter.SubFilter,
+    injector: ?*html_inject.HtmlInjector,
+    gzip: ?*compression.Gzip,
+    writer: *body.BodyWriter,
+
+    fn write(self: *FilterChain, bytes: []const u8) !void {
+        if (self.substitutions) |s| return s.write(bytes, InjectStage{ .chain = self });
+        return self.writeInjected(bytes);
+    }
+
+    fn writeInjected(self: *FilterChain, bytes: []const u8) !void {
+        if (self.injector) |i| return i.write(bytes, CompressStage{ .chain = self });
+        return self.writeCompressed(bytes);
+    }
+
+    fn writeCompressed(self: *FilterChain, bytes: []const u8) !void {
+        if (self.gzip) |g| return g.write(bytes, self.writer);
+        return self.writer.write(bytes);
+    }
+
+    fn finish(self: *FilterChain) !void {
+        if (self.substitutions) |s| try s.finish(InjectStage{ .chain = self });
+        if (self.injector) |i| try i.finish(CompressStage{ .chain = self });
+        if (self.gzip) |g| try g.finish(self.writer);
+        try self.writer.finish();
+    }
+};
+
+const InjectStage = struct {
+    chain: *FilterChain,
+
+    pub fn write(self: InjectStage, bytes: []const u8) !void {
+        return self.chain.writeInjected(bytes);
+    }
+};
+
+const CompressStage = struct {
+    chain: *FilterChain,
+
+    pub fn write(self: CompressStage, bytes: []const u8) !void {
+        return self.chain.writeCompressed(bytes);
+    }
+};
+
+/// Relay the request body as it arrives. Chunked bodies are decoded and
+/// re-chunked so the upstream sees exactly one well-formed message.
+fn streamRequestBody(reader: *body.BodyReader, upstream_stream: std.net.Stream, chunked: bool) !void {
+    var writer = body.BodyWriter{ .stream = upstream_stream, .chunked = chunked };
+    var chunk: [16 * 1024]u8 = undefined;
+    while (true) {
+        const n = try reader.read(&chunk);
+        if (n == 0) break;
+        try writer.write(chunk[0..n]);
+    }
+    try writer.finish();
+}
+
+/// When waiting for the upstream to start answering gives up: at the
+/// deadline or `first_byte_ms` after the request went out, whichever is
+/// first
+const ResponseWait = struct {
+    limit_ms: ?i64,
+    timeout_error: error{ DeadlineExceeded, FirstByteTimeout } = error.DeadlineExceeded,
+
+    fn init(deadline_ms: ?i64, first_byte_ms: u32) ResponseWait {
+        var wait = ResponseWait{ .limit_ms = deadline_ms };
+        if (first_byte_ms > 0) {
+            const first_byte_limit = std.time.milliTimestamp() + first_byte_ms;
+            if (deadline_ms == null or first_byte_limit < deadline_ms.?) {
+                wait.limit_ms = first_byte_limit;
+                wait.timeout_error = error.FirstByteTimeout;
+            }
+        }
+        return wait;
+    }
+};
+
+/// Wait for the upstream to start answering until `wait` gives up, and
+/// abandon the request when the client hangs up meanwhile so the upstream
+/// sees its connection close.
+fn awaitResponse(upstream: std.net.Stream, client: std.net.Stream, wait: *const ResponseWait) !void {
+    _ = try awaitReadable(&[_]std.posix.socket_t{upstream.handle}, client, wait.limit_ms) orelse return wait.timeout_error;
+}
+
+/// Wait for one of `upstreams` to become readable and return its index, or
+/// null once `until_ms` passes (null = no limit). Fails when the client
+/// hangs up meanwhile.
+fn awaitReadable(upstreams: []const std.posix.socket_t, client: std.net.Stream, until_ms: ?i64) !?usize {
+    var handles: [3]std.posix.socket_t = undefined;
+    @memcpy(handles[0..upstreams.len], upstreams);
+    handles[upstreams.len] = client.handle;
+    var watched = upstreams.len + 1;
+    while (true) {
+        var timeout: u32 = 0;
+        if (until_ms) |until| {
+            const left = until - std.time.milliTimestamp();
+            if (left <= 0) return null;
+            timeout = @intCast(@min(left, std.math.maxInt(u32)));
+        }
+
+        const ready = try socket.firstReadable(handles[0..watched], timeout) orelse return null;
+        if (ready < upstreams.len) return ready;
+        if (socket.peerClosed(client.handle)) return error.ClientDisconnected;
+        // Pipelined bytes: the client is still there
+        watched = upstreams.len;
+    }
+}
+
+fn responseTimeouts(route: ?*const config_mod.RouteConfig) config_mod.ResponseTimeoutConfig {
+    return if (route) |r| r.response_timeout else .{};
+}
+
+/// Remaining deadline budget in the route's header format
+fn writeBudget(head: *response.HeadWriter, cfg: *const config_mod.DeadlineConfig, remaining_ms: i64) !void {
+    switch (cfg.format) {
+        .milliseconds => try head.print("{s}: {d}\r\n", .{ cfg.header, remaining_ms }),
+        .seconds => try head.print("{s}: {d}.{d:0>3}\r\n", .{ cfg.header, @divTrunc(remaining_ms, 1000), @as(u64, @intCast(@mod(remaining_ms, 1000))) }),
+        .grpc => try head.print("{s}: {d}m\r\n", .{ cfg.header, remaining_ms }),
+    }
+}
+
+/// Whether a route replaces this upstream status with its own error handling
+pub fn intercepts(route: *const config_mod.RouteConfig, status: u16) bool {
+    if (!route.intercept_errors) return false;
+    return status >= 500 or (status == 404 and route.error_intercept.not_found);
+}
+
+/// Another server from the retry group, while retries remain and the
+/// group's retry budget allows one. Waits out the backoff delay first.
+fn nextServer(retry: ?retry_mod.Retry, failed: std.net.Address, retries: *u32, deadline_ms: ?i64) ?std.net.Address {
+    const r = retry orelse return null;
+    if (retries.* >= r.config.attempts) return null;
+    const delay_ms = retry_mod.backoff(r.config, retries.*);
+    // A retry starting past the deadline could only time out
+    if (deadline_ms) |deadline| {
+        if (std.time.milliTimestamp() + @as(i64, @intCast(delay_ms)) >= deadline) return null;
+    }
+    const next = r.group.selectOther(failed) orelse return null;
+    if (!r.group.retry_budget.withdraw()) {
+        std.debug.print("✗ Retry budget for upstream {s} spent, not retrying\n", .{r.group.name});
+        return null;
+    }
+    retries.* += 1;
+    if (delay_ms > 0) std.Thread.sleep(delay_ms * std.time.ns_per_ms);
+    return next;
+}
+
+fn elapsedSince(start_ms: i64) u32 {
+    return @intCast(std.math.clamp(std.time.milliTimestamp() - start_ms, 0, std.math.maxInt(u32)));
+}
+
+/// Relay a streamed body, holding bytes for at most `interval_ms` so bursts
+/// of small writes reach the client together. `remaining` bounds a body
+/// with a known length; otherwise the upstream closes after the response.
+fn relayCoalesced(source: std.net.Stream, dest: std.net.Stream, remaining: ?usize, interval_ms: u32, watch: ?*ClientWatch) !void {
+    var buf: [16 * 1024]u8 = undefined;
+    var filled: usize = 0;
+    var left = remaining;
+    var held_since: i64 = 0;
+
+    while (left == null or left.? > 0) {
+        if (filled > 0) {
+            const wait = @as(i64, interval_ms) - (std.time.milliTimestamp() - held_since);
+            if (wait <= 0 or filled == buf.len or !try socket.waitReadable(source.handle, @intCast(wait))) {
+                try dest.writeAll(buf[0..filled]);
+                filled = 0;
+                continue;
+            }
+        }
+
+        if (watch) |w| try w.wait(source);
+        const room = buf.len - filled;
+        const n = try source.read(buf[filled..][0..if (left) |l| @min(l, room) else room]);
+        if (n == 0) {
+            if (left != null) return error.UnexpectedEndOfBody;
+            break;
+        }
+        if (filled == 0) held_since = std.time.milliTimestamp();
+        filled += n;
+        if (left) |*l| l.* -= n;
+    }
+    try dest.writeAll(buf[0..filled]);
+}
+
+/// Notices a client hanging up while the upstream is quiet, which writing
+/// to it would only show once the upstream sends more
+const ClientWatch = struct {
+    client: std.net.Stream,
+    /// The route's idle timeout, which waiting here must keep to
+    idle_ms: u32,
+    /// Cleared once the client sends bytes of its own, which can't be
+    /// told apart from a hangup without reading them
+    active: bool = true,
+
+    /// Wait until `upstream` is readable
+    fn wait(self: *ClientWatch, upstream: std.net.Stream) !void {
+        while (self.active) {
+            const handles = [_]std.posix.socket_t{ upstream.handle, self.client.handle };
+            const ready = try socket.firstReadable(&handles, self.idle_ms) orelse return error.UpstreamIdleTimeout;
+            if (ready == 0) return;
+            if (socket.peerClosed(self.client.handle)) return error.ClientDisconnected;
+            self.active = false;
+        }
+    }
+};
+
+/// Server-Sent Events are always streamed
+fn isEventStream(res: *const response.ResponseHead) bool {
+    const content_type = res.header("Content-Type") orelse return false;
+    return std.ascii.startsWithIgnoreCase(content_type, "text/event-stream");
+}
+
+/// Relay a chunked body up to its last chunk, re-encoding the chunks, so
+/// the source connection is left at the end of the response
+fn relayChunked(source: std.net.Stream, dest: std.net.Stream, body_start: []const u8, watch: ?*ClientWatch) !void {
+    var reader = body.BodyReader.init(source, .chunked, body_start);
+    var writer = body.BodyWriter{ .stream = dest, .chunked = true };
+    var chunk: [16 * 1024]u8 = undefined;
+    while (true) {
+        if (watch) |w| {
+            if (reader.pending.len == 0) try w.wait(source);
+        }
+        const n = try reader.read(&chunk);
+        if (n == 0) break;
+        try writer.write(chunk[0..n]);
+    }
+    try writer.finish();
+}
+
+/// Whether the upstream leaves its connection open after this response
+fn keepsAlive(res: *const response.ResponseHead) bool {
+    if (!std.mem.eql(u8, res.version, "HTTP/1.1")) return false;
+    const connection = res.header("Connection") orelse return true;
+    return std.ascii.indexOfIgnoreCase(connection, "close") == null;
+}
+
+fn relayUntilClose(source: std.net.Stream, dest: std.net.Stream, watch: ?*ClientWatch) !void {
+    var buf: [8192]u8 = undefined;
+    while (true) {
+        if (watch) |w| try w.wait(source);
+        const n = try source.read(&buf);
+        if (n == 0) return;
+        try dest.writeAll(buf[0..n]);
+    }
+}
+
+/// Headers of a 200 that its replacement 304 carries
+const not_modified_headers = [_][]const u8{ "Cache-Control", "Content-Location", "Date", "ETag", "Expires", "Last-Modified", "Vary" };
+
+/// Headers describing the exact bytes of the upstream body
+fn describesBody(name: []const u8) bool {
+    return isListed(&[_][]const u8{ "Content-Length", "Transfer-Encoding", "Content-MD5", "ETag" }, name);
+}
+
+fn isHopByHop(name: []const u8) bool {
+    return isListed(&hop_by_hop, name);
+}
+
+fn hasHeader(headers: []const Header, name: []const u8) bool {
+    for (headers) |h| {
+        if (std.ascii.eqlIgnoreCase(h.name, name)) return true;
+    }
+    return false;
+}
+
+fn isListed(names: []const []const u8, name: []const u8) bool {
+    for (names) |n| {
+        if (std.ascii.eqlIgnoreCase(n, name)) return true;
+    }
+    return false;
+}
+
+fn emptyHash() [std.crypto.hash.sha2.Sha256.digest_length]u8 {
+    var hash: [std.crypto.hash.sha2.Sha256.digest_length]u8 = undefined;
+    std.crypto.hash.sha2.Sha256.hash("", &hash, .{});
+    return hash;
+}
+
+test "coalescing flushes a partial buffer once the interval passes" {
+    const upstream = try socket.streamPair();
+    defer upstream[0].close();
+    defer upstream[1].close();
+    const client = try socket.streamPair();
+    defer client[0].close();
+    defer client[1].close();
+
+    const relay = try std.Thread.spawn(.{}, relayCoalesced, .{ upstream[1], client[0], @as(?usize, 10), @as(u32, 20), @as(?*ClientWatch, null) });
+    defer relay.join();
+    // A failed check must not leave the relay waiting for the rest
+    errdefer std.posix.shutdown(upstream[0].handle, .both) catch {};
+
+    // Far short of the buffer, with the rest of the body still to come
+    try upstream[0].writeAll("hello");
+    try std.testing.expect(try socket.waitReadable(client[1].handle, 2000));
+    var buf: [16]u8 = undefined;
+    try std.testing.expectEqualStrings("hello", buf[0..try client[1].read(&buf)]);
+
+    try upstream[0].writeAll("world");
+    try socket.readExact(client[1], buf[0..5]);
+    try std.testing.expectEqualStrings("world", buf[0..5]);
+}
@@ -0,0 +1,67 @@
+const std = @import("std");
+const config = @import("../config/config.zig");
+const response = @import("../server/response.zig");
+
+/// Headers wraith writes itself from the message framing or the
+/// connection, which rules may not touch
+const reserved = [_][]const u8{
+    "Content-Length",
+    "Transfer-Encoding",
+    "Connection",
+    "Keep-Alive",
+    "Proxy-Connection",
+    "TE",
+    "Trailer",
+    "Upgrade",
+};
+
+pub fn validate(route: *const config.RouteConfig) !void {
+    for ([_]*const config.HeaderActionsConfig{ &route.headers.request, &route.headers.response }) |actions| {
+        for (actions.add) |h| try validateHeader(h.name, h.value);
+        for (actions.set) |h| try validateHeader(h.name, h.value);
+        for (actions.remove) |name| try validateHeader(name, "");
+    }
+    // A second Host header makes the request invalid
+    for (route.headers.request.add) |h| {
+        if (std.ascii.eqlIgnoreCase(h.name, "Host")) return error.InvalidHeaderRule;
+    }
+}
+
+fn validateHeader(name: []const u8, value: []const u8) !void {
+    if (name.len == 0 or isListed(&reserved, name)) return error.InvalidHeaderRule;
+    for (name) |c| {
+        if (!isTokenChar(c)) return error.InvalidHeaderRule;
+    }
+    if (std.mem.indexOfAny(u8, value, "\r\n") != null) return error.InvalidHeaderRule;
+}
+
+/// Whether a header of the message passes through: removed and set
+/// headers are dropped, the set ones to be replaced by `write`
+pub fn keeps(actions: *const config.HeaderActionsConfig, name: []const u8) bool {
+    if (isListed(actions.remove, name)) return false;
+    for (actions.set) |h| {
+        if (std.ascii.eqlIgnoreCase(h.name, name)) return false;
+    }
+    return true;
+}
+
+/// Write the headers the rules set and add
+pub fn write(head: *response.HeadWriter, actions: *const config.HeaderActionsConfig) !void {
+    for (actions.set) |h| try head.header(h.name, h.value);
+    for (actions.add) |h| try head.header(h.name, h.value);
+}
+
+fn isTokenChar(c: u8) bool {
+    return switch (c) {
+        'a'...'z', 'A'...'Z', '0'...'9' => true,
+        '!', '#', '$', '%', '&', '\'', '*', '+', '-', '.', '^', '_', '`', '|', '~' => true,
+        else => false,
+    };
+}
+
+fn isListed(names: []const []const u8, name: []const u8) bool {
+    for (names) |n| {
+        if (std.ascii.eqlIgnoreCase(n, name)) return true;
+    }
+    return false;
+}
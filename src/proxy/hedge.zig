@@ -0,0 +1,87 @@
+const std = @import("std");
+const config = @import("../config/config.zig");
+const request = @import("../server/request.zig");
+
+const WINDOW_SIZE = 256;
+
+/// Recent response times for a route, from which the hedge delay is derived
+pub const LatencyWindow = struct {
+    mutex: std.Thread.Mutex = .{},
+    samples: [WINDOW_SIZE]u32 = undefined,
+    count: usize = 0,
+    next: usize = 0,
+    /// Second attempts sent
+    hedged: std.atomic.Value(u64) = std.atomic.Value(u64).init(0),
+    /// Second attempts that answered first
+    hedge_wins: std.atomic.Value(u64) = std.atomic.Value(u64).init(0),
+
+    /// Record a time to first response byte, in milliseconds
+    pub fn record(self: *LatencyWindow, ms: u32) void {
+        self.mutex.lock();
+        defer self.mutex.unlock();
+
+        self.samples[self.next] = ms;
+        self.next = (self.next + 1) % WINDOW_SIZE;
+        self.count = @min(self.count + 1, WINDOW_SIZE);
+    }
+
+    /// How long to wait before hedging: the configured percentile of recent
+    /// responses, or the initial delay until enough have been seen
+    pub fn delay(self: *LatencyWindow, cfg: *const config.HedgeConfig) u32 {
+        self.mutex.lock();
+        defer self.mutex.unlock();
+
+        if (self.count < @max(cfg.min_samples, 1)) return @max(cfg.initial_delay_ms, cfg.min_delay_ms, 1);
+
+        var sorted: [WINDOW_SIZE]u32 = undefined;
+        @memcpy(sorted[0..self.count], self.samples[0..self.count]);
+        std.mem.sort(u32, sorted[0..self.count], {}, std.sort.asc(u32));
+        const rank: usize = @intFromFloat(@as(f64, @floatFromInt(self.count - 1)) * cfg.percentile / 100.0);
+        return @max(sorted[rank], cfg.min_delay_ms, 1);
+    }
+};
+
+/// Latency windows for every route with hedging enabled
+pub const Hedging = struct {
+    allocator: std.mem.Allocator,
+    windows: std.AutoHashMapUnmanaged(*const config.RouteConfig, *LatencyWindow),
+
+    pub fn init(allocator: std.mem.Allocator, cfg: *const config.Config) !Hedging {
+        var self = Hedging{ .allocator = allocator, .windows = .empty };
+        errdefer self.deinit();
+
+        try self.addRoutes(cfg.routes);
+        for (cfg.vhosts) |vhost| try self.addRoutes(vhost.routes);
+        return self;
+    }
+
+    pub fn deinit(self: *Hedging) void {
+        var it = self.windows.valueIterator();
+        while (it.next()) |window| self.allocator.destroy(window.*);
+        self.windows.deinit(self.allocator);
+    }
+
+    fn addRoutes(self: *Hedging, routes: []const config.RouteConfig) !void {
+        for (routes) |*route| {
+            if (!route.hedge.enabled) continue;
+            const window = try self.allocator.create(LatencyWindow);
+            errdefer self.allocator.destroy(window);
+            window.* = .{};
+            try self.windows.put(self.allocator, route, window);
+        }
+    }
+
+    pub fn get(self: *const Hedging, route: *const config.RouteConfig) ?*LatencyWindow {
+        return self.windows.get(route);
+    }
+};
+
+/// Only requests that are safe to send twice are hedged: idempotent
+/// methods without a body
+pub fn eligible(req: *const request.Request) bool {
+    if (req.hasBody()) return false;
+    for ([_][]const u8{ "GET", "HEAD", "OPTIONS" }) |method| {
+        if (std.mem.eql(u8, req.method, method)) return true;
+    }
+    return false;
+}
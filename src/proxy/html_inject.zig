@@ -0,0 +1,97 @@
+const std = @import("std");
+const config = @import("../config/config.zig");
+const response = @import("../server/response.zig");
+const sub_filter = @import("sub_filter.zig");
+
+const html_types = [_][]const u8{"text/html"};
+
+/// Whether a route's fragment should be injected into this response
+pub fn applies(cfg: *const config.HtmlInjectConfig, res: *const response.ResponseHead) bool {
+    if (cfg.fragment.len == 0) return false;
+    if (res.header("Content-Encoding")) |encoding| {
+        if (!std.ascii.eqlIgnoreCase(encoding, "identity") and !std.ascii.eqlIgnoreCase(encoding, "gzip")) return false;
+    }
+    return sub_filter.contentTypeListed(&html_types, res.header("Content-Type") orelse return false);
+}
+
+pub fn isGzip(res: *const response.ResponseHead) bool {
+    const encoding = res.header("Content-Encoding") orelse return false;
+    return std.ascii.eqlIgnoreCase(encoding, "gzip");
+}
+
+/// Inflate a gzip body, failing when it expands beyond `limit` bytes
+pub fn gunzip(allocator: std.mem.Allocator, compressed: []const u8, limit: usize) ![]u8 {
+    const window = try allocator.alloc(u8, std.compress.flate.max_window_len);
+    defer allocator.free(window);
+
+    var input: std.Io.Reader = .fixed(compressed);
+    var decompress: std.compress.flate.Decompress = .init(&input, .gzip, window);
+    return decompress.reader.allocRemaining(allocator, .limited(limit));
+}
+
+/// Insert a configured fragment before the first closing </head> or
+/// </body> tag of a complete document
+pub fn inject(allocator: std.mem.Allocator, cfg: *const config.HtmlInjectConfig, html: []const u8) ![]u8 {
+    const marker = markerFor(cfg.position);
+    const at = std.ascii.indexOfIgnoreCase(html, marker) orelse return allocator.dupe(u8, html);
+    return std.mem.concat(allocator, u8, &[_][]const u8{ html[0..at], cfg.fragment, html[at..] });
+}
+
+fn markerFor(position: config.HtmlInjectPosition) []const u8 {
+    return switch (position) {
+        .head => "</head>",
+        .body => "</body>",
+    };
+}
+
+/// Streaming variant of `inject`. Only the bytes that could begin the
+/// closing tag are held back between writes.
+pub const HtmlInjector = struct {
+    allocator: std.mem.Allocator,
+    config: *const config.HtmlInjectConfig,
+    marker: []const u8,
+    pending: std.ArrayList(u8),
+    injected: bool,
+
+    pub fn init(allocator: std.mem.Allocator, cfg: *const config.HtmlInjectConfig) HtmlInjector {
+        return .{
+            .allocator = allocator,
+            .config = cfg,
+            .marker = markerFor(cfg.position),
+            .pending = .empty,
+            .injected = false,
+        };
+    }
+
+    pub fn deinit(self: *HtmlInjector) void {
+        self.pending.deinit(self.allocator);
+    }
+
+    pub fn write(self: *HtmlInjector, input: []const u8, out: anytype) !void {
+        if (self.injected) return out.write(input);
+
+        try self.pending.appendSlice(self.allocator, input);
+        const data = self.pending.items;
+
+        if (std.ascii.indexOfIgnoreCase(data, self.marker)) |at| {
+            try out.write(data[0..at]);
+            try out.write(self.config.fragment);
+            try out.write(data[at..]);
+            self.pending.clearRetainingCapacity();
+            self.injected = true;
+            return;
+        }
+
+        // Keep a tail that may be the start of a tag split across writes
+        const keep = @min(data.len, self.marker.len - 1);
+        try out.write(data[0 .. data.len - keep]);
+        std.mem.copyForwards(u8, data[0..keep], data[data.len - keep ..]);
+        self.pending.shrinkRetainingCapacity(keep);
+    }
+
+    /// Flush held-back bytes; documents without the tag are left unchanged
+    pub fn finish(self: *HtmlInjector, out: anytype) !void {
+        try out.write(self.pending.items);
+        self.pending.clearRetainingCapacity();
+    }
+};
@@ -0,0 +1,206 @@
+const std = @import("std");
+const config = @import("../config/config.zig");
+const request = @import("../server/request.zig");
+
+/// Longest boundary RFC 2046 allows
+const MAX_BOUNDARY_LEN = 70;
+
+/// Longest part header line inspected
+const MAX_HEADER_LINE = 4096;
+
+/// Checks a multipart/form-data body against a route's limits as it is
+/// read, without holding on to any of it. Fed the decoded body bytes in
+/// order; fails as soon as a limit is exceeded.
+pub const Inspector = struct {
+    cfg: *const config.MultipartLimitsConfig,
+    /// "\r\n--" followed by the boundary
+    delimiter_buf: [4 + MAX_BOUNDARY_LEN]u8,
+    delimiter_len: usize,
+    /// Delimiter bytes matched so far
+    matched: usize,
+    state: State,
+    parts: usize,
+    /// Bytes seen in the current part, including any partial delimiter match
+    part_size: usize,
+    line: [MAX_HEADER_LINE]u8,
+    line_len: usize,
+
+    const State = enum { preamble, after_delimiter, headers, data, epilogue };
+
+    /// Inspector for the request's body, or null when it isn't multipart
+    /// form data
+    pub fn forRequest(cfg: *const config.MultipartLimitsConfig, req: *const request.Request) !?Inspector {
+        const content_type = req.header("Content-Type") orelse return null;
+        if (!std.ascii.startsWithIgnoreCase(std.mem.trimLeft(u8, content_type, " \t"), "multipart/form-data")) return null;
+        const boundary = param(content_type, "boundary") orelse return error.InvalidMultipart;
+        if (boundary.len == 0 or boundary.len > MAX_BOUNDARY_LEN) return error.InvalidMultipart;
+
+        var self = Inspector{
+            .cfg = cfg,
+            .delimiter_buf = undefined,
+            .delimiter_len = 4 + boundary.len,
+            // The first delimiter has no CRLF in front of it
+            .matched = 2,
+            .state = .preamble,
+            .parts = 0,
+            .part_size = 0,
+            .line = undefined,
+            .line_len = 0,
+        };
+        @memcpy(self.delimiter_buf[0..4], "\r\n--");
+        @memcpy(self.delimiter_buf[4..self.delimiter_len], boundary);
+        return self;
+    }
+
+    pub fn feed(self: *Inspector, bytes: []const u8) !void {
+        for (bytes) |c| try self.step(c);
+    }
+
+    fn step(self: *Inspector, c: u8) !void {
+        switch (self.state) {
+            .preamble, .data => {
+                if (self.state == .data) {
+                    self.part_size += 1;
+                    const content = self.part_size - @min(self.part_size, self.matched + 1);
+                    if (self.cfg.max_part_size > 0 and content > self.cfg.max_part_size) return error.PartTooLarge;
+                }
+                if (self.advance(c)) {
+                    self.state = .after_delimiter;
+                    self.line_len = 0;
+                }
+            },
+            .after_delimiter => {
+                // "--" closes the body; anything else runs to the end of
+                // the line, then the next part's headers follow
+                if (self.line_len < 2) {
+                    self.line[self.line_len] = c;
+                    self.line_len += 1;
+                    if (self.line_len == 2 and std.mem.eql(u8, self.line[0..2], "--")) {
+                        self.state = .epilogue;
+                        return;
+                    }
+                }
+                if (c == '\n') try self.startPart();
+            },
+            .headers => {
+                if (c != '\n') {
+                    if (self.line_len == self.line.len) return error.InvalidMultipart;
+                    self.line[self.line_len] = c;
+                    self.line_len += 1;
+                    return;
+                }
+                const line = std.mem.trimRight(u8, self.line[0..self.line_len], "\r");
+                self.line_len = 0;
+                if (line.len == 0) {
+                    self.state = .data;
+                    self.part_size = 0;
+                    self.matched = 0;
+                } else {
+                    try self.checkHeader(line);
+                }
+            },
+            .epilogue => {},
+        }
+    }
+
+    fn startPart(self: *Inspector) !void {
+        self.parts += 1;
+        if (self.cfg.max_parts > 0 and self.parts > self.cfg.max_parts) return error.TooManyParts;
+        self.state = .headers;
+        self.line_len = 0;
+    }
+
+    /// Match the next byte against the delimiter; true once it is complete.
+    /// Only the delimiter's first byte is a CR, so a mismatch restarts the
+    /// match at that byte or at nothing.
+    fn advance(self: *Inspector, c: u8) bool {
+        const delimiter = self.delimiter_buf[0..self.delimiter_len];
+        if (c == delimiter[self.matched]) {
+            self.matched += 1;
+        } else {
+            self.matched = if (c == delimiter[0]) 1 else 0;
+        }
+        if (self.matched < delimiter.len) return false;
+        self.matched = 0;
+        return true;
+    }
+
+    fn checkHeader(self: *Inspector, line: []const u8) !void {
+        const colon = std.mem.indexOfScalar(u8, line, ':') orelse return error.InvalidMultipart;
+        if (!std.ascii.eqlIgnoreCase(std.mem.trim(u8, line[0..colon], " \t"), "Content-Disposition")) return;
+        const value = line[colon + 1 ..];
+
+        var decoded: [MAX_HEADER_LINE]u8 = undefined;
+        if (param(value, "filename")) |name| try self.checkFilename(unquote(name, &decoded));
+        // RFC 5987 form: charset'language'percent-encoded
+        if (param(value, "filename*")) |ext| {
+            const last_quote = std.mem.lastIndexOfScalar(u8, ext, '\'') orelse return error.InvalidMultipart;
+            try self.checkFilename(try percentDecode(ext[last_quote + 1 ..], &decoded));
+        }
+    }
+
+    fn checkFilename(self: *Inspector, name: []const u8) !void {
+        switch (self.cfg.filenames) {
+            .any => {},
+            .safe => {
+                if (name.len > 0 and name[0] == '.') return error.InvalidFilename;
+                for (name) |c| {
+                    if (c == '/' or c == '\\' or std.ascii.isControl(c)) return error.InvalidFilename;
+                }
+            },
+            .strict => {
+                if (name.len > 0 and name[0] == '.') return error.InvalidFilename;
+                for (name) |c| {
+                    if (!std.ascii.isAlphanumeric(c) and c != ' ' and c != '.' and c != '-' and c != '_') return error.InvalidFilename;
+                }
+            },
+        }
+    }
+};
+
+/// Raw value of a `name=value` parameter in a header value like
+/// `form-data; name="file"; filename="a.txt"`
+fn param(value: []const u8, name: []const u8) ?[]const u8 {
+    var params = std.mem.splitScalar(u8, value, ';');
+    _ = params.next();
+    while (params.next()) |p| {
+        const eq = std.mem.indexOfScalar(u8, p, '=') orelse continue;
+        if (!std.ascii.eqlIgnoreCase(std.mem.trim(u8, p[0..eq], " \t"), name)) continue;
+        return std.mem.trim(u8, p[eq + 1 ..], " \t\"");
+    }
+    return null;
+}
+
+/// Drop the backslashes of quoted-pair escapes
+fn unquote(value: []const u8, out: []u8) []const u8 {
+    var len: usize = 0;
+    var escaped = false;
+    for (value) |c| {
+        if (c == '\\' and !escaped) {
+            escaped = true;
+            continue;
+        }
+        escaped = false;
+        out[len] = c;
+        len += 1;
+    }
+    return out[0..len];
+}
+
+fn percentDecode(value: []const u8, out: []u8) ![]const u8 {
+    var len: usize = 0;
+    var i: usize = 0;
+    while (i < value.len) : (len += 1) {
+        if (value[i] == '%') {
+            if (i + 3 > value.len) return error.InvalidMultipart;
+            const high = std.fmt.charToDigit(value[i + 1], 16) catch return error.InvalidMultipart;
+            const low = std.fmt.charToDigit(value[i + 2], 16) catch return error.InvalidMultipart;
+            out[len] = (high << 4) | low;
+            i += 3;
+        } else {
+            out[len] = value[i];
+            i += 1;
+        }
+    }
+    return out[0..len];
+}
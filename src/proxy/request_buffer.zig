@@ -0,0 +1,93 @@
+const std = @import("std");
+const config = @import("../config/config.zig");
+const body = @import("body.zig");
+
+/// A request body read in full before the upstream is contacted. Bodies
+/// over the memory threshold spill to an unlinked temporary file.
+pub const BufferedBody = struct {
+    allocator: std.mem.Allocator,
+    memory: std.ArrayList(u8),
+    file: ?std.fs.File,
+    len: usize,
+
+    /// Read the rest of the body from `reader`; fails with
+    /// `error.BodyTooLarge` past `max_body_size`
+    pub fn read(allocator: std.mem.Allocator, cfg: *const config.RequestBufferConfig, reader: *body.BodyReader) !BufferedBody {
+        var buffered = BufferedBody{
+            .allocator = allocator,
+            .memory = .empty,
+            .file = null,
+            .len = 0,
+        };
+        errdefer buffered.deinit();
+
+        var chunk: [16 * 1024]u8 = undefined;
+        while (true) {
+            const n = try reader.read(&chunk);
+            if (n == 0) break;
+            buffered.len += n;
+            if (cfg.max_body_size > 0 and buffered.len > cfg.max_body_size) return error.BodyTooLarge;
+
+            if (buffered.file) |file| {
+                try file.writeAll(chunk[0..n]);
+            } else if (buffered.memory.items.len + n > cfg.memory_threshold) {
+                const file = try createTempFile(cfg.temp_dir);
+                buffered.file = file;
+                try file.writeAll(buffered.memory.items);
+                try file.writeAll(chunk[0..n]);
+                buffered.memory.clearAndFree(allocator);
+            } else {
+                try buffered.memory.appendSlice(allocator, chunk[0..n]);
+            }
+        }
+        return buffered;
+    }
+
+    pub fn deinit(self: *BufferedBody) void {
+        self.memory.deinit(self.allocator);
+        if (self.file) |file| file.close();
+    }
+
+    /// SHA-256 of the body, for request signing
+    pub fn sha256(self: *BufferedBody) ![std.crypto.hash.sha2.Sha256.digest_length]u8 {
+        var hasher = std.crypto.hash.sha2.Sha256.init(.{});
+        if (self.file) |file| {
+            var chunk: [16 * 1024]u8 = undefined;
+            var offset: u64 = 0;
+            while (true) {
+                const n = try file.pread(&chunk, offset);
+                if (n == 0) break;
+                hasher.update(chunk[0..n]);
+                offset += n;
+            }
+        } else {
+            hasher.update(self.memory.items);
+        }
+        return hasher.finalResult();
+    }
+
+    pub fn writeTo(self: *BufferedBody, stream: std.net.Stream) !void {
+        const file = self.file orelse return stream.writeAll(self.memory.items);
+
+        try file.seekTo(0);
+        var chunk: [16 * 1024]u8 = undefined;
+        while (true) {
+            const n = try file.read(&chunk);
+            if (n == 0) return;
+            try stream.writeAll(chunk[0..n]);
+        }
+    }
+};
+
+/// Create a temporary file and unlink it right away, so it disappears
+/// when closed even if the process dies
+fn createTempFile(temp_dir: []const u8) !std.fs.File {
+    var dir = try std.fs.cwd().openDir(temp_dir, .{});
+    defer dir.close();
+
+    var name_buf: [32]u8 = undefined;
+    const name = try std.fmt.bufPrint(&name_buf, "wraith-body-{x:0>16}", .{std.crypto.random.int(u64)});
+    const file = try dir.createFile(name, .{ .read = true, .exclusive = true, .mode = 0o600 });
+    dir.deleteFile(name) catch {};
+    return file;
+}
@@ -0,0 +1,60 @@
+const std = @import("std");
+const config = @import("../config/config.zig");
+const request = @import("../server/request.zig");
+const manager = @import("../upstream/manager.zig");
+
+/// Methods that can be repeated without changing the result (RFC 9110 9.2.2)
+const idempotent_methods = [_][]const u8{ "GET", "HEAD", "OPTIONS", "TRACE", "PUT", "DELETE" };
+
+/// Where a failed request may be sent again
+pub const Retry = struct {
+    group: *manager.UpstreamGroup,
+    config: *const config.RetryConfig,
+};
+
+/// Whether the request can be sent to the upstream a second time: its
+/// method is one the route retries, idempotent ones by default. The client
+/// opts a POST or PATCH in with an idempotency key, which tells the
+/// upstream to deduplicate it.
+pub fn retryable(req: *const request.Request, cfg: *const config.RetryConfig) bool {
+    const methods: []const []const u8 = if (cfg.methods.len > 0) cfg.methods else &idempotent_methods;
+    for (methods) |method| {
+        if (std.mem.eql(u8, req.method, method)) return true;
+    }
+    if (cfg.idempotency_key_header.len == 0) return false;
+    const key = req.header(cfg.idempotency_key_header) orelse return false;
+    return key.len > 0;
+}
+
+/// Failures caused by the upstream once the request may have been sent.
+/// Errors from the client side or the route's own limits are final.
+pub fn upstreamFailure(err: anyerror) bool {
+    return switch (err) {
+        error.UpstreamClosedConnection,
+        error.ConnectionResetByPeer,
+        error.BrokenPipe,
+        error.ConnectionTimedOut,
+        error.WouldBlock,
+        error.FirstByteTimeout,
+        error.UpstreamIdleTimeout,
+        => true,
+        else => false,
+    };
+}
+
+/// The status of an upstream response head when the route retries it
+pub fn retriedStatus(cfg: *const config.RetryConfig, head: []const u8) ?u16 {
+    // "HTTP/1.1 503 ..."
+    if (head.len < 12 or !std.mem.startsWith(u8, head, "HTTP/")) return null;
+    const status = std.fmt.parseInt(u16, head[9..12], 10) catch return null;
+    return if (std.mem.indexOfScalar(u16, cfg.statuses, status) != null) status else null;
+}
+
+/// Delay before retry number `n` (from 0): the base delay doubled each
+/// time up to the cap, then somewhere between half of that and all of it
+/// so retries from many requests spread out
+pub fn backoff(cfg: *const config.RetryConfig, n: u32) u64 {
+    if (cfg.backoff_ms == 0) return 0;
+    const ceiling = @min(@as(u64, cfg.backoff_ms) << @intCast(@min(n, 20)), cfg.max_backoff_ms);
+    return std.crypto.random.intRangeAtMost(u64, ceiling / 2, ceiling);
+}
@@ -0,0 +1,94 @@
+const std = @import("std");
+const config = @import("../config/config.zig");
+const regex = @import("../routing/regex.zig");
+
+pub fn validate(route: *const config.RouteConfig) !void {
+    for ([_][]const u8{ route.strip_prefix, route.add_prefix }) |prefix| {
+        if (prefix.len > 0 and prefix[0] != '/') return error.InvalidPathPrefix;
+    }
+    const rule = route.rewrite orelse return;
+    var re = regex.Regex.compile(std.heap.page_allocator, rule.pattern) catch return error.InvalidRewritePattern;
+    defer re.deinit();
+    if (regex.highestReference(rule.replacement) > re.groups) return error.InvalidRewritePattern;
+}
+
+/// Whether requests on `route` have their path changed on the way upstream
+pub fn rewrites(route: *const config.RouteConfig) bool {
+    return route.strip_prefix.len > 0 or route.rewrite != null or route.add_prefix.len > 0;
+}
+
+/// Compiled `rewrite` patterns of every route, so requests don't parse them
+pub const Rewrites = struct {
+    allocator: std.mem.Allocator,
+    patterns: std.AutoHashMapUnmanaged(*const config.RouteConfig, regex.Regex),
+
+    pub fn init(allocator: std.mem.Allocator, cfg: *const config.Config) !Rewrites {
+        var self = Rewrites{ .allocator = allocator, .patterns = .empty };
+        errdefer self.deinit();
+
+        try self.addRoutes(cfg.routes);
+        for (cfg.vhosts) |vhost| try self.addRoutes(vhost.routes);
+        return self;
+    }
+
+    pub fn deinit(self: *Rewrites) void {
+        var it = self.patterns.valueIterator();
+        while (it.next()) |re| re.deinit();
+        self.patterns.deinit(self.allocator);
+    }
+
+    fn addRoutes(self: *Rewrites, routes: []const config.RouteConfig) !void {
+        for (routes) |*route| {
+            const rule = route.rewrite orelse continue;
+            var re = try regex.Regex.compile(self.allocator, rule.pattern);
+            errdefer re.deinit();
+            try self.patterns.put(self.allocator, route, re);
+        }
+    }
+
+    /// The target to send upstream for `target` on `route`: strip_prefix
+    /// comes off, then the rewrite applies to the first match in the path,
+    /// then add_prefix goes in front. The query string is kept. Null when
+    /// the route doesn't rewrite paths.
+    pub fn target(self: *const Rewrites, allocator: std.mem.Allocator, route: *const config.RouteConfig, original: []const u8) !?[]const u8 {
+        if (!rewrites(route)) return null;
+        const query_start = std.mem.indexOfScalar(u8, original, '?') orelse original.len;
+        var path = original[0..query_start];
+
+        // Only whole segments come off, so "/api" leaves "/apiary" alone
+        const strip = std.mem.trimRight(u8, route.strip_prefix, "/");
+        if (strip.len > 0 and std.mem.startsWith(u8, path, strip) and
+            (path.len == strip.len or path[strip.len] == '/'))
+        {
+            path = path[strip.len..];
+        }
+
+        var out: std.Io.Writer.Allocating = .init(allocator);
+        errdefer out.deinit();
+        const writer = &out.writer;
+        try writer.writeAll(std.mem.trimRight(u8, route.add_prefix, "/"));
+
+        var captures: regex.Captures = undefined;
+        const re = self.patterns.getPtr(route);
+        if (re != null and re.?.match(path, &captures)) {
+            const span = captures[0].?;
+            var rewritten: std.Io.Writer.Allocating = .init(allocator);
+            defer rewritten.deinit();
+            try rewritten.writer.writeAll(path[0..span.start]);
+            try regex.expand(&rewritten.writer, route.rewrite.?.replacement, path, &captures);
+            try rewritten.writer.writeAll(path[span.end..]);
+            try writePath(writer, rewritten.written());
+        } else {
+            try writePath(writer, path);
+        }
+        try writer.writeAll(original[query_start..]);
+        return try out.toOwnedSlice();
+    }
+};
+
+/// `path` with a leading slash, which stripping or a rewrite may have
+/// taken off
+fn writePath(writer: *std.Io.Writer, path: []const u8) !void {
+    if (path.len == 0 or path[0] != '/') try writer.writeByte('/');
+    try writer.writeAll(path);
+}
@@ -0,0 +1,92 @@
+const std = @import("std");
+const config = @import("../config/config.zig");
+const response = @import("../server/response.zig");
+
+/// Whether a route's substitutions apply to this response: a textual
+/// content type from the configured list and no content coding
+pub fn applies(cfg: *const config.SubFilterConfig, res: *const response.ResponseHead) bool {
+    if (cfg.rules.len == 0) return false;
+    if (res.header("Content-Encoding")) |encoding| {
+        if (!std.ascii.eqlIgnoreCase(encoding, "identity")) return false;
+    }
+    return contentTypeListed(cfg.types, res.header("Content-Type") orelse return false);
+}
+
+/// Match a Content-Type's media type against a list like "text/html" or "text/*"
+pub fn contentTypeListed(types: []const []const u8, content_type: []const u8) bool {
+    const media_type = std.mem.trim(u8, content_type[0 .. std.mem.indexOfScalar(u8, content_type, ';') orelse content_type.len], " \t");
+    for (types) |t| {
+        if (std.mem.eql(u8, t, "*")) return true;
+        if (std.mem.endsWith(u8, t, "/*")) {
+            if (std.ascii.startsWithIgnoreCase(media_type, t[0 .. t.len - 1])) return true;
+        } else if (std.ascii.eqlIgnoreCase(media_type, t)) {
+            return true;
+        }
+    }
+    return false;
+}
+
+/// Streaming find/replace over a response body. Bytes that could be the
+/// start of a match are held back until the next chunk decides them.
+pub const SubFilter = struct {
+    allocator: std.mem.Allocator,
+    config: *const config.SubFilterConfig,
+    pending: std.ArrayList(u8),
+    /// Rules that have matched, consulted when `once` is set
+    applied: []bool,
+
+    pub fn init(allocator: std.mem.Allocator, cfg: *const config.SubFilterConfig) !SubFilter {
+        const applied = try allocator.alloc(bool, cfg.rules.len);
+        @memset(applied, false);
+        return .{
+            .allocator = allocator,
+            .config = cfg,
+            .pending = .empty,
+            .applied = applied,
+        };
+    }
+
+    pub fn deinit(self: *SubFilter) void {
+        self.pending.deinit(self.allocator);
+        self.allocator.free(self.applied);
+    }
+
+    pub fn write(self: *SubFilter, input: []const u8, out: anytype) !void {
+        try self.pending.appendSlice(self.allocator, input);
+        try self.drain(out, false);
+    }
+
+    /// Flush held-back bytes at the end of the body
+    pub fn finish(self: *SubFilter, out: anytype) !void {
+        try self.drain(out, true);
+    }
+
+    fn drain(self: *SubFilter, out: anytype, final: bool) !void {
+        const data = self.pending.items;
+        var i: usize = 0;
+        var emitted: usize = 0;
+
+        scan: while (i < data.len) {
+            const rest = data[i..];
+            for (self.config.rules, 0..) |rule, r| {
+                if (rule.search.len == 0 or (self.config.once and self.applied[r])) continue;
+                if (rest.len >= rule.search.len) {
+                    if (!std.mem.eql(u8, rest[0..rule.search.len], rule.search)) continue;
+                    try out.write(data[emitted..i]);
+                    try out.write(rule.replace);
+                    self.applied[r] = true;
+                    i += rule.search.len;
+                    emitted = i;
+                    continue :scan;
+                }
+                // A match may continue in the next chunk
+                if (!final and std.mem.startsWith(u8, rule.search, rest)) break :scan;
+            }
+            i += 1;
+        }
+
+        try out.write(data[emitted..i]);
+        std.mem.copyForwards(u8, data[0 .. data.len - i], data[i..]);
+        self.pending.shrinkRetainingCapacity(data.len - i);
+    }
+};
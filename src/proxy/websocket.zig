@@ -0,0 +1,106 @@
+const std = @import("std");
+const config = @import("../config/config.zig");
+const request = @import("../server/request.zig");
+const response = @import("../server/response.zig");
+const stream_mod = @import("../stream/proxy.zig");
+const body = @import("body.zig");
+const header_rules = @import("header_rules.zig");
+
+/// Request headers that belong to the client's connection, not the upstream's
+const connection_headers = [_][]const u8{
+    "Connection",
+    "Keep-Alive",
+    "Proxy-Connection",
+    "TE",
+    "Trailer",
+    "Upgrade",
+    "Content-Length",
+    "Transfer-Encoding",
+};
+
+/// Whether the client asks to switch the connection to WebSocket
+/// (RFC 6455 4.1)
+pub fn isUpgrade(req: *const request.Request) bool {
+    if (!std.mem.eql(u8, req.method, "GET")) return false;
+    const upgrade = req.header("Upgrade") orelse return false;
+    const connection = req.header("Connection") orelse return false;
+    return hasToken(upgrade, "websocket") and hasToken(connection, "upgrade");
+}
+
+/// Whether a comma-separated header value lists `token`
+fn hasToken(list: []const u8, token: []const u8) bool {
+    var items = std.mem.splitScalar(u8, list, ',');
+    while (items.next()) |item| {
+        if (std.ascii.eqlIgnoreCase(std.mem.trim(u8, item, " \t"), token)) return true;
+    }
+    return false;
+}
+
+/// Pass the handshake to the upstream and, once it switches protocols,
+/// relay frames both ways until both sides close or the connection idles
+/// past the route's timeout. A refused handshake is relayed as an ordinary
+/// response. Returns the upstream's status; the client connection can't
+/// be reused either way. Takes `upstream`, a connection to the chosen
+/// server, over and closes it.
+pub fn tunnel(
+    client: std.net.Stream,
+    upstream: std.net.Stream,
+    req: *const request.Request,
+    buffered: []const u8,
+    route: *const config.RouteConfig,
+    stats: *stream_mod.StreamStats,
+) !u16 {
+    defer upstream.close();
+
+    var head_buf: [request.MAX_HEAD_SIZE]u8 = undefined;
+    var head = response.HeadWriter{ .buf = &head_buf };
+    try head.print("{s} {s} HTTP/1.1\r\n", .{ req.method, req.target });
+    for (req.headers) |h| {
+        if (isConnectionHeader(h.name) or !header_rules.keeps(&route.headers.request, h.name)) continue;
+        try head.header(h.name, h.value);
+    }
+    try header_rules.write(&head, &route.headers.request);
+    try head.print("Connection: Upgrade\r\nUpgrade: websocket\r\n\r\n", .{});
+    try upstream.writeAll(head.slice());
+    // Frames the client sent without waiting for the handshake follow it
+    try upstream.writeAll(buffered[req.head_len..]);
+
+    var buf: [request.MAX_HEAD_SIZE]u8 = undefined;
+    const n = request.readHead(upstream, &buf) catch |err| switch (err) {
+        error.ConnectionClosed => return error.UpstreamClosedConnection,
+        else => return err,
+    };
+    var headers: [request.MAX_HEADERS]request.Header = undefined;
+    const res = try response.parse(buf[0..n], &headers);
+
+    if (res.status != 101) {
+        try client.writeAll(buf[0..res.head_len]);
+        if (!res.hasBody(req.method)) return res.status;
+        const framing = body.responseFraming(&res);
+        var reader = body.BodyReader.init(upstream, framing, buf[res.head_len..n]);
+        var writer = body.BodyWriter{ .stream = client, .chunked = framing == .chunked };
+        var chunk: [16 * 1024]u8 = undefined;
+        while (true) {
+            const got = try reader.read(&chunk);
+            if (got == 0) break;
+            try writer.write(chunk[0..got]);
+        }
+        try writer.finish();
+        return res.status;
+    }
+
+    // Any frames read along with the head go out with it
+    try client.writeAll(buf[0..n]);
+    _ = stats.connections_total.fetchAdd(1, .monotonic);
+    _ = stats.connections_active.fetchAdd(1, .monotonic);
+    defer _ = stats.connections_active.fetchSub(1, .monotonic);
+    try stream_mod.relay(client, upstream, route.websocket_idle_timeout_ms, stats);
+    return 101;
+}
+
+fn isConnectionHeader(name: []const u8) bool {
+    for (connection_headers) |header| {
+        if (std.ascii.eqlIgnoreCase(name, header)) return true;
+    }
+    return false;
+}
@@ -5,11 +5,92 @@ const std = @import("std");
 
 // Export main modules
 pub const cli = @import("cli/commands.zig");
+pub const replay = @import("cli/replay.zig");
 pub const config = @import("config/config.zig");
+pub const toml = @import("config/toml.zig");
+pub const secret = @import("config/secret.zig");
+pub const redact = @import("config/redact.zig");
 pub const server = @import("server/http_server.zig");
 pub const signals = @import("server/signals.zig");
 pub const tls = @import("server/tls.zig");
+pub const acme = @import("server/acme.zig");
 pub const proxy = @import("proxy/forwarder.zig");
+pub const request = @import("server/request.zig");
+pub const normalize = @import("server/normalize.zig");
+pub const access_log = @import("server/access_log.zig");
+pub const usage = @import("server/usage.zig");
+pub const scrape = @import("server/scrape.zig");
+pub const history = @import("server/history.zig");
+pub const notify = @import("server/notify.zig");
+pub const events = @import("server/events.zig");
+pub const schedule = @import("server/schedule.zig");
+pub const memory = @import("server/memory.zig");
+pub const response = @import("server/response.zig");
+pub const static = @import("server/static.zig");
+pub const static_io = @import("server/static_io.zig");
+pub const conditional = @import("server/conditional.zig");
+pub const s3_origin = @import("server/s3_origin.zig");
+pub const top_urls = @import("server/top_urls.zig");
+pub const downloads = @import("server/downloads.zig");
+pub const metrics = @import("server/metrics.zig");
+pub const respond = @import("server/respond.zig");
+pub const error_pages = @import("server/error_pages.zig");
+pub const problem = @import("server/problem.zig");
+pub const router = @import("routing/router.zig");
+pub const canonical = @import("routing/canonical.zig");
+pub const ab_test = @import("routing/ab_test.zig");
+pub const regex = @import("routing/regex.zig");
+pub const route_stats = @import("routing/stats.zig");
+pub const cors = @import("proxy/cors.zig");
+pub const body = @import("proxy/body.zig");
+pub const sub_filter = @import("proxy/sub_filter.zig");
+pub const html_inject = @import("proxy/html_inject.zig");
+pub const request_buffer = @import("proxy/request_buffer.zig");
+pub const hedge = @import("proxy/hedge.zig");
+pub const rewrite = @import("proxy/rewrite.zig");
+pub const retry = @import("proxy/retry.zig");
+pub const cache_headers = @import("proxy/cache_headers.zig");
+pub const header_rules = @import("proxy/header_rules.zig");
+pub const alt_svc = @import("proxy/alt_svc.zig");
+pub const multipart = @import("proxy/multipart.zig");
+pub const compression = @import("proxy/compression.zig");
+pub const websocket = @import("proxy/websocket.zig");
+pub const acl = @import("security/acl.zig");
+pub const rate_limit = @import("security/rate_limit.zig");
+pub const real_ip = @import("security/real_ip.zig");
+pub const socket = @import("server/socket.zig");
+pub const connections = @import("server/connections.zig");
+pub const dns = @import("dns/resolver.zig");
+pub const dns_message = @import("dns/message.zig");
+pub const dns_cache = @import("dns/cache.zig");
+pub const doh = @import("dns/doh.zig");
+pub const dot = @import("dns/dot.zig");
+pub const do53 = @import("dns/do53.zig");
+pub const dns_local = @import("dns/local.zig");
+pub const upstream = @import("upstream/manager.zig");
+pub const health = @import("upstream/health.zig");
+pub const concurrency = @import("upstream/concurrency.zig");
+pub const egress = @import("upstream/egress.zig");
+pub const signing = @import("upstream/signing.zig");
+pub const cluster = @import("upstream/cluster.zig");
+pub const kubernetes = @import("upstream/kubernetes.zig");
+pub const dns_discovery = @import("upstream/dns_discovery.zig");
+pub const docker = @import("upstream/docker.zig");
+pub const upstream_latency = @import("upstream/latency.zig");
+pub const pool = @import("upstream/pool.zig");
+pub const retry_budget = @import("upstream/retry_budget.zig");
+pub const tls_client = @import("upstream/tls_client.zig");
+pub const stream = @import("stream/proxy.zig");
+pub const demux = @import("server/demux.zig");
+pub const admin = @import("server/admin.zig");
+pub const admin_guard = @import("server/admin_guard.zig");
+pub const admin_auth = @import("server/admin_auth.zig");
+pub const capture = @import("server/capture.zig");
+pub const faults = @import("server/faults.zig");
+pub const probes = @import("server/probes.zig");
+pub const preflight = @import("server/preflight.zig");
+pub const client_hello = @import("server/client_hello.zig");
+pub const fingerprint = @import("server/fingerprint.zig");
 
 test {
     std.testing.refAllDecls(@This());
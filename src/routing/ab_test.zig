@@ -0,0 +1,93 @@
+const std = @import("std");
+const config = @import("../config/config.zig");
+const request = @import("../server/request.zig");
+
+/// Counters for one experiment variant
+pub const VariantStats = struct {
+    requests: std.atomic.Value(u64) = std.atomic.Value(u64).init(0),
+    /// New visitors assigned to the variant
+    assignments: std.atomic.Value(u64) = std.atomic.Value(u64).init(0),
+    upstream_failures: std.atomic.Value(u64) = std.atomic.Value(u64).init(0),
+};
+
+/// Per-variant counters for every route running an A/B test
+pub const Experiments = struct {
+    allocator: std.mem.Allocator,
+    stats: std.AutoHashMapUnmanaged(*const config.RouteConfig, []VariantStats),
+
+    pub fn init(allocator: std.mem.Allocator, cfg: *const config.Config) !Experiments {
+        var self = Experiments{ .allocator = allocator, .stats = .empty };
+        errdefer self.deinit();
+
+        try self.addRoutes(cfg.routes);
+        for (cfg.vhosts) |vhost| try self.addRoutes(vhost.routes);
+        return self;
+    }
+
+    pub fn deinit(self: *Experiments) void {
+        var it = self.stats.valueIterator();
+        while (it.next()) |variants| self.allocator.free(variants.*);
+        self.stats.deinit(self.allocator);
+    }
+
+    fn addRoutes(self: *Experiments, routes: []const config.RouteConfig) !void {
+        for (routes) |*route| {
+            const count = route.ab_test.variants.len;
+            if (count == 0) continue;
+            const variants = try self.allocator.alloc(VariantStats, count);
+            errdefer self.allocator.free(variants);
+            @memset(variants, .{});
+            try self.stats.put(self.allocator, route, variants);
+        }
+    }
+
+    pub fn get(self: *const Experiments, route: *const config.RouteConfig, variant: usize) ?*VariantStats {
+        const variants = self.stats.get(route) orelse return null;
+        return &variants[variant];
+    }
+};
+
+pub const Assignment = struct {
+    /// Index into the route's variants
+    index: usize,
+    variant: *const config.VariantConfig,
+    /// No valid variant cookie was sent, so one must be set
+    new: bool,
+};
+
+/// Variant named by the request's cookie, or a weighted random pick for a new visitor
+pub fn assign(cfg: *const config.AbTestConfig, req: *const request.Request) ?Assignment {
+    if (cfg.variants.len == 0) return null;
+
+    if (req.header("Cookie")) |cookies| {
+        if (cookieValue(cookies, cfg.cookie)) |name| {
+            for (cfg.variants, 0..) |*variant, i| {
+                if (std.mem.eql(u8, variant.name, name)) return .{ .index = i, .variant = variant, .new = false };
+            }
+        }
+    }
+
+    var pick = std.crypto.random.uintLessThan(u32, 100);
+    for (cfg.variants, 0..) |*variant, i| {
+        if (pick < variant.percent) return .{ .index = i, .variant = variant, .new = true };
+        pick -= variant.percent;
+    }
+    // Percentages are validated to add up to 100
+    unreachable;
+}
+
+/// Set-Cookie value pinning a visitor to a variant
+pub fn setCookie(allocator: std.mem.Allocator, cfg: *const config.AbTestConfig, variant: *const config.VariantConfig) ![]const u8 {
+    return std.fmt.allocPrint(allocator, "{s}={s}; Path=/; Max-Age={d}; SameSite=Lax", .{ cfg.cookie, variant.name, cfg.cookie_max_age });
+}
+
+/// Value of a cookie in a Cookie header
+pub fn cookieValue(cookies: []const u8, name: []const u8) ?[]const u8 {
+    var pairs = std.mem.splitScalar(u8, cookies, ';');
+    while (pairs.next()) |pair| {
+        const trimmed = std.mem.trim(u8, pair, " \t");
+        const eq = std.mem.indexOfScalar(u8, trimmed, '=') orelse continue;
+        if (std.mem.eql(u8, trimmed[0..eq], name)) return std.mem.trim(u8, trimmed[eq + 1 ..], "\"");
+    }
+    return null;
+}
@@ -0,0 +1,55 @@
+const std = @import("std");
+const config = @import("../config/config.zig");
+const request = @import("../server/request.zig");
+
+/// HTTP-01 challenges must be answered over plain HTTP on the name being
+/// validated, so they are never redirected
+const ACME_CHALLENGE_PREFIX = "/.well-known/acme-challenge/";
+
+/// Where to redirect a request so it lands on the vhost's canonical URL,
+/// or null when it is already there. `secure` says whether the client
+/// reached wraith over HTTPS.
+pub fn location(
+    allocator: std.mem.Allocator,
+    cfg: *const config.CanonicalConfig,
+    req: *const request.Request,
+    secure: bool,
+) !?[]const u8 {
+    if (cfg.host == .keep and !cfg.https) return null;
+    if (std.mem.startsWith(u8, req.path(), ACME_CHALLENGE_PREFIX)) return null;
+
+    const host = req.host();
+    if (host.len == 0) return null;
+    // Port as the client sent it, kept unless the scheme changes
+    const raw_host = req.header("Host") orelse "";
+    const port = raw_host[@min(host.len, raw_host.len)..];
+
+    const canonical_host = canonicalHost(cfg.host, host);
+    const upgrade = cfg.https and !secure;
+    if (!upgrade and canonical_host.prefix.len == 0 and canonical_host.name.len == host.len) return null;
+
+    const target = if (std.mem.startsWith(u8, req.target, "/")) req.target else "/";
+    if (upgrade or secure) {
+        var port_buf: [8]u8 = undefined;
+        const https_port = if (!upgrade) port else if (cfg.https_port == 443) "" else try std.fmt.bufPrint(&port_buf, ":{d}", .{cfg.https_port});
+        return try std.fmt.allocPrint(allocator, "https://{s}{s}{s}{s}", .{ canonical_host.prefix, canonical_host.name, https_port, target });
+    }
+    return try std.fmt.allocPrint(allocator, "http://{s}{s}{s}{s}", .{ canonical_host.prefix, canonical_host.name, port, target });
+}
+
+const CanonicalName = struct {
+    /// "www." when it has to be added
+    prefix: []const u8 = "",
+    name: []const u8,
+};
+
+fn canonicalHost(rule: config.CanonicalHost, host: []const u8) CanonicalName {
+    // Addresses have no www form
+    if (host[0] == '[' or (std.net.Address.parseIp(host, 0) catch null) != null) return .{ .name = host };
+    const has_www = std.ascii.startsWithIgnoreCase(host, "www.");
+    return switch (rule) {
+        .keep => .{ .name = host },
+        .apex => .{ .name = if (has_www) host["www.".len..] else host },
+        .www => if (has_www) .{ .name = host } else .{ .prefix = "www.", .name = host },
+    };
+}
@@ -0,0 +1,374 @@
+const std = @import("std");
+
+/// Capture groups a pattern may have, so replacements can name them as $1-$9
+pub const MAX_GROUPS = 9;
+
+/// Repetition counts beyond this in {n,m} are refused
+const MAX_REPEAT = 1000;
+
+/// Matching gives up after this many steps, so a pathological pattern
+/// can't stall a request
+const MAX_STEPS = 100_000;
+
+/// Bytes matched by the whole pattern (index 0) or by a capture group
+pub const Span = struct {
+    start: usize,
+    end: usize,
+};
+
+pub const Captures = [MAX_GROUPS + 1]?Span;
+
+const Set = std.StaticBitSet(256);
+
+const Atom = union(enum) {
+    /// One byte from the set; literals, "." and classes alike
+    set: Set,
+    start,
+    end,
+    group: struct {
+        /// Null for (?:...)
+        capture: ?usize,
+        alternatives: []const []const Term,
+    },
+};
+
+const Term = struct {
+    atom: Atom,
+    min: u32 = 1,
+    max: u32 = 1,
+    greedy: bool = true,
+};
+
+/// A small backtracking regular expression for request paths. Supports
+/// literals, ".", classes such as [a-z0-9_] and [^/], the escapes \d \w \s
+/// and their negations, anchors, (capturing) and (?:non-capturing) groups,
+/// alternation, and the quantifiers * + ? {n} {n,} {n,m}, with a trailing
+/// ? for lazy matching.
+pub const Regex = struct {
+    arena: std.heap.ArenaAllocator,
+    alternatives: []const []const Term,
+    groups: usize,
+
+    pub fn compile(allocator: std.mem.Allocator, pattern: []const u8) !Regex {
+        var arena = std.heap.ArenaAllocator.init(allocator);
+        errdefer arena.deinit();
+        var parser = Parser{ .allocator = arena.allocator(), .pattern = pattern };
+        const alternatives = try parser.alternation();
+        if (parser.pos < pattern.len) return error.InvalidRegex;
+        return .{ .arena = arena, .alternatives = alternatives, .groups = parser.groups };
+    }
+
+    pub fn deinit(self: *Regex) void {
+        self.arena.deinit();
+    }
+
+    /// Find the leftmost match in `input`, filling `captures` with where
+    /// the match and each group were. Groups that took no part are null.
+    pub fn match(self: *const Regex, input: []const u8, captures: *Captures) bool {
+        var m = Matcher{ .input = input, .captures = captures };
+        for (0..input.len + 1) |start| {
+            captures.* = [_]?Span{null} ** (MAX_GROUPS + 1);
+            for (self.alternatives) |terms| {
+                if (m.sequence(terms, start, null)) {
+                    captures[0] = .{ .start = start, .end = m.end };
+                    return true;
+                }
+                if (m.steps > MAX_STEPS) return false;
+            }
+        }
+        return false;
+    }
+};
+
+/// Write `replacement` with $0-$9 replaced by what the match and its
+/// groups captured in `input`; "$$" is a literal "$"
+pub fn expand(writer: *std.Io.Writer, replacement: []const u8, input: []const u8, captures: *const Captures) !void {
+    var i: usize = 0;
+    while (i < replacement.len) : (i += 1) {
+        const c = replacement[i];
+        if (c != '$' or i + 1 == replacement.len) {
+            try writer.writeByte(c);
+            continue;
+        }
+        const next = replacement[i + 1];
+        if (next == '$') {
+            try writer.writeByte('$');
+        } else if (std.ascii.isDigit(next)) {
+            if (captures[next - '0']) |span| try writer.writeAll(input[span.start..span.end]);
+        } else {
+            try writer.writeByte(c);
+            continue;
+        }
+        i += 1;
+    }
+}
+
+/// Highest group a replacement refers to, for checking it against the
+/// pattern
+pub fn highestReference(replacement: []const u8) usize {
+    var highest: usize = 0;
+    var i: usize = 0;
+    while (i + 1 < replacement.len) : (i += 1) {
+        if (replacement[i] != '$') continue;
+        const next = replacement[i + 1];
+        if (std.ascii.isDigit(next)) highest = @max(highest, next - '0');
+        i += 1;
+    }
+    return highest;
+}
+
+const Parser = struct {
+    allocator: std.mem.Allocator,
+    pattern: []const u8,
+    pos: usize = 0,
+    groups: usize = 0,
+
+    fn peek(self: *const Parser) ?u8 {
+        return if (self.pos < self.pattern.len) self.pattern[self.pos] else null;
+    }
+
+    fn alternation(self: *Parser) error{ InvalidRegex, OutOfMemory }![]const []const Term {
+        var alternatives: std.ArrayList([]const Term) = .empty;
+        try alternatives.append(self.allocator, try self.sequence());
+        while (self.peek() == '|') {
+            self.pos += 1;
+            try alternatives.append(self.allocator, try self.sequence());
+        }
+        return alternatives.toOwnedSlice(self.allocator);
+    }
+
+    fn sequence(self: *Parser) ![]const Term {
+        var terms: std.ArrayList(Term) = .empty;
+        while (self.peek()) |c| {
+            if (c == '|' or c == ')') break;
+            var term = Term{ .atom = try self.atom() };
+            try self.quantifier(&term);
+            try terms.append(self.allocator, term);
+        }
+        return terms.toOwnedSlice(self.allocator);
+    }
+
+    fn atom(self: *Parser) !Atom {
+        const c = self.pattern[self.pos];
+        self.pos += 1;
+        switch (c) {
+            '^' => return .start,
+            '$' => return .end,
+            '.' => return .{ .set = Set.initFull() },
+            '[' => return .{ .set = try self.class() },
+            '\\' => return .{ .set = try self.escape() },
+            '(' => {
+                var capture: ?usize = null;
+                if (std.mem.startsWith(u8, self.pattern[self.pos..], "?:")) {
+                    self.pos += 2;
+                } else {
+                    if (self.groups == MAX_GROUPS) return error.InvalidRegex;
+                    self.groups += 1;
+                    capture = self.groups;
+                }
+                const alternatives = try self.alternation();
+                if (self.peek() != ')') return error.InvalidRegex;
+                self.pos += 1;
+                return .{ .group = .{ .capture = capture, .alternatives = alternatives } };
+            },
+            '*', '+', '?', '{', ')' => return error.InvalidRegex,
+            else => return .{ .set = single(c) },
+        }
+    }
+
+    fn quantifier(self: *Parser, term: *Term) !void {
+        const c = self.peek() orelse return;
+        switch (c) {
+            '*' => {
+                term.min = 0;
+                term.max = std.math.maxInt(u32);
+            },
+            '+' => term.max = std.math.maxInt(u32),
+            '?' => term.min = 0,
+            '{' => {
+                const close = std.mem.indexOfScalarPos(u8, self.pattern, self.pos, '}') orelse return error.InvalidRegex;
+                const bounds = self.pattern[self.pos + 1 .. close];
+                const comma = std.mem.indexOfScalar(u8, bounds, ',');
+                const min = std.fmt.parseInt(u32, bounds[0 .. comma orelse bounds.len], 10) catch return error.InvalidRegex;
+                const max = if (comma) |i|
+                    (if (i + 1 == bounds.len) std.math.maxInt(u32) else std.fmt.parseInt(u32, bounds[i + 1 ..], 10) catch return error.InvalidRegex)
+                else
+                    min;
+                if (min > max or min > MAX_REPEAT or (max != std.math.maxInt(u32) and max > MAX_REPEAT)) return error.InvalidRegex;
+                term.min = min;
+                term.max = max;
+                self.pos = close;
+            },
+            else => return,
+        }
+        self.pos += 1;
+        // Anchors match no bytes, so repeating them means nothing
+        if (term.atom == .start or term.atom == .end) return error.InvalidRegex;
+        if (self.peek() == '?') {
+            term.greedy = false;
+            self.pos += 1;
+        }
+        if (self.peek()) |next| {
+            if (next == '*' or next == '+' or next == '?' or next == '{') return error.InvalidRegex;
+        }
+    }
+
+    /// After the "[", up to and including the "]"
+    fn class(self: *Parser) !Set {
+        var set = Set.initEmpty();
+        const negated = self.peek() == '^';
+        if (negated) self.pos += 1;
+        var first = true;
+        while (true) : (first = false) {
+            const c = self.peek() orelse return error.InvalidRegex;
+            if (c == ']' and !first) break;
+            self.pos += 1;
+            if (c == '\\') {
+                set.setUnion(try self.escape());
+                continue;
+            }
+            if (self.peek() == '-' and self.pos + 1 < self.pattern.len and self.pattern[self.pos + 1] != ']') {
+                const last = self.pattern[self.pos + 1];
+                if (last < c) return error.InvalidRegex;
+                set.setRangeValue(.{ .start = c, .end = @as(usize, last) + 1 }, true);
+                self.pos += 2;
+            } else {
+                set.set(c);
+            }
+        }
+        self.pos += 1;
+        if (negated) set.toggleAll();
+        return set;
+    }
+
+    /// After the "\"
+    fn escape(self: *Parser) !Set {
+        const c = self.peek() orelse return error.InvalidRegex;
+        self.pos += 1;
+        var set = switch (std.ascii.toLower(c)) {
+            'd' => range('0', '9'),
+            'w' => blk: {
+                var w = range('a', 'z');
+                w.setUnion(range('A', 'Z'));
+                w.setUnion(range('0', '9'));
+                w.set('_');
+                break :blk w;
+            },
+            's' => blk: {
+                var s = Set.initEmpty();
+                for (std.ascii.whitespace) |space| s.set(space);
+                break :blk s;
+            },
+            else => {
+                // Only punctuation is escaped to stand for itself
+                if (std.ascii.isAlphanumeric(c)) return error.InvalidRegex;
+                return single(c);
+            },
+        };
+        if (std.ascii.isUpper(c)) set.toggleAll();
+        return set;
+    }
+};
+
+fn single(c: u8) Set {
+    var set = Set.initEmpty();
+    set.set(c);
+    return set;
+}
+
+fn range(first: u8, last: u8) Set {
+    var set = Set.initEmpty();
+    set.setRangeValue(.{ .start = first, .end = @as(usize, last) + 1 }, true);
+    return set;
+}
+
+/// What to match once the current sequence is done
+const Frame = struct {
+    /// The group whose iteration the sequence was
+    term: *const Term,
+    /// Iterations finished before this one
+    count: u32,
+    /// Where this iteration began
+    start: usize,
+    /// Terms after the group
+    rest: []const Term,
+    next: ?*const Frame,
+};
+
+const Matcher = struct {
+    input: []const u8,
+    captures: *Captures,
+    steps: usize = 0,
+    /// End of the match, once `sequence` succeeds
+    end: usize = 0,
+
+    fn sequence(self: *Matcher, terms: []const Term, pos: usize, next: ?*const Frame) bool {
+        self.steps += 1;
+        if (self.steps > MAX_STEPS) return false;
+        if (terms.len == 0) return self.finishGroup(pos, next);
+        return self.repeat(&terms[0], 0, pos, terms[1..], next);
+    }
+
+    /// A group's iteration ended at `pos`: record its capture, then try
+    /// another iteration or carry on after it
+    fn finishGroup(self: *Matcher, pos: usize, next: ?*const Frame) bool {
+        const frame = next orelse {
+            self.end = pos;
+            return true;
+        };
+        const capture = frame.term.atom.group.capture;
+        const saved = if (capture) |i| self.captures[i] else null;
+        if (capture) |i| self.captures[i] = .{ .start = frame.start, .end = pos };
+
+        // An iteration that matched nothing would match nothing again
+        const matched = if (pos == frame.start)
+            self.sequence(frame.rest, pos, frame.next)
+        else
+            self.repeat(frame.term, frame.count + 1, pos, frame.rest, frame.next);
+        if (!matched) {
+            if (capture) |i| self.captures[i] = saved;
+        }
+        return matched;
+    }
+
+    /// Match `term` having already matched it `count` times, then `rest`
+    fn repeat(self: *Matcher, term: *const Term, count: u32, pos: usize, rest: []const Term, next: ?*const Frame) bool {
+        switch (term.atom) {
+            .start => return pos == 0 and self.sequence(rest, pos, next),
+            .end => return pos == self.input.len and self.sequence(rest, pos, next),
+            .set => |*set| {
+                // Take as many bytes as allowed, then give them back one
+                // at a time (or the other way round when lazy)
+                var available: usize = 0;
+                while (count + available < term.max and pos + available < self.input.len and
+                    set.isSet(self.input[pos + available])) available += 1;
+                if (count + available < term.min) return false;
+                const fewest = term.min -| count;
+                if (term.greedy) {
+                    var taken = available;
+                    while (taken + 1 > fewest) : (taken -= 1) {
+                        if (self.sequence(rest, pos + taken, next)) return true;
+                        if (taken == 0) break;
+                    }
+                } else {
+                    for (fewest..available + 1) |taken| {
+                        if (self.sequence(rest, pos + taken, next)) return true;
+                    }
+                }
+                return false;
+            },
+            .group => |group| {
+                const done = count >= term.min;
+                const more = count < term.max;
+                if (!term.greedy and done and self.sequence(rest, pos, next)) return true;
+                if (more) {
+                    const frame = Frame{ .term = term, .count = count, .start = pos, .rest = rest, .next = next };
+                    for (group.alternatives) |terms| {
+                        if (self.sequence(terms, pos, &frame)) return true;
+                    }
+                }
+                return term.greedy and done and self.sequence(rest, pos, next);
+            },
+        }
+    }
+};
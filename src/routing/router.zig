@@ -0,0 +1,83 @@
+const std = @import("std");
+const config = @import("../config/config.zig");
+const when = @import("when.zig");
+
+/// Matches requests against configured routes by host and path prefix
+pub const Router = struct {
+    routes: []const config.RouteConfig,
+
+    pub fn init(routes: []const config.RouteConfig) Router {
+        return .{ .routes = routes };
+    }
+
+    /// Return the most specific route for the given host and path.
+    /// Exact hosts win over wildcards, then the longest path prefix wins.
+    /// Routes limited to certain times are skipped outside them.
+    pub fn match(self: *const Router, host: []const u8, path: []const u8) ?*const config.RouteConfig {
+        var best: ?*const config.RouteConfig = null;
+        var now_ms: ?i64 = null;
+        for (self.routes) |*route| {
+            if (!hostMatches(route.host, host) or !pathMatches(route.path, path)) continue;
+            if (route.when) |*when_cfg| {
+                if (now_ms == null) now_ms = std.time.milliTimestamp();
+                if (!when.matches(when_cfg, now_ms.?)) continue;
+            }
+            if (best) |current| {
+                const rank = hostRank(route.host);
+                const best_rank = hostRank(current.host);
+                if (rank < best_rank) continue;
+                if (rank == best_rank and route.path.len <= current.path.len) continue;
+            }
+            best = route;
+        }
+        return best;
+    }
+};
+
+/// Return the vhost serving the given host. Exact hostnames win over
+/// wildcards, and "*" only matches when nothing else does.
+pub fn matchVhost(vhosts: []const config.VhostConfig, host: []const u8) ?*const config.VhostConfig {
+    var best: ?*const config.VhostConfig = null;
+    var best_rank: u8 = 0;
+    for (vhosts) |*vhost| {
+        for (vhost.hostnames) |name| {
+            if (!hostMatches(name, host)) continue;
+            const rank = hostRank(name);
+            if (best == null or rank > best_rank) {
+                best = vhost;
+                best_rank = rank;
+            }
+        }
+    }
+    return best;
+}
+
+/// Whether the host matches any of the patterns
+pub fn hostAllowed(patterns: []const []const u8, host: []const u8) bool {
+    for (patterns) |pattern| {
+        if (hostMatches(pattern, host)) return true;
+    }
+    return false;
+}
+
+/// Match a host against "*", "*.example.com" or an exact hostname
+pub fn hostMatches(pattern: []const u8, host: []const u8) bool {
+    if (std.mem.eql(u8, pattern, "*")) return true;
+    if (std.mem.startsWith(u8, pattern, "*.")) {
+        return host.len > pattern.len - 1 and std.ascii.endsWithIgnoreCase(host, pattern[1..]);
+    }
+    return std.ascii.eqlIgnoreCase(pattern, host);
+}
+
+/// Match a path prefix on segment boundaries ("/api" matches "/api/x", not "/apix")
+pub fn pathMatches(prefix: []const u8, path: []const u8) bool {
+    if (prefix.len == 0) return true;
+    if (!std.mem.startsWith(u8, path, prefix)) return false;
+    return path.len == prefix.len or prefix[prefix.len - 1] == '/' or path[prefix.len] == '/';
+}
+
+fn hostRank(pattern: []const u8) u8 {
+    if (std.mem.eql(u8, pattern, "*")) return 0;
+    if (std.mem.startsWith(u8, pattern, "*.")) return 1;
+    return 2;
+}
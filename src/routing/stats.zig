@@ -0,0 +1,175 @@
+const std = @import("std");
+const config = @import("../config/config.zig");
+const metrics = @import("../server/metrics.zig");
+
+/// Latency histogram over a rolling window: the current period plus the
+/// previous one, so percentiles always cover at least one full period
+pub const LatencyHistogram = struct {
+    /// Bucket upper bounds in milliseconds; one more bucket holds slower requests
+    pub const bounds = [_]u32{ 1, 2, 5, 10, 25, 50, 100, 250, 500, 1000, 2500, 5000, 10_000 };
+    pub const PERIOD_MS = 60_000;
+
+    const Counts = [bounds.len + 1]u64;
+
+    mutex: std.Thread.Mutex = .{},
+    current: Counts = [_]u64{0} ** (bounds.len + 1),
+    previous: Counts = [_]u64{0} ** (bounds.len + 1),
+    period_start: i64 = 0,
+
+    pub fn record(self: *LatencyHistogram, ms: u32) void {
+        self.mutex.lock();
+        defer self.mutex.unlock();
+
+        self.rotate(std.time.milliTimestamp());
+        self.current[bucketFor(ms)] += 1;
+    }
+
+    /// Upper bound of the bucket holding the `p`th percentile, in
+    /// milliseconds; null without samples. The overflow bucket reports
+    /// the largest bound.
+    pub fn percentile(self: *LatencyHistogram, p: f64) ?u32 {
+        self.mutex.lock();
+        defer self.mutex.unlock();
+
+        self.rotate(std.time.milliTimestamp());
+        var merged: Counts = undefined;
+        var total: u64 = 0;
+        for (&merged, self.current, self.previous) |*m, c, prev| {
+            m.* = c + prev;
+            total += m.*;
+        }
+        if (total == 0) return null;
+
+        const target: u64 = @max(1, @as(u64, @intFromFloat(@ceil(@as(f64, @floatFromInt(total)) * p / 100.0))));
+        var seen: u64 = 0;
+        for (merged, 0..) |count, i| {
+            seen += count;
+            if (seen >= target) return bounds[@min(i, bounds.len - 1)];
+        }
+        return bounds[bounds.len - 1];
+    }
+
+    fn rotate(self: *LatencyHistogram, now: i64) void {
+        if (now - self.period_start < PERIOD_MS) return;
+        // A gap longer than a whole period leaves nothing recent
+        self.previous = if (now - self.period_start < 2 * PERIOD_MS) self.current else [_]u64{0} ** (bounds.len + 1);
+        self.current = [_]u64{0} ** (bounds.len + 1);
+        self.period_start = now;
+    }
+
+    fn bucketFor(ms: u32) usize {
+        for (bounds, 0..) |bound, i| {
+            if (ms <= bound) return i;
+        }
+        return bounds.len;
+    }
+};
+
+/// Request outcomes for one route
+pub const RouteStats = struct {
+    requests: std.atomic.Value(u64) = std.atomic.Value(u64).init(0),
+    /// Responses by status class, 1xx through 5xx
+    status_classes: [5]std.atomic.Value(u64) = [_]std.atomic.Value(u64){std.atomic.Value(u64).init(0)} ** 5,
+    /// Connections opened to upstream servers, including hedges
+    upstream_attempts: std.atomic.Value(u64) = std.atomic.Value(u64).init(0),
+    latency: LatencyHistogram = .{},
+
+    pub fn record(self: *RouteStats, status: u16, attempts: u32, latency_ms: u32) void {
+        _ = self.requests.fetchAdd(1, .monotonic);
+        if (status >= 100 and status < 600) _ = self.status_classes[status / 100 - 1].fetchAdd(1, .monotonic);
+        _ = self.upstream_attempts.fetchAdd(attempts, .monotonic);
+        self.latency.record(latency_ms);
+    }
+};
+
+/// Stats for every configured route, addressable by route id
+pub const RouteStatsRegistry = struct {
+    allocator: std.mem.Allocator,
+    entries: std.ArrayList(Entry),
+
+    pub const Entry = struct {
+        /// The route's `id`, or its position among all routes
+        id: []const u8,
+        route: *const config.RouteConfig,
+        stats: *RouteStats,
+    };
+
+    pub fn init(allocator: std.mem.Allocator, cfg: *const config.Config) !RouteStatsRegistry {
+        var self = RouteStatsRegistry{ .allocator = allocator, .entries = .empty };
+        errdefer self.deinit();
+
+        try self.addRoutes(cfg.routes);
+        for (cfg.vhosts) |vhost| try self.addRoutes(vhost.routes);
+        return self;
+    }
+
+    pub fn deinit(self: *RouteStatsRegistry) void {
+        for (self.entries.items) |entry| {
+            if (entry.route.id.len == 0) self.allocator.free(entry.id);
+            self.allocator.destroy(entry.stats);
+        }
+        self.entries.deinit(self.allocator);
+    }
+
+    fn addRoutes(self: *RouteStatsRegistry, routes: []const config.RouteConfig) !void {
+        for (routes) |*route| {
+            const id = if (route.id.len > 0)
+                route.id
+            else
+                try std.fmt.allocPrint(self.allocator, "{d}", .{self.entries.items.len});
+            errdefer if (route.id.len == 0) self.allocator.free(id);
+
+            const stats = try self.allocator.create(RouteStats);
+            errdefer self.allocator.destroy(stats);
+            stats.* = .{};
+            try self.entries.append(self.allocator, .{ .id = id, .route = route, .stats = stats });
+        }
+    }
+
+    pub fn entryFor(self: *const RouteStatsRegistry, route: *const config.RouteConfig) ?Entry {
+        for (self.entries.items) |entry| {
+            if (entry.route == route) return entry;
+        }
+        return null;
+    }
+
+    pub fn find(self: *const RouteStatsRegistry, id: []const u8) ?Entry {
+        for (self.entries.items) |entry| {
+            if (std.mem.eql(u8, entry.id, id)) return entry;
+        }
+        return null;
+    }
+
+    pub fn writeMetrics(self: *const RouteStatsRegistry, m: *metrics.Exposition) !void {
+        try m.family("wraith_route_requests_total", .counter, "Requests matched to each route");
+        for (self.entries.items) |entry| {
+            try m.sample("wraith_route_requests_total", &.{.{ .name = "route", .value = entry.id }}, entry.stats.requests.load(.monotonic));
+        }
+        try m.family("wraith_route_responses_total", .counter, "Responses per route by status class");
+        const classes = [_][]const u8{ "1xx", "2xx", "3xx", "4xx", "5xx" };
+        for (self.entries.items) |entry| {
+            for (classes, &entry.stats.status_classes) |class, *count| {
+                try m.sample("wraith_route_responses_total", &.{
+                    .{ .name = "route", .value = entry.id },
+                    .{ .name = "class", .value = class },
+                }, count.load(.monotonic));
+            }
+        }
+        try m.family("wraith_route_upstream_attempts_total", .counter, "Upstream connections per route, including hedges and retries");
+        for (self.entries.items) |entry| {
+            try m.sample("wraith_route_upstream_attempts_total", &.{.{ .name = "route", .value = entry.id }}, entry.stats.upstream_attempts.load(.monotonic));
+        }
+        // Percentiles over the rolling window, as histogram bucket bounds
+        try m.family("wraith_route_latency_ms", .gauge, "Route latency percentiles over the last one to two minutes, in milliseconds");
+        const quantiles = [_]struct { label: []const u8, p: f64 }{ .{ .label = "0.5", .p = 50 }, .{ .label = "0.9", .p = 90 }, .{ .label = "0.99", .p = 99 } };
+        for (self.entries.items) |entry| {
+            for (quantiles) |q| {
+                const ms = entry.stats.latency.percentile(q.p) orelse continue;
+                try m.sample("wraith_route_latency_ms", &.{
+                    .{ .name = "route", .value = entry.id },
+                    .{ .name = "quantile", .value = q.label },
+                }, ms);
+            }
+        }
+    }
+};
@@ -0,0 +1,67 @@
+const std = @import("std");
+const config = @import("../config/config.zig");
+const schedule = @import("../server/schedule.zig");
+
+const ms_per_day = std.time.ms_per_day;
+
+pub fn validate(cfg: *const config.RouteWhenConfig) !void {
+    if ((cfg.start.len == 0) != (cfg.end.len == 0)) return error.InvalidRouteWindow;
+    if (cfg.start.len > 0) {
+        const start = schedule.clockMs(cfg.start) orelse return error.InvalidRouteWindow;
+        const end = schedule.clockMs(cfg.end) orelse return error.InvalidRouteWindow;
+        if (start == end) return error.InvalidRouteWindow;
+    }
+    const from = if (cfg.from.len > 0) parseDate(cfg.from) orelse return error.InvalidRouteWindow else null;
+    const until = if (cfg.until.len > 0) parseDate(cfg.until) orelse return error.InvalidRouteWindow else null;
+    if (from != null and until != null and from.? > until.?) return error.InvalidRouteWindow;
+    _ = parseOffset(cfg.utc_offset) orelse return error.InvalidRouteWindow;
+}
+
+/// Whether a route limited by `cfg` matches at `now_ms`. A window that
+/// crosses midnight belongs to the day it opened on, for `days`, `from`
+/// and `until` alike.
+pub fn matches(cfg: *const config.RouteWhenConfig, now_ms: i64) bool {
+    // Validated at startup
+    const local_ms = now_ms + parseOffset(cfg.utc_offset).?;
+    var day = @divFloor(local_ms, ms_per_day);
+    const time_of_day = local_ms - day * ms_per_day;
+
+    if (cfg.start.len > 0) {
+        const start = schedule.clockMs(cfg.start).?;
+        const end = schedule.clockMs(cfg.end).?;
+        if (start < end) {
+            if (time_of_day < start or time_of_day >= end) return false;
+        } else if (time_of_day < end) {
+            day -= 1;
+        } else if (time_of_day < start) {
+            return false;
+        }
+    }
+    if (cfg.days.len > 0) {
+        // 1970-01-01 was a Thursday
+        const weekday: config.Weekday = @enumFromInt(@mod(day + 3, 7));
+        if (std.mem.indexOfScalar(config.Weekday, cfg.days, weekday) == null) return false;
+    }
+    if (cfg.from.len > 0 and day < parseDate(cfg.from).?) return false;
+    if (cfg.until.len > 0 and day > parseDate(cfg.until).?) return false;
+    return true;
+}
+
+/// "YYYY-MM-DD" as days since 1970-01-01
+fn parseDate(text: []const u8) ?i64 {
+    if (text.len != 10 or text[4] != '-' or text[7] != '-') return null;
+    const year = std.fmt.parseInt(i64, text[0..4], 10) catch return null;
+    const month = std.fmt.parseInt(u8, text[5..7], 10) catch return null;
+    const day = std.fmt.parseInt(u8, text[8..10], 10) catch return null;
+    if (month == 0 or month > 12 or day == 0 or day > 31) return null;
+    return schedule.daysFromCivil(year, month, day);
+}
+
+/// "+HH:MM", "-HH:MM" or "Z" in milliseconds
+fn parseOffset(text: []const u8) ?i64 {
+    if (std.mem.eql(u8, text, "Z")) return 0;
+    if (text.len != 6 or (text[0] != '+' and text[0] != '-')) return null;
+    const ms = schedule.clockMs(text[1..]) orelse return null;
+    if (ms > 14 * std.time.ms_per_hour) return null;
+    return if (text[0] == '-') -ms else ms;
+}
@@ -0,0 +1,92 @@
+const std = @import("std");
+const config = @import("../config/config.zig");
+
+/// IPv4 or IPv6 network in CIDR notation. IPv4 networks are stored as
+/// IPv4-mapped IPv6 so both families compare uniformly.
+pub const Cidr = struct {
+    addr: [16]u8,
+    prefix_len: u8,
+
+    /// Parse "10.0.0.0/8", "fd00::/8" or a bare address (host route)
+    pub fn parse(text: []const u8) !Cidr {
+        const slash = std.mem.indexOfScalar(u8, text, '/');
+        const ip_text = if (slash) |i| text[0..i] else text;
+        const ip = std.net.Address.parseIp(ip_text, 0) catch return error.InvalidCidr;
+        const bytes = addressBytes(ip) orelse return error.InvalidCidr;
+
+        const is_v4 = ip.any.family == std.posix.AF.INET;
+        const max: u8 = if (is_v4) 32 else 128;
+        const prefix_len = if (slash) |i|
+            std.fmt.parseInt(u8, text[i + 1 ..], 10) catch return error.InvalidCidr
+        else
+            max;
+        if (prefix_len > max) return error.InvalidCidr;
+
+        return .{
+            .addr = bytes,
+            .prefix_len = if (is_v4) prefix_len + 96 else prefix_len,
+        };
+    }
+
+    pub fn contains(self: Cidr, ip: [16]u8) bool {
+        const full_bytes = self.prefix_len / 8;
+        if (!std.mem.eql(u8, self.addr[0..full_bytes], ip[0..full_bytes])) return false;
+
+        const rem_bits: u3 = @intCast(self.prefix_len % 8);
+        if (rem_bits == 0) return true;
+        const mask: u8 = @as(u8, 0xff) << @intCast(8 - @as(u4, rem_bits));
+        return (self.addr[full_bytes] & mask) == (ip[full_bytes] & mask);
+    }
+};
+
+/// Address bytes as IPv6, mapping IPv4 into ::ffff:0:0/96
+pub fn addressBytes(addr: std.net.Address) ?[16]u8 {
+    switch (addr.any.family) {
+        std.posix.AF.INET => {
+            var bytes = [_]u8{0} ** 16;
+            bytes[10] = 0xff;
+            bytes[11] = 0xff;
+            const v4: *const [4]u8 = @ptrCast(&addr.in.sa.addr);
+            @memcpy(bytes[12..16], v4);
+            return bytes;
+        },
+        std.posix.AF.INET6 => return addr.in6.sa.addr,
+        else => return null,
+    }
+}
+
+/// Whether any entry of a CIDR list contains the address
+pub fn listContains(list: []const []const u8, ip: [16]u8) bool {
+    for (list) |entry| {
+        const cidr = Cidr.parse(entry) catch continue;
+        if (cidr.contains(ip)) return true;
+    }
+    return false;
+}
+
+/// Check that every entry of a CIDR list parses
+pub fn validateList(list: []const []const u8) !void {
+    for (list) |entry| _ = try Cidr.parse(entry);
+}
+
+/// Allow/deny access control list. Deny entries win; a non-empty allow
+/// list admits only matching clients.
+pub const Acl = struct {
+    policy: *const config.AclConfig,
+
+    pub fn init(policy: *const config.AclConfig) Acl {
+        return .{ .policy = policy };
+    }
+
+    pub fn isEmpty(self: Acl) bool {
+        return self.policy.allow.len == 0 and self.policy.deny.len == 0;
+    }
+
+    pub fn permits(self: Acl, client: std.net.Address) bool {
+        if (self.isEmpty()) return true;
+        const ip = addressBytes(client) orelse return false;
+        if (listContains(self.policy.deny, ip)) return false;
+        if (self.policy.allow.len == 0) return true;
+        return listContains(self.policy.allow, ip);
+    }
+};
@@ -0,0 +1,318 @@
+const std = @import("std");
+const config = @import("../config/config.zig");
+const acl = @import("acl.zig");
+const metrics = @import("../server/metrics.zig");
+const schedule_mod = @import("../server/schedule.zig");
+
+/// Client tables are split into this many independently locked shards
+const SHARDS = 16;
+
+/// Why a request was turned away
+pub const Reason = enum { global, per_ip, route };
+
+/// Token bucket refilled continuously at `rate` tokens per second
+const Bucket = struct {
+    tokens: f64,
+    updated_ms: i64,
+
+    fn full(burst: f64, now_ms: i64) Bucket {
+        return .{ .tokens = burst, .updated_ms = now_ms };
+    }
+
+    fn take(self: *Bucket, rate: f64, burst: f64, now_ms: i64) bool {
+        const elapsed: f64 = @floatFromInt(@max(now_ms - self.updated_ms, 0));
+        self.tokens = @min(burst, self.tokens + elapsed * rate / std.time.ms_per_s);
+        self.updated_ms = now_ms;
+        if (self.tokens < 1) return false;
+        self.tokens -= 1;
+        return true;
+    }
+};
+
+/// A client's bucket, linked into its shard's recency list
+const Entry = struct {
+    ip: [16]u8,
+    bucket: Bucket,
+    /// Towards the most recently seen client
+    newer: ?*Entry = null,
+    /// Towards the least recently seen client
+    older: ?*Entry = null,
+};
+
+const Shard = struct {
+    mutex: std.Thread.Mutex = .{},
+    map: std.AutoHashMapUnmanaged([16]u8, *Entry) = .empty,
+    newest: ?*Entry = null,
+    oldest: ?*Entry = null,
+
+    fn unlink(self: *Shard, entry: *Entry) void {
+        if (entry.newer) |newer| newer.older = entry.older else self.newest = entry.older;
+        if (entry.older) |older| older.newer = entry.newer else self.oldest = entry.newer;
+        entry.newer = null;
+        entry.older = null;
+    }
+
+    fn pushNewest(self: *Shard, entry: *Entry) void {
+        entry.older = self.newest;
+        if (self.newest) |newest| newest.newer = entry;
+        self.newest = entry;
+        if (self.oldest == null) self.oldest = entry;
+    }
+};
+
+/// Token buckets per client address. Clients are spread over shards by
+/// address so concurrent requests rarely wait on the same lock. Each shard
+/// keeps its clients in recency order: idle clients expire once their
+/// bucket would have refilled anyway, and when a shard is full the least
+/// recently seen client is evicted rather than clearing the whole table.
+pub const ClientTable = struct {
+    allocator: std.mem.Allocator,
+    rate: f64,
+    burst: f64,
+    per_shard: usize,
+    idle_ms: i64,
+    shards: [SHARDS]Shard = [_]Shard{.{}} ** SHARDS,
+    /// Clients dropped while their bucket was still refilling
+    evictions: std.atomic.Value(u64) = std.atomic.Value(u64).init(0),
+    /// Idle clients forgotten
+    expirations: std.atomic.Value(u64) = std.atomic.Value(u64).init(0),
+
+    pub fn init(allocator: std.mem.Allocator, rate: f64, burst: u32, max_clients: usize, idle_timeout_ms: u32) ClientTable {
+        const burst_tokens: f64 = if (burst > 0) @floatFromInt(burst) else @max(1, @ceil(rate));
+        // Past this, a forgotten client is indistinguishable from a new one
+        const refill_ms: i64 = @intFromFloat(@ceil(burst_tokens / rate * std.time.ms_per_s));
+        return .{
+            .allocator = allocator,
+            .rate = rate,
+            .burst = burst_tokens,
+            .per_shard = @max(1, max_clients / SHARDS),
+            .idle_ms = if (idle_timeout_ms > 0) idle_timeout_ms else refill_ms,
+        };
+    }
+
+    pub fn deinit(self: *ClientTable) void {
+        for (&self.shards) |*shard| {
+            var it = shard.map.valueIterator();
+            while (it.next()) |entry| self.allocator.destroy(entry.*);
+            shard.map.deinit(self.allocator);
+        }
+    }
+
+    /// Take a token for the client; false when it is over its limit.
+    /// `scale` multiplies the rate and burst, as a scheduled change may.
+    pub fn allow(self: *ClientTable, ip: [16]u8, now_ms: i64, scale: f64) bool {
+        const rate = self.rate * scale;
+        const burst = @max(1, self.burst * scale);
+        const shard = &self.shards[std.hash.Wyhash.hash(0, &ip) % SHARDS];
+        shard.mutex.lock();
+        defer shard.mutex.unlock();
+
+        if (shard.map.get(ip)) |entry| {
+            shard.unlink(entry);
+            shard.pushNewest(entry);
+            if (now_ms - entry.bucket.updated_ms > self.idle_ms) entry.bucket = Bucket.full(burst, now_ms);
+            return entry.bucket.take(rate, burst, now_ms);
+        }
+
+        self.expire(shard, now_ms);
+        // Without memory to track the client, fail open
+        const entry = self.reuseOldest(shard) orelse (self.allocator.create(Entry) catch return true);
+        entry.* = .{ .ip = ip, .bucket = Bucket.full(burst, now_ms) };
+        shard.map.put(self.allocator, ip, entry) catch {
+            self.allocator.destroy(entry);
+            return true;
+        };
+        shard.pushNewest(entry);
+        return entry.bucket.take(rate, burst, now_ms);
+    }
+
+    /// Forget clients idle past the expiry, oldest first
+    fn expire(self: *ClientTable, shard: *Shard, now_ms: i64) void {
+        while (shard.oldest) |oldest| {
+            if (now_ms - oldest.bucket.updated_ms <= self.idle_ms) return;
+            shard.unlink(oldest);
+            _ = shard.map.remove(oldest.ip);
+            self.allocator.destroy(oldest);
+            _ = self.expirations.fetchAdd(1, .monotonic);
+        }
+    }
+
+    /// When the shard is full, evict its least recently seen client and
+    /// return the entry for reuse
+    fn reuseOldest(self: *ClientTable, shard: *Shard) ?*Entry {
+        if (shard.map.count() < self.per_shard) return null;
+        const oldest = shard.oldest orelse return null;
+        shard.unlink(oldest);
+        _ = shard.map.remove(oldest.ip);
+        _ = self.evictions.fetchAdd(1, .monotonic);
+        return oldest;
+    }
+
+    /// Clients currently tracked
+    pub fn size(self: *ClientTable) usize {
+        var total: usize = 0;
+        for (&self.shards) |*shard| {
+            shard.mutex.lock();
+            defer shard.mutex.unlock();
+            total += shard.map.count();
+        }
+        return total;
+    }
+};
+
+/// Request counters, for the admin API
+pub const Stats = struct {
+    allowed: u64,
+    denied_global: u64,
+    denied_per_ip: u64,
+    denied_route: u64,
+    clients: usize,
+    evictions: u64,
+    expirations: u64,
+
+    fn add(self: *Stats, table: *ClientTable) void {
+        self.clients += table.size();
+        self.evictions += table.evictions.load(.monotonic);
+        self.expirations += table.expirations.load(.monotonic);
+    }
+};
+
+/// Global, per-client and per-route request rate limits
+pub const RateLimiter = struct {
+    allocator: std.mem.Allocator,
+    config: *const config.RateLimitConfig,
+    mutex: std.Thread.Mutex = .{},
+    global: Bucket,
+    per_ip: ?ClientTable = null,
+    routes: std.AutoHashMapUnmanaged(*const config.RouteConfig, *ClientTable) = .empty,
+    /// Scheduled changes that scale every limit while they are open
+    schedule: ?*const schedule_mod.Schedule = null,
+    allowed: std.atomic.Value(u64) = std.atomic.Value(u64).init(0),
+    denied: [3]std.atomic.Value(u64) = [_]std.atomic.Value(u64){std.atomic.Value(u64).init(0)} ** 3,
+
+    pub fn init(allocator: std.mem.Allocator, cfg: *const config.Config) !RateLimiter {
+        const limits = &cfg.rate_limit;
+        var self = RateLimiter{
+            .allocator = allocator,
+            .config = limits,
+            .global = Bucket.full(globalBurst(limits), std.time.milliTimestamp()),
+        };
+        errdefer self.deinit();
+
+        if (limits.enabled and limits.per_ip.enabled) {
+            const per_ip = &limits.per_ip;
+            self.per_ip = ClientTable.init(allocator, per_ip.requests_per_second, per_ip.burst, per_ip.max_clients, per_ip.idle_timeout_ms);
+        }
+        try self.addRoutes(cfg.routes);
+        for (cfg.vhosts) |vhost| try self.addRoutes(vhost.routes);
+        return self;
+    }
+
+    pub fn deinit(self: *RateLimiter) void {
+        if (self.per_ip) |*table| table.deinit();
+        var it = self.routes.valueIterator();
+        while (it.next()) |table| {
+            table.*.deinit();
+            self.allocator.destroy(table.*);
+        }
+        self.routes.deinit(self.allocator);
+    }
+
+    fn addRoutes(self: *RateLimiter, routes: []const config.RouteConfig) !void {
+        for (routes) |*route| {
+            const limit = &route.rate_limit;
+            if (limit.requests_per_second <= 0) continue;
+            const table = try self.allocator.create(ClientTable);
+            errdefer self.allocator.destroy(table);
+            table.* = ClientTable.init(self.allocator, limit.requests_per_second, limit.burst, limit.max_clients, 0);
+            try self.routes.put(self.allocator, route, table);
+        }
+    }
+
+    fn globalBurst(limits: *const config.RateLimitConfig) f64 {
+        if (limits.burst > 0) return @floatFromInt(limits.burst);
+        return @max(1, @ceil(limits.requests_per_second));
+    }
+
+    /// Check the global and per-client limits before routing; null when
+    /// the request may proceed
+    pub fn check(self: *RateLimiter, client: std.net.Address) ?Reason {
+        const limits = self.config;
+        if (!limits.enabled) return null;
+        const now_ms = std.time.milliTimestamp();
+        const scale = self.limitScale(now_ms);
+
+        if (limits.requests_per_second > 0) {
+            self.mutex.lock();
+            const ok = self.global.take(limits.requests_per_second * scale, @max(1, globalBurst(limits) * scale), now_ms);
+            self.mutex.unlock();
+            if (!ok) return self.deny(.global);
+        }
+        if (self.per_ip) |*table| {
+            if (acl.addressBytes(client)) |ip| {
+                if (!table.allow(ip, now_ms, scale)) return self.deny(.per_ip);
+            }
+        }
+        _ = self.allowed.fetchAdd(1, .monotonic);
+        return null;
+    }
+
+    /// Check a route's per-client limit; false when the client is over it
+    pub fn checkRoute(self: *RateLimiter, route: *const config.RouteConfig, client: std.net.Address) bool {
+        const table = self.routes.get(route) orelse return true;
+        const ip = acl.addressBytes(client) orelse return true;
+        const now_ms = std.time.milliTimestamp();
+        if (table.allow(ip, now_ms, self.limitScale(now_ms))) return true;
+        _ = self.deny(.route);
+        return false;
+    }
+
+    fn limitScale(self: *const RateLimiter, now_ms: i64) f64 {
+        const schedule = self.schedule orelse return 1;
+        return schedule.rateLimitScale(now_ms);
+    }
+
+    fn deny(self: *RateLimiter, reason: Reason) Reason {
+        _ = self.denied[@intFromEnum(reason)].fetchAdd(1, .monotonic);
+        return reason;
+    }
+
+    /// Seconds until a denied client can expect a token, for Retry-After
+    pub fn retryAfter(self: *const RateLimiter, reason: Reason, route: ?*const config.RouteConfig) u32 {
+        const rate = switch (reason) {
+            .global => self.config.requests_per_second,
+            .per_ip => self.config.per_ip.requests_per_second,
+            .route => if (route) |r| r.rate_limit.requests_per_second else 1,
+        };
+        if (rate <= 0) return 1;
+        return @intFromFloat(@max(1, @ceil(1 / rate)));
+    }
+
+    pub fn writeMetrics(self: *RateLimiter, m: *metrics.Exposition) !void {
+        const s = self.stats();
+        try m.single("wraith_rate_limit_allowed_total", .counter, "Requests that passed the global and per-client limits", s.allowed);
+        try m.family("wraith_rate_limit_denied_total", .counter, "Requests refused with 429, by the limit that refused them");
+        try m.sample("wraith_rate_limit_denied_total", &.{.{ .name = "reason", .value = "global" }}, s.denied_global);
+        try m.sample("wraith_rate_limit_denied_total", &.{.{ .name = "reason", .value = "per_ip" }}, s.denied_per_ip);
+        try m.sample("wraith_rate_limit_denied_total", &.{.{ .name = "reason", .value = "route" }}, s.denied_route);
+        try m.single("wraith_rate_limit_clients", .gauge, "Client addresses tracked by per-client and route limits", s.clients);
+        try m.single("wraith_rate_limit_evictions_total", .counter, "Clients dropped from a full table while still limited", s.evictions);
+        try m.single("wraith_rate_limit_expirations_total", .counter, "Idle clients forgotten", s.expirations);
+    }
+
+    pub fn stats(self: *RateLimiter) Stats {
+        var result = Stats{
+            .allowed = self.allowed.load(.monotonic),
+            .denied_global = self.denied[@intFromEnum(Reason.global)].load(.monotonic),
+            .denied_per_ip = self.denied[@intFromEnum(Reason.per_ip)].load(.monotonic),
+            .denied_route = self.denied[@intFromEnum(Reason.route)].load(.monotonic),
+            .clients = 0,
+            .evictions = 0,
+            .expirations = 0,
+        };
+        if (self.per_ip) |*table| result.add(table);
+        var it = self.routes.valueIterator();
+        while (it.next()) |table| result.add(table.*);
+        return result;
+    }
+};
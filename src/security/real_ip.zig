@@ -0,0 +1,140 @@
+const std = @import("std");
+const config = @import("../config/config.zig");
+const request = @import("../server/request.zig");
+const socket = @import("../server/socket.zig");
+const acl = @import("acl.zig");
+
+/// Longest PROXY protocol v1 line, including CRLF
+const V1_MAX_LEN = 107;
+const V2_SIGNATURE = "\r\n\r\n\x00\r\nQUIT\n";
+
+/// Whether the peer may report a client address for the connection
+pub fn trusted(cfg: *const config.RealIpConfig, peer: std.net.Address) bool {
+    const ip = acl.addressBytes(peer) orelse return false;
+    return acl.listContains(cfg.trusted_proxies, ip);
+}
+
+/// The address a request is attributed to. Requests from untrusted peers
+/// are attributed to the peer, so clients can't choose their own address.
+pub fn clientAddress(cfg: *const config.RealIpConfig, peer: std.net.Address, req: *const request.Request) std.net.Address {
+    if (cfg.source == .peer or !trusted(cfg, peer)) return peer;
+    return switch (cfg.source) {
+        .peer => peer,
+        .x_forwarded_for => forwardedFor(cfg, peer, req),
+        .cf_connecting_ip => parseHop(req.header("CF-Connecting-IP") orelse "") orelse peer,
+        .x_real_ip => parseHop(req.header("X-Real-IP") orelse "") orelse peer,
+    };
+}
+
+/// Whether a trusted proxy in front of wraith says the client connected
+/// over HTTPS. The first X-Forwarded-Proto entry is the client's scheme.
+pub fn forwardedHttps(cfg: *const config.RealIpConfig, peer: std.net.Address, req: *const request.Request) bool {
+    if (!trusted(cfg, peer)) return false;
+    const proto = req.header("X-Forwarded-Proto") orelse return false;
+    var schemes = std.mem.splitScalar(u8, proto, ',');
+    return std.ascii.eqlIgnoreCase(std.mem.trim(u8, schemes.first(), " \t"), "https");
+}
+
+/// Walk X-Forwarded-For from the right, past the trusted proxies; the first
+/// untrusted hop is the client. Entries left of it may be forged and are
+/// ignored. A malformed entry ends the walk at the last hop that parsed.
+fn forwardedFor(cfg: *const config.RealIpConfig, peer: std.net.Address, req: *const request.Request) std.net.Address {
+    var client = peer;
+    var i = req.headers.len;
+    while (i > 0) {
+        i -= 1;
+        const h = req.headers[i];
+        if (!std.ascii.eqlIgnoreCase(h.name, "X-Forwarded-For")) continue;
+        var hops = std.mem.splitBackwardsScalar(u8, h.value, ',');
+        while (hops.next()) |hop| {
+            client = parseHop(hop) orelse return client;
+            if (!trusted(cfg, client)) return client;
+        }
+    }
+    return client;
+}
+
+/// "203.0.113.7", "2001:db8::1", or either with a port ("[2001:db8::1]:443")
+fn parseHop(text: []const u8) ?std.net.Address {
+    const hop = std.mem.trim(u8, text, " \t");
+    if (hop.len == 0) return null;
+    return std.net.Address.parseIp(hop, 0) catch std.net.Address.parseIpAndPort(hop) catch null;
+}
+
+/// Read the PROXY protocol header a trusted load balancer sends ahead of
+/// the client's bytes. Returns the client address, or null for health
+/// checks and unknown transports that carry none.
+pub fn readProxyHeader(stream: std.net.Stream, timeout_ms: u32) !?std.net.Address {
+    try socket.setTimeouts(stream.handle, timeout_ms);
+    defer socket.setTimeouts(stream.handle, 0) catch {};
+
+    // Both versions are told apart by their first five bytes; no byte past
+    // the header is consumed
+    var start: [5]u8 = undefined;
+    try socket.readExact(stream, &start);
+    if (std.mem.eql(u8, &start, "PROXY")) return readV1(stream);
+    if (std.mem.eql(u8, &start, V2_SIGNATURE[0..5])) return readV2(stream);
+    return error.MissingProxyHeader;
+}
+
+/// "PROXY TCP4 <src> <dst> <src port> <dst port>\r\n"
+fn readV1(stream: std.net.Stream) !?std.net.Address {
+    var line: [V1_MAX_LEN]u8 = undefined;
+    var len: usize = 0;
+    while (len < 2 or !std.mem.eql(u8, line[len - 2 .. len], "\r\n")) {
+        if (len == line.len) return error.InvalidProxyHeader;
+        try socket.readExact(stream, line[len .. len + 1]);
+        len += 1;
+    }
+
+    var fields = std.mem.tokenizeScalar(u8, line[0 .. len - 2], ' ');
+    const transport = fields.next() orelse return error.InvalidProxyHeader;
+    if (std.mem.eql(u8, transport, "UNKNOWN")) return null;
+    if (!std.mem.eql(u8, transport, "TCP4") and !std.mem.eql(u8, transport, "TCP6")) return error.InvalidProxyHeader;
+
+    const source = fields.next() orelse return error.InvalidProxyHeader;
+    _ = fields.next() orelse return error.InvalidProxyHeader;
+    const port = std.fmt.parseInt(u16, fields.next() orelse return error.InvalidProxyHeader, 10) catch return error.InvalidProxyHeader;
+    return std.net.Address.parseIp(source, port) catch return error.InvalidProxyHeader;
+}
+
+/// Binary header: signature, version and command, family, address length,
+/// then the addresses
+fn readV2(stream: std.net.Stream) !?std.net.Address {
+    var header: [16]u8 = undefined;
+    @memcpy(header[0..5], V2_SIGNATURE[0..5]);
+    try socket.readExact(stream, header[5..]);
+    if (!std.mem.eql(u8, header[0..12], V2_SIGNATURE)) return error.InvalidProxyHeader;
+    if (header[12] >> 4 != 2) return error.InvalidProxyHeader;
+
+    const len = std.mem.readInt(u16, header[14..16], .big);
+    var addresses: [36]u8 = undefined;
+    const kept = @min(len, addresses.len);
+    try socket.readExact(stream, addresses[0..kept]);
+    // Skip TLVs and Unix socket addresses
+    var skip: [256]u8 = undefined;
+    var remaining: usize = len - kept;
+    while (remaining > 0) {
+        const step = @min(remaining, skip.len);
+        try socket.readExact(stream, skip[0..step]);
+        remaining -= step;
+    }
+
+    // LOCAL connections come from the balancer itself
+    if (header[12] & 0x0f == 0) return null;
+    switch (header[13]) {
+        // TCP over IPv4
+        0x11 => {
+            if (len < 12) return error.InvalidProxyHeader;
+            const port = std.mem.readInt(u16, addresses[8..10], .big);
+            return std.net.Address.initIp4(addresses[0..4].*, port);
+        },
+        // TCP over IPv6
+        0x21 => {
+            if (len < 36) return error.InvalidProxyHeader;
+            const port = std.mem.readInt(u16, addresses[32..34], .big);
+            return std.net.Address.initIp6(addresses[0..16].*, port, 0, 0);
+        },
+        else => return null,
+    }
+}
@@ -0,0 +1,164 @@
+const std = @import("std");
+const config = @import("../config/config.zig");
+const request = @import("request.zig");
+
+/// An open log file shared by every config that names its path
+const Sink = struct {
+    file: std.fs.File,
+    mutex: std.Thread.Mutex = .{},
+};
+
+/// Where one request is logged
+pub const Target = struct {
+    sink: *Sink,
+    format: config.AccessLogFormat,
+};
+
+/// One finished request
+pub const Entry = struct {
+    client: std.net.Address,
+    req: *const request.Request,
+    status: u16,
+    started_ms: i64,
+    duration_ms: u32,
+};
+
+/// Access log files for the server, its vhosts and its routes, opened once
+/// at startup in append mode
+pub const AccessLogs = struct {
+    allocator: std.mem.Allocator,
+    config: *const config.Config,
+    sinks: std.StringHashMapUnmanaged(*Sink),
+
+    pub fn init(allocator: std.mem.Allocator, cfg: *const config.Config) !AccessLogs {
+        var self = AccessLogs{
+            .allocator = allocator,
+            .config = cfg,
+            .sinks = .empty,
+        };
+        errdefer self.deinit();
+
+        try self.open(&cfg.logging.access_log);
+        for (cfg.routes) |route| {
+            if (route.access_log) |*log| try self.open(log);
+        }
+        for (cfg.vhosts) |vhost| {
+            if (vhost.access_log) |*log| try self.open(log);
+            if (vhost.logging) |*logging| try self.open(&logging.access_log);
+            for (vhost.routes) |route| {
+                if (route.access_log) |*log| try self.open(log);
+            }
+        }
+        return self;
+    }
+
+    pub fn deinit(self: *AccessLogs) void {
+        var it = self.sinks.valueIterator();
+        while (it.next()) |sink| {
+            sink.*.file.close();
+            self.allocator.destroy(sink.*);
+        }
+        self.sinks.deinit(self.allocator);
+    }
+
+    fn open(self: *AccessLogs, log: *const config.AccessLogConfig) !void {
+        if (log.path.len == 0 or self.sinks.contains(log.path)) return;
+        const file = std.fs.cwd().createFile(log.path, .{ .truncate = false }) catch |err| {
+            std.debug.print("✗ Access log {s}: {any}\n", .{ log.path, err });
+            return err;
+        };
+        errdefer file.close();
+        try file.seekFromEnd(0);
+
+        const sink = try self.allocator.create(Sink);
+        errdefer self.allocator.destroy(sink);
+        sink.* = .{ .file = file };
+        try self.sinks.put(self.allocator, log.path, sink);
+    }
+
+    /// The log a request belongs in: its route's, else its vhost's, else
+    /// the server's
+    pub fn target(self: *const AccessLogs, vhost: ?*const config.VhostConfig, route: ?*const config.RouteConfig) ?Target {
+        const log = blk: {
+            if (route) |r| {
+                if (r.access_log) |*log| break :blk log;
+            }
+            if (vhost) |v| {
+                if (v.access_log) |*log| break :blk log;
+                if (v.logging) |*logging| break :blk &logging.access_log;
+            }
+            break :blk &self.config.logging.access_log;
+        };
+        if (log.path.len == 0) return null;
+        return .{ .sink = self.sinks.get(log.path) orelse return null, .format = log.format };
+    }
+
+    pub fn write(allocator: std.mem.Allocator, log: Target, entry: Entry) void {
+        const line = format(allocator, log.format, entry) catch |err| {
+            std.debug.print("✗ Access log entry dropped: {any}\n", .{err});
+            return;
+        };
+        log.sink.mutex.lock();
+        defer log.sink.mutex.unlock();
+        log.sink.file.writeAll(line) catch |err| {
+            std.debug.print("✗ Access log write failed: {any}\n", .{err});
+        };
+    }
+};
+
+fn format(allocator: std.mem.Allocator, log_format: config.AccessLogFormat, entry: Entry) ![]u8 {
+    const req = entry.req;
+    var client_buf: [64]u8 = undefined;
+    const client = clientText(&client_buf, entry.client);
+    var time_buf: [32]u8 = undefined;
+    const time = clfTime(&time_buf, entry.started_ms);
+    const referer = req.header("Referer") orelse "-";
+    const user_agent = req.header("User-Agent") orelse "-";
+
+    return switch (log_format) {
+        .common => std.fmt.allocPrint(allocator, "{s} - - [{s}] \"{s} {s} {s}\" {d} -\n", .{
+            client, time, req.method, req.target, req.version, entry.status,
+        }),
+        .combined => std.fmt.allocPrint(allocator, "{s} - - [{s}] \"{s} {s} {s}\" {d} - \"{s}\" \"{s}\"\n", .{
+            client, time, req.method, req.target, req.version, entry.status, referer, user_agent,
+        }),
+        .json => std.fmt.allocPrint(allocator, "{s}\n", .{try std.json.Stringify.valueAlloc(allocator, .{
+            .time_ms = entry.started_ms,
+            .client = client,
+            .host = req.host(),
+            .method = req.method,
+            .uri = req.target,
+            .protocol = req.version,
+            .status = entry.status,
+            .duration_ms = entry.duration_ms,
+            .referer = req.header("Referer"),
+            .user_agent = req.header("User-Agent"),
+        }, .{})}),
+    };
+}
+
+/// Client IP without the port
+fn clientText(buf: []u8, address: std.net.Address) []const u8 {
+    const text = std.fmt.bufPrint(buf, "{f}", .{address}) catch return "-";
+    const colon = std.mem.lastIndexOfScalar(u8, text, ':') orelse return text;
+    const host = text[0..colon];
+    return std.mem.trim(u8, host, "[]");
+}
+
+const month_names = [_][]const u8{ "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec" };
+
+/// "10/Oct/2000:13:55:36 +0000"
+fn clfTime(buf: []u8, ms: i64) []const u8 {
+    const epoch: std.time.epoch.EpochSeconds = .{ .secs = @intCast(@divFloor(@max(ms, 0), 1000)) };
+    const year_day = epoch.getEpochDay().calculateYearDay();
+    const month_day = year_day.calculateMonthDay();
+    const day_seconds = epoch.getDaySeconds();
+    return std.fmt.bufPrint(buf, "{d:0>2}/{s}/{d}:{d:0>2}:{d:0>2}:{d:0>2} +0000", .{
+        month_day.day_index + 1,
+        month_names[month_day.month.numeric() - 1],
+        year_day.year,
+        day_seconds.getHoursIntoDay(),
+        day_seconds.getMinutesIntoHour(),
+        day_seconds.getSecondsIntoMinute(),
+    }) catch "-";
+}
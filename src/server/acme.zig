@@ -0,0 +1,102 @@
+const std = @import("std");
+const config = @import("../config/config.zig");
+const secret_mod = @import("../config/secret.zig");
+
+const HmacSha256 = std.crypto.auth.hmac.sha2.HmacSha256;
+const base64url = std.base64.url_safe_no_pad;
+
+pub const LETS_ENCRYPT_PRODUCTION = "https://acme-v02.api.letsencrypt.org/directory";
+pub const LETS_ENCRYPT_STAGING = "https://acme-staging-v02.api.letsencrypt.org/directory";
+pub const ZEROSSL = "https://acme.zerossl.com/v2/DV90";
+
+pub fn validate(cfg: *const config.AcmeConfig) !void {
+    if (!cfg.enabled) return;
+    _ = try directoryHost(cfg.directory_url);
+    if (cfg.email) |email| {
+        const at = std.mem.indexOfScalar(u8, email, '@') orelse return error.InvalidAcmeEmail;
+        if (at == 0 or at == email.len - 1) return error.InvalidAcmeEmail;
+    }
+    if (cfg.eab) |*eab| {
+        if (eab.key_id.len == 0 or eab.hmac_key.len == 0) return error.InvalidAcmeEab;
+        // References are resolved, and checked, by preflight
+        if (!secret_mod.isReference(eab.hmac_key)) {
+            var key_buf: [256]u8 = undefined;
+            _ = try decodeHmacKey(eab.hmac_key, &key_buf);
+        }
+    } else if (std.mem.eql(u8, cfg.directory_url, ZEROSSL)) {
+        // ZeroSSL only issues to accounts bound to one of its dashboards
+        return error.MissingAcmeEab;
+    }
+}
+
+pub const DirectoryHost = struct {
+    host: []const u8,
+    port: u16,
+};
+
+/// Host and port of an https:// ACME directory URL
+pub fn directoryHost(url: []const u8) !DirectoryHost {
+    const uri = std.Uri.parse(url) catch return error.InvalidAcmeDirectory;
+    if (!std.mem.eql(u8, uri.scheme, "https")) return error.InvalidAcmeDirectory;
+    const host = uri.host orelse return error.InvalidAcmeDirectory;
+    const name = switch (host) {
+        .raw, .percent_encoded => |name| name,
+    };
+    if (name.len == 0) return error.InvalidAcmeDirectory;
+    return .{ .host = name, .port = uri.port orelse 443 };
+}
+
+/// HMAC keys are handed out base64url-encoded; padding is tolerated
+fn decodeHmacKey(encoded: []const u8, buf: []u8) ![]u8 {
+    const trimmed = std.mem.trimRight(u8, encoded, "=");
+    const len = base64url.Decoder.calcSizeForSlice(trimmed) catch return error.InvalidAcmeEab;
+    if (len == 0 or len > buf.len) return error.InvalidAcmeEab;
+    base64url.Decoder.decode(buf[0..len], trimmed) catch return error.InvalidAcmeEab;
+    return buf[0..len];
+}
+
+/// The externalAccountBinding member of a newAccount request (RFC 8555
+/// 7.3.4): the account's public JWK, signed with HS256 under the key the CA
+/// issued
+pub fn externalAccountBinding(
+    allocator: std.mem.Allocator,
+    eab: *const config.AcmeEabConfig,
+    account_jwk: []const u8,
+    new_account_url: []const u8,
+) ![]u8 {
+    const header = try std.json.Stringify.valueAlloc(allocator, .{
+        .alg = "HS256",
+        .kid = eab.key_id,
+        .url = new_account_url,
+    }, .{});
+    defer allocator.free(header);
+
+    const protected = try encode(allocator, header);
+    defer allocator.free(protected);
+    const payload = try encode(allocator, account_jwk);
+    defer allocator.free(payload);
+
+    var encoded_buf: [512]u8 = undefined;
+    var key_buf: [256]u8 = undefined;
+    const key = try decodeHmacKey(try secret_mod.resolveInto(eab.hmac_key, &encoded_buf), &key_buf);
+    var mac = HmacSha256.init(key);
+    mac.update(protected);
+    mac.update(".");
+    mac.update(payload);
+    var digest: [HmacSha256.mac_length]u8 = undefined;
+    mac.final(&digest);
+    const signature = try encode(allocator, &digest);
+    defer allocator.free(signature);
+
+    return std.json.Stringify.valueAlloc(allocator, .{
+        .protected = protected,
+        .payload = payload,
+        .signature = signature,
+    }, .{});
+}
+
+fn encode(allocator: std.mem.Allocator, bytes: []const u8) ![]u8 {
+    const out = try allocator.alloc(u8, base64url.Encoder.calcSize(bytes.len));
+    _ = base64url.Encoder.encode(out, bytes);
+    return out;
+}
@@ -0,0 +1,523 @@
+const std = @import("std");
+const config = @import("../config/config.zig");
+const request = @import("request.zig");
+const response = @import("response.zig");
+const signals = @import("signals.zig");
+const socket = @import("socket.zig");
+const stats_mod = @import("../routing/stats.zig");
+const capture_mod = @import("capture.zig");
+const faults_mod = @import("faults.zig");
+const rate_limit_mod = @import("../security/rate_limit.zig");
+const upstream_mod = @import("../upstream/manager.zig");
+const latency_mod = @import("../upstream/latency.zig");
+const pool_mod = @import("../upstream/pool.zig");
+const connections_mod = @import("connections.zig");
+const static_mod = @import("static.zig");
+const s3_origin_mod = @import("s3_origin.zig");
+const top_urls_mod = @import("top_urls.zig");
+const router_mod = @import("../routing/router.zig");
+const metrics = @import("metrics.zig");
+const usage_mod = @import("usage.zig");
+const admin_guard = @import("admin_guard.zig");
+const redact = @import("../config/redact.zig");
+const admin_auth = @import("admin_auth.zig");
+const scrape_mod = @import("scrape.zig");
+const history_mod = @import("history.zig");
+const events_mod = @import("events.zig");
+const schedule_mod = @import("schedule.zig");
+
+const Header = request.Header;
+
+/// Read and write timeout for admin connections, in milliseconds
+const TIMEOUT_MS = 5000;
+
+/// Grafana dashboard for the metrics served on /metrics
+const dashboard_json = @embedFile("grafana_dashboard.json");
+
+/// Admin API on its own listener. Requests are few, so connections are
+/// served one at a time on the listener thread.
+pub const AdminServer = struct {
+    allocator: std.mem.Allocator,
+    config: *const config.AdminConfig,
+    /// Served by /admin/config
+    server_config: *const config.Config,
+    route_stats: *const stats_mod.RouteStatsRegistry,
+    capture: *capture_mod.Capture,
+    faults: *faults_mod.Faults,
+    rate_limiter: *rate_limit_mod.RateLimiter,
+    upstreams: *upstream_mod.UpstreamManager,
+    connections: *connections_mod.ConnectionTracker,
+    etags: *static_mod.EtagCache,
+    s3_origins: *s3_origin_mod.S3Origins,
+    usage: *usage_mod.Usage,
+    scrape: *scrape_mod.ScrapeGuard,
+    history: *history_mod.History,
+    schedule: *schedule_mod.Schedule,
+    /// For resolving static cache invalidations to mounts
+    vhosts: []const config.VhostConfig,
+    guard: admin_guard.AdminGuard,
+    auth: admin_auth.Authenticator,
+
+    pub fn init(
+        allocator: std.mem.Allocator,
+        cfg: *const config.AdminConfig,
+        server_config: *const config.Config,
+        route_stats: *const stats_mod.RouteStatsRegistry,
+        capture: *capture_mod.Capture,
+        faults: *faults_mod.Faults,
+        rate_limiter: *rate_limit_mod.RateLimiter,
+        upstreams: *upstream_mod.UpstreamManager,
+        connections: *connections_mod.ConnectionTracker,
+        etags: *static_mod.EtagCache,
+        s3_origins: *s3_origin_mod.S3Origins,
+        usage: *usage_mod.Usage,
+        scrape: *scrape_mod.ScrapeGuard,
+        history: *history_mod.History,
+        events: *events_mod.EventBus,
+        schedule: *schedule_mod.Schedule,
+        vhosts: []const config.VhostConfig,
+    ) !AdminServer {
+        return .{
+            .allocator = allocator,
+            .config = cfg,
+            .server_config = server_config,
+            .route_stats = route_stats,
+            .capture = capture,
+            .faults = faults,
+            .rate_limiter = rate_limiter,
+            .upstreams = upstreams,
+            .connections = connections,
+            .etags = etags,
+            .s3_origins = s3_origins,
+            .usage = usage,
+            .scrape = scrape,
+            .history = history,
+            .schedule = schedule,
+            .vhosts = vhosts,
+            .guard = try admin_guard.AdminGuard.init(allocator, &cfg.protection, events),
+            .auth = try admin_auth.Authenticator.init(allocator, cfg.tokens),
+        };
+    }
+
+    /// Thread entry point
+    pub fn run(self: *AdminServer) void {
+        self.start() catch |err| {
+            std.debug.print("✗ Admin listener failed: {any}\n", .{err});
+        };
+    }
+
+    pub fn start(self: *AdminServer) !void {
+        const addr = try std.net.Address.parseIpAndPort(self.config.listen);
+        var server = try addr.listen(.{
+            .reuse_address = true,
+        });
+        defer server.deinit();
+
+        std.debug.print("✓ Admin API listening on {s}\n", .{self.config.listen});
+
+        while (!signals.shouldShutdown()) {
+            const conn = server.accept() catch |err| {
+                if (signals.shouldShutdown()) break;
+                std.debug.print("✗ Admin accept failed: {any}\n", .{err});
+                continue;
+            };
+            defer conn.stream.close();
+
+            self.handleConnection(conn.stream, conn.address) catch |err| {
+                std.debug.print("✗ Admin request failed: {any}\n", .{err});
+            };
+        }
+    }
+
+    fn handleConnection(self: *AdminServer, stream: std.net.Stream, peer: std.net.Address) !void {
+        try socket.setTimeouts(stream.handle, TIMEOUT_MS);
+
+        // Turned away before the request is even read
+        switch (self.guard.admit(peer)) {
+            .allowed => {},
+            .rate_limited, .locked_out => |retry_after| {
+                var retry_buf: [16]u8 = undefined;
+                return response.write(stream, 429, &[_]Header{
+                    .{ .name = "Content-Type", .value = "text/plain" },
+                    .{ .name = "Retry-After", .value = try std.fmt.bufPrint(&retry_buf, "{d}", .{retry_after}) },
+                }, "429 Too Many Requests\n");
+            },
+        }
+
+        var buf: [8192]u8 = undefined;
+        const n = request.readHeadTimeout(stream, &buf, TIMEOUT_MS) catch |err| switch (err) {
+            error.HeadTooLarge => return response.writeError(stream, 431),
+            error.Timeout => return response.writeError(stream, 408),
+            else => return err,
+        };
+        var headers: [request.MAX_HEADERS]Header = undefined;
+        const req = request.parse(buf[0..n], &headers) catch return response.writeError(stream, 400);
+
+        var arena = std.heap.ArenaAllocator.init(self.allocator);
+        defer arena.deinit();
+        const allocator = arena.allocator();
+
+        const path = req.path();
+
+        // Scrapers may use a metrics token in place of an admin token
+        if (std.mem.eql(u8, path, "/metrics")) {
+            switch (self.scrape.check(peer, &req)) {
+                .forbidden => return response.writeError(stream, 403),
+                .granted => return self.writeMetrics(allocator, stream),
+                .no_token => {},
+            }
+        }
+
+        const result = self.auth.authenticate(&req);
+        switch (result) {
+            .open => {},
+            .granted => |role| {
+                self.guard.authSucceeded(peer);
+                if (!role.allows(admin_auth.requiredRole(&req))) {
+                    self.guard.audit("forbidden", peer);
+                    return response.writeError(stream, 403);
+                }
+            },
+            .missing, .invalid => {
+                if (result == .invalid) self.guard.authFailed(peer);
+                return response.write(stream, 401, &[_]Header{
+                    .{ .name = "Content-Type", .value = "text/plain" },
+                    .{ .name = "WWW-Authenticate", .value = "Bearer realm=\"wraith-admin\"" },
+                }, "401 Unauthorized\n");
+            },
+        }
+
+        // Starting and stopping a capture change state, so POST is accepted too
+        if (std.mem.eql(u8, path, "/admin/capture/start") or std.mem.eql(u8, path, "/admin/capture/stop")) {
+            if (!std.mem.eql(u8, req.method, "GET") and !std.mem.eql(u8, req.method, "POST")) {
+                return response.writeError(stream, 405);
+            }
+            if (std.mem.eql(u8, path, "/admin/capture/start")) {
+                const route = req.queryParam("route");
+                if (route) |id| {
+                    if (self.route_stats.find(id) == null) return response.writeError(stream, 404);
+                }
+                const count_param = req.queryParam("count") orelse "100";
+                const count = std.fmt.parseInt(usize, count_param, 10) catch return response.writeError(stream, 400);
+                try self.capture.start(route, count);
+            } else {
+                self.capture.stop();
+            }
+            return writeJson(stream, try std.json.Stringify.valueAlloc(allocator, self.capture.status(), .{}));
+        }
+
+        if (std.mem.eql(u8, path, "/admin/faults/set") or std.mem.eql(u8, path, "/admin/faults/clear")) {
+            if (!self.config.faults.enabled) return response.writeError(stream, 404);
+            if (!std.mem.eql(u8, req.method, "POST")) return response.writeError(stream, 405);
+            const route = req.queryParam("route");
+            if (route) |id| {
+                if (self.route_stats.find(id) == null) return response.writeError(stream, 404);
+            }
+            if (std.mem.eql(u8, path, "/admin/faults/set")) {
+                const fault = parseFault(&self.config.faults, &req) orelse return response.writeError(stream, 400);
+                try self.faults.set(route orelse return response.writeError(stream, 400), fault);
+            } else {
+                _ = self.faults.clear(route);
+            }
+            return writeJson(stream, try self.faults.json(allocator));
+        }
+
+        if (std.mem.eql(u8, path, "/admin/static/cache/flush")) {
+            if (!std.mem.eql(u8, req.method, "POST")) return response.writeError(stream, 405);
+            const removed = if (req.queryParam("path")) |url_path| self.invalidateStatic(req.queryParam("host"), url_path) else self.flushStatic();
+            return writeJson(stream, try std.json.Stringify.valueAlloc(allocator, removed, .{}));
+        }
+
+        if (std.mem.eql(u8, path, "/admin/schedule/cancel")) {
+            if (!std.mem.eql(u8, req.method, "POST")) return response.writeError(stream, 405);
+            const name = req.queryParam("name") orelse return response.writeError(stream, 400);
+            const now_ms = std.time.milliTimestamp();
+            if (!self.schedule.cancel(name, now_ms)) return response.writeError(stream, 404);
+            return writeJson(stream, try self.schedule.json(allocator, now_ms));
+        }
+
+        if (!std.mem.eql(u8, req.method, "GET")) return response.writeError(stream, 405);
+
+        if (std.mem.eql(u8, path, "/admin/capture")) {
+            return writeJson(stream, try std.json.Stringify.valueAlloc(allocator, self.capture.status(), .{}));
+        }
+        if (std.mem.eql(u8, path, "/admin/capture.har")) {
+            return response.write(stream, 200, &[_]Header{
+                .{ .name = "Content-Type", .value = "application/json" },
+                .{ .name = "Content-Disposition", .value = "attachment; filename=\"wraith-capture.har\"" },
+                .{ .name = "Cache-Control", .value = "no-store" },
+            }, try self.capture.har(allocator));
+        }
+
+        if (std.mem.eql(u8, path, "/admin/faults")) {
+            if (!self.config.faults.enabled) return response.writeError(stream, 404);
+            return writeJson(stream, try self.faults.json(allocator));
+        }
+        if (std.mem.eql(u8, path, "/admin/rate-limit")) {
+            return writeJson(stream, try std.json.Stringify.valueAlloc(allocator, self.rate_limiter.stats(), .{}));
+        }
+        if (std.mem.eql(u8, path, "/admin/stats")) {
+            return writeJson(stream, try self.connections.statsJson(allocator));
+        }
+        if (std.mem.eql(u8, path, "/admin/upstreams")) {
+            return writeJson(stream, try upstreamListJson(allocator, self.upstreams));
+        }
+        if (std.mem.eql(u8, path, "/admin/routes")) {
+            return writeJson(stream, try self.routeList(allocator));
+        }
+        if (std.mem.eql(u8, path, "/admin/config")) {
+            const include_secrets = std.mem.eql(u8, req.queryParam("include_secrets") orelse "false", "true");
+            if (include_secrets and !self.config.expose_secrets) return response.writeError(stream, 403);
+            return writeJson(stream, try redact.json(allocator, self.server_config, include_secrets));
+        }
+        if (std.mem.eql(u8, path, "/admin/usage")) {
+            return writeJson(stream, try self.usage.rollupsJson(allocator, req.queryParam("date"), req.queryParam("vhost")));
+        }
+        if (std.mem.eql(u8, path, "/admin/history")) {
+            if (!self.server_config.history.enabled) return response.writeError(stream, 404);
+            const body = try self.history.json(allocator, req.queryParam("tier") orelse "1m") orelse return response.writeError(stream, 400);
+            return writeJson(stream, body);
+        }
+        if (std.mem.eql(u8, path, "/admin/static/cache")) {
+            return writeJson(stream, try self.staticCacheJson(allocator));
+        }
+        if (std.mem.eql(u8, path, "/admin/static/cache/top")) {
+            return writeJson(stream, try self.topUrlsJson(allocator));
+        }
+        if (std.mem.eql(u8, path, "/admin/schedule")) {
+            return writeJson(stream, try self.schedule.json(allocator, std.time.milliTimestamp()));
+        }
+        if (std.mem.eql(u8, path, "/metrics")) return self.writeMetrics(allocator, stream);
+        if (std.mem.eql(u8, path, "/admin/metrics/dashboard.json")) {
+            return response.write(stream, 200, &[_]Header{
+                .{ .name = "Content-Type", .value = "application/json" },
+                .{ .name = "Content-Disposition", .value = "attachment; filename=\"wraith-dashboard.json\"" },
+            }, dashboard_json);
+        }
+        if (routeIdFromStatsPath(path)) |id| {
+            const entry = self.route_stats.find(id) orelse return response.writeError(stream, 404);
+            return writeJson(stream, try routeStatsJson(allocator, entry));
+        }
+        return response.writeError(stream, 404);
+    }
+
+    fn writeMetrics(self: *AdminServer, allocator: std.mem.Allocator, stream: std.net.Stream) !void {
+        const sources = scrape_mod.Sources{
+            .rate_limiter = self.rate_limiter,
+            .connections = self.connections,
+            .usage = self.usage,
+            .route_stats = self.route_stats,
+            .upstreams = self.upstreams,
+            .s3_origins = self.s3_origins,
+        };
+        return response.write(stream, 200, &[_]Header{
+            .{ .name = "Content-Type", .value = metrics.content_type },
+            .{ .name = "Cache-Control", .value = "no-store" },
+        }, try sources.render(allocator));
+    }
+
+    fn staticCacheJson(self: *AdminServer, allocator: std.mem.Allocator) ![]const u8 {
+        var buckets: std.ArrayList(s3_origin_mod.CacheStats) = .empty;
+        var it = self.s3_origins.origins.valueIterator();
+        while (it.next()) |origin| try buckets.append(allocator, origin.*.stats());
+        return std.json.Stringify.valueAlloc(allocator, .{ .etags = self.etags.stats(), .s3 = buckets.items }, .{});
+    }
+
+    fn topUrlsJson(self: *AdminServer, allocator: std.mem.Allocator) ![]const u8 {
+        const Mount = struct {
+            path: []const u8,
+            bucket: []const u8,
+            report: top_urls_mod.Report,
+        };
+        var mounts: std.ArrayList(Mount) = .empty;
+        var it = self.s3_origins.origins.valueIterator();
+        while (it.next()) |origin| {
+            const tracker = if (origin.*.top) |*t| t else continue;
+            try mounts.append(allocator, .{
+                .path = origin.*.mount.path,
+                .bucket = origin.*.mount.s3.bucket,
+                .report = try tracker.latest(allocator),
+            });
+        }
+        return std.json.Stringify.valueAlloc(allocator, .{ .s3 = mounts.items }, .{});
+    }
+
+    const Removed = struct {
+        etags: usize = 0,
+        s3_objects: usize = 0,
+    };
+
+    fn flushStatic(self: *AdminServer) Removed {
+        var removed = Removed{ .etags = self.etags.flush() };
+        var it = self.s3_origins.origins.valueIterator();
+        while (it.next()) |origin| removed.s3_objects += origin.*.flush();
+        return removed;
+    }
+
+    /// Forget what is cached for one URL path, on the vhost serving `host`
+    /// or on every vhost
+    fn invalidateStatic(self: *AdminServer, host: ?[]const u8, url_path: []const u8) Removed {
+        var removed = Removed{};
+        const vhosts: []const config.VhostConfig = if (host) |h|
+            @as(*const [1]config.VhostConfig, router_mod.matchVhost(self.vhosts, h) orelse return removed)
+        else
+            self.vhosts;
+
+        for (vhosts) |*vhost| {
+            const mount = static_mod.matchMount(vhost.static, url_path) orelse continue;
+            var rel_buf: [std.fs.max_path_bytes]u8 = undefined;
+            const rel = static_mod.relativePath(&rel_buf, mount.path, url_path) catch continue;
+            if (self.s3_origins.get(mount)) |origin| {
+                if (origin.invalidate(rel)) removed.s3_objects += 1;
+            } else {
+                var key_buf: [std.fs.max_path_bytes]u8 = undefined;
+                const key = static_mod.etagKey(&key_buf, mount, rel) catch continue;
+                if (self.etags.invalidate(key)) removed.etags += 1;
+            }
+        }
+        return removed;
+    }
+
+    fn routeList(self: *AdminServer, allocator: std.mem.Allocator) ![]const u8 {
+        const RouteSummary = struct {
+            id: []const u8,
+            host: []const u8,
+            path: []const u8,
+            upstream: []const u8,
+            requests: u64,
+        };
+        const routes = try allocator.alloc(RouteSummary, self.route_stats.entries.items.len);
+        for (self.route_stats.entries.items, routes) |entry, *summary| {
+            summary.* = .{
+                .id = entry.id,
+                .host = entry.route.host,
+                .path = entry.route.path,
+                .upstream = entry.route.upstream,
+                .requests = entry.stats.requests.load(.monotonic),
+            };
+        }
+        return std.json.Stringify.valueAlloc(allocator, .{ .routes = routes }, .{});
+    }
+};
+
+/// Live membership and health of every upstream group
+fn upstreamListJson(allocator: std.mem.Allocator, upstreams: *upstream_mod.UpstreamManager) ![]const u8 {
+    const ServerSummary = struct {
+        address: []const u8,
+        weight: u32,
+        backup: bool,
+        healthy: bool,
+        /// A cluster peer currently reports the server down
+        peer_down: bool,
+    };
+    const Percentiles = latency_mod.UpstreamLatency.Percentiles;
+    const GroupSummary = struct {
+        name: []const u8,
+        load_balancing: []const u8,
+        concurrency_limit: ?u32,
+        /// Rolling percentiles of proxied exchanges, in milliseconds
+        latency_ms: struct { connect: Percentiles, ttfb: Percentiles, total: Percentiles },
+        pool: pool_mod.ConnectionPool.Summary,
+        /// Null unless the group's servers speak TLS
+        tls: ?struct { handshakes: u64, handshake_failures: u64 },
+        servers: []ServerSummary,
+    };
+
+    const now_ms = std.time.milliTimestamp();
+    const groups = try allocator.alloc(GroupSummary, upstreams.groups.items.len);
+    for (upstreams.groups.items, groups) |group, *summary| {
+        const servers = try group.snapshot(allocator);
+        const server_summaries = try allocator.alloc(ServerSummary, servers.len);
+        for (servers, server_summaries) |server, *s| {
+            s.* = .{
+                .address = try std.fmt.allocPrint(allocator, "{f}", .{server.address}),
+                .weight = server.weight,
+                .backup = server.backup,
+                .healthy = server.healthy,
+                .peer_down = now_ms < server.peer_down_until,
+            };
+        }
+        summary.* = .{
+            .name = group.name,
+            .load_balancing = @tagName(group.config.load_balancing),
+            .concurrency_limit = if (group.limiter) |*limiter| limiter.currentLimit() else null,
+            .latency_ms = .{
+                .connect = group.latency.percentiles(.connect),
+                .ttfb = group.latency.percentiles(.ttfb),
+                .total = group.latency.percentiles(.total),
+            },
+            .pool = group.pool.summary(),
+            .tls = if (group.tls) |*client| .{
+                .handshakes = client.stats.handshakes.load(.monotonic),
+                .handshake_failures = client.stats.handshake_failures.load(.monotonic),
+            } else null,
+            .servers = server_summaries,
+        };
+    }
+    return std.json.Stringify.valueAlloc(allocator, .{ .upstreams = groups }, .{});
+}
+
+/// Rolling latency percentiles, status class counts and upstream attempts for a route
+pub fn routeStatsJson(allocator: std.mem.Allocator, entry: stats_mod.RouteStatsRegistry.Entry) ![]const u8 {
+    const stats = entry.stats;
+    const classes = &stats.status_classes;
+    return std.json.Stringify.valueAlloc(allocator, .{
+        .id = entry.id,
+        .host = entry.route.host,
+        .path = entry.route.path,
+        .upstream = entry.route.upstream,
+        .requests = stats.requests.load(.monotonic),
+        .upstream_attempts = stats.upstream_attempts.load(.monotonic),
+        .latency_ms = .{
+            .p50 = stats.latency.percentile(50),
+            .p90 = stats.latency.percentile(90),
+            .p99 = stats.latency.percentile(99),
+        },
+        .status = .{
+            .@"1xx" = classes[0].load(.monotonic),
+            .@"2xx" = classes[1].load(.monotonic),
+            .@"3xx" = classes[2].load(.monotonic),
+            .@"4xx" = classes[3].load(.monotonic),
+            .@"5xx" = classes[4].load(.monotonic),
+        },
+    }, .{});
+}
+
+/// A fault from the delay_ms, abort_percent, status and duration_s query
+/// parameters, or null when one is malformed or past the configured limits
+fn parseFault(cfg: *const config.FaultInjectionConfig, req: *const request.Request) ?faults_mod.Fault {
+    const delay_ms = std.fmt.parseInt(u32, req.queryParam("delay_ms") orelse "0", 10) catch return null;
+    const abort_percent = std.fmt.parseInt(u8, req.queryParam("abort_percent") orelse "0", 10) catch return null;
+    const status = std.fmt.parseInt(u16, req.queryParam("status") orelse "503", 10) catch return null;
+    const duration_s = if (req.queryParam("duration_s")) |d|
+        (std.fmt.parseInt(u32, d, 10) catch return null)
+    else
+        cfg.default_duration_s;
+
+    if (delay_ms == 0 and abort_percent == 0) return null;
+    if (delay_ms > cfg.max_delay_ms or abort_percent > 100) return null;
+    if (status < 400 or status > 599) return null;
+    if (duration_s == 0 or duration_s > cfg.max_duration_s) return null;
+    return .{
+        .delay_ms = delay_ms,
+        .abort_percent = abort_percent,
+        .abort_status = status,
+        .expires_ms = std.time.milliTimestamp() + @as(i64, duration_s) * std.time.ms_per_s,
+    };
+}
+
+/// Extract `id` from "/admin/routes/{id}/stats"
+fn routeIdFromStatsPath(path: []const u8) ?[]const u8 {
+    const prefix = "/admin/routes/";
+    const suffix = "/stats";
+    if (!std.mem.startsWith(u8, path, prefix) or !std.mem.endsWith(u8, path, suffix)) return null;
+    if (path.len <= prefix.len + suffix.len) return null;
+    return path[prefix.len .. path.len - suffix.len];
+}
+
+fn writeJson(stream: std.net.Stream, json: []const u8) !void {
+    try response.write(stream, 200, &[_]Header{
+        .{ .name = "Content-Type", .value = "application/json" },
+        .{ .name = "Cache-Control", .value = "no-store" },
+    }, json);
+}
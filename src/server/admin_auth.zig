@@ -0,0 +1,69 @@
+const std = @import("std");
+const config = @import("../config/config.zig");
+const request = @import("request.zig");
+const secret_mod = @import("../config/secret.zig");
+
+const Sha256 = std.crypto.hash.sha2.Sha256;
+
+pub const Result = union(enum) {
+    /// No tokens are configured; every request has full access
+    open,
+    granted: config.AdminRole,
+    /// No bearer token was sent
+    missing,
+    /// The token matches none configured
+    invalid,
+};
+
+/// Bearer tokens for the admin API and the roles they carry. Used only on
+/// the admin listener thread, so the token secrets need no lock.
+pub const Authenticator = struct {
+    allocator: std.mem.Allocator,
+    tokens: []const config.AdminTokenConfig,
+    secrets: []secret_mod.Secret,
+
+    pub fn init(allocator: std.mem.Allocator, tokens: []const config.AdminTokenConfig) !Authenticator {
+        const secrets = try allocator.alloc(secret_mod.Secret, tokens.len);
+        for (tokens, secrets) |token, *s| s.* = .{ .source = token.token };
+        return .{ .allocator = allocator, .tokens = tokens, .secrets = secrets };
+    }
+
+    pub fn deinit(self: *Authenticator) void {
+        for (self.secrets) |*s| s.deinit(self.allocator);
+        self.allocator.free(self.secrets);
+    }
+
+    pub fn authenticate(self: *Authenticator, req: *const request.Request) Result {
+        if (self.tokens.len == 0) return .open;
+        const authorization = req.header("Authorization") orelse return .missing;
+        if (!std.ascii.startsWithIgnoreCase(authorization, "Bearer ")) return .missing;
+        const presented = std.mem.trim(u8, authorization["Bearer ".len..], " \t");
+
+        // Digests are compared so the comparison time reveals neither the
+        // tokens' contents nor their lengths
+        var presented_digest: [Sha256.digest_length]u8 = undefined;
+        Sha256.hash(presented, &presented_digest, .{});
+        const now_ms = std.time.milliTimestamp();
+        var role: ?config.AdminRole = null;
+        for (self.tokens, self.secrets) |token, *s| {
+            const expected = s.get(self.allocator, now_ms) catch |err| {
+                std.debug.print("✗ Admin token {s}: {any}\n", .{ token.name, err });
+                continue;
+            };
+            var expected_digest: [Sha256.digest_length]u8 = undefined;
+            Sha256.hash(expected, &expected_digest, .{});
+            if (std.crypto.timing_safe.eql([Sha256.digest_length]u8, presented_digest, expected_digest)) role = token.role;
+        }
+        return if (role) |r| .{ .granted = r } else .invalid;
+    }
+};
+
+/// Role an admin request needs: reading is open to read-only tokens,
+/// changing state takes an operator, and credentials take an admin
+pub fn requiredRole(req: *const request.Request) config.AdminRole {
+    const path = req.path();
+    if (std.mem.eql(u8, path, "/admin/config") and std.mem.eql(u8, req.queryParam("include_secrets") orelse "", "true")) return .admin;
+    if (std.mem.startsWith(u8, path, "/admin/capture/")) return .operator;
+    if (!std.mem.eql(u8, req.method, "GET")) return .operator;
+    return .read_only;
+}
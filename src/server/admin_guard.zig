@@ -0,0 +1,169 @@
+const std = @import("std");
+const config = @import("../config/config.zig");
+const acl = @import("../security/acl.zig");
+const events_mod = @import("events.zig");
+
+/// Clients tracked before idle ones are forgotten
+const MAX_CLIENTS = 4096;
+
+pub const Verdict = union(enum) {
+    allowed,
+    /// Over the request rate; seconds until the next request is accepted
+    rate_limited: u32,
+    /// Too many failed authentications; seconds until the lockout ends
+    locked_out: u32,
+};
+
+const Client = struct {
+    tokens: f64,
+    updated_ms: i64,
+    failures: u32 = 0,
+    first_failure_ms: i64 = 0,
+    locked_until_ms: i64 = 0,
+};
+
+/// Per-address rate limit and brute-force lockout in front of the admin
+/// API, with every rejection written to the audit log
+pub const AdminGuard = struct {
+    allocator: std.mem.Allocator,
+    config: *const config.AdminProtectionConfig,
+    mutex: std.Thread.Mutex = .{},
+    clients: std.AutoHashMapUnmanaged([16]u8, Client) = .empty,
+    audit_file: ?std.fs.File = null,
+    /// Lockouts are published as ip_blocked
+    events: *events_mod.EventBus,
+
+    pub fn init(allocator: std.mem.Allocator, cfg: *const config.AdminProtectionConfig, bus: *events_mod.EventBus) !AdminGuard {
+        var self = AdminGuard{ .allocator = allocator, .config = cfg, .events = bus };
+        if (cfg.audit_log.len > 0) {
+            const file = std.fs.cwd().createFile(cfg.audit_log, .{ .truncate = false }) catch |err| {
+                std.debug.print("✗ Admin audit log {s}: {any}\n", .{ cfg.audit_log, err });
+                return err;
+            };
+            errdefer file.close();
+            try file.seekFromEnd(0);
+            self.audit_file = file;
+        }
+        return self;
+    }
+
+    pub fn deinit(self: *AdminGuard) void {
+        if (self.audit_file) |file| file.close();
+        self.clients.deinit(self.allocator);
+    }
+
+    /// Whether a request from `peer` may be served
+    pub fn admit(self: *AdminGuard, peer: std.net.Address) Verdict {
+        const ip = acl.addressBytes(peer) orelse return .allowed;
+        const now = std.time.milliTimestamp();
+        const verdict = blk: {
+            self.mutex.lock();
+            defer self.mutex.unlock();
+
+            const client = self.client(ip, now) orelse break :blk Verdict.allowed;
+            if (client.locked_until_ms > now) {
+                break :blk Verdict{ .locked_out = secondsUntil(client.locked_until_ms, now) };
+            }
+            const per_minute = self.config.requests_per_minute;
+            if (per_minute == 0) break :blk Verdict.allowed;
+
+            const burst: f64 = @floatFromInt(per_minute);
+            const elapsed: f64 = @floatFromInt(@max(now - client.updated_ms, 0));
+            client.tokens = @min(burst, client.tokens + elapsed * burst / std.time.ms_per_min);
+            client.updated_ms = now;
+            if (client.tokens < 1) {
+                const wait_ms: i64 = @intFromFloat(@ceil((1 - client.tokens) * std.time.ms_per_min / burst));
+                break :blk Verdict{ .rate_limited = secondsUntil(now + wait_ms, now) };
+            }
+            client.tokens -= 1;
+            break :blk Verdict.allowed;
+        };
+        switch (verdict) {
+            .allowed => {},
+            .rate_limited => self.audit("rate_limited", peer),
+            .locked_out => self.audit("locked_out_request", peer),
+        }
+        return verdict;
+    }
+
+    /// Count a failed authentication; enough of them within the failure
+    /// window lock the address out
+    pub fn authFailed(self: *AdminGuard, peer: std.net.Address) void {
+        const ip = acl.addressBytes(peer) orelse return;
+        const now = std.time.milliTimestamp();
+        const locked = blk: {
+            self.mutex.lock();
+            defer self.mutex.unlock();
+
+            const client = self.client(ip, now) orelse break :blk false;
+            const window_ms = @as(i64, self.config.failure_window_s) * std.time.ms_per_s;
+            if (client.failures == 0 or now - client.first_failure_ms > window_ms) {
+                client.failures = 0;
+                client.first_failure_ms = now;
+            }
+            client.failures += 1;
+            if (self.config.max_auth_failures == 0 or client.failures < self.config.max_auth_failures) break :blk false;
+            client.failures = 0;
+            client.locked_until_ms = now + @as(i64, self.config.lockout_s) * std.time.ms_per_s;
+            break :blk true;
+        };
+        self.audit("auth_failed", peer);
+        if (locked) {
+            self.audit("locked_out", peer);
+            var subject_buf: [64]u8 = undefined;
+            const subject = std.fmt.bufPrint(&subject_buf, "{f}", .{peer}) catch "";
+            self.events.publish(.ip_blocked, subject, "Client {f} locked out of the admin API for {d}s after repeated failed authentications", .{ peer, self.config.lockout_s });
+        }
+    }
+
+    /// A successful authentication clears the address's failures
+    pub fn authSucceeded(self: *AdminGuard, peer: std.net.Address) void {
+        const ip = acl.addressBytes(peer) orelse return;
+        self.mutex.lock();
+        defer self.mutex.unlock();
+        if (self.clients.getPtr(ip)) |client| client.failures = 0;
+    }
+
+    /// The address's entry, created with a full bucket. Null when the table
+    /// is full of clients that can't be forgotten yet.
+    fn client(self: *AdminGuard, ip: [16]u8, now: i64) ?*Client {
+        if (!self.clients.contains(ip) and self.clients.count() >= MAX_CLIENTS) self.prune(now);
+        const entry = self.clients.getOrPut(self.allocator, ip) catch return null;
+        if (!entry.found_existing) {
+            entry.value_ptr.* = .{ .tokens = @floatFromInt(self.config.requests_per_minute), .updated_ms = now };
+        }
+        return entry.value_ptr;
+    }
+
+    /// Forget clients idle for a minute that aren't locked out or counting
+    /// failures
+    fn prune(self: *AdminGuard, now: i64) void {
+        const window_ms = @as(i64, self.config.failure_window_s) * std.time.ms_per_s;
+        var it = self.clients.iterator();
+        while (it.next()) |entry| {
+            const c = entry.value_ptr;
+            const idle = now - c.updated_ms > std.time.ms_per_min;
+            const failing = c.failures > 0 and now - c.first_failure_ms <= window_ms;
+            if (idle and !failing and c.locked_until_ms <= now) self.clients.removeByPtr(entry.key_ptr);
+        }
+    }
+
+    /// One JSON line per event, to the audit log or stderr
+    pub fn audit(self: *AdminGuard, event: []const u8, peer: std.net.Address) void {
+        var buf: [256]u8 = undefined;
+        const line = std.fmt.bufPrint(&buf, "{{\"time_ms\":{d},\"source\":\"admin\",\"event\":\"{s}\",\"client\":\"{f}\"}}\n", .{
+            std.time.milliTimestamp(), event, peer,
+        }) catch return;
+        if (self.audit_file) |file| {
+            self.mutex.lock();
+            defer self.mutex.unlock();
+            file.writeAll(line) catch |err| std.debug.print("✗ Admin audit write failed: {any}\n", .{err});
+        } else {
+            std.debug.print("{s}", .{line});
+        }
+    }
+};
+
+fn secondsUntil(until_ms: i64, now: i64) u32 {
+    return @intCast(@max(1, @divFloor(until_ms - now + std.time.ms_per_s - 1, std.time.ms_per_s)));
+}
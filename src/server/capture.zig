@@ -0,0 +1,224 @@
+const std = @import("std");
+const config = @import("../config/config.zig");
+const request = @import("request.zig");
+const response = @import("response.zig");
+
+const Header = request.Header;
+
+/// Replaces the value of redacted headers
+const REDACTED = "[redacted]";
+
+/// Response details filled in by the forwarder for a captured request
+pub const ResponseRecord = struct {
+    allocator: std.mem.Allocator,
+    version: []const u8 = "HTTP/1.1",
+    headers: []const Header = &[_]Header{},
+
+    /// Copy the upstream response head; it lives in the forwarder's buffer
+    pub fn set(self: *ResponseRecord, res: *const response.ResponseHead) !void {
+        self.version = try self.allocator.dupe(u8, res.version);
+        self.headers = try dupeHeaders(self.allocator, res.headers);
+    }
+};
+
+/// One captured request/response pair
+pub const Entry = struct {
+    route: []const u8,
+    started_ms: i64,
+    time_ms: u32,
+    method: []const u8,
+    url: []const u8,
+    http_version: []const u8,
+    request_headers: []const Header,
+    status: u16,
+    response_version: []const u8,
+    response_headers: []const Header,
+};
+
+/// Admin-triggered recording of request metadata for matching traffic,
+/// exported as a HAR file. Header values on the redaction list are masked
+/// before anything is stored.
+pub const Capture = struct {
+    allocator: std.mem.Allocator,
+    config: *const config.CaptureConfig,
+    mutex: std.Thread.Mutex,
+    /// Owns the entries and the route filter
+    arena: std.heap.ArenaAllocator,
+    entries: std.ArrayList(Entry),
+    /// Route id to capture (null = all routes)
+    route: ?[]const u8,
+    /// Requests still to be captured; zero when idle
+    remaining: usize,
+
+    pub fn init(allocator: std.mem.Allocator, cfg: *const config.CaptureConfig) Capture {
+        return .{
+            .allocator = allocator,
+            .config = cfg,
+            .mutex = .{},
+            .arena = std.heap.ArenaAllocator.init(allocator),
+            .entries = .empty,
+            .route = null,
+            .remaining = 0,
+        };
+    }
+
+    pub fn deinit(self: *Capture) void {
+        self.arena.deinit();
+    }
+
+    /// Discard earlier entries and capture the next `count` requests
+    pub fn start(self: *Capture, route: ?[]const u8, count: usize) !void {
+        self.mutex.lock();
+        defer self.mutex.unlock();
+
+        _ = self.arena.reset(.retain_capacity);
+        self.entries = .empty;
+        self.route = if (route) |r| try self.arena.allocator().dupe(u8, r) else null;
+        self.remaining = @min(count, self.config.max_entries);
+    }
+
+    pub fn stop(self: *Capture) void {
+        self.mutex.lock();
+        defer self.mutex.unlock();
+        self.remaining = 0;
+    }
+
+    /// Reserve a slot for a request on `route`; false when it isn't captured
+    pub fn claim(self: *Capture, route: []const u8) bool {
+        self.mutex.lock();
+        defer self.mutex.unlock();
+
+        if (self.remaining == 0) return false;
+        if (self.route) |r| {
+            if (!std.mem.eql(u8, r, route)) return false;
+        }
+        self.remaining -= 1;
+        return true;
+    }
+
+    /// Store a claimed entry, copying and redacting it
+    pub fn add(self: *Capture, entry: Entry) !void {
+        self.mutex.lock();
+        defer self.mutex.unlock();
+
+        const allocator = self.arena.allocator();
+        var stored = entry;
+        stored.route = try allocator.dupe(u8, entry.route);
+        stored.method = try allocator.dupe(u8, entry.method);
+        stored.url = try allocator.dupe(u8, entry.url);
+        stored.http_version = try allocator.dupe(u8, entry.http_version);
+        stored.response_version = try allocator.dupe(u8, entry.response_version);
+        stored.request_headers = try self.redactHeaders(allocator, entry.request_headers);
+        stored.response_headers = try self.redactHeaders(allocator, entry.response_headers);
+        try self.entries.append(allocator, stored);
+    }
+
+    /// Whether a capture is in progress, and how many entries it holds
+    pub fn status(self: *Capture) struct { active: bool, entries: usize, remaining: usize } {
+        self.mutex.lock();
+        defer self.mutex.unlock();
+        return .{ .active = self.remaining > 0, .entries = self.entries.items.len, .remaining = self.remaining };
+    }
+
+    /// Serialize the captured entries as HAR 1.2
+    pub fn har(self: *Capture, allocator: std.mem.Allocator) ![]const u8 {
+        self.mutex.lock();
+        defer self.mutex.unlock();
+
+        const entries = try allocator.alloc(HarEntry, self.entries.items.len);
+        for (self.entries.items, entries) |entry, *out| {
+            out.* = .{
+                .startedDateTime = try isoTimestamp(allocator, entry.started_ms),
+                .time = entry.time_ms,
+                .request = .{
+                    .method = entry.method,
+                    .url = entry.url,
+                    .httpVersion = entry.http_version,
+                    .headers = entry.request_headers,
+                },
+                .response = .{
+                    .status = entry.status,
+                    .statusText = response.reasonPhrase(entry.status),
+                    .httpVersion = entry.response_version,
+                    .headers = entry.response_headers,
+                    .content = .{ .mimeType = request.findHeader(entry.response_headers, "Content-Type") orelse "" },
+                },
+                .timings = .{ .wait = entry.time_ms },
+                .comment = entry.route,
+            };
+        }
+
+        return std.json.Stringify.valueAlloc(allocator, .{
+            .log = .{
+                .version = "1.2",
+                .creator = .{ .name = "wraith", .version = "0.0.0" },
+                .entries = entries,
+            },
+        }, .{});
+    }
+
+    fn redactHeaders(self: *Capture, allocator: std.mem.Allocator, headers: []const Header) ![]const Header {
+        const copy = try dupeHeaders(allocator, headers);
+        for (copy) |*h| {
+            for (self.config.redact_headers) |name| {
+                if (std.ascii.eqlIgnoreCase(h.name, name)) h.value = REDACTED;
+            }
+        }
+        return copy;
+    }
+};
+
+const HarEntry = struct {
+    startedDateTime: []const u8,
+    time: u32,
+    request: struct {
+        method: []const u8,
+        url: []const u8,
+        httpVersion: []const u8,
+        cookies: []const Header = &[_]Header{},
+        headers: []const Header,
+        queryString: []const Header = &[_]Header{},
+        headersSize: i64 = -1,
+        bodySize: i64 = -1,
+    },
+    response: struct {
+        status: u16,
+        statusText: []const u8,
+        httpVersion: []const u8,
+        cookies: []const Header = &[_]Header{},
+        headers: []const Header,
+        content: struct { size: i64 = -1, mimeType: []const u8 },
+        redirectURL: []const u8 = "",
+        headersSize: i64 = -1,
+        bodySize: i64 = -1,
+    },
+    cache: struct {} = .{},
+    timings: struct { send: u32 = 0, wait: u32, receive: u32 = 0 },
+    /// Route id the request matched
+    comment: []const u8,
+};
+
+fn dupeHeaders(allocator: std.mem.Allocator, headers: []const Header) ![]Header {
+    const copy = try allocator.alloc(Header, headers.len);
+    for (headers, copy) |h, *c| {
+        c.* = .{ .name = try allocator.dupe(u8, h.name), .value = try allocator.dupe(u8, h.value) };
+    }
+    return copy;
+}
+
+/// Format a millisecond Unix timestamp as ISO 8601 in UTC
+fn isoTimestamp(allocator: std.mem.Allocator, ms: i64) ![]const u8 {
+    const epoch = std.time.epoch.EpochSeconds{ .secs = @intCast(@divFloor(ms, 1000)) };
+    const year_day = epoch.getEpochDay().calculateYearDay();
+    const month_day = year_day.calculateMonthDay();
+    const day_seconds = epoch.getDaySeconds();
+    return std.fmt.allocPrint(allocator, "{d:0>4}-{d:0>2}-{d:0>2}T{d:0>2}:{d:0>2}:{d:0>2}.{d:0>3}Z", .{
+        year_day.year,
+        month_day.month.numeric(),
+        month_day.day_index + 1,
+        day_seconds.getHoursIntoDay(),
+        day_seconds.getMinutesIntoHour(),
+        day_seconds.getSecondsIntoMinute(),
+        @as(u64, @intCast(@mod(ms, 1000))),
+    });
+}
@@ -0,0 +1,156 @@
+const std = @import("std");
+
+/// TLS record content type for handshake messages
+pub const CONTENT_HANDSHAKE: u8 = 0x16;
+/// Handshake message type of a ClientHello
+pub const HANDSHAKE_CLIENT_HELLO: u8 = 0x01;
+
+pub const EXT_SERVER_NAME: u16 = 0;
+pub const EXT_SUPPORTED_GROUPS: u16 = 10;
+pub const EXT_EC_POINT_FORMATS: u16 = 11;
+pub const EXT_SIGNATURE_ALGORITHMS: u16 = 13;
+pub const EXT_ALPN: u16 = 16;
+pub const EXT_SUPPORTED_VERSIONS: u16 = 43;
+
+/// Fields of a ClientHello needed for routing and fingerprinting. Slices
+/// point into the buffer that was parsed; lists are raw, without their
+/// length prefix.
+pub const ClientHello = struct {
+    /// Host name from the server_name extension, if sent
+    server_name: ?[]const u8 = null,
+    /// Raw ALPN protocol_name_list (without its length prefix)
+    alpn: []const u8 = "",
+    /// legacy_version
+    version: u16 = 0,
+    /// Cipher suites, two bytes each
+    cipher_suites: []const u8 = "",
+    /// Every extension in the order sent, for walking with `ExtensionIterator`
+    extensions: []const u8 = "",
+    /// Named groups, two bytes each
+    supported_groups: []const u8 = "",
+    /// Point formats, one byte each
+    ec_point_formats: []const u8 = "",
+    /// Signature schemes, two bytes each
+    signature_algorithms: []const u8 = "",
+    /// Versions, two bytes each
+    supported_versions: []const u8 = "",
+
+    /// Whether the client offered the given ALPN protocol
+    pub fn offersAlpn(self: *const ClientHello, protocol: []const u8) bool {
+        var it = AlpnIterator{ .list = self.alpn };
+        while (it.next()) |offered| {
+            if (std.mem.eql(u8, offered, protocol)) return true;
+        }
+        return false;
+    }
+};
+
+/// Extension types of a ClientHello in the order they were sent
+pub const ExtensionIterator = struct {
+    reader: Reader,
+
+    pub fn init(extensions: []const u8) ExtensionIterator {
+        return .{ .reader = .{ .buf = extensions } };
+    }
+
+    pub fn next(self: *ExtensionIterator) ?u16 {
+        // Already bounds-checked by `parse`
+        const ext_type = self.reader.int16() catch return null;
+        _ = self.reader.bytes(self.reader.int16() catch return null) catch return null;
+        return ext_type;
+    }
+};
+
+pub const AlpnIterator = struct {
+    list: []const u8,
+    pos: usize = 0,
+
+    pub fn next(self: *AlpnIterator) ?[]const u8 {
+        if (self.pos >= self.list.len) return null;
+        const len = self.list[self.pos];
+        if (self.pos + 1 + len > self.list.len) return null;
+        const protocol = self.list[self.pos + 1 ..][0..len];
+        self.pos += 1 + len;
+        return protocol;
+    }
+};
+
+/// Whether `buf` starts like a TLS handshake record
+pub fn looksLikeTls(buf: []const u8) bool {
+    return buf.len >= 3 and buf[0] == CONTENT_HANDSHAKE and buf[1] == 0x03;
+}
+
+/// Bytes needed to hold the first TLS record, once its header is available
+pub fn recordLength(buf: []const u8) ?usize {
+    if (buf.len < 5) return null;
+    return 5 + @as(usize, std.mem.readInt(u16, buf[3..5], .big));
+}
+
+/// Parse the ClientHello carried by the first TLS record in `buf`
+pub fn parse(buf: []const u8) !ClientHello {
+    if (!looksLikeTls(buf)) return error.NotTls;
+    const record_len = recordLength(buf) orelse return error.Incomplete;
+    if (buf.len < record_len) return error.Incomplete;
+
+    var r = Reader{ .buf = buf[5..record_len] };
+    if (try r.byte() != HANDSHAKE_CLIENT_HELLO) return error.NotClientHello;
+    _ = try r.bytes(3); // handshake length
+    var hello = ClientHello{ .version = try r.int16() };
+    _ = try r.bytes(32); // random
+    _ = try r.bytes(try r.byte()); // legacy_session_id
+    hello.cipher_suites = try r.bytes(try r.int16());
+    _ = try r.bytes(try r.byte()); // legacy_compression_methods
+
+    if (r.remaining() == 0) return hello;
+
+    hello.extensions = try r.bytes(try r.int16());
+    var extensions = Reader{ .buf = hello.extensions };
+    while (extensions.remaining() > 0) {
+        const ext_type = try extensions.int16();
+        var ext = Reader{ .buf = try extensions.bytes(try extensions.int16()) };
+        switch (ext_type) {
+            EXT_SERVER_NAME => {
+                var names = Reader{ .buf = try ext.bytes(try ext.int16()) };
+                while (names.remaining() > 0) {
+                    const name_type = try names.byte();
+                    const name = try names.bytes(try names.int16());
+                    if (name_type == 0) hello.server_name = name;
+                }
+            },
+            EXT_ALPN => hello.alpn = try ext.bytes(try ext.int16()),
+            EXT_SUPPORTED_GROUPS => hello.supported_groups = try ext.bytes(try ext.int16()),
+            EXT_EC_POINT_FORMATS => hello.ec_point_formats = try ext.bytes(try ext.byte()),
+            EXT_SIGNATURE_ALGORITHMS => hello.signature_algorithms = try ext.bytes(try ext.int16()),
+            EXT_SUPPORTED_VERSIONS => hello.supported_versions = try ext.bytes(try ext.byte()),
+            else => {},
+        }
+    }
+    return hello;
+}
+
+/// Bounds-checked cursor over handshake bytes
+pub const Reader = struct {
+    buf: []const u8,
+    pos: usize = 0,
+
+    pub fn remaining(self: *const Reader) usize {
+        return self.buf.len - self.pos;
+    }
+
+    pub fn byte(self: *Reader) !u8 {
+        const b = try self.bytes(1);
+        return b[0];
+    }
+
+    pub fn int16(self: *Reader) !u16 {
+        const b = try self.bytes(2);
+        return std.mem.readInt(u16, b[0..2], .big);
+    }
+
+    pub fn bytes(self: *Reader, len: usize) ![]const u8 {
+        if (self.remaining() < len) return error.Truncated;
+        const out = self.buf[self.pos..][0..len];
+        self.pos += len;
+        return out;
+    }
+};
@@ -0,0 +1,88 @@
+const std = @import("std");
+const request = @import("request.zig");
+const response = @import("response.zig");
+
+/// Whether a 200 response is one the client already has, judging by the
+/// validators the client sent. If-None-Match takes precedence over
+/// If-Modified-Since, as RFC 9110 requires.
+pub fn notModified(req: *const request.Request, res: *const response.ResponseHead) bool {
+    if (!std.mem.eql(u8, req.method, "GET") and !std.mem.eql(u8, req.method, "HEAD")) return false;
+    if (res.status != 200) return false;
+
+    if (req.header("If-None-Match")) |if_none_match| {
+        const etag = res.header("ETag") orelse return false;
+        return noneMatch(if_none_match, etag);
+    }
+    if (req.header("If-Modified-Since")) |if_modified_since| {
+        const since = parseHttpDate(if_modified_since) orelse return false;
+        const modified = parseHttpDate(res.header("Last-Modified") orelse return false) orelse return false;
+        return modified <= since;
+    }
+    return false;
+}
+
+/// Whether an If-None-Match value lists `etag` ("*" matches anything).
+/// Uses the weak comparison RFC 9110 requires for If-None-Match, so
+/// W/"x" and "x" match each other.
+pub fn noneMatch(if_none_match: []const u8, etag: []const u8) bool {
+    const value = std.mem.trim(u8, if_none_match, " \t");
+    if (std.mem.eql(u8, value, "*")) return true;
+
+    const wanted = opaqueTag(etag) orelse return false;
+    var rest = value;
+    while (nextTag(&rest)) |tag| {
+        if (std.mem.eql(u8, tag, wanted)) return true;
+    }
+    return false;
+}
+
+/// Next entity tag of a comma-separated list, without its weak prefix and
+/// quotes. Tags may themselves contain commas, so the list is split on
+/// the quotes rather than the commas.
+fn nextTag(rest: *[]const u8) ?[]const u8 {
+    var list = std.mem.trimLeft(u8, rest.*, " \t,");
+    if (std.mem.startsWith(u8, list, "W/")) list = list[2..];
+    if (list.len == 0 or list[0] != '"') return null;
+    const end = std.mem.indexOfScalarPos(u8, list, 1, '"') orelse return null;
+    rest.* = list[end + 1 ..];
+    return list[1..end];
+}
+
+/// The quoted part of an ETag like `W/"abc"` or `"abc"`
+fn opaqueTag(etag: []const u8) ?[]const u8 {
+    var rest = etag;
+    return nextTag(&rest);
+}
+
+const month_names = [_][]const u8{ "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec" };
+
+/// Seconds since the epoch of an IMF-fixdate such as
+/// "Sun, 06 Nov 1994 08:49:37 GMT". The obsolete RFC 850 and asctime
+/// forms aren't accepted; callers treat them as absent.
+pub fn parseHttpDate(text: []const u8) ?i64 {
+    const value = std.mem.trim(u8, text, " \t");
+    if (value.len != 29 or value[3] != ',' or !std.mem.endsWith(u8, value, " GMT")) return null;
+
+    const day = std.fmt.parseInt(u8, value[5..7], 10) catch return null;
+    const month: u8 = for (month_names, 1..) |name, i| {
+        if (std.mem.eql(u8, value[8..11], name)) break @intCast(i);
+    } else return null;
+    const year = std.fmt.parseInt(i64, value[12..16], 10) catch return null;
+    const hour = std.fmt.parseInt(u8, value[17..19], 10) catch return null;
+    const minute = std.fmt.parseInt(u8, value[20..22], 10) catch return null;
+    const second = std.fmt.parseInt(u8, value[23..25], 10) catch return null;
+    if (day == 0 or day > 31 or hour > 23 or minute > 59 or second > 60) return null;
+
+    return daysFromCivil(year, month, day) * std.time.s_per_day + @as(i64, hour) * 3600 + @as(i64, minute) * 60 + second;
+}
+
+/// Days between 1970-01-01 and a proleptic Gregorian date
+fn daysFromCivil(year: i64, month: u8, day: u8) i64 {
+    const y = if (month <= 2) year - 1 else year;
+    const era = @divFloor(y, 400);
+    const year_of_era = y - era * 400;
+    const shifted_month: i64 = if (month > 2) month - 3 else month + 9;
+    const day_of_year = @divFloor(153 * shifted_month + 2, 5) + day - 1;
+    const day_of_era = year_of_era * 365 + @divFloor(year_of_era, 4) - @divFloor(year_of_era, 100) + day_of_year;
+    return era * 146097 + day_of_era - 719468;
+}
@@ -0,0 +1,187 @@
+const std = @import("std");
+const config = @import("../config/config.zig");
+const acl = @import("../security/acl.zig");
+const metrics = @import("metrics.zig");
+
+/// Why a new connection was refused
+pub const Refusal = enum { server_full, client_full };
+
+/// Client addresses reported by the admin stats, busiest first
+const TOP_CLIENTS = 10;
+
+/// Registry of open client connections, updated as connections are
+/// accepted and closed. Enforces the server-wide and per-client limits and
+/// backs the admin connection stats.
+pub const ConnectionTracker = struct {
+    allocator: std.mem.Allocator,
+    config: *const config.ServerConfig,
+    mutex: std.Thread.Mutex = .{},
+    active: usize = 0,
+    /// Bound listener addresses
+    listeners: []const std.net.Address,
+    /// Open connections per listener, indexed like `listeners`
+    per_listener: []usize,
+    /// Open connections per client address
+    per_client: std.AutoHashMapUnmanaged([16]u8, u32) = .empty,
+    accepted: std.atomic.Value(u64) = std.atomic.Value(u64).init(0),
+    refused_server_full: std.atomic.Value(u64) = std.atomic.Value(u64).init(0),
+    refused_client_full: std.atomic.Value(u64) = std.atomic.Value(u64).init(0),
+    /// Proxied requests whose client left before the response started
+    client_aborts_waiting: std.atomic.Value(u64) = std.atomic.Value(u64).init(0),
+    /// Proxied requests whose client left while the response was relayed
+    client_aborts_streaming: std.atomic.Value(u64) = std.atomic.Value(u64).init(0),
+
+    pub fn init(allocator: std.mem.Allocator, cfg: *const config.ServerConfig, listeners: []const std.net.Address) !ConnectionTracker {
+        const per_listener = try allocator.alloc(usize, listeners.len);
+        @memset(per_listener, 0);
+        return .{
+            .allocator = allocator,
+            .config = cfg,
+            .listeners = listeners,
+            .per_listener = per_listener,
+        };
+    }
+
+    pub fn deinit(self: *ConnectionTracker) void {
+        self.allocator.free(self.per_listener);
+        self.per_client.deinit(self.allocator);
+    }
+
+    /// Register a newly accepted connection, or say why it must be refused.
+    /// Every registered connection must later be passed to `close`.
+    pub fn open(self: *ConnectionTracker, listener: usize, client: std.net.Address) ?Refusal {
+        self.mutex.lock();
+        defer self.mutex.unlock();
+
+        const max = self.config.max_connections;
+        if (max > 0 and self.active >= max) {
+            _ = self.refused_server_full.fetchAdd(1, .monotonic);
+            return .server_full;
+        }
+
+        if (acl.addressBytes(client)) |ip| {
+            const entry = self.per_client.getOrPut(self.allocator, ip) catch null;
+            if (entry) |e| {
+                if (!e.found_existing) e.value_ptr.* = 0;
+                const per_client_max = self.config.max_connections_per_client;
+                if (per_client_max > 0 and e.value_ptr.* >= per_client_max) {
+                    _ = self.refused_client_full.fetchAdd(1, .monotonic);
+                    return .client_full;
+                }
+                e.value_ptr.* += 1;
+            }
+        }
+
+        self.active += 1;
+        self.per_listener[listener] += 1;
+        _ = self.accepted.fetchAdd(1, .monotonic);
+        return null;
+    }
+
+    pub fn close(self: *ConnectionTracker, listener: usize, client: std.net.Address) void {
+        self.mutex.lock();
+        defer self.mutex.unlock();
+
+        self.active -|= 1;
+        self.per_listener[listener] -|= 1;
+        const ip = acl.addressBytes(client) orelse return;
+        if (self.per_client.getPtr(ip)) |count| {
+            count.* -|= 1;
+            if (count.* == 0) _ = self.per_client.remove(ip);
+        }
+    }
+
+    /// Count a proxied request given up on because its client went away;
+    /// `responded` is whether the response head had been sent
+    pub fn recordClientAbort(self: *ConnectionTracker, responded: bool) void {
+        const counter = if (responded) &self.client_aborts_streaming else &self.client_aborts_waiting;
+        _ = counter.fetchAdd(1, .monotonic);
+    }
+
+    pub fn activeCount(self: *ConnectionTracker) usize {
+        self.mutex.lock();
+        defer self.mutex.unlock();
+        return self.active;
+    }
+
+    pub fn writeMetrics(self: *ConnectionTracker, m: *metrics.Exposition) !void {
+        self.mutex.lock();
+        defer self.mutex.unlock();
+
+        try m.single("wraith_connections_active", .gauge, "Open client connections", self.active);
+        try m.family("wraith_listener_connections_active", .gauge, "Open client connections per listener");
+        for (self.listeners, self.per_listener) |address, count| {
+            var address_buf: [64]u8 = undefined;
+            const listener = std.fmt.bufPrint(&address_buf, "{f}", .{address}) catch "";
+            try m.sample("wraith_listener_connections_active", &.{.{ .name = "listener", .value = listener }}, count);
+        }
+        try m.single("wraith_connections_clients", .gauge, "Client addresses with open connections", self.per_client.count());
+        try m.single("wraith_connections_accepted_total", .counter, "Connections accepted", self.accepted.load(.monotonic));
+        try m.family("wraith_connections_refused_total", .counter, "Connections closed on accept by a connection limit");
+        try m.sample("wraith_connections_refused_total", &.{.{ .name = "reason", .value = "server_full" }}, self.refused_server_full.load(.monotonic));
+        try m.sample("wraith_connections_refused_total", &.{.{ .name = "reason", .value = "client_full" }}, self.refused_client_full.load(.monotonic));
+        try m.family("wraith_client_aborts_total", .counter, "Proxied requests cancelled because the client disconnected");
+        try m.sample("wraith_client_aborts_total", &.{.{ .name = "stage", .value = "waiting" }}, self.client_aborts_waiting.load(.monotonic));
+        try m.sample("wraith_client_aborts_total", &.{.{ .name = "stage", .value = "streaming" }}, self.client_aborts_streaming.load(.monotonic));
+    }
+
+    /// Stats for the admin API, with the busiest client addresses
+    pub fn statsJson(self: *ConnectionTracker, allocator: std.mem.Allocator) ![]const u8 {
+        const Client = struct { address: []const u8, connections: u32 };
+        const Listener = struct { address: []const u8, active: usize };
+
+        var active: usize = 0;
+        var listener_stats: []Listener = &.{};
+        var clients: std.ArrayList(Client) = .empty;
+        {
+            self.mutex.lock();
+            defer self.mutex.unlock();
+
+            active = self.active;
+            listener_stats = try allocator.alloc(Listener, self.per_listener.len);
+            for (self.listeners, self.per_listener, listener_stats) |address, count, *stat| {
+                stat.* = .{ .address = try std.fmt.allocPrint(allocator, "{f}", .{address}), .active = count };
+            }
+            var it = self.per_client.iterator();
+            while (it.next()) |entry| {
+                const address = std.net.Address.initIp6(entry.key_ptr.*, 0, 0, 0);
+                try clients.append(allocator, .{ .address = try formatIp(allocator, address), .connections = entry.value_ptr.* });
+            }
+        }
+
+        std.mem.sort(Client, clients.items, {}, struct {
+            fn busier(_: void, a: Client, b: Client) bool {
+                return a.connections > b.connections;
+            }
+        }.busier);
+
+        return std.json.Stringify.valueAlloc(allocator, .{
+            .active = active,
+            .max_connections = self.config.max_connections,
+            .accepted = self.accepted.load(.monotonic),
+            .refused = .{
+                .server_full = self.refused_server_full.load(.monotonic),
+                .client_full = self.refused_client_full.load(.monotonic),
+            },
+            .client_aborts = .{
+                .waiting = self.client_aborts_waiting.load(.monotonic),
+                .streaming = self.client_aborts_streaming.load(.monotonic),
+            },
+            .listeners = listener_stats,
+            .clients = clients.items.len,
+            .top_clients = clients.items[0..@min(clients.items.len, TOP_CLIENTS)],
+        }, .{});
+    }
+};
+
+/// IPv4 clients are stored as mapped IPv6 addresses; show them as IPv4
+fn formatIp(allocator: std.mem.Allocator, address: std.net.Address) ![]const u8 {
+    const bytes = address.in6.sa.addr;
+    const mapped_prefix = [_]u8{0} ** 10 ++ [_]u8{ 0xff, 0xff };
+    if (std.mem.eql(u8, bytes[0..12], &mapped_prefix)) {
+        return std.fmt.allocPrint(allocator, "{d}.{d}.{d}.{d}", .{ bytes[12], bytes[13], bytes[14], bytes[15] });
+    }
+    const text = try std.fmt.allocPrint(allocator, "{f}", .{address});
+    // Drop the ":0" port suffix and brackets
+    return std.mem.trim(u8, text[0 .. std.mem.lastIndexOfScalar(u8, text, ':') orelse text.len], "[]");
+}
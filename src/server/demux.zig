@@ -0,0 +1,190 @@
+const std = @import("std");
+const config = @import("../config/config.zig");
+const client_hello = @import("client_hello.zig");
+const router = @import("../routing/router.zig");
+const signals = @import("signals.zig");
+const stream_mod = @import("../stream/proxy.zig");
+
+/// Bytes peeked from a new connection; large enough for typical ClientHellos
+pub const PEEK_SIZE = 4096;
+
+pub const DemuxStats = struct {
+    connections_total: std.atomic.Value(u64) = std.atomic.Value(u64).init(0),
+    passthrough: std.atomic.Value(u64) = std.atomic.Value(u64).init(0),
+    rejected: std.atomic.Value(u64) = std.atomic.Value(u64).init(0),
+};
+
+/// Protocol identified from a connection's first bytes
+pub const Protocol = enum {
+    tls,
+    ssh,
+    http,
+    unknown,
+};
+
+/// What to do with a classified connection
+pub const Target = union(enum) {
+    /// Relay the raw bytes to a backend
+    passthrough: []const u8,
+    reject,
+};
+
+/// Shares one listening port between TLS (routed by SNI/ALPN) and
+/// plain-text protocols such as SSH. Every connection is passed through;
+/// none is terminated here.
+pub const DemuxServer = struct {
+    allocator: std.mem.Allocator,
+    config: *const config.DemuxConfig,
+    stats: DemuxStats,
+    stream_stats: stream_mod.StreamStats,
+
+    pub fn init(allocator: std.mem.Allocator, cfg: *const config.Config) DemuxServer {
+        return .{
+            .allocator = allocator,
+            .config = &cfg.server.demux,
+            .stats = .{},
+            .stream_stats = .{},
+        };
+    }
+
+    /// Thread entry point
+    pub fn run(self: *DemuxServer) void {
+        self.start() catch |err| {
+            std.debug.print("✗ Demux listener {s} failed: {any}\n", .{ self.config.listen, err });
+        };
+    }
+
+    pub fn start(self: *DemuxServer) !void {
+        const addr = try std.net.Address.parseIpAndPort(self.config.listen);
+        var server = try addr.listen(.{
+            .reuse_address = true,
+        });
+        defer server.deinit();
+
+        std.debug.print("✓ Protocol demux listening on {s}\n", .{self.config.listen});
+
+        while (!signals.shouldShutdown()) {
+            const conn = server.accept() catch |err| {
+                if (signals.shouldShutdown()) break;
+                return err;
+            };
+
+            const thread = std.Thread.spawn(.{}, handleConnection, .{ self, conn.stream }) catch |err| {
+                std.debug.print("✗ Demux connection thread failed: {any}\n", .{err});
+                conn.stream.close();
+                continue;
+            };
+            thread.detach();
+        }
+    }
+
+    fn handleConnection(self: *DemuxServer, stream: std.net.Stream) void {
+        defer stream.close();
+        _ = self.stats.connections_total.fetchAdd(1, .monotonic);
+
+        var buf: [PEEK_SIZE]u8 = undefined;
+        const peeked = peek(stream, &buf, self.config.peek_timeout_ms) catch |err| {
+            std.debug.print("✗ Demux peek failed: {any}\n", .{err});
+            return;
+        };
+
+        switch (self.classify(peeked)) {
+            .passthrough => |backend| {
+                _ = self.stats.passthrough.fetchAdd(1, .monotonic);
+                self.passthrough(stream, backend) catch |err| {
+                    std.debug.print("✗ Demux passthrough to {s} failed: {any}\n", .{ backend, err });
+                };
+            },
+            .reject => {
+                _ = self.stats.rejected.fetchAdd(1, .monotonic);
+            },
+        }
+    }
+
+    /// Route a connection by its first bytes. TLS goes to the first route
+    /// matching its SNI and ALPN, other protocols to their route; anything
+    /// unmatched goes to the default backend.
+    pub fn classify(self: *const DemuxServer, peeked: []const u8) Target {
+        const protocol = detect(peeked);
+        // A ClientHello larger than the peek buffer is routed without SNI/ALPN
+        const hello: client_hello.ClientHello = if (protocol == .tls)
+            client_hello.parse(peeked) catch .{}
+        else
+            .{};
+
+        for (self.config.routes) |route| {
+            if (!std.mem.eql(u8, route.protocol, @tagName(protocol))) continue;
+            if (protocol == .tls) {
+                if (!router.hostMatches(route.sni, hello.server_name orelse "")) continue;
+                if (route.alpn.len > 0 and !hello.offersAlpn(route.alpn)) continue;
+            }
+            return .{ .passthrough = route.backend };
+        }
+        if (self.config.default_backend.len > 0) return .{ .passthrough = self.config.default_backend };
+        return .reject;
+    }
+
+    fn passthrough(self: *DemuxServer, client: std.net.Stream, backend: []const u8) !void {
+        const addr = try std.net.Address.parseIpAndPort(backend);
+        const upstream = std.net.tcpConnectToAddress(addr) catch |err| {
+            _ = self.stream_stats.upstream_failures.fetchAdd(1, .monotonic);
+            return err;
+        };
+        defer upstream.close();
+
+        // Peeked bytes are still queued on the socket, so the backend sees them too
+        try stream_mod.relay(client, upstream, self.config.idle_timeout_ms, &self.stream_stats);
+    }
+};
+
+/// Identify the protocol from a connection's first bytes
+pub fn detect(buf: []const u8) Protocol {
+    if (client_hello.looksLikeTls(buf)) return .tls;
+    if (std.mem.startsWith(u8, buf, "SSH-")) return .ssh;
+    if (isHttpRequestLine(buf)) return .http;
+    return .unknown;
+}
+
+/// An uppercase method token followed by a space
+fn isHttpRequestLine(buf: []const u8) bool {
+    for (buf, 0..) |c, i| {
+        if (c == ' ') return i > 0;
+        if (!std.ascii.isUpper(c) or i >= 16) return false;
+    }
+    return false;
+}
+
+/// Whether enough bytes have arrived to classify the connection
+fn isComplete(buf: []const u8) bool {
+    if (buf.len > 0 and buf[0] == client_hello.CONTENT_HANDSHAKE) {
+        const needed = client_hello.recordLength(buf) orelse return false;
+        return buf.len >= @min(needed, PEEK_SIZE);
+    }
+    return buf.len >= 8;
+}
+
+/// Peek at the first bytes without consuming them, waiting up to
+/// `timeout_ms` for a full ClientHello or protocol banner. Returns whatever
+/// arrived when the client stays silent.
+pub fn peek(stream: std.net.Stream, buf: []u8, timeout_ms: u32) ![]const u8 {
+    const deadline = std.time.milliTimestamp() + timeout_ms;
+    var len: usize = 0;
+    while (true) {
+        const remaining = deadline - std.time.milliTimestamp();
+        if (remaining <= 0) return buf[0..len];
+
+        var fds = [_]std.posix.pollfd{
+            .{ .fd = stream.handle, .events = std.posix.POLL.IN, .revents = 0 },
+        };
+        if (try std.posix.poll(&fds, @intCast(@min(remaining, std.math.maxInt(i32)))) == 0) return buf[0..len];
+
+        const n = try std.posix.recv(stream.handle, buf, std.posix.MSG.PEEK);
+        if (n == 0) return error.ConnectionClosed;
+        if (isComplete(buf[0..n])) return buf[0..n];
+
+        // The socket stays readable while peeked data is queued; back off
+        // until more arrives
+        if (n == len) std.Thread.sleep(10 * std.time.ns_per_ms);
+        len = n;
+    }
+}
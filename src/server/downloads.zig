@@ -0,0 +1,88 @@
+const std = @import("std");
+const config = @import("../config/config.zig");
+const request = @import("request.zig");
+const response = @import("response.zig");
+const acl = @import("../security/acl.zig");
+
+const Header = request.Header;
+
+const Key = struct {
+    mount: *const config.StaticMountConfig,
+    ip: [16]u8,
+};
+
+/// Downloads in progress per static mount and client address, for the
+/// mounts' `downloads.max_per_client`
+pub const DownloadLimiter = struct {
+    allocator: std.mem.Allocator,
+    mutex: std.Thread.Mutex = .{},
+    /// Signalled whenever a download ends
+    released: std.Thread.Condition = .{},
+    active: std.AutoHashMapUnmanaged(Key, u32) = .empty,
+
+    pub fn init(allocator: std.mem.Allocator) DownloadLimiter {
+        return .{ .allocator = allocator };
+    }
+
+    pub fn deinit(self: *DownloadLimiter) void {
+        self.active.deinit(self.allocator);
+    }
+
+    /// Take a slot for sending `size` bytes from `mount` to `client`,
+    /// waiting up to the mount's queue timeout for one of the client's
+    /// other downloads to end. Fails with `error.TooManyDownloads`.
+    pub fn acquire(self: *DownloadLimiter, mount: *const config.StaticMountConfig, client: std.net.Address, size: u64) !Slot {
+        const limits = &mount.downloads;
+        if (limits.max_per_client == 0 or size < limits.min_size) return .{};
+        // Unix socket clients share no address to limit by
+        const ip = acl.addressBytes(client) orelse return .{};
+        const key = Key{ .mount = mount, .ip = ip };
+
+        self.mutex.lock();
+        defer self.mutex.unlock();
+
+        const deadline = std.time.nanoTimestamp() + @as(i128, limits.queue_timeout_ms) * std.time.ns_per_ms;
+        while (true) {
+            // Without memory to count, the download goes unlimited
+            const entry = self.active.getOrPut(self.allocator, key) catch return .{};
+            if (!entry.found_existing) entry.value_ptr.* = 0;
+            if (entry.value_ptr.* < limits.max_per_client) {
+                entry.value_ptr.* += 1;
+                return .{ .limiter = self, .key = key };
+            }
+
+            const remaining = deadline - std.time.nanoTimestamp();
+            if (remaining <= 0) return error.TooManyDownloads;
+            self.released.timedWait(&self.mutex, @intCast(remaining)) catch {};
+        }
+    }
+
+    fn release(self: *DownloadLimiter, key: Key) void {
+        self.mutex.lock();
+        defer self.mutex.unlock();
+
+        if (self.active.getPtr(key)) |count| {
+            count.* -|= 1;
+            if (count.* == 0) _ = self.active.remove(key);
+        }
+        self.released.broadcast();
+    }
+};
+
+/// A download counted against its client until released
+pub const Slot = struct {
+    limiter: ?*DownloadLimiter = null,
+    key: Key = undefined,
+
+    pub fn release(self: Slot) void {
+        if (self.limiter) |limiter| limiter.release(self.key);
+    }
+};
+
+/// 429 for a client over its download limit
+pub fn writeRejection(stream: std.net.Stream) !void {
+    try response.write(stream, 429, &[_]Header{
+        .{ .name = "Content-Type", .value = "text/plain" },
+        .{ .name = "Retry-After", .value = "5" },
+    }, "429 Too Many Requests\n");
+}
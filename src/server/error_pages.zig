@@ -0,0 +1,71 @@
+const std = @import("std");
+const config = @import("../config/config.zig");
+const request = @import("request.zig");
+const response = @import("response.zig");
+const static = @import("static.zig");
+
+const Header = request.Header;
+
+/// Largest error page file that will be loaded
+const MAX_PAGE_SIZE = 1024 * 1024;
+
+/// Configured error page bodies by status, loaded once at startup
+pub const ErrorPages = struct {
+    allocator: std.mem.Allocator,
+    pages: std.AutoHashMapUnmanaged(u16, Page),
+    /// Loaded file contents; several statuses may share one
+    bodies: std.ArrayList([]const u8),
+
+    const Page = struct {
+        body: []const u8,
+        content_type: []const u8,
+    };
+
+    pub fn init(allocator: std.mem.Allocator, pages: []const config.ErrorPageConfig) !ErrorPages {
+        var self = ErrorPages{ .allocator = allocator, .pages = .empty, .bodies = .empty };
+        errdefer self.deinit();
+
+        for (pages) |page_cfg| {
+            const body = try std.fs.cwd().readFileAlloc(allocator, page_cfg.file, MAX_PAGE_SIZE);
+            self.bodies.append(allocator, body) catch |err| {
+                allocator.free(body);
+                return err;
+            };
+            for (page_cfg.statuses) |status| {
+                try self.pages.put(allocator, status, .{ .body = body, .content_type = static.contentType(page_cfg.file) });
+            }
+        }
+        return self;
+    }
+
+    pub fn deinit(self: *ErrorPages) void {
+        for (self.bodies.items) |body| self.allocator.free(body);
+        self.bodies.deinit(self.allocator);
+        self.pages.deinit(self.allocator);
+    }
+
+    /// Write the configured page for `status`, or the plain-text default.
+    /// `extra_headers` are sent along, e.g. CORS headers for the route.
+    pub fn write(
+        self: *const ErrorPages,
+        allocator: std.mem.Allocator,
+        stream: std.net.Stream,
+        method: []const u8,
+        status: u16,
+        extra_headers: []const Header,
+        keep_alive: bool,
+    ) !void {
+        var default_buf: [128]u8 = undefined;
+        const page = self.pages.get(status) orelse Page{
+            .body = try std.fmt.bufPrint(&default_buf, "{d} {s}\n", .{ status, response.reasonPhrase(status) }),
+            .content_type = "text/plain",
+        };
+
+        const headers = try allocator.alloc(Header, extra_headers.len + 1);
+        @memcpy(headers[0..extra_headers.len], extra_headers);
+        headers[extra_headers.len] = .{ .name = "Content-Type", .value = page.content_type };
+
+        try response.writeHead(stream, status, headers, page.body.len, keep_alive);
+        if (!std.mem.eql(u8, method, "HEAD")) try stream.writeAll(page.body);
+    }
+};
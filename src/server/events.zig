@@ -0,0 +1,260 @@
+const std = @import("std");
+const config = @import("../config/config.zig");
+const secret_mod = @import("../config/secret.zig");
+const signals = @import("signals.zig");
+const notify = @import("notify.zig");
+const rate_limit_mod = @import("../security/rate_limit.zig");
+
+/// Events waiting to be delivered before new ones are dropped
+const MAX_PENDING = 1024;
+/// Longest Kafka REST Proxy URL, after resolving a reference
+const MAX_URL_LEN = 2048;
+
+pub const Event = struct {
+    kind: config.EventKind,
+    /// What the event is about, like "api/10.0.1.5:8080" for an upstream
+    /// server
+    subject: []const u8,
+    message: []const u8,
+    time_ms: i64,
+
+    pub fn json(self: *const Event, allocator: std.mem.Allocator) ![]u8 {
+        return std.json.Stringify.valueAlloc(allocator, .{
+            .event = @tagName(self.kind),
+            .subject = self.subject,
+            .message = self.message,
+            .time_ms = self.time_ms,
+        }, .{});
+    }
+};
+
+/// Whether a sink or webhook filtering on `kinds` takes `kind`
+pub fn wanted(kinds: []const config.EventKind, kind: config.EventKind) bool {
+    return kinds.len == 0 or std.mem.indexOfScalar(config.EventKind, kinds, kind) != null;
+}
+
+/// Internal state changes, published by whichever thread notices them and
+/// delivered in order from a thread of its own, so a slow sink never holds
+/// up a health check or the admin API
+pub const EventBus = struct {
+    allocator: std.mem.Allocator,
+    config: *const config.EventsConfig,
+    mutex: std.Thread.Mutex = .{},
+    wake: std.Thread.Condition = .{},
+    pending: std.ArrayList(Event) = .empty,
+    notifier: notify.Notifier,
+    sinks: []Sink,
+
+    pub fn init(allocator: std.mem.Allocator, cfg: *const config.Config) !EventBus {
+        const sinks = try allocator.alloc(Sink, cfg.events.sinks.len);
+        errdefer allocator.free(sinks);
+        var opened: usize = 0;
+        errdefer for (sinks[0..opened]) |*sink| sink.deinit();
+        for (cfg.events.sinks, sinks) |*sink_cfg, *sink| {
+            sink.* = try Sink.init(sink_cfg);
+            opened += 1;
+        }
+        return .{
+            .allocator = allocator,
+            .config = &cfg.events,
+            .notifier = notify.Notifier.init(allocator, &cfg.notifications),
+            .sinks = sinks,
+        };
+    }
+
+    pub fn deinit(self: *EventBus) void {
+        for (self.pending.items) |event| self.free(event);
+        self.pending.deinit(self.allocator);
+        for (self.sinks) |*sink| sink.deinit();
+        self.allocator.free(self.sinks);
+        self.notifier.deinit();
+    }
+
+    /// Whether anything consumes events, so the delivery thread is needed
+    pub fn enabled(self: *const EventBus) bool {
+        return self.sinks.len > 0 or self.notifier.config.webhooks.len > 0;
+    }
+
+    pub fn publish(self: *EventBus, kind: config.EventKind, subject: []const u8, comptime fmt: []const u8, args: anytype) void {
+        if (!self.enabled()) return;
+        self.mutex.lock();
+        defer self.mutex.unlock();
+
+        if (self.pending.items.len >= MAX_PENDING) {
+            std.debug.print("✗ Event queue full, dropping {s} for {s}\n", .{ @tagName(kind), subject });
+            return;
+        }
+        self.queue(kind, subject, fmt, args) catch |err| {
+            std.debug.print("✗ Event {s} for {s} not queued: {any}\n", .{ @tagName(kind), subject, err });
+            return;
+        };
+        self.wake.signal();
+    }
+
+    fn queue(self: *EventBus, kind: config.EventKind, subject: []const u8, comptime fmt: []const u8, args: anytype) !void {
+        const owned_subject = try self.allocator.dupe(u8, subject);
+        errdefer self.allocator.free(owned_subject);
+        const message = try std.fmt.allocPrint(self.allocator, fmt, args);
+        errdefer self.allocator.free(message);
+        try self.pending.append(self.allocator, .{
+            .kind = kind,
+            .subject = owned_subject,
+            .message = message,
+            .time_ms = std.time.milliTimestamp(),
+        });
+    }
+
+    fn free(self: *EventBus, event: Event) void {
+        self.allocator.free(event.subject);
+        self.allocator.free(event.message);
+    }
+
+    /// Thread entry point: deliver queued events, and watch rate-limit
+    /// denials for spikes, until shutdown
+    pub fn run(self: *EventBus, rate_limiter: *rate_limit_mod.RateLimiter) void {
+        var window_start = std.time.milliTimestamp();
+        var window_denied = deniedTotal(rate_limiter);
+        while (!signals.shouldShutdown()) {
+            if (self.next()) |event| {
+                defer self.free(event);
+                self.deliver(&event);
+            }
+
+            const now = std.time.milliTimestamp();
+            if (now - window_start < std.time.ms_per_min) continue;
+            const denied = deniedTotal(rate_limiter);
+            const spike = self.config.rate_limit_spike;
+            if (spike > 0 and denied - window_denied >= spike) {
+                self.publish(.rate_limit_spike, "rate_limit", "{d} requests refused by rate limits in the last minute (threshold {d})", .{ denied - window_denied, spike });
+            }
+            window_start = now;
+            window_denied = denied;
+        }
+    }
+
+    /// The oldest queued event, waiting up to a second for one
+    fn next(self: *EventBus) ?Event {
+        self.mutex.lock();
+        defer self.mutex.unlock();
+        if (self.pending.items.len == 0) {
+            self.wake.timedWait(&self.mutex, std.time.ns_per_s) catch {};
+        }
+        if (self.pending.items.len == 0) return null;
+        return self.pending.orderedRemove(0);
+    }
+
+    fn deliver(self: *EventBus, event: *const Event) void {
+        self.notifier.deliver(event);
+        for (self.sinks) |*sink| {
+            if (!wanted(sink.config.events, event.kind)) continue;
+            sink.deliver(self.allocator, event) catch |err| {
+                std.debug.print("✗ {s} event sink failed for {s}: {any}\n", .{ @tagName(sink.config.kind), @tagName(event.kind), err });
+            };
+        }
+    }
+};
+
+/// One configured destination for events
+const Sink = struct {
+    config: *const config.EventSinkConfig,
+    /// log: the file appended to, or null for stderr
+    file: ?std.fs.File = null,
+    /// nats: the open connection, reconnected after a failure
+    nats: ?std.net.Stream = null,
+
+    fn init(cfg: *const config.EventSinkConfig) !Sink {
+        var sink = Sink{ .config = cfg };
+        if (cfg.kind == .log and cfg.path.len > 0) {
+            const file = std.fs.cwd().createFile(cfg.path, .{ .truncate = false }) catch |err| {
+                std.debug.print("✗ Event log {s}: {any}\n", .{ cfg.path, err });
+                return err;
+            };
+            errdefer file.close();
+            try file.seekFromEnd(0);
+            sink.file = file;
+        }
+        return sink;
+    }
+
+    fn deinit(self: *Sink) void {
+        if (self.file) |file| file.close();
+        if (self.nats) |stream| stream.close();
+    }
+
+    fn deliver(self: *Sink, allocator: std.mem.Allocator, event: *const Event) !void {
+        const payload = try event.json(allocator);
+        defer allocator.free(payload);
+        switch (self.config.kind) {
+            .log => if (self.file) |file| {
+                try file.writeAll(payload);
+                try file.writeAll("\n");
+            } else {
+                std.debug.print("{s}\n", .{payload});
+            },
+            .webhook => try notify.post(allocator, self.config.url, .json, event),
+            .nats => self.publishNats(allocator, event.kind, payload) catch |err| {
+                // The next event starts over with a fresh connection
+                if (self.nats) |stream| stream.close();
+                self.nats = null;
+                return err;
+            },
+            .kafka => try self.produceKafka(allocator, event, payload),
+        }
+    }
+
+    /// PUB over a plain NATS connection. The server's INFO is read and
+    /// CONNECT sent once per connection; without "verbose", no reply is
+    /// expected to each PUB.
+    fn publishNats(self: *Sink, allocator: std.mem.Allocator, kind: config.EventKind, payload: []const u8) !void {
+        if (self.nats == null) {
+            const address = try std.net.Address.parseIpAndPort(self.config.address);
+            const stream = try std.net.tcpConnectToAddress(address);
+            errdefer stream.close();
+
+            var info_buf: [4096]u8 = undefined;
+            const n = try stream.read(&info_buf);
+            if (!std.mem.startsWith(u8, info_buf[0..n], "INFO ")) return error.InvalidNatsGreeting;
+
+            var token_buf: [512]u8 = undefined;
+            const token = try secret_mod.resolveInto(self.config.token, &token_buf);
+            const connect = if (token.len > 0)
+                try std.json.Stringify.valueAlloc(allocator, .{ .verbose = false, .pedantic = false, .name = "wraith", .auth_token = token }, .{})
+            else
+                try std.json.Stringify.valueAlloc(allocator, .{ .verbose = false, .pedantic = false, .name = "wraith" }, .{});
+            defer allocator.free(connect);
+            try stream.writeAll("CONNECT ");
+            try stream.writeAll(connect);
+            try stream.writeAll("\r\n");
+            self.nats = stream;
+        }
+
+        const stream = self.nats.?;
+        const head = try std.fmt.allocPrint(allocator, "PUB {s}.{s} {d}\r\n", .{ self.config.subject, @tagName(kind), payload.len });
+        defer allocator.free(head);
+        try stream.writeAll(head);
+        try stream.writeAll(payload);
+        try stream.writeAll("\r\n");
+    }
+
+    /// One record per event through a Kafka REST Proxy (v2 API), keyed by
+    /// the event's subject so a subject's events stay in one partition
+    fn produceKafka(self: *Sink, allocator: std.mem.Allocator, event: *const Event, payload: []const u8) !void {
+        var url_buf: [MAX_URL_LEN]u8 = undefined;
+        const base = std.mem.trimRight(u8, try secret_mod.resolveInto(self.config.url, &url_buf), "/");
+        const url = try std.fmt.allocPrint(allocator, "{s}/topics/{s}", .{ base, self.config.topic });
+        defer allocator.free(url);
+
+        const value = try std.json.parseFromSlice(std.json.Value, allocator, payload, .{});
+        defer value.deinit();
+        const body = try std.json.Stringify.valueAlloc(allocator, .{
+            .records = &[_]struct { key: []const u8, value: std.json.Value }{.{ .key = event.subject, .value = value.value }},
+        }, .{});
+        defer allocator.free(body);
+        try notify.postJson(allocator, url, "application/vnd.kafka.json.v2+json", body);
+    }
+};
+
+fn deniedTotal(rate_limiter: *rate_limit_mod.RateLimiter) u64 {
+    const s = rate_limiter.stats();
+    return s.denied_global + s.denied_per_ip + s.denied_route;
+}
@@ -0,0 +1,145 @@
+const std = @import("std");
+const config = @import("../config/config.zig");
+
+/// What an injected fault does to a route's requests
+pub const Fault = struct {
+    /// Held before the request is handled, in milliseconds
+    delay_ms: u32 = 0,
+    /// Requests answered with `abort_status` instead of being handled, in
+    /// percent
+    abort_percent: u8 = 0,
+    abort_status: u16 = 503,
+    /// Unix milliseconds when the fault lifts
+    expires_ms: i64 = 0,
+};
+
+/// What to do with one request
+pub const Action = struct {
+    delay_ms: u32 = 0,
+    /// Status to answer with instead of handling the request
+    abort: ?u16 = null,
+};
+
+const Entry = struct {
+    fault: Fault,
+    delayed: u64 = 0,
+    aborted: u64 = 0,
+};
+
+/// Faults set through the admin API for chaos testing, by route id. Each
+/// lifts on its own once it expires.
+pub const Faults = struct {
+    allocator: std.mem.Allocator,
+    config: *const config.FaultInjectionConfig,
+    mutex: std.Thread.Mutex = .{},
+    /// Keys are owned
+    routes: std.StringHashMapUnmanaged(Entry) = .empty,
+
+    pub fn init(allocator: std.mem.Allocator, cfg: *const config.FaultInjectionConfig) Faults {
+        return .{ .allocator = allocator, .config = cfg };
+    }
+
+    pub fn deinit(self: *Faults) void {
+        var it = self.routes.keyIterator();
+        while (it.next()) |key| self.allocator.free(key.*);
+        self.routes.deinit(self.allocator);
+    }
+
+    /// Inject `fault` into requests on `route` until it expires, replacing
+    /// any fault already set there
+    pub fn set(self: *Faults, route: []const u8, fault: Fault) !void {
+        self.mutex.lock();
+        defer self.mutex.unlock();
+
+        if (self.routes.getPtr(route)) |entry| {
+            entry.* = .{ .fault = fault };
+            return;
+        }
+        const key = try self.allocator.dupe(u8, route);
+        errdefer self.allocator.free(key);
+        try self.routes.put(self.allocator, key, .{ .fault = fault });
+        std.debug.print("✓ Injecting faults into route {s}\n", .{route});
+    }
+
+    /// Lift the fault on `route`, or every fault when null. Returns how
+    /// many were lifted.
+    pub fn clear(self: *Faults, route: ?[]const u8) usize {
+        self.mutex.lock();
+        defer self.mutex.unlock();
+
+        const id = route orelse {
+            const count = self.routes.count();
+            var it = self.routes.keyIterator();
+            while (it.next()) |key| self.allocator.free(key.*);
+            self.routes.clearRetainingCapacity();
+            return count;
+        };
+        const removed = self.routes.fetchRemove(id) orelse return 0;
+        self.allocator.free(removed.key);
+        return 1;
+    }
+
+    /// The fault to apply to a request on `route`, if one is set
+    pub fn check(self: *Faults, route: []const u8) ?Action {
+        self.mutex.lock();
+        defer self.mutex.unlock();
+
+        if (self.routes.count() == 0) return null;
+        self.pruneLocked(std.time.milliTimestamp());
+        const entry = self.routes.getPtr(route) orelse return null;
+        const fault = entry.fault;
+
+        var action = Action{ .delay_ms = fault.delay_ms };
+        if (fault.delay_ms > 0) entry.delayed += 1;
+        if (fault.abort_percent > 0 and std.crypto.random.uintLessThan(u8, 100) < fault.abort_percent) {
+            action.abort = fault.abort_status;
+            entry.aborted += 1;
+        }
+        return action;
+    }
+
+    /// Faults in effect with their counts, for /admin/faults
+    pub fn json(self: *Faults, allocator: std.mem.Allocator) ![]const u8 {
+        self.mutex.lock();
+        defer self.mutex.unlock();
+
+        const now_ms = std.time.milliTimestamp();
+        self.pruneLocked(now_ms);
+        const Item = struct {
+            route: []const u8,
+            delay_ms: u32,
+            abort_percent: u8,
+            abort_status: u16,
+            expires_in_s: i64,
+            delayed: u64,
+            aborted: u64,
+        };
+        var items: std.ArrayList(Item) = .empty;
+        var it = self.routes.iterator();
+        while (it.next()) |e| {
+            const fault = e.value_ptr.fault;
+            try items.append(allocator, .{
+                .route = e.key_ptr.*,
+                .delay_ms = fault.delay_ms,
+                .abort_percent = fault.abort_percent,
+                .abort_status = fault.abort_status,
+                .expires_in_s = @divFloor(fault.expires_ms - now_ms + std.time.ms_per_s - 1, std.time.ms_per_s),
+                .delayed = e.value_ptr.delayed,
+                .aborted = e.value_ptr.aborted,
+            });
+        }
+        return std.json.Stringify.valueAlloc(allocator, items.items, .{});
+    }
+
+    /// Drop faults past their expiry
+    fn pruneLocked(self: *Faults, now_ms: i64) void {
+        var it = self.routes.iterator();
+        while (it.next()) |e| {
+            if (e.value_ptr.fault.expires_ms > now_ms) continue;
+            std.debug.print("✓ Fault injection on route {s} expired\n", .{e.key_ptr.*});
+            const key = e.key_ptr.*;
+            self.routes.removeByPtr(e.key_ptr);
+            self.allocator.free(key);
+        }
+    }
+};
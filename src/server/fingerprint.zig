@@ -0,0 +1,252 @@
+const std = @import("std");
+const config = @import("../config/config.zig");
+const client_hello = @import("client_hello.zig");
+const request = @import("request.zig");
+
+const Md5 = std.crypto.hash.Md5;
+const Sha256 = std.crypto.hash.sha2.Sha256;
+
+/// Hash part standing in for an empty list in JA4
+const EMPTY_HASH = "000000000000";
+
+/// A TLS client's fingerprints, worked out from its ClientHello
+pub const Fingerprint = struct {
+    /// MD5 of the JA3 string, in lowercase hex
+    ja3: [32]u8,
+    /// JA4, as in "t13d1516h2_8daaf6152771_e5627efa2ab1"
+    ja4: [36]u8,
+
+    pub fn compute(allocator: std.mem.Allocator, hello: *const client_hello.ClientHello) !Fingerprint {
+        return .{ .ja3 = try ja3(allocator, hello), .ja4 = try ja4(allocator, hello) };
+    }
+
+    /// Whether a rule names either fingerprint
+    pub fn listed(self: *const Fingerprint, rules: []const []const u8) bool {
+        for (rules) |rule| {
+            if (std.ascii.eqlIgnoreCase(rule, &self.ja3) or std.mem.eql(u8, rule, &self.ja4)) return true;
+        }
+        return false;
+    }
+};
+
+pub fn validate(cfg: *const config.FingerprintConfig) !void {
+    for ([_][]const []const u8{ cfg.allow, cfg.deny }) |rules| {
+        for (rules) |rule| {
+            if (!isJa3(rule) and !isJa4(rule)) return error.InvalidFingerprintRule;
+        }
+    }
+}
+
+fn isJa3(rule: []const u8) bool {
+    if (rule.len != 32) return false;
+    for (rule) |c| if (!std.ascii.isHex(c)) return false;
+    return true;
+}
+
+fn isJa4(rule: []const u8) bool {
+    return rule.len == 36 and rule[0] == 't' and rule[10] == '_' and rule[23] == '_';
+}
+
+/// Whether a connection with this fingerprint may go on to the handshake.
+/// Null means the ClientHello couldn't be read.
+pub fn admitted(cfg: *const config.FingerprintConfig, fingerprint: ?*const Fingerprint) bool {
+    const fp = fingerprint orelse return cfg.allow.len == 0;
+    if (cfg.allow.len > 0 and !fp.listed(cfg.allow)) return false;
+    return !fp.listed(cfg.deny);
+}
+
+/// Replace any fingerprint headers the client sent with the connection's
+/// own. `headers` backs `req.headers` and has room for two more.
+pub fn setHeaders(cfg: *const config.FingerprintConfig, fingerprint: ?*const Fingerprint, req: *request.Request, headers: []request.Header) void {
+    var len: usize = 0;
+    for (req.headers) |h| {
+        if (isHeader(cfg.ja3_header, h.name) or isHeader(cfg.ja4_header, h.name)) continue;
+        headers[len] = h;
+        len += 1;
+    }
+    if (fingerprint) |fp| {
+        const added = [_]request.Header{
+            .{ .name = cfg.ja3_header, .value = &fp.ja3 },
+            .{ .name = cfg.ja4_header, .value = &fp.ja4 },
+        };
+        for (added) |h| {
+            if (h.name.len == 0 or len == headers.len) continue;
+            headers[len] = h;
+            len += 1;
+        }
+    }
+    req.headers = headers[0..len];
+}
+
+fn isHeader(configured: []const u8, name: []const u8) bool {
+    return configured.len > 0 and std.ascii.eqlIgnoreCase(configured, name);
+}
+
+/// GREASE values (RFC 8701) are random per connection and left out of
+/// both fingerprints
+pub fn isGrease(value: u16) bool {
+    return value & 0x0f0f == 0x0a0a and value >> 8 == value & 0xff;
+}
+
+/// MD5 of "version,ciphers,extensions,groups,point formats", each list
+/// in the order sent, in decimal and joined with dashes
+pub fn ja3(allocator: std.mem.Allocator, hello: *const client_hello.ClientHello) ![32]u8 {
+    var text: std.ArrayList(u8) = .empty;
+    defer text.deinit(allocator);
+
+    try text.print(allocator, "{d},", .{hello.version});
+    try appendList(allocator, &text, hello.cipher_suites);
+    try text.append(allocator, ',');
+    var extensions = client_hello.ExtensionIterator.init(hello.extensions);
+    var first = true;
+    while (extensions.next()) |ext| {
+        if (isGrease(ext)) continue;
+        if (!first) try text.append(allocator, '-');
+        try text.print(allocator, "{d}", .{ext});
+        first = false;
+    }
+    try text.append(allocator, ',');
+    try appendList(allocator, &text, hello.supported_groups);
+    try text.append(allocator, ',');
+    for (hello.ec_point_formats, 0..) |format, i| {
+        if (i > 0) try text.append(allocator, '-');
+        try text.print(allocator, "{d}", .{format});
+    }
+
+    var digest: [Md5.digest_length]u8 = undefined;
+    Md5.hash(text.items, &digest, .{});
+    return std.fmt.bytesToHex(digest, .lower);
+}
+
+/// Two-byte values from `list` in decimal, joined with dashes
+fn appendList(allocator: std.mem.Allocator, text: *std.ArrayList(u8), list: []const u8) !void {
+    var first = true;
+    var i: usize = 0;
+    while (i + 2 <= list.len) : (i += 2) {
+        const value = std.mem.readInt(u16, list[i..][0..2], .big);
+        if (isGrease(value)) continue;
+        if (!first) try text.append(allocator, '-');
+        try text.print(allocator, "{d}", .{value});
+        first = false;
+    }
+}
+
+/// JA4: protocol, version, SNI, counts and ALPN, then truncated SHA-256
+/// hashes of the sorted ciphers and of the sorted extensions with the
+/// signature algorithms
+pub fn ja4(allocator: std.mem.Allocator, hello: *const client_hello.ClientHello) ![36]u8 {
+    const ciphers = try values(allocator, hello.cipher_suites);
+    defer allocator.free(ciphers);
+
+    var extensions: std.ArrayList(u16) = .empty;
+    defer extensions.deinit(allocator);
+    var it = client_hello.ExtensionIterator.init(hello.extensions);
+    var extension_count: usize = 0;
+    while (it.next()) |ext| {
+        if (isGrease(ext)) continue;
+        extension_count += 1;
+        // Counted, but left out of the hash
+        if (ext == client_hello.EXT_SERVER_NAME or ext == client_hello.EXT_ALPN) continue;
+        try extensions.append(allocator, ext);
+    }
+
+    const signature_algorithms = try values(allocator, hello.signature_algorithms);
+    defer allocator.free(signature_algorithms);
+
+    const alpn = alpnCode(hello);
+    var out: [36]u8 = undefined;
+    _ = try std.fmt.bufPrint(out[0..10], "t{s}{c}{d:0>2}{d:0>2}{s}", .{
+        versionCode(hello),
+        @as(u8, if (hello.server_name != null) 'd' else 'i'),
+        @min(ciphers.len, 99),
+        @min(extension_count, 99),
+        &alpn,
+    });
+    out[10] = '_';
+    out[23] = '_';
+
+    var text: std.ArrayList(u8) = .empty;
+    defer text.deinit(allocator);
+
+    std.mem.sort(u16, ciphers, {}, std.sort.asc(u16));
+    try appendHex(allocator, &text, ciphers);
+    truncatedHash(out[11..23], text.items);
+
+    text.clearRetainingCapacity();
+    std.mem.sort(u16, extensions.items, {}, std.sort.asc(u16));
+    try appendHex(allocator, &text, extensions.items);
+    if (signature_algorithms.len > 0) {
+        try text.append(allocator, '_');
+        try appendHex(allocator, &text, signature_algorithms);
+    }
+    if (extensions.items.len == 0) {
+        @memcpy(out[24..36], EMPTY_HASH);
+    } else {
+        truncatedHash(out[24..36], text.items);
+    }
+    return out;
+}
+
+/// Two-byte values from `list` without GREASE; owned by the caller
+fn values(allocator: std.mem.Allocator, list: []const u8) ![]u16 {
+    var out: std.ArrayList(u16) = .empty;
+    errdefer out.deinit(allocator);
+    var i: usize = 0;
+    while (i + 2 <= list.len) : (i += 2) {
+        const value = std.mem.readInt(u16, list[i..][0..2], .big);
+        if (!isGrease(value)) try out.append(allocator, value);
+    }
+    return out.toOwnedSlice(allocator);
+}
+
+/// Four-digit lowercase hex values joined with commas
+fn appendHex(allocator: std.mem.Allocator, text: *std.ArrayList(u8), list: []const u16) !void {
+    for (list, 0..) |value, i| {
+        if (i > 0) try text.append(allocator, ',');
+        try text.print(allocator, "{x:0>4}", .{value});
+    }
+}
+
+fn truncatedHash(out: *[12]u8, text: []const u8) void {
+    if (text.len == 0) {
+        @memcpy(out, EMPTY_HASH);
+        return;
+    }
+    var digest: [Sha256.digest_length]u8 = undefined;
+    Sha256.hash(text, &digest, .{});
+    const hex = std.fmt.bytesToHex(digest, .lower);
+    @memcpy(out, hex[0..12]);
+}
+
+/// Highest version offered, from supported_versions when sent
+fn versionCode(hello: *const client_hello.ClientHello) []const u8 {
+    var version = hello.version;
+    var i: usize = 0;
+    var highest: u16 = 0;
+    while (i + 2 <= hello.supported_versions.len) : (i += 2) {
+        const offered = std.mem.readInt(u16, hello.supported_versions[i..][0..2], .big);
+        if (!isGrease(offered)) highest = @max(highest, offered);
+    }
+    if (highest != 0) version = highest;
+    return switch (version) {
+        0x0304 => "13",
+        0x0303 => "12",
+        0x0302 => "11",
+        0x0301 => "10",
+        0x0300 => "s3",
+        else => "00",
+    };
+}
+
+/// First and last character of the first ALPN protocol, or of its hex
+/// form when either isn't alphanumeric
+fn alpnCode(hello: *const client_hello.ClientHello) [2]u8 {
+    var it = client_hello.AlpnIterator{ .list = hello.alpn };
+    const protocol = it.next() orelse return .{ '0', '0' };
+    if (protocol.len == 0) return .{ '0', '0' };
+    const first = protocol[0];
+    const last = protocol[protocol.len - 1];
+    if (std.ascii.isAlphanumeric(first) and std.ascii.isAlphanumeric(last)) return .{ first, last };
+    const digits = "0123456789abcdef";
+    return .{ digits[first >> 4], digits[last & 0x0f] };
+}
@@ -0,0 +1,257 @@
+const std = @import("std");
+const config = @import("../config/config.zig");
+const signals = @import("signals.zig");
+const stats_mod = @import("../routing/stats.zig");
+const connections_mod = @import("connections.zig");
+
+/// Identifies a saved history file and its layout
+const MAGIC = "WRAITHH1";
+/// Saved files larger than this are refused rather than read
+const MAX_FILE_SIZE = 1024 * 1024;
+
+/// Resolution and reach of each tier: five minutes of seconds, three hours
+/// of minutes, a day of five-minute points and a week of hours
+const specs = [_]struct { name: []const u8, step_s: i64, capacity: usize }{
+    .{ .name = "1s", .step_s = 1, .capacity = 300 },
+    .{ .name = "1m", .step_s = 60, .capacity = 180 },
+    .{ .name = "5m", .step_s = 300, .capacity = 288 },
+    .{ .name = "1h", .step_s = 3600, .capacity = 168 },
+};
+
+const Totals = struct { u64, u64 };
+
+/// Traffic over one interval
+pub const Point = struct {
+    /// Unix time the interval starts, in seconds
+    start: i64,
+    requests: u64 = 0,
+    /// 5xx responses
+    errors: u64 = 0,
+    /// Most client connections open at once
+    connections: u64 = 0,
+
+    fn merge(self: *Point, other: Point) void {
+        self.requests += other.requests;
+        self.errors += other.errors;
+        self.connections = @max(self.connections, other.connections);
+    }
+};
+
+/// Closed points of one resolution in a ring, plus the point still filling
+const Tier = struct {
+    step_s: i64,
+    points: []Point,
+    len: usize = 0,
+    next: usize = 0,
+    open: ?Point = null,
+
+    /// Fold a one-second sample into the point covering it
+    fn add(self: *Tier, sample: Point) void {
+        const start = @divFloor(sample.start, self.step_s) * self.step_s;
+        if (self.open) |*open| {
+            if (open.start == start) return open.merge(sample);
+            self.push(open.*);
+        }
+        var point = sample;
+        point.start = start;
+        self.open = point;
+    }
+
+    fn push(self: *Tier, point: Point) void {
+        self.points[self.next] = point;
+        self.next = (self.next + 1) % self.points.len;
+        self.len = @min(self.len + 1, self.points.len);
+    }
+
+    /// The `i`th closed point, oldest first
+    fn at(self: *const Tier, i: usize) Point {
+        return self.points[(self.next + self.points.len - self.len + i) % self.points.len];
+    }
+};
+
+/// Server-wide requests, errors and connections, sampled every second and
+/// downsampled into coarser tiers. Saved to disk so the admin API can show
+/// the last day of traffic across restarts without an external TSDB.
+pub const History = struct {
+    allocator: std.mem.Allocator,
+    config: *const config.HistoryConfig,
+    mutex: std.Thread.Mutex = .{},
+    tiers: [specs.len]Tier,
+    /// Route totals at the previous sample
+    last_requests: u64 = 0,
+    last_errors: u64 = 0,
+
+    pub fn init(allocator: std.mem.Allocator, cfg: *const config.HistoryConfig) !History {
+        var tiers: [specs.len]Tier = undefined;
+        var allocated: usize = 0;
+        errdefer for (tiers[0..allocated]) |tier| allocator.free(tier.points);
+        for (specs, &tiers) |spec, *tier| {
+            tier.* = .{ .step_s = spec.step_s, .points = try allocator.alloc(Point, spec.capacity) };
+            allocated += 1;
+        }
+        return .{
+            .allocator = allocator,
+            .config = cfg,
+            .tiers = tiers,
+        };
+    }
+
+    pub fn deinit(self: *History) void {
+        for (self.tiers) |tier| self.allocator.free(tier.points);
+    }
+
+    /// Thread entry point: restore the saved history, then sample every
+    /// second until shutdown, saving as configured and once more at the end
+    pub fn run(
+        self: *History,
+        route_stats: *const stats_mod.RouteStatsRegistry,
+        connections: *connections_mod.ConnectionTracker,
+    ) void {
+        if (self.config.path.len > 0) {
+            self.load() catch |err| std.debug.print("✗ Traffic history {s} not restored: {any}\n", .{ self.config.path, err });
+        }
+        self.last_requests, self.last_errors = totals(route_stats);
+
+        var last_save = std.time.timestamp();
+        while (!signals.shouldShutdown()) {
+            const now_ms = std.time.milliTimestamp();
+            std.Thread.sleep(@intCast((std.time.ms_per_s - @mod(now_ms, std.time.ms_per_s)) * std.time.ns_per_ms));
+            const now = std.time.timestamp();
+            self.sample(now - 1, totals(route_stats), connections.activeCount());
+            if (self.config.path.len > 0 and now - last_save >= self.config.save_interval_s) {
+                self.saveLogged();
+                last_save = now;
+            }
+        }
+        if (self.config.path.len > 0) self.saveLogged();
+    }
+
+    /// Record the second starting at `second`
+    fn sample(self: *History, second: i64, route_totals: Totals, connections: usize) void {
+        const requests, const errors = route_totals;
+        const point = Point{
+            .start = second,
+            .requests = requests -| self.last_requests,
+            .errors = errors -| self.last_errors,
+            .connections = connections,
+        };
+        self.last_requests = requests;
+        self.last_errors = errors;
+
+        self.mutex.lock();
+        defer self.mutex.unlock();
+        for (&self.tiers) |*tier| tier.add(point);
+    }
+
+    /// Points of the named tier, oldest first, ending with the one still
+    /// filling
+    pub fn json(self: *History, allocator: std.mem.Allocator, tier_name: []const u8) !?[]const u8 {
+        const index = for (specs, 0..) |spec, i| {
+            if (std.mem.eql(u8, spec.name, tier_name)) break i;
+        } else return null;
+
+        var points: std.ArrayList(Point) = .empty;
+        {
+            self.mutex.lock();
+            defer self.mutex.unlock();
+
+            const tier = &self.tiers[index];
+            try points.ensureTotalCapacity(allocator, tier.len + 1);
+            for (0..tier.len) |i| points.appendAssumeCapacity(tier.at(i));
+            if (tier.open) |open| points.appendAssumeCapacity(open);
+        }
+        return try std.json.Stringify.valueAlloc(allocator, .{
+            .tier = specs[index].name,
+            .step_s = specs[index].step_s,
+            .points = points.items,
+        }, .{});
+    }
+
+    fn saveLogged(self: *History) void {
+        self.save() catch |err| std.debug.print("✗ Traffic history {s} not saved: {any}\n", .{ self.config.path, err });
+    }
+
+    /// Write every tier to a temporary file and rename it over the old one,
+    /// so a crash mid-save leaves the previous history intact
+    fn save(self: *History) !void {
+        var out: std.ArrayList(u8) = .empty;
+        defer out.deinit(self.allocator);
+        {
+            self.mutex.lock();
+            defer self.mutex.unlock();
+
+            try out.appendSlice(self.allocator, MAGIC);
+            for (&self.tiers) |*tier| {
+                try appendInt(self.allocator, &out, i64, tier.step_s);
+                try appendInt(self.allocator, &out, u32, @intCast(tier.len + @intFromBool(tier.open != null)));
+                for (0..tier.len) |i| try appendPoint(self.allocator, &out, tier.at(i));
+                if (tier.open) |open| try appendPoint(self.allocator, &out, open);
+            }
+        }
+
+        var tmp_buf: [std.fs.max_path_bytes]u8 = undefined;
+        const tmp_path = try std.fmt.bufPrint(&tmp_buf, "{s}.tmp", .{self.config.path});
+        try std.fs.cwd().writeFile(.{ .sub_path = tmp_path, .data = out.items });
+        try std.fs.cwd().rename(tmp_path, self.config.path);
+    }
+
+    /// Restore saved points still within each tier's reach. A missing file
+    /// is a first start, not an error.
+    fn load(self: *History) !void {
+        const data = std.fs.cwd().readFileAlloc(self.allocator, self.config.path, MAX_FILE_SIZE) catch |err| switch (err) {
+            error.FileNotFound => return,
+            else => return err,
+        };
+        defer self.allocator.free(data);
+        if (!std.mem.startsWith(u8, data, MAGIC)) return error.InvalidHistoryFile;
+
+        var reader = std.Io.Reader.fixed(data[MAGIC.len..]);
+        const now = std.time.timestamp();
+        var restored: usize = 0;
+
+        self.mutex.lock();
+        defer self.mutex.unlock();
+        for (&self.tiers) |*tier| {
+            const step_s = reader.takeInt(i64, .little) catch return error.InvalidHistoryFile;
+            const count = reader.takeInt(u32, .little) catch return error.InvalidHistoryFile;
+            for (0..count) |_| {
+                const point = Point{
+                    .start = reader.takeInt(i64, .little) catch return error.InvalidHistoryFile,
+                    .requests = reader.takeInt(u64, .little) catch return error.InvalidHistoryFile,
+                    .errors = reader.takeInt(u64, .little) catch return error.InvalidHistoryFile,
+                    .connections = reader.takeInt(u64, .little) catch return error.InvalidHistoryFile,
+                };
+                // Tiers from a build with other resolutions are skipped
+                if (step_s != tier.step_s) continue;
+                if (point.start < now - step_s * @as(i64, @intCast(tier.points.len)) or point.start > now) continue;
+                tier.add(point);
+                restored += 1;
+            }
+        }
+        std.debug.print("✓ Traffic history restored from {s}: {d} points\n", .{ self.config.path, restored });
+    }
+};
+
+fn appendPoint(allocator: std.mem.Allocator, out: *std.ArrayList(u8), point: Point) !void {
+    try appendInt(allocator, out, i64, point.start);
+    try appendInt(allocator, out, u64, point.requests);
+    try appendInt(allocator, out, u64, point.errors);
+    try appendInt(allocator, out, u64, point.connections);
+}
+
+fn appendInt(allocator: std.mem.Allocator, out: *std.ArrayList(u8), comptime T: type, value: T) !void {
+    var buf: [@sizeOf(T)]u8 = undefined;
+    std.mem.writeInt(T, &buf, value, .little);
+    try out.appendSlice(allocator, &buf);
+}
+
+/// Requests and 5xx responses over every route since startup
+fn totals(route_stats: *const stats_mod.RouteStatsRegistry) Totals {
+    var requests: u64 = 0;
+    var errors: u64 = 0;
+    for (route_stats.entries.items) |entry| {
+        requests += entry.stats.requests.load(.monotonic);
+        errors += entry.stats.status_classes[4].load(.monotonic);
+    }
+    return .{ requests, errors };
+}
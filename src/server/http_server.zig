@@ -2,20 +2,179 @@ const std = @import("std");
 const zsync = @import("zsync");
 const zhttp = @import("zhttp");
 const signals = @import("signals.zig");
+const socket = @import("socket.zig");
+const request = @import("request.zig");
+const response = @import("response.zig");
+const config_mod = @import("../config/config.zig");
+const secret_mod = @import("../config/secret.zig");
+const router_mod = @import("../routing/router.zig");
+const ab_test_mod = @import("../routing/ab_test.zig");
+const stats_mod = @import("../routing/stats.zig");
+const cors_mod = @import("../proxy/cors.zig");
+const acl_mod = @import("../security/acl.zig");
+const rate_limit_mod = @import("../security/rate_limit.zig");
+const real_ip_mod = @import("../security/real_ip.zig");
 const forwarder_mod = @import("../proxy/forwarder.zig");
+const rewrite_mod = @import("../proxy/rewrite.zig");
+const hedge_mod = @import("../proxy/hedge.zig");
+const retry_mod = @import("../proxy/retry.zig");
+const resolver_mod = @import("../dns/resolver.zig");
+const doh = @import("../dns/doh.zig");
+const dot_mod = @import("../dns/dot.zig");
+const do53_mod = @import("../dns/do53.zig");
+const local_dns_mod = @import("../dns/local.zig");
+const alt_svc_mod = @import("../proxy/alt_svc.zig");
+const upstream_mod = @import("../upstream/manager.zig");
+const concurrency_mod = @import("../upstream/concurrency.zig");
+const docker_mod = @import("../upstream/docker.zig");
+const cluster_mod = @import("../upstream/cluster.zig");
+const stream_mod = @import("../stream/proxy.zig");
+const demux_mod = @import("demux.zig");
+const tls_mod = @import("tls.zig");
+const client_hello_mod = @import("client_hello.zig");
+const fingerprint_mod = @import("fingerprint.zig");
+const admin_mod = @import("admin.zig");
+const capture_mod = @import("capture.zig");
+const faults_mod = @import("faults.zig");
+const static_mod = @import("static.zig");
+const s3_origin_mod = @import("s3_origin.zig");
+const downloads_mod = @import("downloads.zig");
+const static_io_mod = @import("static_io.zig");
+const normalize_mod = @import("normalize.zig");
+const access_log_mod = @import("access_log.zig");
+const usage_mod = @import("usage.zig");
+const canonical_mod = @import("../routing/canonical.zig");
+const scrape_mod = @import("scrape.zig");
+const history_mod = @import("history.zig");
+const events_mod = @import("events.zig");
+const schedule_mod = @import("schedule.zig");
+const memory_mod = @import("memory.zig");
+const metrics = @import("metrics.zig");
+const respond_mod = @import("respond.zig");
+const error_pages_mod = @import("error_pages.zig");
+const probes_mod = @import("probes.zig");
+const problem_mod = @import("problem.zig");
+const connections_mod = @import("connections.zig");
+
+const Header = request.Header;
+
+/// Protocols offered by ALPN on the TLS listeners. Requests are served as
+/// HTTP/1.1, so h2 is left out until there is an HTTP/2 frontend.
+const tls_alpn = [_][]const u8{"http/1.1"};
+
+/// What a TLS listener learned about the client before the handshake
+const TlsClient = struct {
+    /// Null when the ClientHello couldn't be read
+    fingerprint: ?fingerprint_mod.Fingerprint,
+};
 
 pub const HttpServer = struct {
     allocator: std.mem.Allocator,
-    addr: std.net.Address,
-    upstream_addr: ?std.net.Address,
+    config: *const config_mod.Config,
+    /// Every address the HTTP listeners bind: `server.listen`, then
+    /// `server.listen_tls`
+    listen_addresses: []std.net.Address,
+    /// Listeners before this index serve plain HTTP, the rest HTTPS
+    plain_listeners: usize,
+    /// Terminates TLS on the `server.listen_tls` listeners
+    tls_server: ?tls_mod.TlsServer = null,
+    connections: connections_mod.ConnectionTracker,
+    router: router_mod.Router,
     forwarder: forwarder_mod.Forwarder,
+    experiments: ab_test_mod.Experiments,
+    hedging: hedge_mod.Hedging,
+    rewrites: rewrite_mod.Rewrites,
+    route_stats: stats_mod.RouteStatsRegistry,
+    rate_limiter: rate_limit_mod.RateLimiter,
+    capture: capture_mod.Capture,
+    /// Delays and errors injected into routes through the admin API
+    faults: faults_mod.Faults,
+    error_pages: error_pages_mod.ErrorPages,
+    /// Strong ETags of static files
+    etags: static_mod.EtagCache,
+    /// Static mounts served from S3-compatible buckets
+    s3_origins: s3_origin_mod.S3Origins,
+    /// Static downloads in progress per client
+    downloads: downloads_mod.DownloadLimiter,
+    /// Turns at the disk for static file lookups
+    static_io: static_io_mod.DiskGate,
+    alt_svc: alt_svc_mod.AltSvc,
+    access_logs: access_log_mod.AccessLogs,
+    /// Transfer volume per vhost, route and tenant
+    usage: usage_mod.Usage,
+    /// Who may scrape /metrics, here and on the admin listener
+    scrape: scrape_mod.ScrapeGuard,
+    /// Downsampled traffic history for /admin/history
+    history: history_mod.History,
+    history_thread: ?std.Thread = null,
+    /// State changes for notifications and event sinks
+    events: events_mod.EventBus,
+    events_thread: ?std.Thread = null,
+    /// Maintenance windows and timed rate-limit changes
+    schedule: schedule_mod.Schedule,
+    /// Memory held by requests in flight
+    memory: memory_mod.MemoryBudget,
+    upstreams: upstream_mod.UpstreamManager,
+    docker: docker_mod.DockerProvider,
+    docker_thread: ?std.Thread = null,
+    cluster: ?cluster_mod.Cluster = null,
+    stream_proxies: []stream_mod.StreamProxy = &.{},
+    demux_server: ?demux_mod.DemuxServer = null,
+    admin_server: ?admin_mod.AdminServer = null,
+    probes: ?probes_mod.Probes = null,
+    /// Body served to requests matching no vhost when default_vhost.action = page
+    default_page: []const u8 = "",
+    /// Bytes of request head read before the route is known
+    max_head_size: usize,
+    /// Some route has its own header limits, so requests are checked
+    /// against their route's limits rather than server.limits
+    route_header_limits: bool,
+    resolver: resolver_mod.Resolver,
+    dot_server: ?dot_mod.DotServer = null,
+    do53_server: ?do53_mod.Do53Server = null,
 
-    pub fn init(allocator: std.mem.Allocator, addr: std.net.Address, upstream_addr: ?std.net.Address) HttpServer {
+    pub fn init(
+        allocator: std.mem.Allocator,
+        config: *const config_mod.Config,
+    ) !HttpServer {
+        const plain_addresses = try socket.listenAddresses(allocator, config.server.listen, config.server.dual_stack);
+        defer allocator.free(plain_addresses);
+        const tls_addresses = try socket.listenAddresses(allocator, config.server.listen_tls, config.server.dual_stack);
+        defer allocator.free(tls_addresses);
+        const listen_addresses = try std.mem.concat(allocator, std.net.Address, &.{ plain_addresses, tls_addresses });
         return .{
             .allocator = allocator,
-            .addr = addr,
-            .upstream_addr = upstream_addr,
-            .forwarder = forwarder_mod.Forwarder.init(allocator),
+            .config = config,
+            .listen_addresses = listen_addresses,
+            .plain_listeners = plain_addresses.len,
+            .connections = try connections_mod.ConnectionTracker.init(allocator, &config.server, listen_addresses),
+            .router = router_mod.Router.init(config.routes),
+            .forwarder = forwarder_mod.Forwarder.init(allocator, config),
+            .experiments = try ab_test_mod.Experiments.init(allocator, config),
+            .hedging = try hedge_mod.Hedging.init(allocator, config),
+            .rewrites = try rewrite_mod.Rewrites.init(allocator, config),
+            .route_stats = try stats_mod.RouteStatsRegistry.init(allocator, config),
+            .rate_limiter = try rate_limit_mod.RateLimiter.init(allocator, config),
+            .capture = capture_mod.Capture.init(allocator, &config.admin.capture),
+            .faults = faults_mod.Faults.init(allocator, &config.admin.faults),
+            .error_pages = try error_pages_mod.ErrorPages.init(allocator, config.error_pages),
+            .etags = static_mod.EtagCache.init(allocator),
+            .s3_origins = try s3_origin_mod.S3Origins.init(allocator, config),
+            .downloads = downloads_mod.DownloadLimiter.init(allocator),
+            .static_io = static_io_mod.DiskGate.init(&config.server.static_io),
+            .alt_svc = try alt_svc_mod.AltSvc.init(allocator, config),
+            .access_logs = try access_log_mod.AccessLogs.init(allocator, config),
+            .usage = usage_mod.Usage.init(allocator, &config.usage),
+            .scrape = try scrape_mod.ScrapeGuard.init(allocator, &config.metrics),
+            .history = try history_mod.History.init(allocator, &config.history),
+            .events = try events_mod.EventBus.init(allocator, config),
+            .schedule = try schedule_mod.Schedule.init(allocator, config.schedule),
+            .memory = memory_mod.MemoryBudget.init(&config.server.memory),
+            .upstreams = try upstream_mod.UpstreamManager.init(allocator, config.upstreams, &config.egress_proxy),
+            .docker = docker_mod.DockerProvider.init(allocator, &config.docker),
+            .resolver = resolver_mod.Resolver.init(allocator, &config.dns),
+            .max_head_size = config.maxHeadSize(),
+            .route_header_limits = config.hasHeaderLimits(),
         };
     }
 
@@ -23,24 +182,141 @@ pub const HttpServer = struct {
         // Install signal handlers
         signals.installSignalHandlers();
 
+        response.setServerHeader(self.config.server.response_headers.server);
+
+        if (self.config.default_vhost.action == .page) {
+            self.default_page = try std.fs.cwd().readFileAlloc(self.allocator, self.config.default_vhost.page, 1024 * 1024);
+        }
+
+        // Watch discovery-backed upstream groups for membership changes
+        try self.upstreams.startDiscovery();
+        if (self.events.enabled()) {
+            self.events_thread = try std.Thread.spawn(.{}, events_mod.EventBus.run, .{ &self.events, &self.rate_limiter });
+        }
+        try self.upstreams.startHealthChecks(&self.events);
+        self.rate_limiter.schedule = &self.schedule;
+        if (self.config.cluster.enabled) {
+            self.cluster = try cluster_mod.Cluster.init(self.allocator, &self.config.cluster, &self.upstreams);
+            try self.cluster.?.start();
+        }
+        if (self.config.docker.enabled) {
+            self.docker.events = &self.events;
+            self.docker_thread = try std.Thread.spawn(.{}, docker_mod.DockerProvider.run, .{&self.docker});
+        }
+
+        // Layer-4 stream listeners each run on their own thread
+        self.stream_proxies = try self.allocator.alloc(stream_mod.StreamProxy, self.config.stream.servers.len);
+        for (self.config.stream.servers, self.stream_proxies) |*stream_cfg, *proxy| {
+            const group = self.upstreams.get(stream_cfg.upstream) orelse return error.UnknownStreamUpstream;
+            proxy.* = stream_mod.StreamProxy.init(stream_cfg, group);
+            const thread = try std.Thread.spawn(.{}, stream_mod.StreamProxy.run, .{proxy});
+            thread.detach();
+        }
+
+        // Shared-port protocol demultiplexer
+        if (self.config.server.demux.enabled) {
+            self.demux_server = demux_mod.DemuxServer.init(self.allocator, self.config);
+            const thread = try std.Thread.spawn(.{}, demux_mod.DemuxServer.run, .{&self.demux_server.?});
+            thread.detach();
+        }
+
+        if (self.config.history.enabled) {
+            self.history_thread = try std.Thread.spawn(.{}, history_mod.History.run, .{ &self.history, &self.route_stats, &self.connections });
+        }
+
+        if (self.config.admin.enabled) {
+            self.admin_server = try admin_mod.AdminServer.init(self.allocator, &self.config.admin, self.config, &self.route_stats, &self.capture, &self.faults, &self.rate_limiter, &self.upstreams, &self.connections, &self.etags, &self.s3_origins, &self.usage, &self.scrape, &self.history, &self.events, &self.schedule, self.config.vhosts);
+            const thread = try std.Thread.spawn(.{}, admin_mod.AdminServer.run, .{&self.admin_server.?});
+            thread.detach();
+        }
+
+        if (self.config.dns.enabled and self.config.dns.local.enabled) {
+            self.resolver.local = try local_dns_mod.LocalAnswers.init(self.allocator, &self.config.dns.local, self.config.vhosts, &self.upstreams);
+        }
+
+        // DNS-over-TLS runs on its own listener thread
+        if (self.config.dns.enabled and self.config.dns.dot.enabled) {
+            self.dot_server = dot_mod.DotServer.init(self.allocator, self.config, &self.resolver);
+            const thread = try std.Thread.spawn(.{}, dot_mod.DotServer.run, .{&self.dot_server.?});
+            thread.detach();
+        }
+
+        // Plain DNS on UDP and TCP, each address on its own thread
+        if (self.config.dns.enabled and (self.config.dns.udp_enabled or self.config.dns.tcp_enabled)) {
+            self.do53_server = do53_mod.Do53Server.init(self.allocator, &self.config.dns, &self.resolver);
+            try self.do53_server.?.start();
+        }
+
+        self.probes = probes_mod.Probes.init(&self.config.probes, &self.upstreams);
+
         // TODO: Implement zhttp server
         // For MVP, just bind and accept connections
-        var server = try self.addr.listen(.{
-            .reuse_address = true,
-        });
-        defer server.deinit();
-
-        // Get port for display
-        const port = self.addr.getPort();
-        std.debug.print("✓ Server listening on 0.0.0.0:{}\n", .{port});
+        const addresses = self.listen_addresses;
+        if (addresses.len == 0) return error.NoListenAddresses;
+        if (addresses.len > self.plain_listeners) try self.startTls();
+
+        const servers = try self.allocator.alloc(std.net.Server, addresses.len);
+        defer self.allocator.free(servers);
+        var bound: usize = 0;
+        defer for (servers[0..bound]) |*server| server.deinit();
+        for (addresses, servers) |address, *server| {
+            server.* = try socket.listen(address);
+            bound += 1;
+            const scheme = if (bound > self.plain_listeners) "HTTPS" else "HTTP";
+            std.debug.print("✓ Server listening on {f} ({s})\n", .{ address, scheme });
+        }
+        self.probes.?.listening.store(true, .release);
         std.debug.print("✓ Press Ctrl+C for graceful shutdown\n", .{});
 
+        // The first listener is served on this thread, the rest on their own
+        for (servers[1..], 1..) |*server, listener| {
+            const thread = try std.Thread.spawn(.{}, runListener, .{ self, server, listener });
+            thread.detach();
+        }
+        try self.acceptLoop(&servers[0], 0);
+
+        std.debug.print("\n✓ Server stopped gracefully\n", .{});
+    }
+
+    /// Load the certificate served on the TLS listeners
+    fn startTls(self: *HttpServer) !void {
+        const tls_cfg = &self.config.tls;
+        const cert_path = try listenerPath(self.allocator, tls_cfg, tls_cfg.cert, "server.crt");
+        errdefer self.allocator.free(cert_path);
+        const key_path = try listenerPath(self.allocator, tls_cfg, tls_cfg.key, "server.key");
+        errdefer self.allocator.free(key_path);
+        self.tls_server = tls_mod.TlsServer.initWithAlpn(self.allocator, cert_path, key_path, &tls_alpn);
+        try self.tls_server.?.loadCertificate();
+    }
+
+    /// A configured path, or `default_name` in tls.cert_dir; owned by the caller
+    fn listenerPath(allocator: std.mem.Allocator, tls_cfg: *const config_mod.TlsConfig, configured: ?[]const u8, default_name: []const u8) ![]u8 {
+        if (configured) |path| return allocator.dupe(u8, path);
+        return std.fs.path.join(allocator, &[_][]const u8{ tls_cfg.cert_dir, default_name });
+    }
+
+    /// Thread entry point for an additional listener
+    fn runListener(self: *HttpServer, server: *std.net.Server, listener: usize) void {
+        self.acceptLoop(server, listener) catch |err| {
+            std.debug.print("✗ Listener on {f} failed: {any}\n", .{ server.listen_address, err });
+        };
+    }
+
+    /// Accept connections on one listener until shutdown
+    fn acceptLoop(self: *HttpServer, server: *std.net.Server, listener: usize) !void {
         while (!signals.shouldShutdown()) {
             // Check for reload signal
             if (signals.shouldReload()) {
                 std.debug.print("✓ Reloading configuration...\n", .{});
                 // TODO: Reload config with flare
                 signals.resetReload();
+                // Secrets are re-read in place, so a rotation that broke a
+                // reference shows up here rather than on first use
+                var secret_field: []const u8 = "";
+                secret_mod.checkFields(self.config.*, &secret_field) catch |err| {
+                    std.debug.print("✗ {s} does not resolve: {any}\n", .{ secret_field, err });
+                };
+                self.events.publish(.config_reloaded, "config", "Configuration reload signalled", .{});
             }
 
             // Accept connection (blocking)
@@ -50,46 +326,702 @@ pub const HttpServer = struct {
                 return err;
             };
 
-            const client_port = conn.address.getPort();
-            std.debug.print("✓ Accepted connection from 127.0.0.1:{}\n", .{client_port});
+            if (self.connections.open(listener, conn.address)) |refusal| {
+                std.debug.print("✗ Refused connection from {f}: {s}\n", .{ conn.address, @tagName(refusal) });
+                _ = closeWith(conn.stream, 503) catch false;
+                conn.stream.close();
+                continue;
+            }
+
+            std.debug.print("✓ Accepted connection from {f}\n", .{conn.address});
+
+            // Keep-alive connections sit idle between requests, so each one
+            // gets its own thread
+            const thread = std.Thread.spawn(.{}, serveConnection, .{ self, conn, listener }) catch |err| {
+                std.debug.print("✗ Connection thread failed: {any}\n", .{err});
+                self.connections.close(listener, conn.address);
+                conn.stream.close();
+                continue;
+            };
+            thread.detach();
+        }
+    }
+
+    /// Thread entry point for an accepted connection
+    fn serveConnection(self: *HttpServer, conn: std.net.Server.Connection, listener: usize) void {
+        defer self.connections.close(listener, conn.address);
+        defer conn.stream.close();
 
-            // Proxy request to upstream if configured
-            if (self.upstream_addr) |upstream| {
-                self.forwarder.forward(conn.stream, upstream) catch |err| {
-                    std.debug.print("✗ Proxy error: {any}\n", .{err});
+        // Behind a load balancer speaking the PROXY protocol, the client is
+        // the source it reports rather than the balancer itself
+        var client_conn = conn;
+        const real_ip = &self.config.server.real_ip;
+        if (real_ip.proxy_protocol and real_ip_mod.trusted(real_ip, conn.address)) {
+            const source = real_ip_mod.readProxyHeader(conn.stream, self.config.server.timeouts.header_timeout_ms) catch |err| {
+                std.debug.print("✗ PROXY protocol header from {f} rejected: {any}\n", .{ conn.address, err });
+                return;
+            };
+            if (source) |address| {
+                std.debug.print("✓ Connection from {f} proxied for {f}\n", .{ conn.address, address });
+                client_conn.address = address;
+            }
+        }
+
+        if (listener < self.plain_listeners) {
+            self.handleConnection(client_conn, null) catch |err| {
+                std.debug.print("✗ Connection error: {any}\n", .{err});
+            };
+            return;
+        }
+
+        // The handshake gets the header timeout, so a client that stalls in
+        // it can't hold the connection open
+        const header_timeout_ms = self.config.server.timeouts.header_timeout_ms;
+        socket.setTimeouts(conn.stream.handle, header_timeout_ms) catch {};
+
+        // The ClientHello is peeked, leaving it for the handshake, to
+        // fingerprint the client before anything else is done for it
+        const fingerprint_cfg = &self.config.server.fingerprint;
+        var hello_buf: [demux_mod.PEEK_SIZE]u8 = undefined;
+        const client = TlsClient{ .fingerprint = blk: {
+            const peeked = demux_mod.peek(conn.stream, &hello_buf, header_timeout_ms) catch break :blk null;
+            const hello = client_hello_mod.parse(peeked) catch break :blk null;
+            break :blk fingerprint_mod.Fingerprint.compute(self.allocator, &hello) catch null;
+        } };
+        const fingerprint = if (client.fingerprint) |*fp| fp else null;
+        if (fingerprint) |fp| {
+            std.debug.print("✓ TLS client {f} ja3={s} ja4={s}\n", .{ client_conn.address, &fp.ja3, &fp.ja4 });
+        }
+        if (!fingerprint_mod.admitted(fingerprint_cfg, fingerprint)) {
+            std.debug.print("✗ TLS client {f} refused by fingerprint rules\n", .{client_conn.address});
+            return;
+        }
+
+        var session = self.tls_server.?.acceptTls(conn.stream) catch |err| {
+            std.debug.print("✗ TLS handshake with {f} failed: {any}\n", .{ client_conn.address, err });
+            return;
+        };
+        defer session.close();
+        socket.setTimeouts(conn.stream.handle, 0) catch {};
+
+        // Requests are read from and answered over the session's plaintext
+        var bridge: tls_mod.Bridge = undefined;
+        bridge.start(&session) catch |err| {
+            std.debug.print("✗ TLS relay for {f} failed: {any}\n", .{ client_conn.address, err });
+            return;
+        };
+        defer bridge.finish();
+        client_conn.stream = bridge.local;
+        self.handleConnection(client_conn, &client) catch |err| {
+            std.debug.print("✗ Connection error: {any}\n", .{err});
+        };
+    }
+
+    /// Serve requests on a connection until either side closes it, the
+    /// keep-alive timeout passes, or the per-connection request limit is hit.
+    /// `tls` is set when the client connected to a TLS listener.
+    fn handleConnection(self: *HttpServer, conn: std.net.Server.Connection, tls: ?*const TlsClient) !void {
+        const timeouts = &self.config.server.timeouts;
+
+        // Everything the connection's requests hold counts against its budget
+        var budget = memory_mod.ConnectionBudget.init(self.allocator, &self.memory);
+        var arena = std.heap.ArenaAllocator.init(budget.allocator());
+        defer arena.deinit();
+
+        var served: usize = 0;
+        while (true) {
+            _ = arena.reset(.retain_capacity);
+            served += 1;
+
+            const last = timeouts.keepalive_timeout_ms == 0 or
+                (timeouts.max_requests_per_connection > 0 and served >= timeouts.max_requests_per_connection);
+            budget.refused = null;
+            const keep_alive = self.handleRequest(arena.allocator(), conn, tls, &budget, !last) catch |err| switch (err) {
+                error.OutOfMemory => {
+                    const refusal = budget.refused orelse return err;
+                    _ = try closeWith(conn.stream, refusalStatus(refusal));
+                    return;
+                },
+                else => return err,
+            };
+            if (!keep_alive or signals.shouldShutdown()) return;
+
+            // Idle keep-alive connections are closed once the timeout passes
+            if (!try socket.waitReadable(conn.stream.handle, timeouts.keepalive_timeout_ms)) return;
+        }
+    }
+
+    /// Read one request, apply route layers and proxy it upstream. Returns
+    /// whether the connection can carry another request.
+    fn handleRequest(
+        self: *HttpServer,
+        allocator: std.mem.Allocator,
+        conn: std.net.Server.Connection,
+        tls: ?*const TlsClient,
+        budget: *memory_mod.ConnectionBudget,
+        keep_alive_allowed: bool,
+    ) !bool {
+        const limits = &self.config.server.limits;
+        const usage_start = if (self.config.usage.enabled) usage_mod.socketBytes(conn.stream.handle) else null;
+
+        var buf: [request.MAX_HEAD_SIZE]u8 = undefined;
+        const head_limit = @min(self.max_head_size, buf.len);
+        const n = request.readHeadTimeout(conn.stream, buf[0..head_limit], self.config.server.timeouts.header_timeout_ms) catch |err| switch (err) {
+            error.HeadTooLarge => return closeWith(conn.stream, 431),
+            error.Timeout => return closeWith(conn.stream, 408),
+            // Client closed an idle keep-alive connection
+            error.ConnectionClosed => return false,
+            else => return err,
+        };
+
+        if (!limits.reject_obsolete_folding) {
+            request.unfoldHeaders(buf[0 .. request.findHeadEnd(buf[0..n]).? - 2]);
+        }
+
+        var headers: [request.MAX_HEADERS]Header = undefined;
+        var req = request.parse(buf[0..n], &headers) catch |err| switch (err) {
+            error.TooManyHeaders => return closeWith(conn.stream, 431),
+            else => return closeWith(conn.stream, 400),
+        };
+        normalize_mod.apply(allocator, &self.config.server.normalize, &req, headers[0..req.headers.len]) catch |err| switch (err) {
+            error.InvalidPercentEncoding => return closeWith(conn.stream, 400),
+            else => return err,
+        };
+        const started = std.time.milliTimestamp();
+
+        // Reject oversized and ambiguously framed requests before routing,
+        // under the limits of the route the request is headed for
+        const limit_route = if (self.route_header_limits) self.matchRoute(&req) else null;
+        const route_limits = if (limit_route) |route| self.config.requestLimits(route) else limits.*;
+        request.checkLimits(&req, &route_limits) catch |err| switch (err) {
+            error.UriTooLong => return closeWith(conn.stream, 414),
+            error.HeaderTooLarge, error.TooManyHeaders => {
+                const route = limit_route orelse return closeWith(conn.stream, 431);
+                return errorWith(allocator, conn.stream, &req, route.error_format, 431);
+            },
+            error.InvalidContentLength, error.ConflictingFraming, error.InvalidHeader, error.UnexpectedBody => return closeWith(conn.stream, 400),
+            error.UnsupportedTransferCoding => return closeWith(conn.stream, 501),
+        };
+        // Upstreams only see fingerprints wraith worked out itself
+        const fingerprint = if (tls) |client| (if (client.fingerprint) |*fp| fp else null) else null;
+        fingerprint_mod.setHeaders(&self.config.server.fingerprint, fingerprint, &req, &headers);
+
+        // Probe endpoints answer for wraith itself, ahead of every vhost
+        if (self.probes) |*probes| {
+            const reusable = req.wantsKeepAlive() and !req.hasBody() and n == req.head_len;
+            if (try probes.handle(allocator, conn.stream, &req, keep_alive_allowed and reusable)) |reused| return reused;
+        }
+
+        if (self.config.metrics.main_listener and std.mem.eql(u8, req.path(), self.config.metrics.path)) {
+            return self.serveMetrics(allocator, conn, &req);
+        }
+
+        // Unexpected Host headers never reach routes, static roots or the
+        // default vhost, so they can't end up in cached responses or links
+        if (self.config.server.allowed_hosts.len > 0) {
+            if (!request.validHost(&req)) return closeWith(conn.stream, 400);
+            if (!router_mod.hostAllowed(self.config.server.allowed_hosts, req.host())) return closeWith(conn.stream, 421);
+        }
+
+        // Client address behind any trusted proxies, seen by rate limits,
+        // ACLs and templates alike
+        const client_addr = real_ip_mod.clientAddress(&self.config.server.real_ip, conn.address, &req);
+
+        if (self.rate_limiter.check(client_addr)) |reason| {
+            return tooManyRequests(allocator, conn.stream, &req, .text, self.rate_limiter.retryAfter(reason, null));
+        }
+
+        // Past the memory watermark, caches give memory back and new
+        // requests are refused until usage falls
+        const pressure = self.memory.pressure();
+        if (pressure == .entered) self.resolver.cache.shrink(self.resolver.cache.count() / 2);
+        if (pressure != .none) {
+            _ = self.memory.shed.fetchAdd(1, .monotonic);
+            try response.write(conn.stream, 503, &[_]Header{
+                .{ .name = "Content-Type", .value = "text/plain" },
+                .{ .name = "Retry-After", .value = "1" },
+            }, "503 Service Unavailable\n");
+            return false;
+        }
+
+        // Scheduled maintenance answers before any route is tried
+        if (self.schedule.maintenance(req.host(), req.path(), std.time.milliTimestamp())) |maintenance| {
+            var retry_buf: [24]u8 = undefined;
+            try response.write(conn.stream, maintenance.config.status, &[_]Header{
+                .{ .name = "Content-Type", .value = "text/plain" },
+                .{ .name = "Retry-After", .value = try std.fmt.bufPrint(&retry_buf, "{d}", .{maintenance.retry_after_s}) },
+            }, maintenance.config.message);
+            return false;
+        }
+
+        // DNS-over-HTTPS endpoint is served locally, ahead of proxy routes
+        if (self.config.dns.enabled and std.mem.eql(u8, req.path(), self.config.dns.doh_path)) {
+            try doh.handle(&self.resolver, allocator, conn.stream, &req, buf[0..n]);
+            return false;
+        }
+
+        // Response headers contributed by route layers
+        var extra_headers: std.ArrayList(Header) = .empty;
+
+        var upstream_addr: ?std.net.Address = null;
+        var matched_route: ?*const config_mod.RouteConfig = null;
+        var variant_stats: ?*ab_test_mod.VariantStats = null;
+        var hedge: ?forwarder_mod.Hedge = null;
+        var retry: ?retry_mod.Retry = null;
+        var route_stats: ?*stats_mod.RouteStats = null;
+        var route_id: []const u8 = "";
+        var record: ?capture_mod.ResponseRecord = null;
+        var limiter: ?*concurrency_mod.ConcurrencyLimiter = null;
+        errdefer if (limiter) |l| l.release(elapsedMs(started), .ignore);
+        // Group whose server was picked, until the exchange is over
+        var balanced: ?*upstream_mod.UpstreamGroup = null;
+        defer if (balanced) |group| group.release(upstream_addr.?);
+
+        const default_vhost = &self.config.default_vhost;
+        const vhost = router_mod.matchVhost(self.config.vhosts, req.host()) orelse switch (default_vhost.action) {
+            .routes => null,
+            .vhost => router_mod.matchVhost(self.config.vhosts, default_vhost.vhost),
+            .misdirected => return closeWith(conn.stream, 421),
+            .close => return false,
+            .page => {
+                try response.write(conn.stream, default_vhost.status, &[_]Header{
+                    .{ .name = "Content-Type", .value = static_mod.contentType(default_vhost.page) },
+                }, self.default_page);
+                return false;
+            },
+        };
+        // Logged once the response is out, under the route it took. No
+        // response at all is logged as 499, like a client disconnect.
+        response.noteStatus(0);
+        defer if (self.access_logs.target(vhost, matched_route)) |log| {
+            const status = response.lastStatus();
+            access_log_mod.AccessLogs.write(allocator, log, .{
+                .client = client_addr,
+                .req = &req,
+                .status = if (status == 0) 499 else status,
+                .started_ms = started,
+                .duration_ms = elapsedMs(started),
+            });
+        };
+        defer if (self.config.usage.enabled) self.usage.record(vhost, route_id, &req, conn.stream.handle, usage_start);
+        if (vhost) |v| {
+            if (!acl_mod.Acl.init(&v.acl).permits(client_addr)) {
+                return errorWith(allocator, conn.stream, &req, v.error_format, 403);
+            }
+            const secure = tls != null or real_ip_mod.forwardedHttps(&self.config.server.real_ip, conn.address, &req);
+            if (try canonical_mod.location(allocator, &v.canonical, &req, secure)) |location| {
+                try response.write(conn.stream, v.canonical.status, &[_]Header{
+                    .{ .name = "Location", .value = location },
+                }, "");
+                return false;
+            }
+        }
+        const router = if (vhost) |v| router_mod.Router.init(v.routes) else self.router;
+        if (self.alt_svc.value(vhost)) |value| {
+            try extra_headers.append(allocator, .{ .name = alt_svc_mod.HEADER, .value = value });
+        }
+
+        // Container routes take precedence over configured routes
+        const container_addr = if (self.config.docker.enabled) self.docker.match(req.host(), req.path()) else null;
+        // Between a route and a static mount, the longer path prefix wins,
+        // so "/assets" can be served from disk under a "/" route and "/api"
+        // proxied under a "/" mount. Routes win ties.
+        const static_mount = if (vhost) |v| static_mod.matchMount(v.static, req.path()) else null;
+        const proxy_route = if (router.match(req.host(), req.path())) |route| blk: {
+            if (static_mount) |mount| {
+                if (mount.path.len > route.path.len) break :blk null;
+            }
+            break :blk route;
+        } else null;
+
+        if (container_addr) |addr| {
+            upstream_addr = addr;
+        } else if (proxy_route) |route| {
+            matched_route = route;
+            if (self.route_stats.entryFor(route)) |entry| {
+                route_stats = entry.stats;
+                route_id = entry.id;
+                if (self.capture.claim(entry.id)) record = .{ .allocator = allocator };
+            }
+
+            if (!acl_mod.Acl.init(&route.acl).permits(client_addr)) {
+                recordRoute(route_stats, 403, 0, started);
+                if (record) |*r| self.captureExchange(allocator, &req, route_id, r, 403, started);
+                return errorWith(allocator, conn.stream, &req, route.error_format, 403);
+            }
+            if (!self.rate_limiter.checkRoute(route, client_addr)) {
+                recordRoute(route_stats, 429, 0, started);
+                if (record) |*r| self.captureExchange(allocator, &req, route_id, r, 429, started);
+                return tooManyRequests(allocator, conn.stream, &req, route.error_format, self.rate_limiter.retryAfter(.route, route));
+            }
+
+            if (route.cors.enabled) {
+                const cors = cors_mod.Cors.init(&route.cors);
+                if (cors.isPreflight(&req)) {
+                    try cors.writePreflight(allocator, conn.stream, &req);
+                    return false;
+                }
+                try cors.appendHeaders(allocator, &req, &extra_headers);
+            }
 
-                    // Send error response
-                    const error_response =
-                        \\HTTP/1.1 502 Bad Gateway
-                        \\Content-Type: text/plain
-                        \\Content-Length: 28
-                        \\Server: Wraith/0.0.0
-                        \\
-                        \\502 Bad Gateway - Proxy Error
-                    ;
-                    _ = conn.stream.write(error_response) catch {};
+            if (self.faults.check(route_id)) |fault| {
+                if (fault.delay_ms > 0) std.Thread.sleep(@as(u64, fault.delay_ms) * std.time.ns_per_ms);
+                if (fault.abort) |status| {
+                    recordRoute(route_stats, status, 0, started);
+                    if (record) |*r| self.captureExchange(allocator, &req, route_id, r, status, started);
+                    return errorWith(allocator, conn.stream, &req, route.error_format, status);
+                }
+            }
+
+            if (route.respond.status != 0) {
+                // Unread body or pipelined bytes would corrupt the next request
+                const reusable = req.wantsKeepAlive() and !req.hasBody() and n == req.head_len;
+                const reused = try respond_mod.serve(allocator, conn.stream, &req, &route.respond, client_addr, keep_alive_allowed and reusable);
+                recordRoute(route_stats, route.respond.status, 0, started);
+                if (record) |*r| self.captureExchange(allocator, &req, route_id, r, route.respond.status, started);
+                return reused;
+            }
+
+            // A/B tests pin each visitor to a variant's upstream group
+            var upstream_name = route.upstream;
+            if (ab_test_mod.assign(&route.ab_test, &req)) |assignment| {
+                upstream_name = assignment.variant.upstream;
+                variant_stats = self.experiments.get(route, assignment.index);
+                if (variant_stats) |stats| {
+                    _ = stats.requests.fetchAdd(1, .monotonic);
+                    if (assignment.new) _ = stats.assignments.fetchAdd(1, .monotonic);
+                }
+                if (assignment.new) {
+                    const cookie = try ab_test_mod.setCookie(allocator, &route.ab_test, assignment.variant);
+                    try extra_headers.append(allocator, .{ .name = "Set-Cookie", .value = cookie });
+                }
+            }
+
+            if (self.upstreams.get(upstream_name)) |group| {
+                // A discovered group may have no ready endpoints yet
+                const selected = group.select(client_addr) orelse {
+                    recordRoute(route_stats, 503, 0, started);
+                    if (record) |*r| self.captureExchange(allocator, &req, route_id, r, 503, started);
+                    return errorWith(allocator, conn.stream, &req, route.error_format, 503);
                 };
-            } else {
-                // No upstream configured, send default response
-                const response =
-                    \\HTTP/1.1 200 OK
-                    \\Content-Type: text/plain
-                    \\Content-Length: 45
-                    \\Server: Wraith/0.0.0
-                    \\
-                    \\Wraith MVP - Your request was received! 🚀
-                    \\
-                ;
-
-                _ = conn.stream.write(response) catch |err| {
-                    std.debug.print("Failed to send response: {any}\n", .{err});
+                upstream_addr = selected;
+                balanced = group;
+
+                // Shed load beyond what the group has shown it can serve,
+                // lowest priority first
+                if (group.limiter) |*group_limiter| {
+                    if (!group_limiter.acquire(route.priority)) {
+                        recordRoute(route_stats, 503, 0, started);
+                        if (record) |*r| self.captureExchange(allocator, &req, route_id, r, 503, started);
+                        return errorWith(allocator, conn.stream, &req, route.error_format, 503);
+                    }
+                    limiter = group_limiter;
+                }
+
+                // Routes without retries of their own use the group's
+                const retry_cfg = if (route.retry.attempts > 0) &route.retry else &group.config.retry;
+                if (retry_cfg.attempts > 0) retry = .{ .group = group, .config = retry_cfg };
+                group.retry_budget.deposit();
+
+                // Slow idempotent requests may be raced against a second server
+                if (self.hedging.get(route)) |window| {
+                    if (hedge_mod.eligible(&req)) {
+                        if (group.selectOther(selected)) |other| {
+                            hedge = .{ .address = other, .pool = &group.pool, .delay_ms = window.delay(&route.hedge), .window = window };
+                        }
+                    }
+                }
+            }
+        } else if (vhost) |v| {
+            if (static_mount) |mount| {
+                // Unread body or pipelined bytes would corrupt the next request
+                const reusable = req.wantsKeepAlive() and !req.hasBody() and n == req.head_len;
+                if (self.s3_origins.get(mount)) |origin| {
+                    return origin.serve(allocator, conn.stream, &req, &self.downloads, client_addr, keep_alive_allowed and reusable);
+                }
+                return static_mod.serve(conn.stream, &req, mount, &self.etags, &self.static_io, &self.downloads, client_addr, keep_alive_allowed and reusable);
+            }
+            return errorWith(allocator, conn.stream, &req, v.error_format, 404);
+        } else if (self.config.upstreams.len > 0) {
+            // Requests no route claims go to the first upstream group, like
+            // a route with no settings of its own
+            if (self.upstreams.get(self.config.upstreams[0].name)) |group| {
+                upstream_addr = group.select(client_addr) orelse return errorWith(allocator, conn.stream, &req, .text, 503);
+                balanced = group;
+                if (group.limiter) |*group_limiter| {
+                    if (!group_limiter.acquire(.normal)) return errorWith(allocator, conn.stream, &req, .text, 503);
+                    limiter = group_limiter;
+                }
+                if (group.config.retry.attempts > 0) retry = .{ .group = group, .config = &group.config.retry };
+                group.retry_budget.deposit();
+            }
+        }
+
+        // Every proxied request reaches its upstream through a group, so
+        // its health checks, load balancing and pool always apply
+        if (upstream_addr) |upstream| {
+            const keep_alive = keep_alive_allowed and req.wantsKeepAlive();
+            const response_record = if (record) |*r| r else null;
+            const signer = if (balanced) |group| (if (group.signer) |*s| s else null) else null;
+            const pool = if (balanced) |group| &group.pool else null;
+            // Routes may send the upstream a different path than the client asked for
+            var upstream_req = req;
+            if (matched_route) |route| {
+                if (try self.rewrites.target(allocator, route, req.target)) |target| upstream_req.target = target;
+            }
+            const forward_started = std.time.milliTimestamp();
+            var outcome = self.forwarder.forward(budget.allocator(), conn.stream, upstream, &upstream_req, buf[0..n], extra_headers.items, matched_route, hedge, retry, signer, pool, response_record, keep_alive) catch |err| {
+                // A failed write to a departed client surfaces as a broken
+                // pipe or reset; either way the upstream side is already
+                // closed by now, so the request is simply given up
+                const client_gone = err == error.ClientDisconnected or socket.peerClosed(conn.stream.handle);
+                if (client_gone) {
+                    self.connections.recordClientAbort(response.lastStatus() != 0);
+                } else {
+                    std.debug.print("✗ Proxy error: {any}\n", .{err});
+                    if (variant_stats) |stats| _ = stats.upstream_failures.fetchAdd(1, .monotonic);
+                }
+
+                // Send error response
+                const status: u16 = if (client_gone) 499 else switch (err) {
+                    error.BodyTooLarge, error.TooManyParts, error.PartTooLarge => 413,
+                    error.InvalidChunk, error.LineTooLong, error.InvalidMultipart, error.InvalidFilename => 400,
+                    error.DeadlineExceeded, error.FirstByteTimeout, error.UpstreamIdleTimeout => 504,
+                    error.UpstreamPoolExhausted => 503,
+                    // A memory cap was hit rather than memory running out
+                    error.OutOfMemory => if (budget.refused) |refusal| refusalStatus(refusal) else 500,
+                    else => 502,
                 };
+                recordRoute(route_stats, status, if (status >= 499) 1 else 0, started);
+                if (limiter) |l| l.release(elapsedMs(started), if (status >= 502) .dropped else .ignore);
+                if (response_record) |r| self.captureExchange(allocator, &req, route_id, r, status, started);
+                const format = if (matched_route) |r| r.error_format else .text;
+                if (status != 499) _ = errorWith(allocator, conn.stream, &req, format, status) catch false;
+                return false;
+            };
+            if (balanced) |group| group.latency.record(outcome.timing, elapsedMs(forward_started));
+            if (outcome.intercepted) {
+                outcome = try self.recoverError(allocator, conn.stream, &upstream_req, buf[0..n], extra_headers.items, matched_route.?, response_record, outcome);
             }
+            recordRoute(route_stats, outcome.status, outcome.attempts, started);
+            if (limiter) |l| l.release(elapsedMs(started), if (outcome.status >= 500) .dropped else .success);
+            if (response_record) |r| self.captureExchange(allocator, &req, route_id, r, outcome.status, started);
+            return outcome.reusable;
+        }
+        return errorWith(allocator, conn.stream, &req, .text, 404);
+    }
 
-            conn.stream.close();
+    /// The configured route a request will take, as `handleRequest` picks
+    /// it; containers are not considered
+    fn matchRoute(self: *HttpServer, req: *const request.Request) ?*const config_mod.RouteConfig {
+        const default_vhost = &self.config.default_vhost;
+        const vhost = router_mod.matchVhost(self.config.vhosts, req.host()) orelse switch (default_vhost.action) {
+            .routes => null,
+            .vhost => router_mod.matchVhost(self.config.vhosts, default_vhost.vhost),
+            else => return null,
+        };
+        const router = if (vhost) |v| router_mod.Router.init(v.routes) else self.router;
+        return router.match(req.host(), req.path());
+    }
+
+    /// Replace an intercepted upstream error with the response from the
+    /// route's fallback upstream, or with the error page for its status
+    fn recoverError(
+        self: *HttpServer,
+        allocator: std.mem.Allocator,
+        stream: std.net.Stream,
+        req: *const request.Request,
+        buffered: []const u8,
+        extra_headers: []const Header,
+        route: *const config_mod.RouteConfig,
+        response_record: ?*capture_mod.ResponseRecord,
+        failed: forwarder_mod.Outcome,
+    ) !forwarder_mod.Outcome {
+        var outcome = failed;
+        const fallback = route.error_intercept.fallback;
+
+        // The request body was consumed by the first attempt, so only
+        // bodiless requests can be sent again
+        if (fallback.len > 0 and !req.hasBody()) {
+            if (self.upstreams.get(fallback)) |group| {
+                if (group.select(null)) |addr| {
+                    defer group.release(addr);
+                    const signer = if (group.signer) |*s| s else null;
+                    if (self.forwarder.forward(allocator, stream, addr, req, buffered, extra_headers, route, null, null, signer, &group.pool, response_record, failed.reusable)) |retry| {
+                        outcome = retry;
+                        outcome.attempts += failed.attempts;
+                    } else |err| {
+                        std.debug.print("✗ Fallback upstream {s} error: {any}\n", .{ fallback, err });
+                        outcome.attempts += 1;
+                    }
+                    if (!outcome.intercepted) return outcome;
+                }
+            }
         }
 
-        std.debug.print("\n✓ Server stopped gracefully\n", .{});
+        switch (route.error_format) {
+            .text => try self.error_pages.write(allocator, stream, req.method, outcome.status, extra_headers, outcome.reusable),
+            .problem_json => try problem_mod.write(allocator, stream, req, outcome.status, problem_mod.detailFor(outcome.status), extra_headers, outcome.reusable),
+        }
+        return outcome;
+    }
+
+    /// Count a routed request's outcome in its route's stats
+    fn recordRoute(stats: ?*stats_mod.RouteStats, status: u16, attempts: u32, started: i64) void {
+        const route_stats = stats orelse return;
+        route_stats.record(status, attempts, elapsedMs(started));
+    }
+
+    fn elapsedMs(started: i64) u32 {
+        return @intCast(std.math.clamp(std.time.milliTimestamp() - started, 0, std.math.maxInt(u32)));
+    }
+
+    /// Store a captured request with its response metadata
+    fn captureExchange(
+        self: *HttpServer,
+        allocator: std.mem.Allocator,
+        req: *const request.Request,
+        route_id: []const u8,
+        record: *const capture_mod.ResponseRecord,
+        status: u16,
+        started: i64,
+    ) void {
+        const url = std.fmt.allocPrint(allocator, "http://{s}{s}", .{ req.header("Host") orelse "", req.target }) catch return;
+        self.capture.add(.{
+            .route = route_id,
+            .started_ms = started,
+            .time_ms = elapsedMs(started),
+            .method = req.method,
+            .url = url,
+            .http_version = req.version,
+            .request_headers = req.headers,
+            .status = status,
+            .response_version = record.version,
+            .response_headers = record.headers,
+        }) catch |err| {
+            std.debug.print("✗ Capture failed: {any}\n", .{err});
+        };
+    }
+
+    /// Prometheus metrics on the main listener, for scrapers that can't
+    /// reach the admin listener
+    fn serveMetrics(self: *HttpServer, allocator: std.mem.Allocator, conn: std.net.Server.Connection, req: *const request.Request) !bool {
+        switch (self.scrape.check(conn.address, req)) {
+            .forbidden => return closeWith(conn.stream, 403),
+            .no_token => if (self.config.metrics.tokens.len > 0) {
+                try response.write(conn.stream, 401, &[_]Header{
+                    .{ .name = "Content-Type", .value = "text/plain" },
+                    .{ .name = "WWW-Authenticate", .value = "Bearer realm=\"wraith-metrics\"" },
+                }, "401 Unauthorized\n");
+                return false;
+            },
+            .granted => {},
+        }
+        const sources = scrape_mod.Sources{
+            .rate_limiter = &self.rate_limiter,
+            .connections = &self.connections,
+            .usage = &self.usage,
+            .route_stats = &self.route_stats,
+            .upstreams = &self.upstreams,
+            .s3_origins = &self.s3_origins,
+        };
+        try response.write(conn.stream, 200, &[_]Header{
+            .{ .name = "Content-Type", .value = metrics.content_type },
+            .{ .name = "Cache-Control", .value = "no-store" },
+        }, try sources.render(allocator));
+        return false;
+    }
+
+    /// Send a wraith-generated error in the route's or vhost's format; the
+    /// connection is not reused afterwards
+    fn errorWith(
+        allocator: std.mem.Allocator,
+        stream: std.net.Stream,
+        req: *const request.Request,
+        format: config_mod.ErrorFormat,
+        status: u16,
+    ) !bool {
+        switch (format) {
+            .text => try response.writeError(stream, status),
+            .problem_json => try problem_mod.write(allocator, stream, req, status, problem_mod.detailFor(status), &[_]Header{}, false),
+        }
+        return false;
+    }
+
+    /// Reject a request over a rate limit, telling the client when to retry
+    fn tooManyRequests(
+        allocator: std.mem.Allocator,
+        stream: std.net.Stream,
+        req: *const request.Request,
+        format: config_mod.ErrorFormat,
+        retry_after_s: u32,
+    ) !bool {
+        const retry_after = try std.fmt.allocPrint(allocator, "{d}", .{retry_after_s});
+        const headers = [_]Header{.{ .name = "Retry-After", .value = retry_after }};
+        switch (format) {
+            .text => try response.write(stream, 429, &[_]Header{
+                .{ .name = "Content-Type", .value = "text/plain" },
+                headers[0],
+            }, "429 Too Many Requests\n"),
+            .problem_json => try problem_mod.write(allocator, stream, req, 429, problem_mod.detailFor(429), &headers, false),
+        }
+        return false;
+    }
+
+    /// Status for a request refused by a memory budget: over its
+    /// connection's own budget the request is too large, over the global
+    /// one the server is overloaded
+    fn refusalStatus(refusal: memory_mod.Refusal) u16 {
+        return switch (refusal) {
+            .connection => 413,
+            .global => 503,
+        };
+    }
+
+    /// Send an error response; the connection is not reused afterwards
+    fn closeWith(stream: std.net.Stream, status: u16) !bool {
+        try response.writeError(stream, status);
+        return false;
+    }
+
+    pub fn deinit(self: *HttpServer) void {
+        // Stop discovery threads before their state is freed
+        signals.requestShutdown();
+        if (self.docker_thread) |thread| thread.join();
+        // Joined before the stats it samples are freed; it saves on the way out
+        if (self.history_thread) |thread| thread.join();
+        if (self.events_thread) |thread| thread.join();
+        if (self.cluster) |*cluster| cluster.deinit();
+        self.docker.deinit();
+        self.experiments.deinit();
+        self.hedging.deinit();
+        self.rewrites.deinit();
+        self.route_stats.deinit();
+        self.rate_limiter.deinit();
+        self.connections.deinit();
+        self.allocator.free(self.listen_addresses);
+        if (self.tls_server) |tls_server| {
+            self.allocator.free(tls_server.cert_path);
+            self.allocator.free(tls_server.key_path);
+        }
+        self.capture.deinit();
+        self.faults.deinit();
+        self.error_pages.deinit();
+        self.etags.deinit();
+        self.s3_origins.deinit();
+        self.downloads.deinit();
+        self.alt_svc.deinit();
+        self.access_logs.deinit();
+        self.usage.deinit();
+        self.scrape.deinit();
+        self.history.deinit();
+        self.schedule.deinit();
+        if (self.default_page.len > 0) self.allocator.free(self.default_page);
+        self.allocator.free(self.stream_proxies);
+        self.upstreams.deinit();
+        // After the health checkers that publish on it have stopped
+        self.events.deinit();
+        self.resolver.deinit();
     }
 
     pub fn stop(self: *HttpServer) void {
@@ -0,0 +1,133 @@
+const std = @import("std");
+const config = @import("../config/config.zig");
+
+pub fn validate(cfg: *const config.MemoryConfig) !void {
+    if (!(cfg.high_watermark > 0 and cfg.high_watermark <= 1)) return error.InvalidMemoryWatermark;
+}
+
+/// Which cap refused an allocation
+pub const Refusal = enum {
+    /// The connection's own budget; answered with 413
+    connection,
+    /// Everything held by all connections; answered with 503
+    global,
+};
+
+/// Bytes held by requests in flight across every connection
+pub const MemoryBudget = struct {
+    config: *const config.MemoryConfig,
+    used: std.atomic.Value(usize) = std.atomic.Value(usize).init(0),
+    /// Set while usage is past the high watermark
+    shedding: std.atomic.Value(bool) = std.atomic.Value(bool).init(false),
+    refused_connection: std.atomic.Value(u64) = std.atomic.Value(u64).init(0),
+    refused_global: std.atomic.Value(u64) = std.atomic.Value(u64).init(0),
+    shed: std.atomic.Value(u64) = std.atomic.Value(u64).init(0),
+
+    pub fn init(cfg: *const config.MemoryConfig) MemoryBudget {
+        return .{ .config = cfg };
+    }
+
+    /// Whether usage is past the high watermark. Returns `.entered` the
+    /// first time it is after being below, so caches are shrunk once per
+    /// episode rather than on every request.
+    pub fn pressure(self: *MemoryBudget) enum { none, entered, ongoing } {
+        if (self.config.global_limit == 0) return .none;
+        const watermark: usize = @intFromFloat(@as(f64, @floatFromInt(self.config.global_limit)) * self.config.high_watermark);
+        if (self.used.load(.monotonic) < watermark) {
+            self.shedding.store(false, .monotonic);
+            return .none;
+        }
+        if (self.shedding.swap(true, .monotonic)) return .ongoing;
+        std.debug.print("✗ Memory past the high watermark ({d} bytes held), shedding load\n", .{self.used.load(.monotonic)});
+        return .entered;
+    }
+};
+
+/// Allocator for one connection's requests, counting what they hold
+/// against the connection's budget and the global limit. Allocations past
+/// either fail, and `refused` says which cap was hit. Used by the
+/// connection's own thread only.
+pub const ConnectionBudget = struct {
+    parent: std.mem.Allocator,
+    budget: *MemoryBudget,
+    held: usize = 0,
+    refused: ?Refusal = null,
+
+    pub fn init(parent: std.mem.Allocator, budget: *MemoryBudget) ConnectionBudget {
+        return .{ .parent = parent, .budget = budget };
+    }
+
+    pub fn allocator(self: *ConnectionBudget) std.mem.Allocator {
+        return .{
+            .ptr = self,
+            .vtable = &.{ .alloc = alloc, .resize = resize, .remap = remap, .free = free },
+        };
+    }
+
+    fn reserve(self: *ConnectionBudget, len: usize) bool {
+        const cfg = self.budget.config;
+        if (cfg.connection_limit > 0 and self.held + len > cfg.connection_limit) {
+            self.refuse(.connection);
+            return false;
+        }
+        const before = self.budget.used.fetchAdd(len, .monotonic);
+        if (cfg.global_limit > 0 and before + len > cfg.global_limit) {
+            _ = self.budget.used.fetchSub(len, .monotonic);
+            self.refuse(.global);
+            return false;
+        }
+        self.held += len;
+        return true;
+    }
+
+    fn unreserve(self: *ConnectionBudget, len: usize) void {
+        self.held -= len;
+        _ = self.budget.used.fetchSub(len, .monotonic);
+    }
+
+    fn refuse(self: *ConnectionBudget, refusal: Refusal) void {
+        self.refused = refusal;
+        const counter = switch (refusal) {
+            .connection => &self.budget.refused_connection,
+            .global => &self.budget.refused_global,
+        };
+        _ = counter.fetchAdd(1, .monotonic);
+    }
+
+    fn alloc(ctx: *anyopaque, len: usize, alignment: std.mem.Alignment, ret_addr: usize) ?[*]u8 {
+        const self: *ConnectionBudget = @ptrCast(@alignCast(ctx));
+        if (!self.reserve(len)) return null;
+        return self.parent.rawAlloc(len, alignment, ret_addr) orelse {
+            self.unreserve(len);
+            return null;
+        };
+    }
+
+    fn resize(ctx: *anyopaque, memory: []u8, alignment: std.mem.Alignment, new_len: usize, ret_addr: usize) bool {
+        const self: *ConnectionBudget = @ptrCast(@alignCast(ctx));
+        if (new_len > memory.len and !self.reserve(new_len - memory.len)) return false;
+        if (!self.parent.rawResize(memory, alignment, new_len, ret_addr)) {
+            if (new_len > memory.len) self.unreserve(new_len - memory.len);
+            return false;
+        }
+        if (new_len < memory.len) self.unreserve(memory.len - new_len);
+        return true;
+    }
+
+    fn remap(ctx: *anyopaque, memory: []u8, alignment: std.mem.Alignment, new_len: usize, ret_addr: usize) ?[*]u8 {
+        const self: *ConnectionBudget = @ptrCast(@alignCast(ctx));
+        if (new_len > memory.len and !self.reserve(new_len - memory.len)) return null;
+        const remapped = self.parent.rawRemap(memory, alignment, new_len, ret_addr) orelse {
+            if (new_len > memory.len) self.unreserve(new_len - memory.len);
+            return null;
+        };
+        if (new_len < memory.len) self.unreserve(memory.len - new_len);
+        return remapped;
+    }
+
+    fn free(ctx: *anyopaque, memory: []u8, alignment: std.mem.Alignment, ret_addr: usize) void {
+        const self: *ConnectionBudget = @ptrCast(@alignCast(ctx));
+        self.parent.rawFree(memory, alignment, ret_addr);
+        self.unreserve(memory.len);
+    }
+};
@@ -0,0 +1,93 @@
+const std = @import("std");
+
+/// Content-Type of the Prometheus text format
+pub const content_type = "text/plain; version=0.0.4; charset=utf-8";
+
+pub const Kind = enum { counter, gauge, histogram };
+
+pub const Label = struct {
+    name: []const u8,
+    value: []const u8,
+};
+
+/// Prometheus text exposition, written one metric family at a time: a
+/// `family` line pair, then that family's samples
+pub const Exposition = struct {
+    allocator: std.mem.Allocator,
+    out: std.ArrayList(u8) = .empty,
+
+    pub fn init(allocator: std.mem.Allocator) Exposition {
+        return .{ .allocator = allocator };
+    }
+
+    pub fn deinit(self: *Exposition) void {
+        self.out.deinit(self.allocator);
+    }
+
+    pub fn family(self: *Exposition, name: []const u8, kind: Kind, help: []const u8) !void {
+        try self.out.print(self.allocator, "# HELP {s} {s}\n# TYPE {s} {s}\n", .{ name, help, name, @tagName(kind) });
+    }
+
+    pub fn sample(self: *Exposition, name: []const u8, labels: []const Label, value: anytype) !void {
+        try self.out.appendSlice(self.allocator, name);
+        if (labels.len > 0) {
+            try self.out.append(self.allocator, '{');
+            for (labels, 0..) |label, i| {
+                if (i > 0) try self.out.append(self.allocator, ',');
+                try self.out.print(self.allocator, "{s}=\"", .{label.name});
+                try self.escape(label.value);
+                try self.out.append(self.allocator, '"');
+            }
+            try self.out.append(self.allocator, '}');
+        }
+        try self.out.print(self.allocator, " {d}\n", .{value});
+    }
+
+    /// A family with a single unlabelled sample
+    pub fn single(self: *Exposition, name: []const u8, kind: Kind, help: []const u8, value: anytype) !void {
+        try self.family(name, kind, help);
+        try self.sample(name, &.{}, value);
+    }
+
+    /// One histogram's `_bucket`, `_sum` and `_count` samples. `counts`
+    /// holds each bucket's own count, with one more than `bounds` for
+    /// values above the last bound; buckets are written cumulatively.
+    pub fn histogram(self: *Exposition, name: []const u8, labels: []const Label, bounds: []const u32, counts: []const u64, sum: u64) !void {
+        var names: [3][128]u8 = undefined;
+        const bucket_name = try std.fmt.bufPrint(&names[0], "{s}_bucket", .{name});
+        const sum_name = try std.fmt.bufPrint(&names[1], "{s}_sum", .{name});
+        const count_name = try std.fmt.bufPrint(&names[2], "{s}_count", .{name});
+
+        var with_le: [8]Label = undefined;
+        if (labels.len >= with_le.len) return error.TooManyLabels;
+        @memcpy(with_le[0..labels.len], labels);
+        var le_buf: [16]u8 = undefined;
+        var cumulative: u64 = 0;
+        for (counts, 0..) |count, i| {
+            cumulative += count;
+            with_le[labels.len] = .{
+                .name = "le",
+                .value = if (i < bounds.len) try std.fmt.bufPrint(&le_buf, "{d}", .{bounds[i]}) else "+Inf",
+            };
+            try self.sample(bucket_name, with_le[0 .. labels.len + 1], cumulative);
+        }
+        try self.sample(sum_name, labels, sum);
+        try self.sample(count_name, labels, cumulative);
+    }
+
+    pub fn text(self: *const Exposition) []const u8 {
+        return self.out.items;
+    }
+
+    /// Label values escape backslashes, quotes and newlines
+    fn escape(self: *Exposition, value: []const u8) !void {
+        for (value) |c| {
+            switch (c) {
+                '\\' => try self.out.appendSlice(self.allocator, "\\\\"),
+                '"' => try self.out.appendSlice(self.allocator, "\\\""),
+                '\n' => try self.out.appendSlice(self.allocator, "\\n"),
+                else => try self.out.append(self.allocator, c),
+            }
+        }
+    }
+};
@@ -0,0 +1,71 @@
+const std = @import("std");
+const config = @import("../config/config.zig");
+const request = @import("request.zig");
+
+/// Canonicalize the request target and Host header in place, so routing,
+/// ACLs, caches and the upstream all see one spelling of each URL.
+/// Rewritten values are allocated with `allocator`; `headers` is the
+/// mutable backing of `req.headers`.
+pub fn apply(
+    allocator: std.mem.Allocator,
+    cfg: *const config.NormalizeConfig,
+    req: *request.Request,
+    headers: []request.Header,
+) !void {
+    if (cfg.lowercase_host) {
+        for (headers) |*h| {
+            if (std.ascii.eqlIgnoreCase(h.name, "Host")) h.value = try std.ascii.allocLowerString(allocator, h.value);
+        }
+    }
+
+    var target = req.target;
+    if (cfg.strip_fragment) {
+        if (std.mem.indexOfScalar(u8, target, '#')) |hash| target = target[0..hash];
+    }
+    // Asterisk and absolute-form targets are passed through as they are
+    if ((cfg.merge_slashes or cfg.percent_encoding) and target.len > 0 and target[0] == '/') {
+        target = try canonicalTarget(allocator, cfg, target);
+    }
+    req.target = target;
+}
+
+/// Merge slashes in the path and normalize percent-encoding in the path
+/// and query (RFC 3986 6.2.2): unreserved characters are decoded, every
+/// other escape keeps its encoding with uppercase hex digits. Encoded
+/// slashes stay encoded, so they never merge with real ones.
+fn canonicalTarget(allocator: std.mem.Allocator, cfg: *const config.NormalizeConfig, target: []const u8) ![]const u8 {
+    const query_start = std.mem.indexOfScalar(u8, target, '?') orelse target.len;
+    var out = try std.ArrayList(u8).initCapacity(allocator, target.len);
+
+    var i: usize = 0;
+    while (i < target.len) {
+        const c = target[i];
+        if (cfg.merge_slashes and c == '/' and i < query_start and std.mem.endsWith(u8, out.items, "/")) {
+            i += 1;
+            continue;
+        }
+        if (cfg.percent_encoding and c == '%') {
+            if (i + 3 > target.len) return error.InvalidPercentEncoding;
+            const high = std.fmt.charToDigit(target[i + 1], 16) catch return error.InvalidPercentEncoding;
+            const low = std.fmt.charToDigit(target[i + 2], 16) catch return error.InvalidPercentEncoding;
+            const byte = (high << 4) | low;
+            if (isUnreserved(byte)) {
+                out.appendAssumeCapacity(byte);
+            } else {
+                out.appendSliceAssumeCapacity(&.{ '%', hex_upper[high], hex_upper[low] });
+            }
+            i += 3;
+            continue;
+        }
+        out.appendAssumeCapacity(c);
+        i += 1;
+    }
+    return out.items;
+}
+
+const hex_upper = "0123456789ABCDEF";
+
+/// ALPHA / DIGIT / "-" / "." / "_" / "~" (RFC 3986 2.3)
+fn isUnreserved(c: u8) bool {
+    return std.ascii.isAlphanumeric(c) or c == '-' or c == '.' or c == '_' or c == '~';
+}
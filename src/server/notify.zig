@@ -0,0 +1,82 @@
+const std = @import("std");
+const config = @import("../config/config.zig");
+const secret_mod = @import("../config/secret.zig");
+const events = @import("events.zig");
+
+/// Longest webhook URL, after resolving a reference
+const MAX_URL_LEN = 2048;
+
+/// Posts events to the notification webhooks, holding back repeats of an
+/// event about the same subject within the cooldown. Called from the event
+/// bus thread only.
+pub const Notifier = struct {
+    allocator: std.mem.Allocator,
+    config: *const config.NotificationsConfig,
+    /// When each event and subject was last sent, for the cooldown
+    last_sent: std.StringHashMapUnmanaged(i64) = .empty,
+
+    pub fn init(allocator: std.mem.Allocator, cfg: *const config.NotificationsConfig) Notifier {
+        return .{ .allocator = allocator, .config = cfg };
+    }
+
+    pub fn deinit(self: *Notifier) void {
+        var it = self.last_sent.keyIterator();
+        while (it.next()) |key| self.allocator.free(key.*);
+        self.last_sent.deinit(self.allocator);
+    }
+
+    pub fn deliver(self: *Notifier, event: *const events.Event) void {
+        if (self.config.webhooks.len == 0 or self.coolingDown(event)) return;
+        for (self.config.webhooks) |*webhook| {
+            if (!events.wanted(webhook.events, event.kind)) continue;
+            post(self.allocator, webhook.url, webhook.format, event) catch |err| {
+                // The URL may carry credentials, so it isn't logged
+                std.debug.print("✗ Webhook for {s} failed: {any}\n", .{ @tagName(event.kind), err });
+            };
+        }
+    }
+
+    /// Whether the event was sent about the subject too recently to send
+    /// again; otherwise starts a new cooldown
+    fn coolingDown(self: *Notifier, event: *const events.Event) bool {
+        var key_buf: [256]u8 = undefined;
+        const key = std.fmt.bufPrint(&key_buf, "{s} {s}", .{ @tagName(event.kind), event.subject }) catch return false;
+        const cooldown_ms = @as(i64, self.config.cooldown_s) * std.time.ms_per_s;
+        if (self.last_sent.getPtr(key)) |sent| {
+            if (event.time_ms - sent.* < cooldown_ms) return true;
+            sent.* = event.time_ms;
+            return false;
+        }
+        const owned = self.allocator.dupe(u8, key) catch return false;
+        self.last_sent.put(self.allocator, owned, event.time_ms) catch self.allocator.free(owned);
+        return false;
+    }
+};
+
+/// POST an event to `url`, which may be a secret reference
+pub fn post(allocator: std.mem.Allocator, url_source: []const u8, format: config.WebhookFormat, event: *const events.Event) !void {
+    var url_buf: [MAX_URL_LEN]u8 = undefined;
+    const url = try secret_mod.resolveInto(url_source, &url_buf);
+
+    const text = try std.fmt.allocPrint(allocator, "[wraith] {s}", .{event.message});
+    defer allocator.free(text);
+    const payload = switch (format) {
+        .json => try event.json(allocator),
+        .slack => try std.json.Stringify.valueAlloc(allocator, .{ .text = text }, .{}),
+        .discord => try std.json.Stringify.valueAlloc(allocator, .{ .content = text }, .{}),
+    };
+    defer allocator.free(payload);
+    try postJson(allocator, url, "application/json", payload);
+}
+
+pub fn postJson(allocator: std.mem.Allocator, url: []const u8, content_type: []const u8, payload: []const u8) !void {
+    var client: std.http.Client = .{ .allocator = allocator };
+    defer client.deinit();
+    const result = try client.fetch(.{
+        .location = .{ .url = url },
+        .method = .POST,
+        .payload = payload,
+        .headers = .{ .content_type = .{ .override = content_type } },
+    });
+    if (result.status.class() != .success) return error.WebhookRejected;
+}
@@ -0,0 +1,233 @@
+const std = @import("std");
+const config = @import("../config/config.zig");
+const manager = @import("../upstream/manager.zig");
+const socket = @import("socket.zig");
+const acme = @import("acme.zig");
+
+/// File descriptors kept free for logs, discovery and health checks
+const FD_RESERVE = 64;
+
+/// Checks run before any listener is bound, so a broken deployment fails at
+/// startup with a full report instead of degrading at runtime
+pub const Preflight = struct {
+    allocator: std.mem.Allocator,
+    cfg: *const config.Config,
+    failures: usize = 0,
+
+    pub fn init(allocator: std.mem.Allocator, cfg: *const config.Config) Preflight {
+        return .{ .allocator = allocator, .cfg = cfg };
+    }
+
+    /// Run every check and print the results; fails when any check did
+    pub fn run(self: *Preflight) !void {
+        std.debug.print("Preflight checks:\n", .{});
+        self.checkFiles();
+        self.checkCertificates();
+        self.checkUpstreams();
+        self.checkPorts();
+        self.checkFileLimit();
+
+        if (self.failures > 0) {
+            std.debug.print("✗ {d} preflight check(s) failed\n\n", .{self.failures});
+            return error.PreflightFailed;
+        }
+        std.debug.print("✓ All preflight checks passed\n\n", .{});
+    }
+
+    fn report(self: *Preflight, what: []const u8, subject: []const u8, result: anyerror!void) void {
+        if (result) |_| {
+            std.debug.print("  ✓ {s} {s}\n", .{ what, subject });
+        } else |err| {
+            self.failures += 1;
+            std.debug.print("  ✗ {s} {s}: {s}\n", .{ what, subject, @errorName(err) });
+        }
+    }
+
+    /// Static roots, error pages and response bodies served from disk
+    fn checkFiles(self: *Preflight) void {
+        for (self.cfg.vhosts) |vhost| {
+            for (vhost.static) |mount| {
+                if (mount.s3.endpoint.len == 0) self.report("static root", mount.root, readableDir(mount.root));
+            }
+            for (vhost.routes) |route| self.checkRouteFiles(&route);
+        }
+        for (self.cfg.routes) |route| self.checkRouteFiles(&route);
+        for (self.cfg.error_pages) |page| self.report("error page", page.file, readableFile(page.file));
+        if (self.cfg.default_vhost.action == .page) {
+            self.report("default vhost page", self.cfg.default_vhost.page, readableFile(self.cfg.default_vhost.page));
+        }
+    }
+
+    fn checkRouteFiles(self: *Preflight, route: *const config.RouteConfig) void {
+        if (route.respond.status != 0 and route.respond.file.len > 0) {
+            self.report("respond file", route.respond.file, readableFile(route.respond.file));
+        }
+    }
+
+    /// Every certificate and key a TLS listener will load
+    fn checkCertificates(self: *Preflight) void {
+        const cfg = self.cfg;
+        // ACME obtains the default certificate after startup
+        const listener_pair = cfg.tls.cert != null or cfg.tls.key != null;
+        const default_needed = !cfg.tls.acme.enabled and
+            ((cfg.server.listen_tls.len > 0 and !listener_pair) or
+                (cfg.dns.enabled and cfg.dns.dot.enabled and cfg.dns.dot.cert_path == null));
+        if (default_needed) self.checkPair(null, null);
+        if (cfg.server.listen_tls.len > 0 and listener_pair) self.checkPair(cfg.tls.cert, cfg.tls.key);
+        if (cfg.tls.acme.enabled) {
+            self.report("ACME directory", cfg.tls.acme.directory_url, resolvableDirectory(self.allocator, cfg.tls.acme.directory_url));
+        }
+
+        if (cfg.dns.enabled and cfg.dns.dot.enabled and cfg.dns.dot.cert_path != null) {
+            self.checkPair(cfg.dns.dot.cert_path, cfg.dns.dot.key_path);
+        }
+        for (cfg.vhosts) |vhost| {
+            if (vhost.tls.cert_path != null or vhost.tls.key_path != null) self.checkPair(vhost.tls.cert_path, vhost.tls.key_path);
+        }
+    }
+
+    /// Paths default to server.crt/server.key in tls.cert_dir
+    fn checkPair(self: *Preflight, cert_path: ?[]const u8, key_path: ?[]const u8) void {
+        const cert = cert_path orelse std.fs.path.join(self.allocator, &[_][]const u8{ self.cfg.tls.cert_dir, "server.crt" }) catch |err| {
+            return self.report("certificate", self.cfg.tls.cert_dir, err);
+        };
+        defer if (cert_path == null) self.allocator.free(cert);
+        const key = key_path orelse std.fs.path.join(self.allocator, &[_][]const u8{ self.cfg.tls.cert_dir, "server.key" }) catch |err| {
+            return self.report("private key", self.cfg.tls.cert_dir, err);
+        };
+        defer if (key_path == null) self.allocator.free(key);
+
+        self.report("certificate", cert, parseCertificates(self.allocator, cert));
+        self.report("private key", key, parsePrivateKey(self.allocator, key));
+    }
+
+    /// Static upstream servers and S3 endpoints must resolve now; they are
+    /// resolved once at startup
+    fn checkUpstreams(self: *Preflight) void {
+        for (self.cfg.upstreams) |upstream| {
+            for (upstream.servers) |server| {
+                const result: anyerror!void = if (manager.parseServerAddress(self.allocator, server.host)) |_| {} else |err| err;
+                self.report("upstream server", server.host, result);
+            }
+        }
+        for (self.cfg.vhosts) |vhost| {
+            for (vhost.static) |mount| {
+                if (mount.s3.endpoint.len == 0) continue;
+                const result: anyerror!void = if (manager.parseServerAddress(self.allocator, mount.s3.endpoint)) |_| {} else |err| err;
+                self.report("S3 endpoint", mount.s3.endpoint, result);
+            }
+        }
+    }
+
+    /// Every address wraith will listen on must be free to bind
+    fn checkPorts(self: *Preflight) void {
+        const cfg = self.cfg;
+        const addresses = socket.listenAddresses(self.allocator, cfg.server.listen, cfg.server.dual_stack) catch |err| {
+            return self.report("listen addresses", "", err);
+        };
+        defer self.allocator.free(addresses);
+        for (addresses) |address| {
+            var subject_buf: [64]u8 = undefined;
+            const subject = std.fmt.bufPrint(&subject_buf, "{f}", .{address}) catch "";
+            self.report("listen address", subject, bindableAddress(address));
+        }
+        for (cfg.server.listen_tls) |addr| self.report("listen address", addr, bindable(addr));
+        if (cfg.server.demux.enabled) self.report("demux listener", cfg.server.demux.listen, bindable(cfg.server.demux.listen));
+        if (cfg.admin.enabled) self.report("admin listener", cfg.admin.listen, bindable(cfg.admin.listen));
+        if (cfg.dns.enabled) {
+            if (cfg.dns.dot.enabled) {
+                for (cfg.dns.dotAddresses()) |addr| self.report("DoT listener", addr, bindable(addr));
+            }
+            if (cfg.dns.tcp_enabled) {
+                for (cfg.dns.listen_tcp) |addr| self.report("DNS TCP listener", addr, bindable(addr));
+            }
+            if (cfg.dns.udp_enabled) {
+                for (cfg.dns.listen_udp) |addr| self.report("DNS UDP listener", addr, bindableUdp(addr));
+            }
+        }
+        for (cfg.stream.servers) |server| self.report("stream listener", server.listen, bindable(server.listen));
+    }
+
+    /// Each proxied connection needs a descriptor for the client and one for
+    /// the upstream. The soft limit is raised toward the hard limit if needed.
+    fn checkFileLimit(self: *Preflight) void {
+        const max_connections = self.cfg.server.max_connections;
+        if (max_connections == 0) return;
+
+        const needed: u64 = @as(u64, max_connections) * 2 + FD_RESERVE;
+        var subject_buf: [64]u8 = undefined;
+        const subject = std.fmt.bufPrint(&subject_buf, "{d} for max_connections = {d}", .{ needed, max_connections }) catch "";
+        self.report("open file limit", subject, raiseFileLimit(needed));
+    }
+};
+
+fn readableDir(path: []const u8) !void {
+    var dir = try std.fs.cwd().openDir(path, .{ .iterate = true });
+    defer dir.close();
+    var it = dir.iterate();
+    _ = try it.next();
+}
+
+fn readableFile(path: []const u8) !void {
+    const file = try std.fs.cwd().openFile(path, .{});
+    defer file.close();
+    var byte: [1]u8 = undefined;
+    _ = try file.read(&byte);
+}
+
+/// Parse every PEM certificate in the file; at least one must be valid now
+fn parseCertificates(allocator: std.mem.Allocator, path: []const u8) !void {
+    var bundle: std.crypto.Certificate.Bundle = .{};
+    defer bundle.deinit(allocator);
+    try bundle.addCertsFromFilePath(allocator, std.fs.cwd(), path);
+    // Expired and not yet valid certificates are skipped while loading
+    if (bundle.map.count() == 0) return error.NoValidCertificate;
+}
+
+/// The file must hold a PEM private key block with valid base64
+fn parsePrivateKey(allocator: std.mem.Allocator, path: []const u8) !void {
+    const pem = try std.fs.cwd().readFileAlloc(allocator, path, 1024 * 1024);
+    defer allocator.free(pem);
+
+    const begin = std.mem.indexOf(u8, pem, "-----BEGIN ") orelse return error.NotPem;
+    const label_end = std.mem.indexOfPos(u8, pem, begin, "PRIVATE KEY-----") orelse return error.NotPrivateKey;
+    const body_start = label_end + "PRIVATE KEY-----".len;
+    const body_end = std.mem.indexOfPos(u8, pem, body_start, "-----END ") orelse return error.NotPem;
+
+    const decoder = std.base64.standard.decoderWithIgnore(" \t\r\n");
+    const encoded = pem[body_start..body_end];
+    const der = try allocator.alloc(u8, try decoder.calcSizeUpperBound(encoded.len));
+    defer allocator.free(der);
+    const len = try decoder.decode(der, encoded);
+    if (len == 0) return error.EmptyPrivateKey;
+}
+
+/// The CA is contacted after startup, so its name must resolve now
+fn resolvableDirectory(allocator: std.mem.Allocator, url: []const u8) !void {
+    const directory = try acme.directoryHost(url);
+    const list = try std.net.getAddressList(allocator, directory.host, directory.port);
+    defer list.deinit();
+    if (list.addrs.len == 0) return error.UnknownHostName;
+}
+
+fn bindable(addr_text: []const u8) !void {
+    try bindableAddress(try std.net.Address.parseIpAndPort(addr_text));
+}
+
+fn bindableAddress(address: std.net.Address) !void {
+    var server = try socket.listen(address);
+    server.deinit();
+}
+
+fn bindableUdp(addr_text: []const u8) !void {
+    const handle = try socket.bindUdp(try std.net.Address.parseIpAndPort(addr_text));
+    std.posix.close(handle);
+}
+
+fn raiseFileLimit(needed: u64) !void {
+    var limit = try std.posix.getrlimit(.NOFILE);
+    if (limit.cur >= needed) return;
+    if (limit.max < needed) return error.HardLimitTooLow;
+    limit.cur = needed;
+    try std.posix.setrlimit(.NOFILE, limit);
+}
@@ -0,0 +1,118 @@
+const std = @import("std");
+const config = @import("../config/config.zig");
+const request = @import("request.zig");
+const response = @import("response.zig");
+const signals = @import("signals.zig");
+const upstream = @import("../upstream/manager.zig");
+
+const Header = request.Header;
+
+const Kind = enum { livez, readyz, startupz };
+
+const Check = struct {
+    name: []const u8,
+    ok: bool,
+};
+
+/// Kubernetes-style liveness, readiness and startup endpoints, answered on
+/// the main listener before routing
+pub const Probes = struct {
+    config: *const config.ProbesConfig,
+    upstreams: *upstream.UpstreamManager,
+    /// Set once the HTTP listener is bound
+    listening: std.atomic.Value(bool) = std.atomic.Value(bool).init(false),
+
+    pub fn init(cfg: *const config.ProbesConfig, upstreams: *upstream.UpstreamManager) Probes {
+        return .{
+            .config = cfg,
+            .upstreams = upstreams,
+        };
+    }
+
+    /// Answer a probe request. Returns whether the connection can be
+    /// reused, or null when the path is not a probe endpoint.
+    pub fn handle(
+        self: *Probes,
+        allocator: std.mem.Allocator,
+        stream: std.net.Stream,
+        req: *const request.Request,
+        keep_alive: bool,
+    ) !?bool {
+        if (!self.config.enabled) return null;
+
+        const path = req.path();
+        const kind: Kind = if (std.mem.eql(u8, path, self.config.livez_path))
+            .livez
+        else if (std.mem.eql(u8, path, self.config.readyz_path))
+            .readyz
+        else if (std.mem.eql(u8, path, self.config.startupz_path))
+            .startupz
+        else
+            return null;
+
+        var checks: std.ArrayList(Check) = .empty;
+        switch (kind) {
+            // Answering at all shows the process is alive; draining is not a reason to restart it
+            .livez => try checks.append(allocator, .{ .name = "ping", .ok = true }),
+            .startupz => try self.startupChecks(allocator, &checks),
+            .readyz => {
+                try self.startupChecks(allocator, &checks);
+                try checks.append(allocator, .{ .name = "shutdown", .ok = !signals.shouldShutdown() });
+                try checks.append(allocator, .{ .name = "reload", .ok = !signals.shouldReload() });
+                try self.upstreamChecks(allocator, &checks);
+            },
+        }
+
+        var passed = true;
+        for (checks.items) |check| passed = passed and check.ok;
+
+        // Like the Kubernetes API server: terse unless ?verbose is given
+        var body: std.ArrayList(u8) = .empty;
+        if (req.queryParam("verbose") != null) {
+            for (checks.items) |check| {
+                try body.print(allocator, "[{s}]{s} {s}\n", .{ if (check.ok) "+" else "-", check.name, if (check.ok) "ok" else "failed" });
+            }
+        }
+        if (passed) {
+            try body.appendSlice(allocator, "ok\n");
+        } else {
+            try body.print(allocator, "{s} check failed\n", .{@tagName(kind)});
+        }
+
+        const status: u16 = if (passed) 200 else 503;
+        try response.writeHead(stream, status, &[_]Header{
+            .{ .name = "Content-Type", .value = "text/plain; charset=utf-8" },
+            .{ .name = "Cache-Control", .value = "no-store" },
+        }, body.items.len, keep_alive);
+        if (!std.mem.eql(u8, req.method, "HEAD")) try stream.writeAll(body.items);
+        return keep_alive;
+    }
+
+    /// Listener bound and a first round of health checks finished
+    fn startupChecks(self: *Probes, allocator: std.mem.Allocator, checks: *std.ArrayList(Check)) !void {
+        try checks.append(allocator, .{ .name = "listeners", .ok = self.listening.load(.acquire) });
+
+        var checked = true;
+        for (self.upstreams.groups.items) |group| {
+            if (group.config.health_check_interval > 0 and !group.checked.load(.acquire)) checked = false;
+        }
+        try checks.append(allocator, .{ .name = "health-checks", .ok = checked });
+    }
+
+    /// At least one healthy server in each required group
+    fn upstreamChecks(self: *Probes, allocator: std.mem.Allocator, checks: *std.ArrayList(Check)) !void {
+        for (self.upstreams.groups.items) |group| {
+            if (!self.required(group.name)) continue;
+            const name = try std.fmt.allocPrint(allocator, "upstream:{s}", .{group.name});
+            try checks.append(allocator, .{ .name = name, .ok = group.hasHealthy() });
+        }
+    }
+
+    fn required(self: *const Probes, name: []const u8) bool {
+        if (self.config.required_upstreams.len == 0) return true;
+        for (self.config.required_upstreams) |required_name| {
+            if (std.mem.eql(u8, required_name, name)) return true;
+        }
+        return false;
+    }
+};
@@ -0,0 +1,63 @@
+const std = @import("std");
+const request = @import("request.zig");
+const response = @import("response.zig");
+
+const Header = request.Header;
+
+/// The client's X-Request-ID, or a random 128-bit hex id
+pub fn requestId(req: *const request.Request, buf: *[32]u8) []const u8 {
+    if (req.header("X-Request-ID")) |id| {
+        if (id.len > 0 and id.len <= 128) return id;
+    }
+    var bytes: [16]u8 = undefined;
+    std.crypto.random.bytes(&bytes);
+    buf.* = std.fmt.bytesToHex(bytes, .lower);
+    return buf;
+}
+
+/// Write a wraith-generated error as RFC 9457 problem details
+pub fn write(
+    allocator: std.mem.Allocator,
+    stream: std.net.Stream,
+    req: *const request.Request,
+    status: u16,
+    detail: []const u8,
+    extra_headers: []const Header,
+    keep_alive: bool,
+) !void {
+    var id_buf: [32]u8 = undefined;
+    const request_id = requestId(req, &id_buf);
+
+    const body = try std.json.Stringify.valueAlloc(allocator, .{
+        .type = "about:blank",
+        .title = response.reasonPhrase(status),
+        .status = status,
+        .detail = detail,
+        .instance = req.path(),
+        .request_id = request_id,
+    }, .{});
+
+    const headers = try allocator.alloc(Header, extra_headers.len + 2);
+    @memcpy(headers[0..extra_headers.len], extra_headers);
+    headers[extra_headers.len] = .{ .name = "Content-Type", .value = "application/problem+json" };
+    headers[extra_headers.len + 1] = .{ .name = "X-Request-ID", .value = request_id };
+
+    try response.writeHead(stream, status, headers, body.len, keep_alive);
+    if (!std.mem.eql(u8, req.method, "HEAD")) try stream.writeAll(body);
+}
+
+/// Explanation sent with each status wraith generates for a route
+pub fn detailFor(status: u16) []const u8 {
+    return switch (status) {
+        400 => "The request body is malformed.",
+        403 => "Access to this route is denied for the client address.",
+        404 => "No route or file matches the request path.",
+        413 => "The request body exceeds the route's size limit.",
+        429 => "The client has sent too many requests; retry after the time in Retry-After.",
+        431 => "The request headers exceed the limits of the route they are addressed to.",
+        502 => "The upstream server could not be reached or sent an invalid response.",
+        503 => "No upstream server is available to handle the request.",
+        504 => "The upstream server did not respond within the route's deadline.",
+        else => "The upstream server failed to handle the request.",
+    };
+}
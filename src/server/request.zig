@@ -0,0 +1,424 @@
+const std = @import("std");
+const config = @import("../config/config.zig");
+const socket = @import("socket.zig");
+
+/// Hard upper bound on the size of a message head (request line + headers)
+pub const MAX_HEAD_SIZE = 64 * 1024;
+
+/// Hard upper bound on the number of headers parsed from a single message
+pub const MAX_HEADERS = 256;
+
+pub const Header = struct {
+    name: []const u8,
+    value: []const u8,
+};
+
+pub const ParseError = error{
+    IncompleteHead,
+    InvalidRequestLine,
+    InvalidHeader,
+    TooManyHeaders,
+    ObsoleteLineFolding,
+};
+
+pub const LimitError = error{
+    UriTooLong,
+    HeaderTooLarge,
+    TooManyHeaders,
+    InvalidContentLength,
+    ConflictingFraming,
+    InvalidHeader,
+    UnsupportedTransferCoding,
+    UnexpectedBody,
+};
+
+/// Methods whose requests have no defined body semantics (RFC 9110 9.3)
+const bodyless_methods = [_][]const u8{ "GET", "HEAD", "OPTIONS", "DELETE" };
+
+/// Parsed HTTP/1.x request head. All slices point into the read buffer.
+pub const Request = struct {
+    method: []const u8,
+    target: []const u8,
+    version: []const u8,
+    headers: []const Header,
+    /// Length of the head including the terminating blank line
+    head_len: usize,
+
+    /// Look up a header value (case-insensitive)
+    pub fn header(self: *const Request, name: []const u8) ?[]const u8 {
+        return findHeader(self.headers, name);
+    }
+
+    /// Request path without the query string
+    pub fn path(self: *const Request) []const u8 {
+        const end = std.mem.indexOfScalar(u8, self.target, '?') orelse self.target.len;
+        return self.target[0..end];
+    }
+
+    /// Raw value of a query string parameter (not percent-decoded)
+    pub fn queryParam(self: *const Request, name: []const u8) ?[]const u8 {
+        const start = std.mem.indexOfScalar(u8, self.target, '?') orelse return null;
+        var params = std.mem.splitScalar(u8, self.target[start + 1 ..], '&');
+        while (params.next()) |param| {
+            const eq = std.mem.indexOfScalar(u8, param, '=') orelse param.len;
+            if (std.mem.eql(u8, param[0..eq], name)) {
+                return if (eq < param.len) param[eq + 1 ..] else "";
+            }
+        }
+        return null;
+    }
+
+    /// Host header without the port
+    pub fn host(self: *const Request) []const u8 {
+        return stripPort(self.header("Host") orelse "");
+    }
+
+    /// Whether the client allows the connection to be reused: the HTTP/1.1
+    /// default unless it sent "Connection: close", or HTTP/1.0 with keep-alive
+    pub fn wantsKeepAlive(self: *const Request) bool {
+        const connection = self.header("Connection") orelse "";
+        if (std.mem.eql(u8, self.version, "HTTP/1.1")) {
+            return std.ascii.indexOfIgnoreCase(connection, "close") == null;
+        }
+        return std.ascii.indexOfIgnoreCase(connection, "keep-alive") != null;
+    }
+
+    /// Whether a body follows the head
+    pub fn hasBody(self: *const Request) bool {
+        if (self.header("Transfer-Encoding") != null) return true;
+        return (self.contentLength() orelse 0) > 0;
+    }
+
+    /// Declared body length, if any. Transfer-Encoding takes precedence.
+    /// checkLimits rejects lengths that don't fit, so null means none.
+    pub fn contentLength(self: *const Request) ?usize {
+        if (self.header("Transfer-Encoding") != null) return null;
+        const value = self.header("Content-Length") orelse return null;
+        return std.fmt.parseInt(usize, value, 10) catch null;
+    }
+};
+
+/// Look up a header value in a parsed header list (case-insensitive)
+pub fn findHeader(headers: []const Header, name: []const u8) ?[]const u8 {
+    for (headers) |h| {
+        if (std.ascii.eqlIgnoreCase(h.name, name)) return h.value;
+    }
+    return null;
+}
+
+/// Strip the port from a Host header value, handling bracketed IPv6 literals
+pub fn stripPort(value: []const u8) []const u8 {
+    if (std.mem.startsWith(u8, value, "[")) {
+        const end = std.mem.indexOfScalar(u8, value, ']') orelse return value;
+        return value[0 .. end + 1];
+    }
+    const colon = std.mem.lastIndexOfScalar(u8, value, ':') orelse return value;
+    return value[0..colon];
+}
+
+/// Whether the request has exactly one Host header holding a hostname or IP
+/// literal, optionally followed by a port
+pub fn validHost(req: *const Request) bool {
+    var value: ?[]const u8 = null;
+    for (req.headers) |h| {
+        if (!std.ascii.eqlIgnoreCase(h.name, "Host")) continue;
+        if (value != null) return false;
+        value = h.value;
+    }
+    const host_port = value orelse return false;
+    const host = stripPort(host_port);
+    if (host.len == 0) return false;
+
+    if (host.len < host_port.len) {
+        const port = host_port[host.len + 1 ..];
+        if (host_port[host.len] != ':' or port.len == 0 or port.len > 5) return false;
+        for (port) |c| {
+            if (!std.ascii.isDigit(c)) return false;
+        }
+    }
+
+    if (host[0] == '[') {
+        if (host.len < 3 or host[host.len - 1] != ']') return false;
+        for (host[1 .. host.len - 1]) |c| {
+            if (!std.ascii.isHex(c) and c != ':' and c != '.') return false;
+        }
+        return true;
+    }
+    for (host) |c| {
+        if (!std.ascii.isAlphanumeric(c) and c != '-' and c != '.') return false;
+    }
+    return true;
+}
+
+/// Return the length of the head if the buffer contains a complete one
+pub fn findHeadEnd(buf: []const u8) ?usize {
+    const idx = std.mem.indexOf(u8, buf, "\r\n\r\n") orelse return null;
+    return idx + 4;
+}
+
+/// Read from the stream until a complete head is buffered.
+/// Returns the number of bytes read, which may include the start of the body.
+pub fn readHead(stream: std.net.Stream, buf: []u8) !usize {
+    return readHeadTimeout(stream, buf, 0);
+}
+
+/// Like `readHead`, but fails with `error.Timeout` unless the whole head
+/// arrives within `timeout_ms` (0 = no limit)
+pub fn readHeadTimeout(stream: std.net.Stream, buf: []u8, timeout_ms: u32) !usize {
+    const deadline = std.time.milliTimestamp() + timeout_ms;
+    var len: usize = 0;
+    while (findHeadEnd(buf[0..len]) == null) {
+        if (len == buf.len) return error.HeadTooLarge;
+        if (timeout_ms > 0) {
+            const remaining = deadline - std.time.milliTimestamp();
+            if (remaining <= 0) return error.Timeout;
+            if (!try socket.waitReadable(stream.handle, @intCast(remaining))) return error.Timeout;
+        }
+        const n = try stream.read(buf[len..]);
+        if (n == 0) return error.ConnectionClosed;
+        len += n;
+    }
+    return len;
+}
+
+/// Read a Content-Length framed request body into memory.
+/// `buffered` holds everything read from the client so far, head included.
+pub fn readBody(
+    allocator: std.mem.Allocator,
+    stream: std.net.Stream,
+    req: *const Request,
+    buffered: []const u8,
+    max_size: usize,
+) ![]u8 {
+    const len = req.contentLength() orelse return error.LengthRequired;
+    if (len > max_size) return error.BodyTooLarge;
+
+    const body = try allocator.alloc(u8, len);
+    const already = buffered[req.head_len..];
+    const take = @min(already.len, len);
+    @memcpy(body[0..take], already[0..take]);
+    try socket.readExact(stream, body[take..]);
+    return body;
+}
+
+/// Replace obsolete line folding (CRLF followed by SP/HT) with spaces in place
+pub fn unfoldHeaders(head: []u8) void {
+    var i: usize = 0;
+    while (i + 2 < head.len) : (i += 1) {
+        if (head[i] == '\r' and head[i + 1] == '\n' and (head[i + 2] == ' ' or head[i + 2] == '\t')) {
+            head[i] = ' ';
+            head[i + 1] = ' ';
+        }
+    }
+}
+
+/// Enforce configured request limits and unambiguous message framing, at
+/// the configured strictness
+pub fn checkLimits(req: *const Request, limits: *const config.RequestLimitsConfig) LimitError!void {
+    if (req.target.len > limits.max_uri_length) return error.UriTooLong;
+    if (req.head_len > limits.max_head_size) return error.HeaderTooLarge;
+    if (req.headers.len > limits.max_header_count) return error.TooManyHeaders;
+
+    const strictness = limits.strictness;
+    if (strictness != .lenient) {
+        for (req.target) |c| {
+            if (std.ascii.isControl(c)) return error.InvalidHeader;
+        }
+    }
+
+    var content_length: ?[]const u8 = null;
+    var transfer_encoding: ?[]const u8 = null;
+    for (req.headers) |h| {
+        if (h.name.len + 2 + h.value.len > limits.max_header_size) return error.HeaderTooLarge;
+        if (strictness != .lenient and !validField(h)) return error.InvalidHeader;
+
+        if (std.ascii.eqlIgnoreCase(h.name, "Content-Length")) {
+            if (h.value.len == 0) return error.InvalidContentLength;
+            for (h.value) |c| {
+                if (!std.ascii.isDigit(c)) return error.InvalidContentLength;
+            }
+            // contentLength() would read a length too large to hold as no body
+            _ = std.fmt.parseInt(usize, h.value, 10) catch return error.InvalidContentLength;
+            // Repeated Content-Length headers must agree
+            if (content_length) |prev| {
+                if (strictness == .strict or !std.mem.eql(u8, prev, h.value)) return error.ConflictingFraming;
+            }
+            content_length = h.value;
+        } else if (std.ascii.eqlIgnoreCase(h.name, "Transfer-Encoding")) {
+            if (strictness != .lenient) {
+                // A second header adds codings, so chunked would be applied twice
+                if (transfer_encoding != null) return error.ConflictingFraming;
+                // Anything else is read as chunked by some parsers and not
+                // by others ("xchunked", "chunked, identity")
+                if (!std.ascii.eqlIgnoreCase(h.value, "chunked")) return error.UnsupportedTransferCoding;
+            }
+            transfer_encoding = h.value;
+        }
+    }
+
+    if (transfer_encoding != null) {
+        if (content_length != null and (limits.reject_conflicting_framing or strictness == .strict)) {
+            return error.ConflictingFraming;
+        }
+        // HTTP/1.0 has no chunked coding for the next hop to agree on
+        if (strictness != .lenient and std.mem.eql(u8, req.version, "HTTP/1.0")) return error.ConflictingFraming;
+    }
+
+    // Servers disagree on whether these methods' bodies are read at all
+    if (strictness == .strict and (transfer_encoding != null or (content_length != null and !isZero(content_length.?)))) {
+        for (bodyless_methods) |method| {
+            if (std.mem.eql(u8, req.method, method)) return error.UnexpectedBody;
+        }
+    }
+}
+
+/// A token for a name (RFC 9110 5.1) and no control characters other than
+/// tabs in the value; a stray CR or LF would end the line for other parsers
+fn validField(h: Header) bool {
+    for (h.name) |c| {
+        if (!isTokenChar(c)) return false;
+    }
+    for (h.value) |c| {
+        if (std.ascii.isControl(c) and c != '\t') return false;
+    }
+    return true;
+}
+
+fn isTokenChar(c: u8) bool {
+    return std.ascii.isAlphanumeric(c) or std.mem.indexOfScalar(u8, "!#$%&'*+-.^_`|~", c) != null;
+}
+
+fn isZero(digits: []const u8) bool {
+    for (digits) |c| {
+        if (c != '0') return false;
+    }
+    return true;
+}
+
+/// Parse a request head from the start of `buf`
+pub fn parse(buf: []const u8, headers_buf: []Header) ParseError!Request {
+    const head_len = findHeadEnd(buf) orelse return error.IncompleteHead;
+    const head = buf[0 .. head_len - 4];
+
+    const line_end = std.mem.indexOf(u8, head, "\r\n") orelse head.len;
+    var parts = std.mem.splitScalar(u8, head[0..line_end], ' ');
+    const method = parts.next() orelse return error.InvalidRequestLine;
+    const target = parts.next() orelse return error.InvalidRequestLine;
+    const version = parts.next() orelse return error.InvalidRequestLine;
+    if (parts.next() != null or method.len == 0 or target.len == 0 or
+        !std.mem.startsWith(u8, version, "HTTP/1."))
+    {
+        return error.InvalidRequestLine;
+    }
+
+    const header_block = if (line_end < head.len) head[line_end + 2 ..] else "";
+    return .{
+        .method = method,
+        .target = target,
+        .version = version,
+        .headers = try parseHeaders(header_block, headers_buf),
+        .head_len = head_len,
+    };
+}
+
+/// Parse CRLF-separated header lines into `headers_buf`
+pub fn parseHeaders(block: []const u8, headers_buf: []Header) ParseError![]const Header {
+    var count: usize = 0;
+    if (block.len == 0) return headers_buf[0..0];
+
+    var lines = std.mem.splitSequence(u8, block, "\r\n");
+    while (lines.next()) |line| {
+        if (line.len > 0 and (line[0] == ' ' or line[0] == '\t')) return error.ObsoleteLineFolding;
+        const colon = std.mem.indexOfScalar(u8, line, ':') orelse return error.InvalidHeader;
+        if (colon == 0) return error.InvalidHeader;
+        // No whitespace is allowed in or after the field name
+        if (std.mem.indexOfAny(u8, line[0..colon], " \t") != null) return error.InvalidHeader;
+        if (count == headers_buf.len) return error.TooManyHeaders;
+        headers_buf[count] = .{
+            .name = line[0..colon],
+            .value = std.mem.trim(u8, line[colon + 1 ..], " \t"),
+        };
+        count += 1;
+    }
+    return headers_buf[0..count];
+}
+
+/// Parse `head` and check it at `strictness` with otherwise default limits
+fn checkHead(head: []const u8, strictness: config.FramingStrictness) !void {
+    var headers_buf: [16]Header = undefined;
+    const req = try parse(head, &headers_buf);
+    const limits = config.RequestLimitsConfig{ .strictness = strictness };
+    try checkLimits(&req, &limits);
+}
+
+test "Content-Length with Transfer-Encoding is rejected" {
+    const head = "POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 6\r\nTransfer-Encoding: chunked\r\n\r\n";
+    try std.testing.expectError(error.ConflictingFraming, checkHead(head, .standard));
+
+    var headers_buf: [16]Header = undefined;
+    const req = try parse(head, &headers_buf);
+    // Allowed when turned off, except at strict
+    var limits = config.RequestLimitsConfig{ .reject_conflicting_framing = false, .strictness = .standard };
+    try checkLimits(&req, &limits);
+    limits.strictness = .strict;
+    try std.testing.expectError(error.ConflictingFraming, checkLimits(&req, &limits));
+}
+
+test "duplicate Content-Length headers" {
+    const differing = "POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 5\r\nContent-Length: 6\r\n\r\n";
+    try std.testing.expectError(error.ConflictingFraming, checkHead(differing, .lenient));
+    try std.testing.expectError(error.ConflictingFraming, checkHead(differing, .standard));
+
+    const agreeing = "POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 5\r\nContent-Length: 5\r\n\r\n";
+    try checkHead(agreeing, .standard);
+    try std.testing.expectError(error.ConflictingFraming, checkHead(agreeing, .strict));
+
+    try std.testing.expectError(error.InvalidContentLength, checkHead("POST / HTTP/1.1\r\nHost: a\r\nContent-Length: +5\r\n\r\n", .lenient));
+    try std.testing.expectError(error.InvalidContentLength, checkHead("POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 5, 5\r\n\r\n", .lenient));
+}
+
+test "Content-Length too large to hold" {
+    // Dropped silently, it would leave the body to be read as the next request
+    const head = "POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 99999999999999999999999\r\n\r\n";
+    try std.testing.expectError(error.InvalidContentLength, checkHead(head, .lenient));
+    try std.testing.expectError(error.InvalidContentLength, checkHead(head, .standard));
+
+    var buf: [96]u8 = undefined;
+    const max = try std.fmt.bufPrint(&buf, "POST / HTTP/1.1\r\nHost: a\r\nContent-Length: {d}\r\n\r\n", .{std.math.maxInt(usize)});
+    try checkHead(max, .standard);
+}
+
+test "obsolete line folding" {
+    const head = "POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding:\r\n chunked\r\n\r\n";
+    var headers_buf: [16]Header = undefined;
+    try std.testing.expectError(error.ObsoleteLineFolding, parse(head, &headers_buf));
+
+    // Unfolded, the value is read the same way the next hop would read it
+    var unfolded: [head.len]u8 = undefined;
+    @memcpy(&unfolded, head);
+    unfoldHeaders(&unfolded);
+    const req = try parse(&unfolded, &headers_buf);
+    try std.testing.expectEqualStrings("chunked", findHeader(req.headers, "Transfer-Encoding").?);
+}
+
+test "bare LF inside a header line" {
+    // Parsers that end lines at LF see a second Content-Length here
+    const head = "POST / HTTP/1.1\r\nHost: a\r\nX-Note: a\nContent-Length: 5\r\nContent-Length: 0\r\n\r\n";
+    try std.testing.expectError(error.InvalidHeader, checkHead(head, .standard));
+    try std.testing.expectError(error.InvalidHeader, checkHead("GET /a\nb HTTP/1.1\r\nHost: a\r\n\r\n", .standard));
+}
+
+test "obfuscated Transfer-Encoding" {
+    const codings = [_][]const u8{ "xchunked", "chunked, identity", "identity, chunked", "chunked\x0b", "chunk" };
+    for (codings) |coding| {
+        var buf: [128]u8 = undefined;
+        const head = try std.fmt.bufPrint(&buf, "POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: {s}\r\n\r\n", .{coding});
+        try std.testing.expect(std.meta.isError(checkHead(head, .standard)));
+    }
+    // Case and surrounding whitespace don't change the coding
+    try checkHead("POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: \tChunked \r\n\r\n", .standard);
+
+    try std.testing.expectError(error.InvalidHeader, checkHead("POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding : chunked\r\n\r\n", .lenient));
+    try std.testing.expectError(error.ConflictingFraming, checkHead("POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked\r\nTransfer-Encoding: chunked\r\n\r\n", .standard));
+    try std.testing.expectError(error.ConflictingFraming, checkHead("POST / HTTP/1.0\r\nHost: a\r\nTransfer-Encoding: chunked\r\n\r\n", .standard));
+}
@@ -0,0 +1,106 @@
+const std = @import("std");
+const config = @import("../config/config.zig");
+const request = @import("request.zig");
+const response = @import("response.zig");
+const static = @import("static.zig");
+
+const Header = request.Header;
+
+/// Largest body file a `respond` route will send
+const MAX_FILE_SIZE = 1024 * 1024;
+
+/// Send a route's configured response instead of proxying. Returns
+/// whether the connection can be reused.
+pub fn serve(
+    allocator: std.mem.Allocator,
+    stream: std.net.Stream,
+    req: *const request.Request,
+    cfg: *const config.RespondConfig,
+    client_addr: std.net.Address,
+    keep_alive: bool,
+) !bool {
+    const raw = if (cfg.file.len > 0)
+        std.fs.cwd().readFileAlloc(allocator, cfg.file, MAX_FILE_SIZE) catch |err| {
+            std.debug.print("✗ Respond file {s}: {any}\n", .{ cfg.file, err });
+            try response.writeError(stream, 500);
+            return false;
+        }
+    else
+        cfg.body;
+    const content = if (cfg.template) try expand(allocator, raw, req, client_addr) else raw;
+
+    var headers: std.ArrayList(Header) = .empty;
+    var has_content_type = false;
+    for (cfg.headers) |h| {
+        if (std.ascii.eqlIgnoreCase(h.name, "Content-Type")) has_content_type = true;
+        try headers.append(allocator, .{ .name = h.name, .value = h.value });
+    }
+    if (!has_content_type and content.len > 0) {
+        const content_type = if (cfg.file.len > 0) static.contentType(cfg.file) else "text/plain; charset=utf-8";
+        try headers.append(allocator, .{ .name = "Content-Type", .value = content_type });
+    }
+
+    const no_body = cfg.status == 204 or cfg.status == 304 or cfg.status < 200;
+    try response.writeHead(stream, cfg.status, headers.items, if (no_body) null else content.len, keep_alive);
+    if (!no_body and !std.mem.eql(u8, req.method, "HEAD")) try stream.writeAll(content);
+    return keep_alive;
+}
+
+/// Replace `{variable}` placeholders with values from the request.
+/// Unknown placeholders are left as written.
+///
+///   {method} {uri} {path} {query} {host} {remote_addr} {header.<Name>}
+pub fn expand(
+    allocator: std.mem.Allocator,
+    template: []const u8,
+    req: *const request.Request,
+    client_addr: std.net.Address,
+) ![]const u8 {
+    var out: std.ArrayList(u8) = .empty;
+    var rest = template;
+    while (std.mem.indexOfScalar(u8, rest, '{')) |open| {
+        try out.appendSlice(allocator, rest[0..open]);
+        const close = std.mem.indexOfScalarPos(u8, rest, open, '}') orelse {
+            rest = rest[open..];
+            break;
+        };
+        const name = rest[open + 1 .. close];
+        if (try variable(allocator, name, req, client_addr)) |value| {
+            try out.appendSlice(allocator, value);
+        } else {
+            try out.appendSlice(allocator, rest[open .. close + 1]);
+        }
+        rest = rest[close + 1 ..];
+    }
+    try out.appendSlice(allocator, rest);
+    return out.items;
+}
+
+fn variable(
+    allocator: std.mem.Allocator,
+    name: []const u8,
+    req: *const request.Request,
+    client_addr: std.net.Address,
+) !?[]const u8 {
+    if (std.mem.eql(u8, name, "method")) return req.method;
+    if (std.mem.eql(u8, name, "uri")) return req.target;
+    if (std.mem.eql(u8, name, "path")) return req.path();
+    if (std.mem.eql(u8, name, "query")) {
+        const at = std.mem.indexOfScalar(u8, req.target, '?') orelse return "";
+        return req.target[at + 1 ..];
+    }
+    if (std.mem.eql(u8, name, "host")) return req.host();
+    if (std.mem.eql(u8, name, "remote_addr")) return try clientIp(allocator, client_addr);
+    if (std.mem.startsWith(u8, name, "header.")) return req.header(name["header.".len..]) orelse "";
+    return null;
+}
+
+/// Client address without its port
+fn clientIp(allocator: std.mem.Allocator, addr: std.net.Address) ![]const u8 {
+    const text = try std.fmt.allocPrint(allocator, "{f}", .{addr});
+    const colon = std.mem.lastIndexOfScalar(u8, text, ':') orelse return text;
+    const ip = text[0..colon];
+    // IPv6 addresses are printed as [addr]:port
+    if (ip.len >= 2 and ip[0] == '[' and ip[ip.len - 1] == ']') return ip[1 .. ip.len - 1];
+    return ip;
+}
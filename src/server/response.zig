@@ -0,0 +1,167 @@
+const std = @import("std");
+const request = @import("request.zig");
+
+pub const Header = request.Header;
+
+/// Default value of the Server header on wraith-generated responses
+pub const SERVER_NAME = "Wraith/0.0.0";
+
+/// Server header currently sent on wraith-generated responses (empty = omit)
+var server_header: []const u8 = SERVER_NAME;
+
+/// Set the Server header for wraith-generated responses
+pub fn setServerHeader(value: []const u8) void {
+    server_header = value;
+}
+
+/// Status of the last response head this thread wrote, for access logs
+threadlocal var last_status: u16 = 0;
+
+pub fn noteStatus(status: u16) void {
+    last_status = status;
+}
+
+pub fn lastStatus() u16 {
+    return last_status;
+}
+
+/// Parsed HTTP/1.x response head. All slices point into the read buffer.
+pub const ResponseHead = struct {
+    version: []const u8,
+    status: u16,
+    reason: []const u8,
+    headers: []const Header,
+    /// Length of the head including the terminating blank line
+    head_len: usize,
+
+    /// Look up a header value (case-insensitive)
+    pub fn header(self: *const ResponseHead, name: []const u8) ?[]const u8 {
+        return request.findHeader(self.headers, name);
+    }
+
+    /// Declared body length, if any
+    pub fn contentLength(self: *const ResponseHead) ?usize {
+        const value = self.header("Content-Length") orelse return null;
+        return std.fmt.parseInt(usize, value, 10) catch null;
+    }
+
+    /// Whether a body follows this head for a request with the given method
+    pub fn hasBody(self: *const ResponseHead, method: []const u8) bool {
+        if (std.mem.eql(u8, method, "HEAD")) return false;
+        return !(self.status < 200 or self.status == 204 or self.status == 304);
+    }
+};
+
+/// Bounded writer used to serialize message heads without allocating
+pub const HeadWriter = struct {
+    buf: []u8,
+    len: usize = 0,
+
+    pub fn print(self: *HeadWriter, comptime fmt: []const u8, args: anytype) !void {
+        const out = try std.fmt.bufPrint(self.buf[self.len..], fmt, args);
+        self.len += out.len;
+    }
+
+    pub fn header(self: *HeadWriter, name: []const u8, value: []const u8) !void {
+        try self.print("{s}: {s}\r\n", .{ name, value });
+    }
+
+    pub fn slice(self: *const HeadWriter) []const u8 {
+        return self.buf[0..self.len];
+    }
+};
+
+/// Parse a response head from the start of `buf`
+pub fn parse(buf: []const u8, headers_buf: []Header) !ResponseHead {
+    const head_len = request.findHeadEnd(buf) orelse return error.IncompleteHead;
+    const head = buf[0 .. head_len - 4];
+
+    const line_end = std.mem.indexOf(u8, head, "\r\n") orelse head.len;
+    const status_line = head[0..line_end];
+    var parts = std.mem.splitScalar(u8, status_line, ' ');
+    const version = parts.next() orelse return error.InvalidStatusLine;
+    const status_str = parts.next() orelse return error.InvalidStatusLine;
+    if (!std.mem.startsWith(u8, version, "HTTP/1.")) return error.InvalidStatusLine;
+    const status = std.fmt.parseInt(u16, status_str, 10) catch return error.InvalidStatusLine;
+
+    const header_block = if (line_end < head.len) head[line_end + 2 ..] else "";
+    return .{
+        .version = version,
+        .status = status,
+        .reason = parts.rest(),
+        .headers = try request.parseHeaders(header_block, headers_buf),
+        .head_len = head_len,
+    };
+}
+
+pub fn reasonPhrase(status: u16) []const u8 {
+    return switch (status) {
+        200 => "OK",
+        204 => "No Content",
+        301 => "Moved Permanently",
+        302 => "Found",
+        304 => "Not Modified",
+        307 => "Temporary Redirect",
+        308 => "Permanent Redirect",
+        400 => "Bad Request",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        413 => "Content Too Large",
+        414 => "URI Too Long",
+        415 => "Unsupported Media Type",
+        421 => "Misdirected Request",
+        429 => "Too Many Requests",
+        431 => "Request Header Fields Too Large",
+        500 => "Internal Server Error",
+        501 => "Not Implemented",
+        502 => "Bad Gateway",
+        503 => "Service Unavailable",
+        504 => "Gateway Timeout",
+        else => "Unknown",
+    };
+}
+
+/// Write a complete response with an in-memory body
+pub fn write(
+    stream: std.net.Stream,
+    status: u16,
+    headers: []const Header,
+    body: []const u8,
+) !void {
+    const content_length: ?usize = if (status != 204 and status != 304) body.len else null;
+    try writeHead(stream, status, headers, content_length, false);
+    if (body.len > 0) try stream.writeAll(body);
+}
+
+/// Write a response head; the body, if any, is written separately
+pub fn writeHead(
+    stream: std.net.Stream,
+    status: u16,
+    headers: []const Header,
+    content_length: ?usize,
+    keep_alive: bool,
+) !void {
+    var buf: [8192]u8 = undefined;
+    var head = HeadWriter{ .buf = &buf };
+
+    noteStatus(status);
+    try head.print("HTTP/1.1 {d} {s}\r\n", .{ status, reasonPhrase(status) });
+    if (server_header.len > 0) try head.header("Server", server_header);
+    for (headers) |h| try head.header(h.name, h.value);
+    if (content_length) |len| try head.print("Content-Length: {d}\r\n", .{len});
+    try head.header("Connection", if (keep_alive) "keep-alive" else "close");
+    try head.print("\r\n", .{});
+
+    try stream.writeAll(head.slice());
+}
+
+/// Write a plain-text error response for the given status
+pub fn writeError(stream: std.net.Stream, status: u16) !void {
+    var body_buf: [128]u8 = undefined;
+    const body = try std.fmt.bufPrint(&body_buf, "{d} {s}\n", .{ status, reasonPhrase(status) });
+    try write(stream, status, &[_]Header{
+        .{ .name = "Content-Type", .value = "text/plain" },
+    }, body);
+}
@@ -0,0 +1,709 @@
+const std = @import("std");
+const config = @import("../config/config.zig");
+const request = @import("request.zig");
+const response = @import("response.zig");
+const static = @import("static.zig");
+const conditional = @import("conditional.zig");
+const downloads_mod = @import("downloads.zig");
+const socket = @import("socket.zig");
+const egress = @import("../upstream/egress.zig");
+const manager = @import("../upstream/manager.zig");
+const signing = @import("../upstream/signing.zig");
+const metrics = @import("metrics.zig");
+const compression = @import("../proxy/compression.zig");
+const sub_filter = @import("../proxy/sub_filter.zig");
+const top_urls = @import("top_urls.zig");
+
+const Header = request.Header;
+
+/// Request headers passed on to the bucket, which answers ranges and
+/// conditionals itself
+const forwarded_headers = [_][]const u8{ "Range", "If-Range", "If-Match", "If-None-Match", "If-Modified-Since", "If-Unmodified-Since" };
+
+/// Object metadata relayed to the client
+const relayed_headers = [_][]const u8{ "Content-Type", "Content-Range", "Accept-Ranges", "ETag", "Last-Modified", "Cache-Control", "Content-Encoding", "Content-Disposition", "Expires" };
+
+/// Cached copies are named after a hash of the endpoint, bucket and key
+const Name = [32]u8;
+
+/// Bucket-backed static mounts, keyed by their config
+pub const S3Origins = struct {
+    allocator: std.mem.Allocator,
+    origins: std.AutoHashMapUnmanaged(*const config.StaticMountConfig, *Origin),
+
+    pub fn init(allocator: std.mem.Allocator, cfg: *const config.Config) !S3Origins {
+        var self = S3Origins{ .allocator = allocator, .origins = .empty };
+        errdefer self.deinit();
+
+        for (cfg.vhosts) |vhost| {
+            for (vhost.static) |*mount| {
+                if (mount.s3.endpoint.len == 0) continue;
+                const origin = try allocator.create(Origin);
+                errdefer allocator.destroy(origin);
+                try origin.init(allocator, mount, &cfg.egress_proxy);
+                errdefer origin.deinit();
+                try self.origins.put(allocator, mount, origin);
+            }
+        }
+        return self;
+    }
+
+    pub fn deinit(self: *S3Origins) void {
+        var it = self.origins.valueIterator();
+        while (it.next()) |origin| {
+            origin.*.deinit();
+            self.allocator.destroy(origin.*);
+        }
+        self.origins.deinit(self.allocator);
+    }
+
+    pub fn get(self: *const S3Origins, mount: *const config.StaticMountConfig) ?*Origin {
+        return self.origins.get(mount);
+    }
+
+    pub fn writeMetrics(self: *const S3Origins, m: *metrics.Exposition) !void {
+        try m.family("wraith_s3_cache_requests_total", .counter, "Whole-object requests to S3 mounts with a cache, by whether the cache answered");
+        var it = self.origins.valueIterator();
+        while (it.next()) |origin| {
+            if (origin.*.cache == null) continue;
+            const mount = origin.*.mount;
+            try m.sample("wraith_s3_cache_requests_total", &.{
+                .{ .name = "bucket", .value = mount.s3.bucket },
+                .{ .name = "mount", .value = mount.path },
+                .{ .name = "result", .value = "hit" },
+            }, origin.*.cache_hits.load(.monotonic));
+            try m.sample("wraith_s3_cache_requests_total", &.{
+                .{ .name = "bucket", .value = mount.s3.bucket },
+                .{ .name = "mount", .value = mount.path },
+                .{ .name = "result", .value = "miss" },
+            }, origin.*.cache_misses.load(.monotonic));
+        }
+    }
+};
+
+/// A static mount served from a bucket
+pub const Origin = struct {
+    allocator: std.mem.Allocator,
+    mount: *const config.StaticMountConfig,
+    egress: *const config.EgressProxyConfig,
+    address: std.net.Address,
+    /// Referenced by `signer`, so an Origin never moves once initialized
+    signing: config.SigningConfig,
+    signer: signing.Signer,
+    cache: ?Cache,
+    /// Requests answered from the cache, and cacheable ones that weren't
+    cache_hits: std.atomic.Value(u64) = std.atomic.Value(u64).init(0),
+    cache_misses: std.atomic.Value(u64) = std.atomic.Value(u64).init(0),
+    /// Set when the cache keeps a top URL report
+    top: ?top_urls.TopUrls = null,
+
+    fn init(self: *Origin, allocator: std.mem.Allocator, mount: *const config.StaticMountConfig, egress_cfg: *const config.EgressProxyConfig) !void {
+        const s3 = &mount.s3;
+        const host = if (s3.path_style) s3.endpoint else try std.fmt.allocPrint(allocator, "{s}.{s}", .{ s3.bucket, s3.endpoint });
+        errdefer if (!s3.path_style) allocator.free(host);
+
+        self.* = .{
+            .allocator = allocator,
+            .mount = mount,
+            .egress = egress_cfg,
+            .address = try manager.parseServerAddress(allocator, s3.endpoint),
+            .signing = .{
+                .kind = .aws_sigv4,
+                .host = host,
+                .secret = s3.secret,
+                .access_key_id = s3.access_key_id,
+                .session_token = s3.session_token,
+                .region = s3.region,
+                .service = "s3",
+            },
+            .signer = undefined,
+            .cache = if (s3.cache.dir.len > 0) try Cache.init(allocator, &s3.cache) else null,
+            .top = if (s3.cache.dir.len > 0 and s3.cache.top_urls > 0) top_urls.TopUrls.init(allocator, &s3.cache) else null,
+        };
+        self.signer = signing.Signer.init(allocator, &self.signing);
+    }
+
+    fn deinit(self: *Origin) void {
+        self.signer.deinit();
+        if (!self.mount.s3.path_style) self.allocator.free(self.signing.host);
+        if (self.cache) |*cache| cache.deinit();
+        if (self.top) |*top| top.deinit();
+    }
+
+    /// Serve the object a request under the mount names. `client` is
+    /// counted against the mount's download limit. Returns whether the
+    /// connection can be reused.
+    pub fn serve(
+        self: *Origin,
+        allocator: std.mem.Allocator,
+        stream: std.net.Stream,
+        req: *const request.Request,
+        downloads: *downloads_mod.DownloadLimiter,
+        client: std.net.Address,
+        keep_alive: bool,
+    ) !bool {
+        if (try static.rejectMethod(stream, req)) return false;
+
+        var path_buf: [std.fs.max_path_bytes]u8 = undefined;
+        const rel = static.relativePath(&path_buf, self.mount.path, req.path()) catch {
+            try response.writeError(stream, 400);
+            return false;
+        };
+
+        var key_buf: [std.fs.max_path_bytes]u8 = undefined;
+        const key = self.objectKey(&key_buf, rel) catch {
+            try response.writeError(stream, 414);
+            return false;
+        };
+
+        // Copies are whole objects, so ranges always go to the bucket
+        if (self.cache) |*cache| {
+            if (req.header("Range") == null) {
+                const served = cache.serve(stream, req, cacheName(&self.mount.s3, key), self.mount, downloads, client, keep_alive) catch |err| switch (err) {
+                    error.TooManyDownloads => {
+                        try downloads_mod.writeRejection(stream);
+                        return false;
+                    },
+                    else => return err,
+                };
+                if (served) |sent| {
+                    _ = self.cache_hits.fetchAdd(1, .monotonic);
+                    if (self.top) |*top| top.record(req.path(), sent);
+                    return keep_alive;
+                }
+                _ = self.cache_misses.fetchAdd(1, .monotonic);
+            }
+        }
+        return self.fetch(allocator, stream, req, key, downloads, client, keep_alive);
+    }
+
+    /// Object key for a path below the mount. Buckets have no directories;
+    /// a trailing slash names the index object.
+    fn objectKey(self: *const Origin, buf: []u8, rel: []const u8) ![]const u8 {
+        const is_index = rel.len == 0 or rel[rel.len - 1] == '/';
+        return std.fmt.bufPrint(buf, "{s}{s}{s}", .{ self.mount.s3.prefix, rel, if (is_index) self.mount.index else "" });
+    }
+
+    /// Cache usage for the admin API
+    pub fn stats(self: *Origin) CacheStats {
+        var result = CacheStats{ .path = self.mount.path, .bucket = self.mount.s3.bucket };
+        if (self.cache) |*cache| {
+            cache.mutex.lock();
+            defer cache.mutex.unlock();
+            result.entries = cache.entries.count();
+            result.bytes = cache.size;
+            result.max_bytes = cache.config.max_size;
+            result.fills = cache.fills;
+            result.evictions = cache.evictions;
+        }
+        result.hits = self.cache_hits.load(.monotonic);
+        result.misses = self.cache_misses.load(.monotonic);
+        if (result.hits + result.misses > 0) {
+            result.hit_ratio = @as(f64, @floatFromInt(result.hits)) / @as(f64, @floatFromInt(result.hits + result.misses));
+        }
+        return result;
+    }
+
+    /// Delete every cached copy. Returns how many there were.
+    pub fn flush(self: *Origin) usize {
+        const cache = if (self.cache) |*c| c else return 0;
+        cache.mutex.lock();
+        defer cache.mutex.unlock();
+        const count = cache.entries.count();
+        var it = cache.entries.keyIterator();
+        while (it.next()) |name| cache.dir.deleteFile(name) catch {};
+        cache.entries.clearRetainingCapacity();
+        cache.size = 0;
+        return count;
+    }
+
+    /// Delete the cached copy of the object at `rel` below the mount
+    pub fn invalidate(self: *Origin, rel: []const u8) bool {
+        const cache = if (self.cache) |*c| c else return false;
+        var key_buf: [std.fs.max_path_bytes]u8 = undefined;
+        const key = self.objectKey(&key_buf, rel) catch return false;
+        const name = cacheName(&self.mount.s3, key);
+
+        cache.mutex.lock();
+        defer cache.mutex.unlock();
+        const entry = cache.entries.get(name) orelse return false;
+        cache.remove(name, entry);
+        return true;
+    }
+
+    /// Stream the object from the bucket, copying it into the cache on the way
+    fn fetch(
+        self: *Origin,
+        allocator: std.mem.Allocator,
+        stream: std.net.Stream,
+        req: *const request.Request,
+        key: []const u8,
+        downloads: *downloads_mod.DownloadLimiter,
+        client: std.net.Address,
+        keep_alive: bool,
+    ) !bool {
+        const s3 = &self.mount.s3;
+        var arena = std.heap.ArenaAllocator.init(allocator);
+        defer arena.deinit();
+
+        var target: std.ArrayList(u8) = .empty;
+        if (s3.path_style) {
+            try target.append(arena.allocator(), '/');
+            try encodeKey(arena.allocator(), &target, s3.bucket);
+        }
+        try target.append(arena.allocator(), '/');
+        try encodeKey(arena.allocator(), &target, key);
+
+        const signed = self.signer.sign(arena.allocator(), req.method, target.items, "", null) catch |err| {
+            std.debug.print("✗ Signing request for S3 endpoint {s}: {any}\n", .{ s3.endpoint, err });
+            try response.writeError(stream, 500);
+            return false;
+        };
+
+        var out_buf: [request.MAX_HEAD_SIZE]u8 = undefined;
+        var head = response.HeadWriter{ .buf = &out_buf };
+        try head.print("{s} {s} HTTP/1.1\r\n", .{ req.method, target.items });
+        for (signed) |h| try head.header(h.name, h.value);
+        for (forwarded_headers) |name| {
+            if (req.header(name)) |value| try head.header(name, value);
+        }
+        try head.print("Connection: close\r\n\r\n", .{});
+
+        const upstream = egress.connect(self.egress, self.address, null) catch |err| return badGateway(stream, s3.endpoint, err);
+        defer upstream.close();
+        try socket.setTimeouts(upstream.handle, s3.timeout_ms);
+        upstream.writeAll(head.slice()) catch |err| return badGateway(stream, s3.endpoint, err);
+
+        var buf: [request.MAX_HEAD_SIZE]u8 = undefined;
+        var headers: [request.MAX_HEADERS]Header = undefined;
+        const n = request.readHead(upstream, &buf) catch |err| return badGateway(stream, s3.endpoint, err);
+        const res = response.parse(buf[0..n], &headers) catch |err| return badGateway(stream, s3.endpoint, err);
+
+        switch (res.status) {
+            200, 206, 304, 412, 416 => {},
+            404 => {
+                try response.writeError(stream, 404);
+                return false;
+            },
+            // Without list permission a missing key is answered 403, but so
+            // are bad credentials
+            403 => {
+                std.debug.print("✗ S3 endpoint {s} denied {s}\n", .{ s3.endpoint, key });
+                try response.writeError(stream, 404);
+                return false;
+            },
+            else => return badGateway(stream, s3.endpoint, error.UnexpectedStatus),
+        }
+
+        const content_type = res.header("Content-Type") orelse static.contentType(key);
+        var relayed: [relayed_headers.len + 1]Header = undefined;
+        var count: usize = 0;
+        for (relayed_headers) |name| {
+            if (res.header(name)) |value| {
+                relayed[count] = .{ .name = name, .value = value };
+                count += 1;
+            }
+        }
+        if (res.header("Content-Type") == null and (res.status == 200 or res.status == 206)) {
+            relayed[count] = .{ .name = "Content-Type", .value = content_type };
+            count += 1;
+        }
+
+        const length = res.contentLength();
+        const has_body = res.hasBody(req.method);
+        if (has_body and length == null) return badGateway(stream, s3.endpoint, error.MissingContentLength);
+        const slot: downloads_mod.Slot = if (has_body) downloads.acquire(self.mount, client, length.?) catch {
+            try downloads_mod.writeRejection(stream);
+            return false;
+        } else .{};
+        defer slot.release();
+        try response.writeHead(stream, res.status, relayed[0..count], length, keep_alive);
+        if (!has_body) {
+            if (self.top) |*top| top.record(req.path(), 0);
+            return keep_alive;
+        }
+
+        const len = length.?;
+        var fill: ?Fill = null;
+        defer if (fill) |*f| f.abort();
+        if (self.cache) |*cache| {
+            if (res.status == 200 and len <= cache.config.max_object_size) {
+                const compress = cache.config.compression == .gzip and res.header("Content-Encoding") == null and
+                    sub_filter.contentTypeListed(cache.config.compress_types, content_type);
+                fill = cache.begin(cacheName(s3, key), res.header("ETag") orelse "", content_type, len, compress);
+            }
+        }
+
+        const body_start = buf[res.head_len..n];
+        const first = body_start[0..@min(body_start.len, len)];
+        try stream.writeAll(first);
+        if (fill) |*f| f.write(first);
+
+        var remaining = len - first.len;
+        var chunk: [16 * 1024]u8 = undefined;
+        while (remaining > 0) {
+            const read = try upstream.read(chunk[0..@min(chunk.len, remaining)]);
+            if (read == 0) return error.UpstreamClosedConnection;
+            try stream.writeAll(chunk[0..read]);
+            if (fill) |*f| f.write(chunk[0..read]);
+            remaining -= read;
+        }
+
+        if (fill) |*f| {
+            f.commit();
+            fill = null;
+        }
+        if (self.top) |*top| top.record(req.path(), len);
+        return keep_alive;
+    }
+};
+
+pub const CacheStats = struct {
+    path: []const u8,
+    bucket: []const u8,
+    entries: usize = 0,
+    bytes: u64 = 0,
+    /// 0 when the mount has no cache
+    max_bytes: u64 = 0,
+    /// Whole-object requests the cache answered, and those it didn't
+    hits: u64 = 0,
+    misses: u64 = 0,
+    hit_ratio: f64 = 0,
+    /// Copies stored since startup
+    fills: u64 = 0,
+    /// Copies removed to stay within max_bytes
+    evictions: u64 = 0,
+};
+
+const Entry = struct {
+    size: u64,
+    stored_ms: i64,
+};
+
+/// Whole objects on local disk. Each file holds the object's ETag, its
+/// Content-Type, the coding the copy is stored with ("" or "gzip") and the
+/// object's size on a line each, then the stored bytes.
+const Cache = struct {
+    allocator: std.mem.Allocator,
+    config: *const config.S3CacheConfig,
+    dir: std.fs.Dir,
+    mutex: std.Thread.Mutex = .{},
+    entries: std.AutoHashMapUnmanaged(Name, Entry) = .empty,
+    /// Bytes of every copy, metadata included
+    size: u64 = 0,
+    fills: u64 = 0,
+    evictions: u64 = 0,
+
+    /// Take over the copies already in the directory; partial copies left
+    /// by a crash are removed
+    fn init(allocator: std.mem.Allocator, cfg: *const config.S3CacheConfig) !Cache {
+        try std.fs.cwd().makePath(cfg.dir);
+        var dir = try std.fs.cwd().openDir(cfg.dir, .{ .iterate = true });
+        errdefer dir.close();
+
+        var self = Cache{ .allocator = allocator, .config = cfg, .dir = dir };
+        errdefer self.entries.deinit(allocator);
+
+        var it = dir.iterate();
+        while (try it.next()) |file| {
+            if (file.kind != .file) continue;
+            if (std.mem.endsWith(u8, file.name, ".tmp")) {
+                dir.deleteFile(file.name) catch {};
+                continue;
+            }
+            if (file.name.len != @sizeOf(Name)) continue;
+            const stat = dir.statFile(file.name) catch continue;
+            try self.entries.put(allocator, file.name[0..@sizeOf(Name)].*, .{
+                .size = stat.size,
+                .stored_ms = @intCast(@divFloor(stat.mtime, std.time.ns_per_ms)),
+            });
+            self.size += stat.size;
+        }
+        self.evict();
+        return self;
+    }
+
+    fn deinit(self: *Cache) void {
+        self.entries.deinit(self.allocator);
+        self.dir.close();
+    }
+
+    /// Answer from a fresh copy of the object, if there is one. Returns
+    /// the object bytes sent, or null when the request wasn't answered.
+    fn serve(
+        self: *Cache,
+        stream: std.net.Stream,
+        req: *const request.Request,
+        name: Name,
+        mount: *const config.StaticMountConfig,
+        downloads: *downloads_mod.DownloadLimiter,
+        client: std.net.Address,
+        keep_alive: bool,
+    ) !?u64 {
+        if (!self.fresh(name)) return null;
+        const file = self.dir.openFile(&name, .{}) catch return null;
+        defer file.close();
+        const stat = try file.stat();
+
+        var meta_buf: [1024]u8 = undefined;
+        const meta_len = try file.pread(&meta_buf, 0);
+        var lines = std.mem.splitScalar(u8, meta_buf[0..meta_len], '\n');
+        const etag = lines.next() orelse return null;
+        const content_type = lines.next() orelse return null;
+        const encoding = lines.next() orelse return null;
+        const size_line = lines.next() orelse return null;
+        const offset = etag.len + content_type.len + encoding.len + size_line.len + 4;
+        if (offset > meta_len) return null;
+        const size = std.fmt.parseInt(u64, size_line, 10) catch return null;
+        const stored = stat.size - offset;
+        const compressed = std.mem.eql(u8, encoding, "gzip");
+        // Copies written before the coding line existed don't add up and
+        // are fetched again
+        if (!compressed and (encoding.len > 0 or size != stored)) return null;
+
+        // Compressed copies go out as they are to clients that take gzip
+        const send_gzip = compressed and compression.acceptsGzip(req.header("Accept-Encoding") orelse "");
+        var etag_buf: [meta_buf.len + 2]u8 = undefined;
+        const sent_etag = if (send_gzip and !std.mem.startsWith(u8, etag, "W/"))
+            // Equivalent to the object but not the same bytes
+            std.fmt.bufPrint(&etag_buf, "W/{s}", .{etag}) catch unreachable
+        else
+            etag;
+
+        var headers: [4]Header = undefined;
+        var header_count: usize = 0;
+        if (etag.len > 0) {
+            headers[header_count] = .{ .name = "ETag", .value = sent_etag };
+            header_count += 1;
+        }
+        if (compressed) {
+            headers[header_count] = .{ .name = "Vary", .value = "Accept-Encoding" };
+            header_count += 1;
+        }
+        if (etag.len > 0) {
+            if (req.header("If-None-Match")) |if_none_match| {
+                if (conditional.noneMatch(if_none_match, sent_etag)) {
+                    try response.writeHead(stream, 304, headers[0..header_count], null, keep_alive);
+                    return 0;
+                }
+            }
+        }
+        headers[header_count] = .{ .name = "Content-Type", .value = content_type };
+        header_count += 1;
+        if (send_gzip) {
+            headers[header_count] = .{ .name = "Content-Encoding", .value = "gzip" };
+            header_count += 1;
+        }
+
+        const length: usize = @intCast(if (send_gzip) stored else size);
+        if (std.mem.eql(u8, req.method, "HEAD")) {
+            try response.writeHead(stream, 200, headers[0..header_count], length, keep_alive);
+            return 0;
+        }
+
+        const slot = try downloads.acquire(mount, client, length);
+        defer slot.release();
+        try response.writeHead(stream, 200, headers[0..header_count], length, keep_alive);
+        if (compressed and !send_gzip) {
+            try self.writeDecompressed(file, offset, stream);
+            return length;
+        }
+
+        var chunk: [16 * 1024]u8 = undefined;
+        var position: u64 = offset;
+        while (position < stat.size) {
+            const read = try file.pread(chunk[0..@min(chunk.len, stat.size - position)], position);
+            if (read == 0) return error.UnexpectedEndOfFile;
+            try stream.writeAll(chunk[0..read]);
+            position += read;
+        }
+        return length;
+    }
+
+    /// Stream a gzip copy to a client that doesn't accept gzip
+    fn writeDecompressed(self: *Cache, file: std.fs.File, offset: u64, stream: std.net.Stream) !void {
+        var read_buf: [16 * 1024]u8 = undefined;
+        var file_reader = file.reader(&read_buf);
+        try file_reader.seekTo(offset);
+        const window = try self.allocator.alloc(u8, std.compress.flate.max_window_len);
+        defer self.allocator.free(window);
+        var gunzip = std.compress.flate.Decompress.init(&file_reader.interface, .gzip, window);
+
+        var chunk: [16 * 1024]u8 = undefined;
+        while (true) {
+            const read = try gunzip.reader.readSliceShort(&chunk);
+            if (read == 0) break;
+            try stream.writeAll(chunk[0..read]);
+        }
+    }
+
+    /// Whether a copy exists within its TTL; expired copies are removed
+    fn fresh(self: *Cache, name: Name) bool {
+        self.mutex.lock();
+        defer self.mutex.unlock();
+
+        const entry = self.entries.get(name) orelse return false;
+        if (std.time.milliTimestamp() - entry.stored_ms < @as(i64, self.config.ttl_s) * std.time.ms_per_s) return true;
+        self.remove(name, entry);
+        return false;
+    }
+
+    /// Start copying an object of `size` bytes into the cache, or null if
+    /// the copy can't be created. With `compress` the copy is stored
+    /// gzipped. Concurrent copies of one object each get their own file.
+    fn begin(self: *Cache, name: Name, etag: []const u8, content_type: []const u8, size: u64, compress: bool) ?Fill {
+        var fill = Fill{ .cache = self, .name = name, .tmp_buf = undefined, .tmp_len = 0, .file = undefined };
+        const tmp = std.fmt.bufPrint(&fill.tmp_buf, "{s}.{x:0>16}.tmp", .{ &name, std.crypto.random.int(u64) }) catch unreachable;
+        fill.tmp_len = tmp.len;
+        fill.file = self.dir.createFile(tmp, .{ .exclusive = true }) catch return null;
+
+        // Stored as it is when there's no memory for the compressor
+        if (compress) fill.gzip = compression.Gzip.create(self.allocator, self.config.compression_level) catch null;
+
+        var size_buf: [24]u8 = undefined;
+        fill.store(etag);
+        fill.store("\n");
+        fill.store(content_type);
+        fill.store("\n");
+        fill.store(if (fill.gzip != null) "gzip\n" else "\n");
+        fill.store(std.fmt.bufPrint(&size_buf, "{d}\n", .{size}) catch unreachable);
+        return fill;
+    }
+
+    fn add(self: *Cache, name: Name, size: u64) void {
+        self.mutex.lock();
+        defer self.mutex.unlock();
+
+        const entry = self.entries.getOrPut(self.allocator, name) catch {
+            self.dir.deleteFile(&name) catch {};
+            return;
+        };
+        if (entry.found_existing) self.size -|= entry.value_ptr.size;
+        entry.value_ptr.* = .{ .size = size, .stored_ms = std.time.milliTimestamp() };
+        self.size += size;
+        self.fills += 1;
+        self.evict();
+    }
+
+    /// Remove the oldest copies until the cache fits in `max_size`
+    fn evict(self: *Cache) void {
+        while (self.size > self.config.max_size) {
+            var oldest: ?Name = null;
+            var oldest_entry: Entry = undefined;
+            var it = self.entries.iterator();
+            while (it.next()) |e| {
+                if (oldest == null or e.value_ptr.stored_ms < oldest_entry.stored_ms) {
+                    oldest = e.key_ptr.*;
+                    oldest_entry = e.value_ptr.*;
+                }
+            }
+            self.remove(oldest orelse return, oldest_entry);
+            self.evictions += 1;
+        }
+    }
+
+    fn remove(self: *Cache, name: Name, entry: Entry) void {
+        self.dir.deleteFile(&name) catch {};
+        _ = self.entries.remove(name);
+        self.size -|= entry.size;
+    }
+};
+
+/// An object being copied into the cache as it streams to the client.
+/// The copy only becomes visible once the whole object is written.
+const Fill = struct {
+    cache: *Cache,
+    name: Name,
+    tmp_buf: [@sizeOf(Name) + 21]u8,
+    tmp_len: usize,
+    file: std.fs.File,
+    written: u64 = 0,
+    /// Set when the copy is stored compressed
+    gzip: ?*compression.Gzip = null,
+    failed: bool = false,
+
+    /// Where the compressor's output goes
+    const Output = struct {
+        fill: *Fill,
+
+        pub fn write(self: Output, bytes: []const u8) !void {
+            try self.fill.file.writeAll(bytes);
+            self.fill.written += bytes.len;
+        }
+    };
+
+    fn tmpName(self: *const Fill) []const u8 {
+        return self.tmp_buf[0..self.tmp_len];
+    }
+
+    /// Add object bytes to the copy. A failed write gives up on the copy,
+    /// never on the response.
+    fn write(self: *Fill, bytes: []const u8) void {
+        if (self.failed) return;
+        const gzip = self.gzip orelse return self.store(bytes);
+        gzip.write(bytes, Output{ .fill = self }) catch {
+            self.failed = true;
+        };
+    }
+
+    /// Write bytes to the file as they are
+    fn store(self: *Fill, bytes: []const u8) void {
+        if (self.failed) return;
+        (Output{ .fill = self }).write(bytes) catch {
+            self.failed = true;
+        };
+    }
+
+    fn commit(self: *Fill) void {
+        if (self.gzip) |gzip| {
+            if (!self.failed) gzip.finish(Output{ .fill = self }) catch {
+                self.failed = true;
+            };
+            gzip.destroy();
+        }
+        self.file.close();
+        if (self.failed) {
+            self.cache.dir.deleteFile(self.tmpName()) catch {};
+            return;
+        }
+        self.cache.dir.rename(self.tmpName(), &self.name) catch {
+            self.cache.dir.deleteFile(self.tmpName()) catch {};
+            return;
+        };
+        self.cache.add(self.name, self.written);
+    }
+
+    fn abort(self: *Fill) void {
+        if (self.gzip) |gzip| gzip.destroy();
+        self.file.close();
+        self.cache.dir.deleteFile(self.tmpName()) catch {};
+    }
+};
+
+fn cacheName(s3: *const config.S3OriginConfig, key: []const u8) Name {
+    var hasher = std.crypto.hash.sha2.Sha256.init(.{});
+    hasher.update(s3.endpoint);
+    hasher.update("/");
+    hasher.update(s3.bucket);
+    hasher.update("/");
+    hasher.update(key);
+    const digest = hasher.finalResult();
+    return std.fmt.bytesToHex(digest[0..16].*, .lower);
+}
+
+/// Percent-encode an object key for the request target, keeping '/'
+fn encodeKey(allocator: std.mem.Allocator, out: *std.ArrayList(u8), key: []const u8) !void {
+    for (key) |c| {
+        if (std.ascii.isAlphanumeric(c) or c == '-' or c == '_' or c == '.' or c == '~' or c == '/') {
+            try out.append(allocator, c);
+        } else {
+            try out.print(allocator, "%{X:0>2}", .{c});
+        }
+    }
+}
+
+fn badGateway(stream: std.net.Stream, endpoint: []const u8, err: anyerror) !bool {
+    std.debug.print("✗ S3 endpoint {s}: {any}\n", .{ endpoint, err });
+    try response.writeError(stream, 502);
+    return false;
+}
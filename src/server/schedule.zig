@@ -0,0 +1,198 @@
+const std = @import("std");
+const config = @import("../config/config.zig");
+const router = @import("../routing/router.zig");
+
+const ms_per_day = std.time.ms_per_day;
+
+/// When a change applies, parsed from its "HH:MM" or "YYYY-MM-DDTHH:MM" form
+const Time = union(enum) {
+    /// Milliseconds after midnight UTC, every day
+    daily: i64,
+    /// Milliseconds since the epoch
+    once: i64,
+};
+
+/// One occurrence of a change
+pub const Window = struct {
+    start_ms: i64,
+    end_ms: i64,
+};
+
+pub fn validate(changes: []const config.ScheduledChangeConfig) !void {
+    for (changes, 0..) |*change, i| {
+        if (change.name.len == 0) return error.InvalidScheduledChange;
+        for (changes[0..i]) |earlier| {
+            if (std.mem.eql(u8, earlier.name, change.name)) return error.DuplicateScheduledChange;
+        }
+        const start = parseTime(change.start) orelse return error.InvalidScheduleTime;
+        const end = parseTime(change.end) orelse return error.InvalidScheduleTime;
+        switch (start) {
+            .daily => |s| if (end != .daily or end.daily == s) return error.InvalidScheduleTime,
+            .once => |s| if (end != .once or end.once <= s) return error.InvalidScheduleTime,
+        }
+        if (!(change.rate_limit_scale > 0)) return error.InvalidScheduledChange;
+        if (change.maintenance) |maintenance| {
+            if (maintenance.status < 400 or maintenance.status > 599) return error.InvalidScheduledChange;
+        }
+    }
+}
+
+fn parseTime(text: []const u8) ?Time {
+    if (text.len == 5) return .{ .daily = clockMs(text) orelse return null };
+    const value = if (std.mem.endsWith(u8, text, "Z")) text[0 .. text.len - 1] else text;
+    if (value.len != 16 or value[4] != '-' or value[7] != '-' or value[10] != 'T') return null;
+    const year = std.fmt.parseInt(i64, value[0..4], 10) catch return null;
+    const month = std.fmt.parseInt(u8, value[5..7], 10) catch return null;
+    const day = std.fmt.parseInt(u8, value[8..10], 10) catch return null;
+    if (month == 0 or month > 12 or day == 0 or day > 31) return null;
+    return .{ .once = daysFromCivil(year, month, day) * ms_per_day + (clockMs(value[11..]) orelse return null) };
+}
+
+/// "HH:MM" as milliseconds after midnight
+pub fn clockMs(text: []const u8) ?i64 {
+    if (text.len != 5 or text[2] != ':') return null;
+    const hour = std.fmt.parseInt(u8, text[0..2], 10) catch return null;
+    const minute = std.fmt.parseInt(u8, text[3..5], 10) catch return null;
+    if (hour > 23 or minute > 59) return null;
+    return (@as(i64, hour) * 60 + minute) * std.time.ms_per_min;
+}
+
+/// Days between 1970-01-01 and a proleptic Gregorian date
+pub fn daysFromCivil(year: i64, month: u8, day: u8) i64 {
+    const y = if (month <= 2) year - 1 else year;
+    const era = @divFloor(y, 400);
+    const year_of_era = y - era * 400;
+    const shifted_month: i64 = if (month > 2) month - 3 else month + 9;
+    const day_of_year = @divFloor(153 * shifted_month + 2, 5) + day - 1;
+    const day_of_era = year_of_era * 365 + @divFloor(year_of_era, 4) - @divFloor(year_of_era, 100) + day_of_year;
+    return era * 146097 + day_of_era - 719468;
+}
+
+/// The occurrence of a change in progress at `now_ms`, or else the next
+/// one; null once a single window has passed
+pub fn window(change: *const config.ScheduledChangeConfig, now_ms: i64) ?Window {
+    // Validated at startup
+    const start = parseTime(change.start).?;
+    const end = parseTime(change.end).?;
+    switch (start) {
+        .once => |s| return if (now_ms < end.once) .{ .start_ms = s, .end_ms = end.once } else null,
+        .daily => |s| {
+            const e = end.daily;
+            const midnight = @divFloor(now_ms, ms_per_day) * ms_per_day;
+            if (s < e) {
+                const today = Window{ .start_ms = midnight + s, .end_ms = midnight + e };
+                if (now_ms < today.end_ms) return today;
+                return .{ .start_ms = today.start_ms + ms_per_day, .end_ms = today.end_ms + ms_per_day };
+            }
+            // Crosses midnight: yesterday's occurrence may still be open
+            if (now_ms < midnight + e) return .{ .start_ms = midnight - ms_per_day + s, .end_ms = midnight + e };
+            return .{ .start_ms = midnight + s, .end_ms = midnight + ms_per_day + e };
+        },
+    }
+}
+
+/// A maintenance window open for a request
+pub const Maintenance = struct {
+    config: *const config.ScheduledMaintenanceConfig,
+    /// Seconds until the window closes, for Retry-After
+    retry_after_s: i64,
+};
+
+/// The configured changes and which occurrences were cancelled. Whether a
+/// change applies is worked out from the clock on each lookup, so there is
+/// no timer thread to keep in step.
+pub const Schedule = struct {
+    allocator: std.mem.Allocator,
+    changes: []const config.ScheduledChangeConfig,
+    /// Per change: end of the last occurrence cancelled through the admin
+    /// API. Occurrences ending by then are skipped.
+    cancelled_until: []std.atomic.Value(i64),
+
+    pub fn init(allocator: std.mem.Allocator, changes: []const config.ScheduledChangeConfig) !Schedule {
+        const cancelled_until = try allocator.alloc(std.atomic.Value(i64), changes.len);
+        for (cancelled_until) |*until| until.* = std.atomic.Value(i64).init(std.math.minInt(i64));
+        return .{ .allocator = allocator, .changes = changes, .cancelled_until = cancelled_until };
+    }
+
+    pub fn deinit(self: *Schedule) void {
+        self.allocator.free(self.cancelled_until);
+    }
+
+    /// The window of change `i` open at `now_ms`, if it wasn't cancelled
+    fn open(self: *const Schedule, i: usize, now_ms: i64) ?Window {
+        const w = window(&self.changes[i], now_ms) orelse return null;
+        if (now_ms < w.start_ms or w.end_ms <= self.cancelled_until[i].load(.monotonic)) return null;
+        return w;
+    }
+
+    /// Factor for every rate limit; the lowest of the open changes
+    pub fn rateLimitScale(self: *const Schedule, now_ms: i64) f64 {
+        var scale: f64 = 1;
+        for (self.changes, 0..) |change, i| {
+            if (change.rate_limit_scale == 1 or self.open(i, now_ms) == null) continue;
+            scale = @min(scale, change.rate_limit_scale);
+        }
+        return scale;
+    }
+
+    /// The open maintenance window covering a request, if any
+    pub fn maintenance(self: *const Schedule, host: []const u8, path: []const u8, now_ms: i64) ?Maintenance {
+        for (self.changes, 0..) |*change, i| {
+            const m = if (change.maintenance) |*cfg| cfg else continue;
+            if (m.hosts.len > 0 and !router.hostAllowed(m.hosts, host)) continue;
+            if (!router.pathMatches(m.path, path)) continue;
+            const w = self.open(i, now_ms) orelse continue;
+            return .{ .config = m, .retry_after_s = @max(1, @divFloor(w.end_ms - now_ms + std.time.ms_per_s - 1, std.time.ms_per_s)) };
+        }
+        return null;
+    }
+
+    /// Skip the open or next occurrence of the named change. False when no
+    /// change has that name or it has no occurrence left.
+    pub fn cancel(self: *Schedule, name: []const u8, now_ms: i64) bool {
+        for (self.changes, 0..) |*change, i| {
+            if (!std.mem.eql(u8, change.name, name)) continue;
+            var w = window(change, now_ms) orelse return false;
+            // With this occurrence already cancelled, cancel the one after
+            if (w.end_ms <= self.cancelled_until[i].load(.monotonic)) {
+                w = window(change, w.end_ms) orelse return false;
+            }
+            self.cancelled_until[i].store(w.end_ms, .monotonic);
+            std.debug.print("✓ Scheduled change {s} cancelled until {d}\n", .{ name, w.end_ms });
+            return true;
+        }
+        return false;
+    }
+
+    /// Every change with its open or next occurrence, for /admin/schedule
+    pub fn json(self: *const Schedule, allocator: std.mem.Allocator, now_ms: i64) ![]const u8 {
+        const Entry = struct {
+            name: []const u8,
+            start: []const u8,
+            end: []const u8,
+            active: bool,
+            cancelled: bool,
+            next_start_ms: ?i64,
+            next_end_ms: ?i64,
+            maintenance_path: ?[]const u8,
+            rate_limit_scale: f64,
+        };
+        const entries = try allocator.alloc(Entry, self.changes.len);
+        defer allocator.free(entries);
+        for (self.changes, entries, 0..) |*change, *entry, i| {
+            const w = window(change, now_ms);
+            entry.* = .{
+                .name = change.name,
+                .start = change.start,
+                .end = change.end,
+                .active = self.open(i, now_ms) != null,
+                .cancelled = if (w) |next| next.end_ms <= self.cancelled_until[i].load(.monotonic) else false,
+                .next_start_ms = if (w) |next| next.start_ms else null,
+                .next_end_ms = if (w) |next| next.end_ms else null,
+                .maintenance_path = if (change.maintenance) |m| m.path else null,
+                .rate_limit_scale = change.rate_limit_scale,
+            };
+        }
+        return std.json.Stringify.valueAlloc(allocator, .{ .now_ms = now_ms, .changes = entries }, .{});
+    }
+};
@@ -0,0 +1,88 @@
+const std = @import("std");
+const config = @import("../config/config.zig");
+const request = @import("request.zig");
+const metrics = @import("metrics.zig");
+const acl = @import("../security/acl.zig");
+const secret_mod = @import("../config/secret.zig");
+const rate_limit_mod = @import("../security/rate_limit.zig");
+const connections_mod = @import("connections.zig");
+const usage_mod = @import("usage.zig");
+const stats_mod = @import("../routing/stats.zig");
+const upstream_mod = @import("../upstream/manager.zig");
+const s3_origin_mod = @import("s3_origin.zig");
+
+const Sha256 = std.crypto.hash.sha2.Sha256;
+
+/// Everything that contributes metric families to a scrape
+pub const Sources = struct {
+    rate_limiter: *rate_limit_mod.RateLimiter,
+    connections: *connections_mod.ConnectionTracker,
+    usage: *usage_mod.Usage,
+    route_stats: *const stats_mod.RouteStatsRegistry,
+    upstreams: *const upstream_mod.UpstreamManager,
+    s3_origins: *const s3_origin_mod.S3Origins,
+
+    pub fn render(self: Sources, allocator: std.mem.Allocator) ![]const u8 {
+        var exposition = metrics.Exposition.init(allocator);
+        try self.rate_limiter.writeMetrics(&exposition);
+        try self.connections.writeMetrics(&exposition);
+        try self.usage.writeMetrics(&exposition);
+        try self.route_stats.writeMetrics(&exposition);
+        try self.upstreams.writeMetrics(&exposition);
+        try self.s3_origins.writeMetrics(&exposition);
+        return exposition.text();
+    }
+};
+
+pub const Access = enum {
+    /// The client address is outside `metrics.allow`
+    forbidden,
+    /// A scrape token was presented
+    granted,
+    /// No scrape token was presented, or none is configured
+    no_token,
+};
+
+/// Who may scrape /metrics, on the admin and main listeners alike
+pub const ScrapeGuard = struct {
+    allocator: std.mem.Allocator,
+    config: *const config.MetricsConfig,
+    mutex: std.Thread.Mutex = .{},
+    tokens: []secret_mod.Secret,
+
+    pub fn init(allocator: std.mem.Allocator, cfg: *const config.MetricsConfig) !ScrapeGuard {
+        const tokens = try allocator.alloc(secret_mod.Secret, cfg.tokens.len);
+        for (cfg.tokens, tokens) |source, *token| token.* = .{ .source = source };
+        return .{ .allocator = allocator, .config = cfg, .tokens = tokens };
+    }
+
+    pub fn deinit(self: *ScrapeGuard) void {
+        for (self.tokens) |*token| token.deinit(self.allocator);
+        self.allocator.free(self.tokens);
+    }
+
+    pub fn check(self: *ScrapeGuard, peer: std.net.Address, req: *const request.Request) Access {
+        if (self.config.allow.len > 0) {
+            const ip = acl.addressBytes(peer) orelse return .forbidden;
+            if (!acl.listContains(self.config.allow, ip)) return .forbidden;
+        }
+        if (self.tokens.len == 0) return .no_token;
+        const authorization = req.header("Authorization") orelse return .no_token;
+        if (!std.ascii.startsWithIgnoreCase(authorization, "Bearer ")) return .no_token;
+
+        var presented: [Sha256.digest_length]u8 = undefined;
+        Sha256.hash(std.mem.trim(u8, authorization["Bearer ".len..], " \t"), &presented, .{});
+
+        self.mutex.lock();
+        defer self.mutex.unlock();
+        const now_ms = std.time.milliTimestamp();
+        var matched = false;
+        for (self.tokens) |*token| {
+            const value = token.get(self.allocator, now_ms) catch continue;
+            var expected: [Sha256.digest_length]u8 = undefined;
+            Sha256.hash(value, &expected, .{});
+            if (std.crypto.timing_safe.eql([Sha256.digest_length]u8, presented, expected)) matched = true;
+        }
+        return if (matched) .granted else .no_token;
+    }
+};
@@ -55,6 +55,11 @@ pub fn shouldReload() bool {
     return should_reload.load(.seq_cst);
 }
 
+/// Request shutdown from within the process
+pub fn requestShutdown() void {
+    should_shutdown.store(true, .seq_cst);
+}
+
 /// Reset reload flag after processing
 pub fn resetReload() void {
     should_reload.store(false, .seq_cst);
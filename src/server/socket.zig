@@ -0,0 +1,127 @@
+const std = @import("std");
+const builtin = @import("builtin");
+
+/// Apply send and receive timeouts to a socket (0 = no timeout)
+pub fn setTimeouts(handle: std.posix.socket_t, timeout_ms: u32) !void {
+    const tv = std.posix.timeval{
+        .sec = @intCast(timeout_ms / 1000),
+        .usec = @intCast((timeout_ms % 1000) * 1000),
+    };
+    try std.posix.setsockopt(handle, std.posix.SOL.SOCKET, std.posix.SO.RCVTIMEO, std.mem.asBytes(&tv));
+    try std.posix.setsockopt(handle, std.posix.SOL.SOCKET, std.posix.SO.SNDTIMEO, std.mem.asBytes(&tv));
+}
+
+/// Send small writes immediately instead of waiting to fill a segment
+pub fn setNoDelay(handle: std.posix.socket_t) !void {
+    try std.posix.setsockopt(handle, std.posix.IPPROTO.TCP, std.posix.TCP.NODELAY, std.mem.asBytes(&@as(c_int, 1)));
+}
+
+/// Wait until the socket is readable; false when `timeout_ms` passes
+/// first (0 = wait indefinitely)
+pub fn waitReadable(handle: std.posix.socket_t, timeout_ms: u32) !bool {
+    var fds = [_]std.posix.pollfd{
+        .{ .fd = handle, .events = std.posix.POLL.IN, .revents = 0 },
+    };
+    const timeout: i32 = if (timeout_ms == 0) -1 else @intCast(@min(timeout_ms, std.math.maxInt(i32)));
+    return try std.posix.poll(&fds, timeout) > 0;
+}
+
+/// Whether the peer has closed or reset the connection, without consuming
+/// anything it sent; bytes waiting to be read mean it hasn't
+pub fn peerClosed(handle: std.posix.socket_t) bool {
+    var peek: [1]u8 = undefined;
+    const n = std.posix.recv(handle, &peek, std.posix.MSG.PEEK | std.posix.MSG.DONTWAIT) catch |err| return err != error.WouldBlock;
+    return n == 0;
+}
+
+/// Wait until any of the sockets is readable and return its index,
+/// preferring earlier ones; null when `timeout_ms` passes first (0 = wait indefinitely)
+pub fn firstReadable(handles: []const std.posix.socket_t, timeout_ms: u32) !?usize {
+    var fds: [8]std.posix.pollfd = undefined;
+    std.debug.assert(handles.len <= fds.len);
+    for (handles, fds[0..handles.len]) |handle, *fd| {
+        fd.* = .{ .fd = handle, .events = std.posix.POLL.IN, .revents = 0 };
+    }
+    const timeout: i32 = if (timeout_ms == 0) -1 else @intCast(@min(timeout_ms, std.math.maxInt(i32)));
+    if (try std.posix.poll(fds[0..handles.len], timeout) == 0) return null;
+    for (fds[0..handles.len], 0..) |fd, i| {
+        if (fd.revents != 0) return i;
+    }
+    return null;
+}
+
+/// Bind a TCP listener. IPv6 sockets are made v6-only so "[::]" and
+/// "0.0.0.0" listeners can share a port on every platform.
+pub fn listen(address: std.net.Address) !std.net.Server {
+    const posix = std.posix;
+    const handle = try posix.socket(address.any.family, posix.SOCK.STREAM | posix.SOCK.CLOEXEC, posix.IPPROTO.TCP);
+    var server: std.net.Server = .{ .listen_address = undefined, .stream = .{ .handle = handle } };
+    errdefer server.stream.close();
+
+    try posix.setsockopt(handle, posix.SOL.SOCKET, posix.SO.REUSEADDR, std.mem.asBytes(&@as(c_int, 1)));
+    if (address.any.family == posix.AF.INET6) {
+        try posix.setsockopt(handle, posix.IPPROTO.IPV6, posix.IPV6.V6ONLY, std.mem.asBytes(&@as(c_int, 1)));
+    }
+
+    var len = address.getOsSockLen();
+    try posix.bind(handle, &address.any, len);
+    try posix.listen(handle, 128);
+    try posix.getsockname(handle, &server.listen_address.any, &len);
+    return server;
+}
+
+/// Bind a UDP socket, v6-only for IPv6 addresses like `listen`
+pub fn bindUdp(address: std.net.Address) !std.posix.socket_t {
+    const posix = std.posix;
+    const handle = try posix.socket(address.any.family, posix.SOCK.DGRAM | posix.SOCK.CLOEXEC, posix.IPPROTO.UDP);
+    errdefer posix.close(handle);
+
+    try posix.setsockopt(handle, posix.SOL.SOCKET, posix.SO.REUSEADDR, std.mem.asBytes(&@as(c_int, 1)));
+    if (address.any.family == posix.AF.INET6) {
+        try posix.setsockopt(handle, posix.IPPROTO.IPV6, posix.IPV6.V6ONLY, std.mem.asBytes(&@as(c_int, 1)));
+    }
+    try posix.bind(handle, &address.any, address.getOsSockLen());
+    return handle;
+}
+
+/// A connected pair of local stream sockets
+pub fn streamPair() ![2]std.net.Stream {
+    if (builtin.os.tag != .linux) return error.Unsupported;
+    const linux = std.os.linux;
+    var fds: [2]i32 = undefined;
+    const rc = linux.socketpair(linux.AF.UNIX, linux.SOCK.STREAM | linux.SOCK.CLOEXEC, 0, &fds);
+    return switch (std.posix.errno(rc)) {
+        .SUCCESS => .{ .{ .handle = fds[0] }, .{ .handle = fds[1] } },
+        else => |err| std.posix.unexpectedErrno(err),
+    };
+}
+
+/// Parse listen addresses, adding "[::]:port" for each "0.0.0.0:port" when
+/// `dual_stack` is set and the IPv6 address is not already listed
+pub fn listenAddresses(allocator: std.mem.Allocator, listen_addrs: []const []const u8, dual_stack: bool) ![]std.net.Address {
+    var addresses: std.ArrayList(std.net.Address) = .empty;
+    errdefer addresses.deinit(allocator);
+
+    for (listen_addrs) |text| try addresses.append(allocator, try std.net.Address.parseIpAndPort(text));
+    if (dual_stack) {
+        for (0..addresses.items.len) |i| {
+            const v4 = addresses.items[i];
+            if (v4.any.family != std.posix.AF.INET or v4.in.sa.addr != 0) continue;
+            const v6 = std.net.Address.initIp6([_]u8{0} ** 16, v4.getPort(), 0, 0);
+            for (addresses.items) |existing| {
+                if (existing.eql(v6)) break;
+            } else try addresses.append(allocator, v6);
+        }
+    }
+    return addresses.toOwnedSlice(allocator);
+}
+
+/// Read exactly `buf.len` bytes from the stream
+pub fn readExact(stream: std.net.Stream, buf: []u8) !void {
+    var filled: usize = 0;
+    while (filled < buf.len) {
+        const n = try stream.read(buf[filled..]);
+        if (n == 0) return error.EndOfStream;
+        filled += n;
+    }
+}
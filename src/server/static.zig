@@ -0,0 +1,427 @@
+const std = @import("std");
+const config = @import("../config/config.zig");
+const request = @import("request.zig");
+const response = @import("response.zig");
+const router = @import("../routing/router.zig");
+const conditional = @import("conditional.zig");
+const downloads_mod = @import("downloads.zig");
+const static_io_mod = @import("static_io.zig");
+
+const Header = request.Header;
+
+/// Files whose strong ETags are remembered; past this the cache starts over
+const MAX_ETAGS = 10_000;
+
+/// An ETag header value, quotes and weak prefix included
+pub const Etag = struct {
+    buf: [64]u8 = undefined,
+    len: usize = 0,
+
+    pub fn slice(self: *const Etag) []const u8 {
+        return self.buf[0..self.len];
+    }
+};
+
+/// Strong ETags by file path. Each entry remembers the file version it was
+/// computed for, so a changed file is hashed again on its next request.
+pub const EtagCache = struct {
+    allocator: std.mem.Allocator,
+    mutex: std.Thread.Mutex = .{},
+    entries: std.StringHashMapUnmanaged(Entry) = .empty,
+
+    const Entry = struct {
+        mtime: i128,
+        size: u64,
+        etag: Etag,
+    };
+
+    pub fn init(allocator: std.mem.Allocator) EtagCache {
+        return .{ .allocator = allocator };
+    }
+
+    pub fn deinit(self: *EtagCache) void {
+        self.clear();
+        self.entries.deinit(self.allocator);
+    }
+
+    fn clear(self: *EtagCache) void {
+        var it = self.entries.keyIterator();
+        while (it.next()) |key| self.allocator.free(key.*);
+        self.entries.clearRetainingCapacity();
+    }
+
+    /// Entry count and capacity for the admin API
+    pub fn stats(self: *EtagCache) struct { entries: usize, max_entries: usize } {
+        self.mutex.lock();
+        defer self.mutex.unlock();
+        return .{ .entries = self.entries.count(), .max_entries = MAX_ETAGS };
+    }
+
+    /// Forget every ETag. Returns how many there were.
+    pub fn flush(self: *EtagCache) usize {
+        self.mutex.lock();
+        defer self.mutex.unlock();
+        const count = self.entries.count();
+        self.clear();
+        return count;
+    }
+
+    /// Forget the ETag of the file at `path`, a key from `etagKey`
+    pub fn invalidate(self: *EtagCache, path: []const u8) bool {
+        self.mutex.lock();
+        defer self.mutex.unlock();
+        const removed = self.entries.fetchRemove(path) orelse return false;
+        self.allocator.free(removed.key);
+        return true;
+    }
+
+    fn get(self: *EtagCache, path: []const u8, stat: std.fs.File.Stat) ?Etag {
+        self.mutex.lock();
+        defer self.mutex.unlock();
+
+        const entry = self.entries.get(path) orelse return null;
+        if (entry.mtime != stat.mtime or entry.size != stat.size) return null;
+        return entry.etag;
+    }
+
+    /// Remember an ETag; a failed allocation only costs a recomputation
+    fn put(self: *EtagCache, path: []const u8, stat: std.fs.File.Stat, etag: Etag) void {
+        self.mutex.lock();
+        defer self.mutex.unlock();
+
+        const entry: Entry = .{ .mtime = stat.mtime, .size = stat.size, .etag = etag };
+        if (self.entries.getPtr(path)) |existing| {
+            existing.* = entry;
+            return;
+        }
+        if (self.entries.count() >= MAX_ETAGS) self.clear();
+        const key = self.allocator.dupe(u8, path) catch return;
+        self.entries.put(self.allocator, key, entry) catch self.allocator.free(key);
+    }
+};
+
+/// Longest static mount whose path prefix matches
+pub fn matchMount(mounts: []const config.StaticMountConfig, path: []const u8) ?*const config.StaticMountConfig {
+    var best: ?*const config.StaticMountConfig = null;
+    for (mounts) |*mount| {
+        if (!router.pathMatches(mount.path, path)) continue;
+        if (best) |current| {
+            if (mount.path.len <= current.path.len) continue;
+        }
+        best = mount;
+    }
+    return best;
+}
+
+/// Serve a file from a static mount. Finding the file and working out its
+/// ETag take a turn at `disk`; `client` is counted against the mount's
+/// download limit while the file is sent. Returns whether the connection
+/// can be reused.
+pub fn serve(
+    stream: std.net.Stream,
+    req: *const request.Request,
+    mount: *const config.StaticMountConfig,
+    etags: *EtagCache,
+    disk: *static_io_mod.DiskGate,
+    downloads: *downloads_mod.DownloadLimiter,
+    client: std.net.Address,
+    keep_alive: bool,
+) !bool {
+    if (try rejectMethod(stream, req)) return false;
+    const is_head = std.mem.eql(u8, req.method, "HEAD");
+
+    var path_buf: [std.fs.max_path_bytes]u8 = undefined;
+    const rel = relativePath(&path_buf, mount.path, req.path()) catch {
+        try response.writeError(stream, 400);
+        return false;
+    };
+
+    var turn = disk.acquire() catch {
+        try response.write(stream, 503, &[_]Header{
+            .{ .name = "Content-Type", .value = "text/plain" },
+            .{ .name = "Retry-After", .value = "1" },
+        }, "503 Service Unavailable\n");
+        return false;
+    };
+    defer turn.release();
+
+    var root = std.fs.cwd().openDir(mount.root, .{}) catch |err| {
+        std.debug.print("✗ Static root {s}: {any}\n", .{ mount.root, err });
+        try response.writeError(stream, 500);
+        return false;
+    };
+    defer root.close();
+
+    const file = openFile(root, rel, mount.index) catch |err| switch (err) {
+        error.IsDir => {
+            // Directory without a trailing slash: redirect so relative links resolve
+            var location_buf: [4096]u8 = undefined;
+            const location = std.fmt.bufPrint(&location_buf, "{s}/", .{req.path()}) catch {
+                try response.writeError(stream, 414);
+                return false;
+            };
+            try response.write(stream, 301, &[_]Header{
+                .{ .name = "Location", .value = location },
+            }, "");
+            return false;
+        },
+        error.FileNotFound, error.NotDir => {
+            try response.writeError(stream, 404);
+            return false;
+        },
+        error.AccessDenied => {
+            try response.writeError(stream, 403);
+            return false;
+        },
+        else => return err,
+    };
+    defer file.close();
+
+    const stat = try file.stat();
+    const is_index = rel.len == 0 or rel[rel.len - 1] == '/';
+    const name = if (is_index) mount.index else rel;
+
+    var headers: [2]Header = undefined;
+    headers[0] = .{ .name = "Content-Type", .value = contentType(name) };
+    var header_count: usize = 1;
+
+    var key_buf: [std.fs.max_path_bytes]u8 = undefined;
+    const key = try etagKey(&key_buf, mount, rel);
+    const etag = try etagFor(etags, mount, key, file, stat);
+    if (etag) |*tag| {
+        headers[1] = .{ .name = "ETag", .value = tag.slice() };
+        header_count = 2;
+        if (req.header("If-None-Match")) |if_none_match| {
+            if (conditional.noneMatch(if_none_match, tag.slice())) {
+                try response.writeHead(stream, 304, headers[1..2], null, keep_alive);
+                return keep_alive;
+            }
+        }
+    }
+
+    if (is_head) {
+        try response.writeHead(stream, 200, headers[0..header_count], stat.size, keep_alive);
+        return keep_alive;
+    }
+
+    // Sending is paced by the client, not the disk
+    turn.release();
+
+    const slot = downloads.acquire(mount, client, stat.size) catch {
+        try downloads_mod.writeRejection(stream);
+        return false;
+    };
+    defer slot.release();
+    try response.writeHead(stream, 200, headers[0..header_count], stat.size, keep_alive);
+
+    if (mount.sendfile) {
+        try sendFile(stream, file, stat.size);
+    } else {
+        try copyFile(stream, file, stat.size);
+    }
+    return keep_alive;
+}
+
+/// Key of a file in the ETag cache, from its path below the mount
+pub fn etagKey(buf: []u8, mount: *const config.StaticMountConfig, rel: []const u8) ![]const u8 {
+    const is_index = rel.len == 0 or rel[rel.len - 1] == '/';
+    return std.fmt.bufPrint(buf, "{s}/{s}{s}", .{ mount.root, rel, if (is_index) mount.index else "" });
+}
+
+/// Answer 405 to anything but GET and HEAD. Returns whether the request
+/// was rejected.
+pub fn rejectMethod(stream: std.net.Stream, req: *const request.Request) !bool {
+    if (std.mem.eql(u8, req.method, "GET") or std.mem.eql(u8, req.method, "HEAD")) return false;
+    try response.write(stream, 405, &[_]Header{
+        .{ .name = "Allow", .value = "GET, HEAD" },
+        .{ .name = "Content-Type", .value = "text/plain" },
+    }, "405 Method Not Allowed\n");
+    return true;
+}
+
+/// The file's ETag under the mount's policy, or null when ETags are off
+fn etagFor(etags: *EtagCache, mount: *const config.StaticMountConfig, path: []const u8, file: std.fs.File, stat: std.fs.File.Stat) !?Etag {
+    var etag = Etag{};
+    switch (mount.etag) {
+        .off => return null,
+        .weak => {
+            const text = try std.fmt.bufPrint(&etag.buf, "W/\"{x}-{x}\"", .{ stat.mtime, stat.size });
+            etag.len = text.len;
+        },
+        .strong => {
+            if (etags.get(path, stat)) |cached| return cached;
+            switch (mount.etag_hash) {
+                .sha256 => etag = quoted(&try digestFile(std.crypto.hash.sha2.Sha256, file, stat.size)),
+                .blake3 => etag = quoted(&try digestFile(std.crypto.hash.Blake3, file, stat.size)),
+                .wyhash => {
+                    var hasher = std.hash.Wyhash.init(0);
+                    try hashFile(file, stat.size, &hasher);
+                    var digest: [8]u8 = undefined;
+                    std.mem.writeInt(u64, &digest, hasher.final(), .big);
+                    etag = quoted(&digest);
+                },
+            }
+            etags.put(path, stat, etag);
+        },
+    }
+    return etag;
+}
+
+fn digestFile(comptime Hash: type, file: std.fs.File, size: u64) ![Hash.digest_length]u8 {
+    var hasher = Hash.init(.{});
+    try hashFile(file, size, &hasher);
+    var digest: [Hash.digest_length]u8 = undefined;
+    hasher.final(&digest);
+    return digest;
+}
+
+/// Feed the file to `hasher` without moving its read position
+fn hashFile(file: std.fs.File, size: u64, hasher: anytype) !void {
+    var chunk: [64 * 1024]u8 = undefined;
+    var offset: u64 = 0;
+    while (offset < size) {
+        const n = try file.pread(chunk[0..@min(chunk.len, size - offset)], offset);
+        if (n == 0) return error.UnexpectedEndOfFile;
+        hasher.update(chunk[0..n]);
+        offset += n;
+    }
+}
+
+/// Strong ETag from the first 128 bits of a digest
+fn quoted(digest: []const u8) Etag {
+    var etag = Etag{};
+    const text = std.fmt.bufPrint(&etag.buf, "\"{x}\"", .{digest[0..@min(digest.len, 16)]}) catch unreachable;
+    etag.len = text.len;
+    return etag;
+}
+
+/// Hand the copy to the kernel. std.posix.sendfile itself falls back to
+/// reading and writing where the platform or file type doesn't support it.
+fn sendFile(stream: std.net.Stream, file: std.fs.File, size: u64) !void {
+    var offset: u64 = 0;
+    while (offset < size) {
+        const n = try std.posix.sendfile(stream.handle, file.handle, offset, size - offset, &.{}, &.{}, 0);
+        if (n == 0) return error.UnexpectedEndOfFile;
+        offset += n;
+    }
+}
+
+fn copyFile(stream: std.net.Stream, file: std.fs.File, size: u64) !void {
+    var chunk: [16 * 1024]u8 = undefined;
+    var remaining = size;
+    while (remaining > 0) {
+        const n = try file.read(chunk[0..@min(chunk.len, remaining)]);
+        if (n == 0) return error.UnexpectedEndOfFile;
+        try stream.writeAll(chunk[0..n]);
+        remaining -= n;
+    }
+}
+
+/// Open `rel` under `root`, falling back to the index file for directories.
+/// Fails with `error.IsDir` for a directory path missing its trailing slash.
+fn openFile(root: std.fs.Dir, rel: []const u8, index: []const u8) !std.fs.File {
+    if (rel.len == 0 or rel[rel.len - 1] == '/') {
+        var dir = if (rel.len == 0) try root.openDir(".", .{}) else try root.openDir(rel, .{});
+        defer dir.close();
+        return dir.openFile(index, .{});
+    }
+
+    const file = try root.openFile(rel, .{});
+    errdefer file.close();
+    if ((try file.stat()).kind == .directory) return error.IsDir;
+    return file;
+}
+
+/// Percent-decode the request path below the mount prefix. Rejects dot
+/// segments, empty segments, encoded separators and NUL bytes so the result
+/// stays relative and cannot escape the mount root.
+pub fn relativePath(buf: []u8, prefix: []const u8, path: []const u8) ![]const u8 {
+    const rest = std.mem.trimLeft(u8, path[@min(prefix.len, path.len)..], "/");
+
+    var len: usize = 0;
+    var i: usize = 0;
+    while (i < rest.len) : (len += 1) {
+        if (len == buf.len) return error.NameTooLong;
+        if (rest[i] == '%') {
+            if (i + 2 >= rest.len) return error.InvalidPath;
+            const byte = std.fmt.parseInt(u8, rest[i + 1 .. i + 3], 16) catch return error.InvalidPath;
+            // A separator decoded after the path was split on them
+            if (byte == '/' or byte == '\\') return error.InvalidPath;
+            buf[len] = byte;
+            i += 3;
+        } else {
+            buf[len] = rest[i];
+            i += 1;
+        }
+    }
+    const decoded = buf[0..len];
+
+    if (decoded.len > 0 and decoded[0] == '/') return error.InvalidPath;
+    if (std.mem.indexOfScalar(u8, decoded, 0) != null) return error.InvalidPath;
+    var segments = std.mem.splitScalar(u8, decoded, '/');
+    while (segments.next()) |segment| {
+        // Only a trailing slash, asking for the index, leaves one empty
+        if (segment.len == 0 and segments.peek() != null) return error.InvalidPath;
+        if (std.mem.eql(u8, segment, "..") or std.mem.eql(u8, segment, ".")) return error.InvalidPath;
+        if (std.mem.indexOfScalar(u8, segment, '\\') != null) return error.InvalidPath;
+    }
+    return decoded;
+}
+
+/// Content-Type from the file extension
+pub fn contentType(name: []const u8) []const u8 {
+    const ext = std.fs.path.extension(name);
+    const types = [_]struct { []const u8, []const u8 }{
+        .{ ".html", "text/html; charset=utf-8" },
+        .{ ".htm", "text/html; charset=utf-8" },
+        .{ ".css", "text/css; charset=utf-8" },
+        .{ ".js", "text/javascript; charset=utf-8" },
+        .{ ".mjs", "text/javascript; charset=utf-8" },
+        .{ ".json", "application/json" },
+        .{ ".xml", "application/xml" },
+        .{ ".txt", "text/plain; charset=utf-8" },
+        .{ ".svg", "image/svg+xml" },
+        .{ ".png", "image/png" },
+        .{ ".jpg", "image/jpeg" },
+        .{ ".jpeg", "image/jpeg" },
+        .{ ".gif", "image/gif" },
+        .{ ".webp", "image/webp" },
+        .{ ".avif", "image/avif" },
+        .{ ".ico", "image/x-icon" },
+        .{ ".woff", "font/woff" },
+        .{ ".woff2", "font/woff2" },
+        .{ ".wasm", "application/wasm" },
+        .{ ".pdf", "application/pdf" },
+        .{ ".mp4", "video/mp4" },
+        .{ ".webm", "video/webm" },
+    };
+    for (types) |entry| {
+        if (std.ascii.eqlIgnoreCase(ext, entry[0])) return entry[1];
+    }
+    return "application/octet-stream";
+}
+
+test "relativePath stays below the mount root" {
+    var buf: [256]u8 = undefined;
+    try std.testing.expectEqualStrings("css/site.css", try relativePath(&buf, "/assets", "/assets/css/site.css"));
+    try std.testing.expectEqualStrings("docs/", try relativePath(&buf, "/", "/docs/"));
+    try std.testing.expectEqualStrings("a b.txt", try relativePath(&buf, "/", "/a%20b.txt"));
+    try std.testing.expectEqualStrings("", try relativePath(&buf, "/assets", "/assets"));
+
+    const escapes = [_][]const u8{
+        "/%2Fetc%2Fpasswd",
+        "/%2F..%2F..%2Fetc%2Fpasswd",
+        "/..%2Fsecret",
+        "/a/..%2F..%2Fsecret",
+        "/..%5Csecret",
+        "/../secret",
+        "/a/%2e%2e/%2e%2e/secret",
+        "/a//b",
+        "/a%00.txt",
+        "/a%2",
+        "/a%zz",
+    };
+    for (escapes) |path| {
+        try std.testing.expectError(error.InvalidPath, relativePath(&buf, "/", path));
+    }
+}
@@ -0,0 +1,81 @@
+const std = @import("std");
+const config = @import("../config/config.zig");
+
+/// Bounds how many requests do static file disk work at once. Each
+/// connection has its own thread, so without a bound a burst of lookups in
+/// a large directory or of strong ETag hashing puts every thread on the
+/// disk together; with one, the excess queues and the disk stays busy on
+/// the requests it can finish.
+pub const DiskGate = struct {
+    config: *const config.StaticIoConfig,
+    mutex: std.Thread.Mutex = .{},
+    /// Signalled whenever a turn ends
+    released: std.Thread.Condition = .{},
+    active: u32 = 0,
+    /// Requests that had to wait for a turn
+    queued: std.atomic.Value(u64) = std.atomic.Value(u64).init(0),
+    /// Requests refused after waiting out the queue timeout
+    rejected: std.atomic.Value(u64) = std.atomic.Value(u64).init(0),
+
+    pub fn init(cfg: *const config.StaticIoConfig) DiskGate {
+        return .{ .config = cfg };
+    }
+
+    /// Wait up to the queue timeout for a turn at the disk. Fails with
+    /// `error.DiskBusy`.
+    pub fn acquire(self: *DiskGate) !Turn {
+        const limit = self.config.max_concurrent;
+        if (limit == 0) return .{};
+
+        self.mutex.lock();
+        defer self.mutex.unlock();
+
+        if (self.active >= limit) _ = self.queued.fetchAdd(1, .monotonic);
+        const deadline = std.time.nanoTimestamp() + @as(i128, self.config.queue_timeout_ms) * std.time.ns_per_ms;
+        while (self.active >= limit) {
+            const remaining = deadline - std.time.nanoTimestamp();
+            if (remaining <= 0) {
+                _ = self.rejected.fetchAdd(1, .monotonic);
+                return error.DiskBusy;
+            }
+            self.released.timedWait(&self.mutex, @intCast(remaining)) catch {};
+        }
+        self.active += 1;
+        return .{ .gate = self };
+    }
+
+    fn release(self: *DiskGate) void {
+        self.mutex.lock();
+        defer self.mutex.unlock();
+
+        self.active -= 1;
+        self.released.signal();
+    }
+};
+
+/// A turn at the disk, held until released
+pub const Turn = struct {
+    gate: ?*DiskGate = null,
+
+    /// Later calls do nothing, so a turn given back early can still be
+    /// released by a defer
+    pub fn release(self: *Turn) void {
+        if (self.gate) |gate| gate.release();
+        self.gate = null;
+    }
+};
+
+test "turns beyond the limit wait, then are refused" {
+    const cfg = config.StaticIoConfig{ .max_concurrent = 1, .queue_timeout_ms = 10 };
+    var gate = DiskGate.init(&cfg);
+
+    var first = try gate.acquire();
+    try std.testing.expectError(error.DiskBusy, gate.acquire());
+    try std.testing.expectEqual(@as(u64, 1), gate.rejected.load(.monotonic));
+
+    first.release();
+    first.release();
+    var second = try gate.acquire();
+    defer second.release();
+    try std.testing.expectEqual(@as(u32, 1), gate.active);
+}
@@ -1,31 +1,136 @@
 const std = @import("std");
 const zcrypto = @import("zcrypto");
+const socket = @import("socket.zig");
+
+/// TLS handshake and session counters
+pub const TlsStats = struct {
+    handshakes: std.atomic.Value(u64) = std.atomic.Value(u64).init(0),
+    handshake_failures: std.atomic.Value(u64) = std.atomic.Value(u64).init(0),
+    sessions_active: std.atomic.Value(u64) = std.atomic.Value(u64).init(0),
+};
 
 /// TLS 1.3 server configuration
 pub const TlsServer = struct {
     allocator: std.mem.Allocator,
     cert_path: []const u8,
     key_path: []const u8,
+    /// ALPN protocols offered during the handshake, in preference order
+    alpn_protocols: []const []const u8,
+    stats: TlsStats,
 
     pub fn init(allocator: std.mem.Allocator, cert_path: []const u8, key_path: []const u8) TlsServer {
         return .{
             .allocator = allocator,
             .cert_path = cert_path,
             .key_path = key_path,
+            .alpn_protocols = &[_][]const u8{},
+            .stats = .{},
         };
     }
 
+    pub fn initWithAlpn(
+        allocator: std.mem.Allocator,
+        cert_path: []const u8,
+        key_path: []const u8,
+        alpn_protocols: []const []const u8,
+    ) TlsServer {
+        var server = init(allocator, cert_path, key_path);
+        server.alpn_protocols = alpn_protocols;
+        return server;
+    }
+
+    /// Fails until the handshake is implemented, so nothing binds a TLS
+    /// listener that can only drop its clients
     pub fn loadCertificate(self: *TlsServer) !void {
         // TODO: Implement with zcrypto TLS module
         _ = self;
-        std.debug.print("TODO: Load TLS certificate from {s}\n", .{self.cert_path});
+        return error.TlsHandshakeUnavailable;
     }
 
-    pub fn acceptTls(self: *TlsServer, stream: std.net.Stream) !void {
-        // TODO: Implement TLS handshake with zcrypto
-        _ = self;
+    pub fn acceptTls(self: *TlsServer, stream: std.net.Stream) !TlsSession {
+        // TODO: Implement TLS handshake with zcrypto, negotiating self.alpn_protocols
         _ = stream;
-        std.debug.print("TODO: Perform TLS 1.3 handshake\n", .{});
+        _ = self.stats.handshake_failures.fetchAdd(1, .monotonic);
+        return error.TlsHandshakeUnavailable;
+    }
+};
+
+/// Server side of an established TLS session
+pub const TlsSession = struct {
+    server: *TlsServer,
+    stream: std.net.Stream,
+    /// Protocol selected via ALPN, if any
+    alpn: ?[]const u8 = null,
+
+    pub fn read(self: *TlsSession, buf: []u8) !usize {
+        // TODO: Decrypt application data records with zcrypto
+        _ = self;
+        _ = buf;
+        return error.TlsHandshakeUnavailable;
+    }
+
+    pub fn writeAll(self: *TlsSession, bytes: []const u8) !void {
+        // TODO: Encrypt application data records with zcrypto
+        _ = self;
+        _ = bytes;
+        return error.TlsHandshakeUnavailable;
+    }
+
+    pub fn close(self: *TlsSession) void {
+        _ = self.server.stats.sessions_active.fetchSub(1, .monotonic);
+    }
+};
+
+/// Relays a session's plaintext over a local socket pair, so code written
+/// against std.net.Stream can serve a TLS client unchanged. `local` is the
+/// end that code reads requests from and writes responses to.
+pub const Bridge = struct {
+    session: *TlsSession,
+    local: std.net.Stream,
+    /// The end relayed to and from the session
+    relay: std.net.Stream,
+    thread: std.Thread,
+
+    /// Start relaying; `self` must stay put until `finish`
+    pub fn start(self: *Bridge, session: *TlsSession) !void {
+        const pair = try socket.streamPair();
+        errdefer for (pair) |end| end.close();
+        self.* = .{ .session = session, .local = pair[0], .relay = pair[1], .thread = undefined };
+        self.thread = try std.Thread.spawn(.{}, pump, .{self});
+    }
+
+    /// Close the local end and wait for the relay to drain and stop
+    pub fn finish(self: *Bridge) void {
+        std.posix.shutdown(self.local.handle, .send) catch {};
+        self.thread.join();
+        self.local.close();
+        self.relay.close();
+    }
+
+    fn pump(self: *Bridge) void {
+        self.relayUntilClosed() catch |err| switch (err) {
+            error.ConnectionResetByPeer, error.BrokenPipe => {},
+            else => std.debug.print("✗ TLS relay stopped: {any}\n", .{err}),
+        };
+        // Either side going away ends the exchange for the other
+        std.posix.shutdown(self.relay.handle, .both) catch {};
+    }
+
+    fn relayUntilClosed(self: *Bridge) !void {
+        var buf: [16 * 1024]u8 = undefined;
+        const handles = [_]std.posix.socket_t{ self.session.stream.handle, self.relay.handle };
+        while (true) {
+            const ready = try socket.firstReadable(&handles, 0) orelse continue;
+            if (ready == 0) {
+                const n = try self.session.read(&buf);
+                if (n == 0) return;
+                try self.relay.writeAll(buf[0..n]);
+            } else {
+                const n = try self.relay.read(&buf);
+                if (n == 0) return;
+                try self.session.writeAll(buf[0..n]);
+            }
+        }
     }
 };
 
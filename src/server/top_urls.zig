@@ -0,0 +1,147 @@
+const std = @import("std");
+const config = @import("../config/config.zig");
+
+pub const Item = struct {
+    url: []const u8,
+    requests: u64,
+    bytes: u64,
+};
+
+/// The most requested URLs and the URLs that sent the most bytes over one
+/// interval
+pub const Report = struct {
+    /// Unix milliseconds; both 0 until the first interval has ended
+    start_ms: i64 = 0,
+    end_ms: i64 = 0,
+    /// Requests for URLs beyond `top_max_tracked`, left out of the lists
+    untracked: u64 = 0,
+    by_requests: []const Item = &.{},
+    by_bytes: []const Item = &.{},
+};
+
+const Count = struct {
+    requests: u64 = 0,
+    bytes: u64 = 0,
+};
+
+/// Requests per URL for the current interval, and the report for the last
+/// one. The report is computed when an interval ends, so reading it costs
+/// no more than copying it.
+pub const TopUrls = struct {
+    allocator: std.mem.Allocator,
+    config: *const config.S3CacheConfig,
+    mutex: std.Thread.Mutex = .{},
+    /// Keys are owned
+    counts: std.StringHashMapUnmanaged(Count) = .empty,
+    untracked: u64 = 0,
+    start_ms: i64,
+    /// Owned, URLs included
+    report: Report = .{},
+
+    pub fn init(allocator: std.mem.Allocator, cfg: *const config.S3CacheConfig) TopUrls {
+        return .{ .allocator = allocator, .config = cfg, .start_ms = std.time.milliTimestamp() };
+    }
+
+    pub fn deinit(self: *TopUrls) void {
+        self.clearCounts();
+        self.counts.deinit(self.allocator);
+        freeItems(self.allocator, self.report.by_requests);
+        freeItems(self.allocator, self.report.by_bytes);
+    }
+
+    /// Count a request for `url` that sent `bytes` of the object
+    pub fn record(self: *TopUrls, url: []const u8, bytes: u64) void {
+        self.mutex.lock();
+        defer self.mutex.unlock();
+
+        self.roll(std.time.milliTimestamp());
+        if (self.counts.getPtr(url)) |count| {
+            count.requests += 1;
+            count.bytes += bytes;
+            return;
+        }
+        if (self.counts.count() >= self.config.top_max_tracked) {
+            self.untracked += 1;
+            return;
+        }
+        const key = self.allocator.dupe(u8, url) catch return;
+        self.counts.put(self.allocator, key, .{ .requests = 1, .bytes = bytes }) catch self.allocator.free(key);
+    }
+
+    /// A copy of the last interval's report, allocated with `allocator`
+    pub fn latest(self: *TopUrls, allocator: std.mem.Allocator) !Report {
+        self.mutex.lock();
+        defer self.mutex.unlock();
+
+        self.roll(std.time.milliTimestamp());
+        var report = self.report;
+        report.by_requests = try copyItems(allocator, self.report.by_requests);
+        report.by_bytes = try copyItems(allocator, self.report.by_bytes);
+        return report;
+    }
+
+    /// Replace the report once the interval is over and start counting
+    /// afresh
+    fn roll(self: *TopUrls, now_ms: i64) void {
+        if (now_ms - self.start_ms < @as(i64, self.config.top_interval_s) * std.time.ms_per_s) return;
+
+        const by_requests = self.ranked(.requests) catch return;
+        const by_bytes = self.ranked(.bytes) catch {
+            freeItems(self.allocator, by_requests);
+            return;
+        };
+        freeItems(self.allocator, self.report.by_requests);
+        freeItems(self.allocator, self.report.by_bytes);
+        self.report = .{
+            .start_ms = self.start_ms,
+            .end_ms = now_ms,
+            .untracked = self.untracked,
+            .by_requests = by_requests,
+            .by_bytes = by_bytes,
+        };
+        self.clearCounts();
+        self.untracked = 0;
+        self.start_ms = now_ms;
+    }
+
+    /// The `top_urls` URLs with the highest count of `by`, highest first
+    fn ranked(self: *TopUrls, comptime by: enum { requests, bytes }) ![]const Item {
+        var items: std.ArrayList(Item) = .empty;
+        defer items.deinit(self.allocator);
+        var it = self.counts.iterator();
+        while (it.next()) |e| {
+            try items.append(self.allocator, .{ .url = e.key_ptr.*, .requests = e.value_ptr.requests, .bytes = e.value_ptr.bytes });
+        }
+        std.mem.sort(Item, items.items, {}, struct {
+            fn higher(_: void, a: Item, b: Item) bool {
+                return @field(a, @tagName(by)) > @field(b, @tagName(by));
+            }
+        }.higher);
+        return copyItems(self.allocator, items.items[0..@min(items.items.len, self.config.top_urls)]);
+    }
+
+    fn clearCounts(self: *TopUrls) void {
+        var it = self.counts.keyIterator();
+        while (it.next()) |key| self.allocator.free(key.*);
+        self.counts.clearRetainingCapacity();
+    }
+};
+
+fn copyItems(allocator: std.mem.Allocator, items: []const Item) ![]const Item {
+    const out = try allocator.alloc(Item, items.len);
+    var copied: usize = 0;
+    errdefer {
+        for (out[0..copied]) |item| allocator.free(item.url);
+        allocator.free(out);
+    }
+    for (items, out) |item, *copy| {
+        copy.* = .{ .url = try allocator.dupe(u8, item.url), .requests = item.requests, .bytes = item.bytes };
+        copied += 1;
+    }
+    return out;
+}
+
+fn freeItems(allocator: std.mem.Allocator, items: []const Item) void {
+    for (items) |item| allocator.free(item.url);
+    allocator.free(items);
+}
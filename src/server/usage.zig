@@ -0,0 +1,277 @@
+const std = @import("std");
+const builtin = @import("builtin");
+const config = @import("../config/config.zig");
+const request = @import("request.zig");
+const metrics = @import("metrics.zig");
+
+const Sha256 = std.crypto.hash.sha2.Sha256;
+
+/// Bytes a connection has moved so far, as counted by the kernel
+pub const SocketBytes = struct {
+    received: u64,
+    sent: u64,
+};
+
+/// TCP_INFO on Linux (<netinet/tcp.h>)
+const TCP_INFO = 11;
+
+/// Payload bytes received and sent on a TCP socket, from TCP_INFO. Counts
+/// what actually crossed the wire, whichever part of the server wrote it.
+/// Null where the kernel doesn't report them.
+pub fn socketBytes(handle: std.posix.socket_t) ?SocketBytes {
+    if (builtin.os.tag != .linux) return null;
+    // struct tcp_info up to tcpi_bytes_retrans (Linux 4.19); older kernels
+    // fill less of it and leave the rest zero
+    var info = [_]u8{0} ** 216;
+    std.posix.getsockopt(handle, std.posix.IPPROTO.TCP, TCP_INFO, &info) catch return null;
+    const acked = std.mem.readInt(u64, info[120..128], builtin.cpu.arch.endian());
+    const received = std.mem.readInt(u64, info[128..136], builtin.cpu.arch.endian());
+    const sent = std.mem.readInt(u64, info[200..208], builtin.cpu.arch.endian());
+    const retransmitted = std.mem.readInt(u64, info[208..216], builtin.cpu.arch.endian());
+    return .{
+        .received = received,
+        .sent = if (sent > 0) sent -| retransmitted else acked,
+    };
+}
+
+pub const Counters = struct {
+    requests: u64 = 0,
+    bytes_in: u64 = 0,
+    bytes_out: u64 = 0,
+
+    fn add(self: *Counters, bytes_in: u64, bytes_out: u64) void {
+        self.requests += 1;
+        self.bytes_in += bytes_in;
+        self.bytes_out += bytes_out;
+    }
+};
+
+/// One vhost, route and tenant combination. Vhost and route names point
+/// into the config and the route stats registry, which outlive the usage
+/// tables.
+const Series = struct {
+    vhost: []const u8,
+    route: []const u8,
+    key: [16]u8,
+    key_len: u8,
+
+    fn keyName(self: *const Series) []const u8 {
+        return self.key[0..self.key_len];
+    }
+};
+
+const SeriesContext = struct {
+    pub fn hash(_: SeriesContext, series: Series) u64 {
+        var h = std.hash.Wyhash.init(0);
+        h.update(series.vhost);
+        h.update(&.{0});
+        h.update(series.route);
+        h.update(&.{0});
+        h.update(series.keyName());
+        return h.final();
+    }
+
+    pub fn eql(_: SeriesContext, a: Series, b: Series) bool {
+        return std.mem.eql(u8, a.vhost, b.vhost) and
+            std.mem.eql(u8, a.route, b.route) and
+            std.mem.eql(u8, a.keyName(), b.keyName());
+    }
+};
+
+const SeriesMap = std.HashMapUnmanaged(Series, Counters, SeriesContext, std.hash_map.default_max_load_percentage);
+
+/// Usage for one UTC day
+const Day = struct {
+    /// Days since the Unix epoch
+    day: u64,
+    series: SeriesMap = .empty,
+};
+
+/// Transfer volume per vhost, route and tenant: running totals for
+/// Prometheus and daily rollups for the admin API
+pub const Usage = struct {
+    allocator: std.mem.Allocator,
+    config: *const config.UsageConfig,
+    mutex: std.Thread.Mutex = .{},
+    totals: SeriesMap = .empty,
+    /// Oldest first, at most `retention_days` of them
+    days: std.ArrayList(Day) = .empty,
+
+    pub fn init(allocator: std.mem.Allocator, cfg: *const config.UsageConfig) Usage {
+        return .{ .allocator = allocator, .config = cfg };
+    }
+
+    pub fn deinit(self: *Usage) void {
+        self.totals.deinit(self.allocator);
+        for (self.days.items) |*day| day.series.deinit(self.allocator);
+        self.days.deinit(self.allocator);
+    }
+
+    /// Account one request under `vhost` and `route` (empty when the
+    /// request matched neither), with the bytes its connection moved since
+    /// `before`
+    pub fn record(
+        self: *Usage,
+        vhost: ?*const config.VhostConfig,
+        route: []const u8,
+        req: *const request.Request,
+        handle: std.posix.socket_t,
+        before: ?SocketBytes,
+    ) void {
+        const start = before orelse return;
+        const now = socketBytes(handle) orelse return;
+
+        var series = Series{
+            .vhost = if (vhost) |v| v.hostnames[0] else "",
+            .route = route,
+            .key = undefined,
+            .key_len = 0,
+        };
+        if (self.config.key_header.len > 0) {
+            if (req.header(self.config.key_header)) |value| {
+                var digest: [Sha256.digest_length]u8 = undefined;
+                Sha256.hash(value, &digest, .{});
+                series.key = std.fmt.bytesToHex(digest[0..8], .lower);
+                series.key_len = 16;
+            }
+        }
+        const bytes_in = now.received -| start.received;
+        const bytes_out = now.sent -| start.sent;
+        const day: u64 = @intCast(@divFloor(std.time.timestamp(), std.time.s_per_day));
+
+        self.mutex.lock();
+        defer self.mutex.unlock();
+
+        const today = self.today(day) catch return;
+        // Past the series limit, new tenants share one "other" series
+        if (series.key_len > 0 and !self.totals.contains(series) and self.totals.count() >= self.config.max_series) {
+            @memcpy(series.key[0..5], "other");
+            series.key_len = 5;
+        }
+        self.add(&self.totals, series, bytes_in, bytes_out) catch return;
+        self.add(&today.series, series, bytes_in, bytes_out) catch return;
+    }
+
+    fn add(self: *Usage, map: *SeriesMap, series: Series, bytes_in: u64, bytes_out: u64) !void {
+        const entry = try map.getOrPut(self.allocator, series);
+        if (!entry.found_existing) entry.value_ptr.* = .{};
+        entry.value_ptr.add(bytes_in, bytes_out);
+    }
+
+    /// The rollup for `day`, started when the date changes. Rollups past
+    /// the retention period are dropped.
+    fn today(self: *Usage, day: u64) !*Day {
+        if (self.days.items.len > 0) {
+            const last = &self.days.items[self.days.items.len - 1];
+            if (last.day >= day) return last;
+        }
+        try self.days.append(self.allocator, .{ .day = day });
+        while (self.days.items.len > self.config.retention_days) {
+            var oldest = self.days.orderedRemove(0);
+            oldest.series.deinit(self.allocator);
+        }
+        return &self.days.items[self.days.items.len - 1];
+    }
+
+    pub fn writeMetrics(self: *Usage, m: *metrics.Exposition) !void {
+        self.mutex.lock();
+        defer self.mutex.unlock();
+
+        const families = [_]struct { name: []const u8, help: []const u8 }{
+            .{ .name = "wraith_usage_requests_total", .help = "Requests per vhost, route and key" },
+            .{ .name = "wraith_usage_bytes_in_total", .help = "Bytes received from clients per vhost, route and key" },
+            .{ .name = "wraith_usage_bytes_out_total", .help = "Bytes sent to clients per vhost, route and key" },
+        };
+        for (families, 0..) |family, i| {
+            try m.family(family.name, .counter, family.help);
+            var it = self.totals.iterator();
+            while (it.next()) |entry| {
+                const series = entry.key_ptr;
+                const labels = [_]metrics.Label{
+                    .{ .name = "vhost", .value = series.vhost },
+                    .{ .name = "route", .value = series.route },
+                    .{ .name = "key", .value = series.keyName() },
+                };
+                const counters = entry.value_ptr;
+                try m.sample(family.name, &labels, switch (i) {
+                    0 => counters.requests,
+                    1 => counters.bytes_in,
+                    else => counters.bytes_out,
+                });
+            }
+        }
+    }
+
+    /// Daily rollups for the admin API, newest first, optionally for one
+    /// date ("2025-01-31") or one vhost
+    pub fn rollupsJson(self: *Usage, allocator: std.mem.Allocator, date: ?[]const u8, vhost: ?[]const u8) ![]const u8 {
+        const Entry = struct {
+            vhost: []const u8,
+            route: []const u8,
+            key: []const u8,
+            requests: u64,
+            bytes_in: u64,
+            bytes_out: u64,
+        };
+        const Rollup = struct {
+            date: []const u8,
+            requests: u64 = 0,
+            bytes_in: u64 = 0,
+            bytes_out: u64 = 0,
+            entries: []Entry,
+        };
+
+        var rollups: std.ArrayList(Rollup) = .empty;
+        {
+            self.mutex.lock();
+            defer self.mutex.unlock();
+
+            var i = self.days.items.len;
+            while (i > 0) {
+                i -= 1;
+                const day = &self.days.items[i];
+                const day_date = try dateText(allocator, day.day);
+                if (date) |wanted| {
+                    if (!std.mem.eql(u8, wanted, day_date)) continue;
+                }
+
+                var rollup = Rollup{ .date = day_date, .entries = &.{} };
+                var entries: std.ArrayList(Entry) = .empty;
+                var it = day.series.iterator();
+                while (it.next()) |entry| {
+                    const series = entry.key_ptr;
+                    if (vhost) |wanted| {
+                        if (!std.mem.eql(u8, wanted, series.vhost)) continue;
+                    }
+                    const counters = entry.value_ptr;
+                    rollup.requests += counters.requests;
+                    rollup.bytes_in += counters.bytes_in;
+                    rollup.bytes_out += counters.bytes_out;
+                    try entries.append(allocator, .{
+                        .vhost = try allocator.dupe(u8, series.vhost),
+                        .route = try allocator.dupe(u8, series.route),
+                        .key = try allocator.dupe(u8, series.keyName()),
+                        .requests = counters.requests,
+                        .bytes_in = counters.bytes_in,
+                        .bytes_out = counters.bytes_out,
+                    });
+                }
+                rollup.entries = entries.items;
+                try rollups.append(allocator, rollup);
+            }
+        }
+        return std.json.Stringify.valueAlloc(allocator, .{ .days = rollups.items }, .{});
+    }
+};
+
+/// "2025-01-31"
+fn dateText(allocator: std.mem.Allocator, day: u64) ![]const u8 {
+    const epoch_day: std.time.epoch.EpochDay = .{ .day = @intCast(day) };
+    const year_day = epoch_day.calculateYearDay();
+    const month_day = year_day.calculateMonthDay();
+    return std.fmt.allocPrint(allocator, "{d}-{d:0>2}-{d:0>2}", .{
+        year_day.year,
+        month_day.month.numeric(),
+        month_day.day_index + 1,
+    });
+}
@@ -0,0 +1,147 @@
+const std = @import("std");
+const config = @import("../config/config.zig");
+const signals = @import("../server/signals.zig");
+const upstream_mod = @import("../upstream/manager.zig");
+
+pub const StreamStats = struct {
+    connections_total: std.atomic.Value(u64) = std.atomic.Value(u64).init(0),
+    connections_active: std.atomic.Value(u64) = std.atomic.Value(u64).init(0),
+    /// Bytes received from clients and sent upstream
+    bytes_in: std.atomic.Value(u64) = std.atomic.Value(u64).init(0),
+    /// Bytes received from upstreams and sent to clients
+    bytes_out: std.atomic.Value(u64) = std.atomic.Value(u64).init(0),
+    idle_timeouts: std.atomic.Value(u64) = std.atomic.Value(u64).init(0),
+    upstream_failures: std.atomic.Value(u64) = std.atomic.Value(u64).init(0),
+};
+
+/// Layer-4 TCP proxy for one `[[stream.servers]]` listener
+pub const StreamProxy = struct {
+    config: *const config.StreamServerConfig,
+    group: *upstream_mod.UpstreamGroup,
+    stats: StreamStats,
+
+    pub fn init(cfg: *const config.StreamServerConfig, group: *upstream_mod.UpstreamGroup) StreamProxy {
+        return .{
+            .config = cfg,
+            .group = group,
+            .stats = .{},
+        };
+    }
+
+    /// Thread entry point
+    pub fn run(self: *StreamProxy) void {
+        self.start() catch |err| {
+            std.debug.print("✗ Stream listener {s} failed: {any}\n", .{ self.config.listen, err });
+        };
+    }
+
+    pub fn start(self: *StreamProxy) !void {
+        const addr = try std.net.Address.parseIpAndPort(self.config.listen);
+        var server = try addr.listen(.{
+            .reuse_address = true,
+        });
+        defer server.deinit();
+
+        std.debug.print("✓ Stream proxy listening on {s} -> {s}\n", .{ self.config.listen, self.group.name });
+
+        while (!signals.shouldShutdown()) {
+            const conn = server.accept() catch |err| {
+                if (signals.shouldShutdown()) break;
+                return err;
+            };
+
+            // Stream connections are long-lived, so each gets its own thread
+            const thread = std.Thread.spawn(.{}, handleConnection, .{ self, conn }) catch |err| {
+                std.debug.print("✗ Stream connection thread failed: {any}\n", .{err});
+                conn.stream.close();
+                continue;
+            };
+            thread.detach();
+        }
+    }
+
+    fn handleConnection(self: *StreamProxy, conn: std.net.Server.Connection) void {
+        const client = conn.stream;
+        defer client.close();
+
+        _ = self.stats.connections_total.fetchAdd(1, .monotonic);
+        _ = self.stats.connections_active.fetchAdd(1, .monotonic);
+        defer _ = self.stats.connections_active.fetchSub(1, .monotonic);
+
+        const upstream = self.connectUpstream(conn.address) catch |err| {
+            _ = self.stats.upstream_failures.fetchAdd(1, .monotonic);
+            std.debug.print("✗ Stream upstream {s} unavailable: {any}\n", .{ self.group.name, err });
+            return;
+        };
+        defer self.group.release(upstream.address);
+        defer upstream.stream.close();
+
+        relay(client, upstream.stream, self.config.idle_timeout_ms, &self.stats) catch |err| {
+            std.debug.print("✗ Stream relay error: {any}\n", .{err});
+        };
+    }
+
+    /// Connect to the next server of the group, moving on when one refuses.
+    /// The server stays counted as busy until released.
+    fn connectUpstream(self: *StreamProxy, client: std.net.Address) !Upstream {
+        const attempts = self.group.serverCount();
+        if (attempts == 0) return error.NoUpstreamServers;
+
+        var last_err: anyerror = error.NoUpstreamServers;
+        for (0..attempts) |_| {
+            const addr = self.group.select(client) orelse break;
+            const stream = self.group.connect(addr) catch |err| {
+                self.group.release(addr);
+                last_err = err;
+                continue;
+            };
+            return .{ .stream = stream, .address = addr };
+        }
+        return last_err;
+    }
+
+    const Upstream = struct {
+        stream: std.net.Stream,
+        address: std.net.Address,
+    };
+};
+
+/// Copy bytes in both directions until both sides have closed or the
+/// connection has been idle for `idle_timeout_ms` (0 = never)
+pub fn relay(client: std.net.Stream, upstream: std.net.Stream, idle_timeout_ms: u32, stats: *StreamStats) !void {
+    var fds = [_]std.posix.pollfd{
+        .{ .fd = client.handle, .events = std.posix.POLL.IN, .revents = 0 },
+        .{ .fd = upstream.handle, .events = std.posix.POLL.IN, .revents = 0 },
+    };
+    const peers = [_]std.net.Stream{ upstream, client };
+    const counters = [_]*std.atomic.Value(u64){ &stats.bytes_in, &stats.bytes_out };
+    const timeout: i32 = if (idle_timeout_ms == 0)
+        -1
+    else
+        @intCast(@min(idle_timeout_ms, std.math.maxInt(i32)));
+
+    var buf: [16 * 1024]u8 = undefined;
+    var open: usize = fds.len;
+    while (open > 0) {
+        const ready = try std.posix.poll(&fds, timeout);
+        if (ready == 0) {
+            _ = stats.idle_timeouts.fetchAdd(1, .monotonic);
+            return;
+        }
+
+        for (&fds, 0..) |*fd, i| {
+            if (fd.fd < 0 or fd.revents == 0) continue;
+
+            const n = try std.posix.read(fd.fd, &buf);
+            if (n == 0) {
+                // Half-close: pass the EOF on and keep relaying the other direction
+                std.posix.shutdown(peers[i].handle, .send) catch {};
+                fd.fd = -1;
+                open -= 1;
+                continue;
+            }
+            try peers[i].writeAll(buf[0..n]);
+            _ = counters[i].fetchAdd(n, .monotonic);
+        }
+    }
+}
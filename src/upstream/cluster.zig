@@ -0,0 +1,185 @@
+const std = @import("std");
+const config = @import("../config/config.zig");
+const secret_mod = @import("../config/secret.zig");
+const signals = @import("../server/signals.zig");
+const socket = @import("../server/socket.zig");
+const manager = @import("manager.zig");
+
+const HmacSha256 = std.crypto.auth.hmac.sha2.HmacSha256;
+
+/// Largest UDP payload sent or accepted
+const MAX_MESSAGE = 65_000;
+const MAGIC = "wraith-cluster 1";
+
+/// Gossips upstream health with the other instances of a cluster. Each
+/// message lists the servers the sender's health checks consider down;
+/// receivers avoid those servers until the report times out, so a failure
+/// seen by one instance is acted on by all of them.
+///
+/// Messages are datagrams of the form
+///
+///     <hex HMAC-SHA256 of the rest>\n
+///     wraith-cluster 1 <node_id> <sent_ms>\n
+///     down <group> <address>\n ...
+pub const Cluster = struct {
+    allocator: std.mem.Allocator,
+    config: *const config.ClusterConfig,
+    upstreams: *manager.UpstreamManager,
+    node_id: []const u8,
+    handle: std.posix.socket_t,
+    peers: []std.net.Address,
+    threads: [2]?std.Thread = .{ null, null },
+    /// Shared by the sending and receiving threads
+    secret: secret_mod.Secret,
+    secret_mutex: std.Thread.Mutex = .{},
+
+    pub fn init(allocator: std.mem.Allocator, cfg: *const config.ClusterConfig, upstreams: *manager.UpstreamManager) !Cluster {
+        const listen_addr = try std.net.Address.parseIpAndPort(cfg.listen);
+        const peers = try allocator.alloc(std.net.Address, cfg.peers.len);
+        errdefer allocator.free(peers);
+        for (cfg.peers, peers) |text, *peer| peer.* = try std.net.Address.parseIpAndPort(text);
+
+        const posix = std.posix;
+        const handle = try posix.socket(listen_addr.any.family, posix.SOCK.DGRAM | posix.SOCK.CLOEXEC, posix.IPPROTO.UDP);
+        errdefer posix.close(handle);
+        try posix.setsockopt(handle, posix.SOL.SOCKET, posix.SO.REUSEADDR, std.mem.asBytes(&@as(c_int, 1)));
+        try posix.bind(handle, &listen_addr.any, listen_addr.getOsSockLen());
+        // Wake up regularly to notice shutdown
+        try socket.setTimeouts(handle, 500);
+
+        return .{
+            .allocator = allocator,
+            .config = cfg,
+            .upstreams = upstreams,
+            .node_id = if (cfg.node_id.len > 0) cfg.node_id else cfg.listen,
+            .handle = handle,
+            .peers = peers,
+            .secret = .{ .source = cfg.secret },
+        };
+    }
+
+    pub fn deinit(self: *Cluster) void {
+        for (self.threads) |thread| {
+            if (thread) |t| t.join();
+        }
+        std.posix.close(self.handle);
+        self.allocator.free(self.peers);
+        self.secret.deinit(self.allocator);
+    }
+
+    /// Start the sending and receiving threads; they stop on shutdown
+    pub fn start(self: *Cluster) !void {
+        self.threads[0] = try std.Thread.spawn(.{}, gossipLoop, .{self});
+        self.threads[1] = try std.Thread.spawn(.{}, receiveLoop, .{self});
+        std.debug.print("✓ Cluster node {s} listening on {s} with {d} peer(s)\n", .{ self.node_id, self.config.listen, self.peers.len });
+    }
+
+    fn gossipLoop(self: *Cluster) void {
+        while (!signals.shouldShutdown()) {
+            self.broadcast() catch |err| {
+                std.debug.print("✗ Cluster gossip failed: {any}\n", .{err});
+            };
+            var remaining: u64 = self.config.gossip_interval_ms;
+            while (remaining > 0 and !signals.shouldShutdown()) {
+                const step = @min(remaining, 100);
+                std.Thread.sleep(step * std.time.ns_per_ms);
+                remaining -= step;
+            }
+        }
+    }
+
+    /// Send this instance's view to every peer. A message with no servers
+    /// still goes out, so peers hear that everything recovered.
+    fn broadcast(self: *Cluster) !void {
+        const message = try self.encode();
+        defer self.allocator.free(message);
+
+        for (self.peers) |peer| {
+            _ = std.posix.sendto(self.handle, message, 0, &peer.any, peer.getOsSockLen()) catch |err| {
+                std.debug.print("✗ Cluster peer {f} unreachable: {any}\n", .{ peer, err });
+            };
+        }
+    }
+
+    fn encode(self: *Cluster) ![]u8 {
+        var body: std.ArrayList(u8) = .empty;
+        defer body.deinit(self.allocator);
+        try body.print(self.allocator, "{s} {s} {d}\n", .{ MAGIC, self.node_id, std.time.milliTimestamp() });
+
+        for (self.upstreams.groups.items) |group| {
+            const down = try group.unhealthyServers(self.allocator);
+            defer self.allocator.free(down);
+            for (down) |address| {
+                const line_start = body.items.len;
+                try body.print(self.allocator, "down {s} {f}\n", .{ group.name, address });
+                // Whatever doesn't fit waits for the groups to recover
+                if (body.items.len + 2 * HmacSha256.mac_length + 1 > MAX_MESSAGE) {
+                    body.shrinkRetainingCapacity(line_start);
+                    break;
+                }
+            }
+        }
+
+        var mac: [HmacSha256.mac_length]u8 = undefined;
+        try self.sign(&mac, body.items);
+        return std.fmt.allocPrint(self.allocator, "{s}\n{s}", .{ std.fmt.bytesToHex(mac, .lower), body.items });
+    }
+
+    fn receiveLoop(self: *Cluster) void {
+        var buf: [MAX_MESSAGE]u8 = undefined;
+        while (!signals.shouldShutdown()) {
+            const n = std.posix.recvfrom(self.handle, &buf, 0, null, null) catch |err| switch (err) {
+                error.WouldBlock => continue,
+                else => {
+                    std.debug.print("✗ Cluster receive failed: {any}\n", .{err});
+                    continue;
+                },
+            };
+            self.apply(buf[0..n]) catch |err| {
+                std.debug.print("✗ Cluster message rejected: {any}\n", .{err});
+            };
+        }
+    }
+
+    fn sign(self: *Cluster, mac: *[HmacSha256.mac_length]u8, body: []const u8) !void {
+        self.secret_mutex.lock();
+        defer self.secret_mutex.unlock();
+        HmacSha256.create(mac, body, try self.secret.get(self.allocator, std.time.milliTimestamp()));
+    }
+
+    /// Verify a peer's message and mark the servers it reports down
+    fn apply(self: *Cluster, message: []const u8) !void {
+        const newline = std.mem.indexOfScalar(u8, message, '\n') orelse return error.MalformedMessage;
+        if (newline != 2 * HmacSha256.mac_length) return error.MalformedMessage;
+        var mac: [HmacSha256.mac_length]u8 = undefined;
+        _ = std.fmt.hexToBytes(&mac, message[0..newline]) catch return error.MalformedMessage;
+
+        const body = message[newline + 1 ..];
+        var expected: [HmacSha256.mac_length]u8 = undefined;
+        try self.sign(&expected, body);
+        if (!std.crypto.timing_safe.eql([HmacSha256.mac_length]u8, mac, expected)) return error.BadSignature;
+
+        var lines = std.mem.splitScalar(u8, body, '\n');
+        const header = lines.next() orelse return error.MalformedMessage;
+        if (!std.mem.startsWith(u8, header, MAGIC ++ " ")) return error.UnsupportedVersion;
+        var fields = std.mem.tokenizeScalar(u8, header[MAGIC.len..], ' ');
+        const node_id = fields.next() orelse return error.MalformedMessage;
+        const sent_ms = std.fmt.parseInt(i64, fields.next() orelse return error.MalformedMessage, 10) catch return error.MalformedMessage;
+        if (std.mem.eql(u8, node_id, self.node_id)) return;
+
+        // Stale or replayed messages say nothing about the present
+        const now_ms = std.time.milliTimestamp();
+        if (@abs(now_ms - sent_ms) > self.config.peer_timeout_ms) return error.StaleMessage;
+
+        const until_ms = sent_ms + self.config.peer_timeout_ms;
+        while (lines.next()) |line| {
+            var parts = std.mem.tokenizeScalar(u8, line, ' ');
+            const kind = parts.next() orelse continue;
+            if (!std.mem.eql(u8, kind, "down")) continue;
+            const group_name = parts.next() orelse continue;
+            const address = std.net.Address.parseIpAndPort(parts.next() orelse continue) catch continue;
+            const group = self.upstreams.get(group_name) orelse continue;
+            group.markPeerDown(address, until_ms);
+        }
+    }
+};
@@ -0,0 +1,196 @@
+const std = @import("std");
+const config = @import("../config/config.zig");
+
+/// How a finished request should adjust the limit
+pub const Result = enum {
+    /// The upstream answered
+    success,
+    /// The upstream failed or timed out: a sign of overload
+    dropped,
+    /// Ended for reasons unrelated to the upstream's load, e.g. the client left
+    ignore,
+};
+
+/// A request waiting for a slot, owned by the waiting thread
+const Waiter = struct {
+    priority: config.RequestPriority,
+    admitted: bool = false,
+    /// Pushed out of a full queue by a higher-priority request
+    evicted: bool = false,
+};
+
+/// Learns how many requests an upstream group can serve at once from
+/// response latency and turns away requests beyond that limit. Requests
+/// over the limit may wait in a queue that admits higher priorities first.
+pub const ConcurrencyLimiter = struct {
+    allocator: std.mem.Allocator,
+    config: *const config.AdaptiveConcurrencyConfig,
+    mutex: std.Thread.Mutex = .{},
+    /// Signalled when waiters are admitted or evicted
+    changed: std.Thread.Condition = .{},
+    limit: f64,
+    in_flight: u32 = 0,
+    /// In arrival order
+    queue: std.ArrayList(*Waiter) = .empty,
+    /// Long-term average latency; the baseline the gradient compares against
+    baseline_ms: f64 = 0,
+    /// Requests turned away because the limit was reached
+    rejected: std.atomic.Value(u64) = std.atomic.Value(u64).init(0),
+
+    pub fn init(allocator: std.mem.Allocator, cfg: *const config.AdaptiveConcurrencyConfig) ConcurrencyLimiter {
+        return .{
+            .allocator = allocator,
+            .config = cfg,
+            .limit = @floatFromInt(cfg.initial_limit),
+        };
+    }
+
+    pub fn deinit(self: *ConcurrencyLimiter) void {
+        self.queue.deinit(self.allocator);
+    }
+
+    /// Claim a slot, waiting up to the queue timeout when the upstream is at
+    /// its limit; false when the request is rejected
+    pub fn acquire(self: *ConcurrencyLimiter, priority: config.RequestPriority) bool {
+        self.mutex.lock();
+        defer self.mutex.unlock();
+
+        if (self.fits(priority) and !self.queuedAhead(priority)) {
+            self.in_flight += 1;
+            return true;
+        }
+        if (self.config.queue_timeout_ms == 0 or !self.makeRoom(priority)) return self.reject();
+
+        var waiter = Waiter{ .priority = priority };
+        self.queue.append(self.allocator, &waiter) catch return self.reject();
+
+        const deadline = std.time.milliTimestamp() + self.config.queue_timeout_ms;
+        while (!waiter.admitted and !waiter.evicted) {
+            const remaining = deadline - std.time.milliTimestamp();
+            if (remaining <= 0) break;
+            self.changed.timedWait(&self.mutex, @as(u64, @intCast(remaining)) * std.time.ns_per_ms) catch {};
+        }
+        if (waiter.admitted) return true;
+        if (!waiter.evicted) self.dequeue(&waiter);
+        return self.reject();
+    }
+
+    fn reject(self: *ConcurrencyLimiter) bool {
+        _ = self.rejected.fetchAdd(1, .monotonic);
+        return false;
+    }
+
+    /// Low-priority requests may only use a share of the limit, leaving
+    /// headroom for everything else
+    fn fits(self: *const ConcurrencyLimiter, priority: config.RequestPriority) bool {
+        var capacity = @floor(self.limit);
+        if (priority == .low) capacity = @max(1, @floor(capacity * self.config.low_priority_share));
+        return @as(f64, @floatFromInt(self.in_flight)) < capacity;
+    }
+
+    /// Whether a queued request of the same or higher priority is waiting
+    fn queuedAhead(self: *const ConcurrencyLimiter, priority: config.RequestPriority) bool {
+        for (self.queue.items) |waiter| {
+            if (@intFromEnum(waiter.priority) <= @intFromEnum(priority)) return true;
+        }
+        return false;
+    }
+
+    /// Ensure the queue has space, evicting the newest waiter of the lowest
+    /// priority below `priority`; false when the request should be rejected
+    fn makeRoom(self: *ConcurrencyLimiter, priority: config.RequestPriority) bool {
+        if (self.queue.items.len < self.config.max_queue) return true;
+
+        var victim: ?usize = null;
+        for (self.queue.items, 0..) |waiter, i| {
+            if (@intFromEnum(waiter.priority) <= @intFromEnum(priority)) continue;
+            if (victim) |v| {
+                if (@intFromEnum(waiter.priority) < @intFromEnum(self.queue.items[v].priority)) continue;
+            }
+            victim = i;
+        }
+        const index = victim orelse return false;
+        self.queue.items[index].evicted = true;
+        _ = self.queue.orderedRemove(index);
+        self.changed.broadcast();
+        return true;
+    }
+
+    fn dequeue(self: *ConcurrencyLimiter, waiter: *Waiter) void {
+        for (self.queue.items, 0..) |queued, i| {
+            if (queued == waiter) {
+                _ = self.queue.orderedRemove(i);
+                return;
+            }
+        }
+    }
+
+    /// Hand free slots to waiters, highest priority first, then oldest
+    fn admitWaiters(self: *ConcurrencyLimiter) void {
+        var admitted = false;
+        while (true) {
+            var best: ?usize = null;
+            for (self.queue.items, 0..) |waiter, i| {
+                if (!self.fits(waiter.priority)) continue;
+                if (best) |b| {
+                    if (@intFromEnum(waiter.priority) >= @intFromEnum(self.queue.items[b].priority)) continue;
+                }
+                best = i;
+            }
+            const index = best orelse break;
+            self.queue.items[index].admitted = true;
+            _ = self.queue.orderedRemove(index);
+            self.in_flight += 1;
+            admitted = true;
+        }
+        if (admitted) self.changed.broadcast();
+    }
+
+    /// Return a slot and feed the request's latency to the controller
+    pub fn release(self: *ConcurrencyLimiter, latency_ms: u32, result: Result) void {
+        self.mutex.lock();
+        defer self.mutex.unlock();
+
+        const in_flight = self.in_flight;
+        self.in_flight -|= 1;
+        defer self.admitWaiters();
+        if (result == .ignore) return;
+
+        const cfg = self.config;
+        const sample: f64 = @floatFromInt(@max(latency_ms, 1));
+        const next = switch (cfg.algorithm) {
+            .aimd => self.aimd(sample, result, in_flight),
+            .gradient => self.gradient(sample, result),
+        };
+        self.limit = std.math.clamp(next, @as(f64, @floatFromInt(cfg.min_limit)), @as(f64, @floatFromInt(cfg.max_limit)));
+    }
+
+    /// Grow by one while the limit is in use and responses are fast enough;
+    /// shrink by the backoff ratio on failures or slow responses
+    fn aimd(self: *ConcurrencyLimiter, sample: f64, result: Result, in_flight: u32) f64 {
+        const threshold: f64 = @floatFromInt(self.config.latency_threshold_ms);
+        if (result == .dropped or sample > threshold) return self.limit * self.config.backoff_ratio;
+        // An idle limit says nothing about what the upstream could take
+        if (@as(f64, @floatFromInt(in_flight)) * 2 < self.limit) return self.limit;
+        return self.limit + 1;
+    }
+
+    /// Scale the limit by how the sample compares with the long-term
+    /// latency, leaving headroom of sqrt(limit) for queueing
+    fn gradient(self: *ConcurrencyLimiter, sample: f64, result: Result) f64 {
+        if (self.baseline_ms == 0) self.baseline_ms = sample;
+        // Slow drift so the baseline follows real changes but not spikes
+        self.baseline_ms = self.baseline_ms * 0.99 + sample * 0.01;
+
+        const ratio = if (result == .dropped) 0.5 else std.math.clamp(self.baseline_ms / sample, 0.5, 1.0);
+        const estimate = self.limit * ratio + @sqrt(self.limit);
+        const smoothing = self.config.smoothing;
+        return self.limit * (1 - smoothing) + estimate * smoothing;
+    }
+
+    pub fn currentLimit(self: *ConcurrencyLimiter) u32 {
+        self.mutex.lock();
+        defer self.mutex.unlock();
+        return @intFromFloat(@floor(self.limit));
+    }
+};
@@ -0,0 +1,197 @@
+const std = @import("std");
+const config = @import("../config/config.zig");
+const manager = @import("manager.zig");
+const signals = @import("../server/signals.zig");
+const message = @import("../dns/message.zig");
+const resolver_mod = @import("../dns/resolver.zig");
+
+const Srv = struct {
+    priority: u16,
+    weight: u16,
+    port: u16,
+    target: []const u8,
+};
+
+/// Keeps an upstream group in sync with the SRV records of a DNS name, as
+/// served by Consul or for Kubernetes headless Services
+pub const DnsWatcher = struct {
+    allocator: std.mem.Allocator,
+    group: *manager.UpstreamGroup,
+    config: *const config.DnsDiscoveryConfig,
+    /// Settings `resolver` refers to, so a watcher never moves once
+    /// initialized
+    dns: config.DnsConfig,
+    resolver: resolver_mod.Resolver,
+    /// Nameservers read from /etc/resolv.conf when none are configured
+    system_nameservers: []const []const u8 = &.{},
+
+    pub fn init(
+        self: *DnsWatcher,
+        allocator: std.mem.Allocator,
+        group: *manager.UpstreamGroup,
+        cfg: *const config.DnsDiscoveryConfig,
+    ) !void {
+        const system = if (cfg.nameservers.len == 0) try systemNameservers(allocator) else &[_][]const u8{};
+        self.* = .{
+            .allocator = allocator,
+            .group = group,
+            .config = cfg,
+            .dns = .{
+                .upstreams = if (system.len > 0) system else cfg.nameservers,
+                .timeout_ms = cfg.timeout_ms,
+                // Every refresh should see the records as they are now
+                .cache = .{ .enabled = false },
+            },
+            .resolver = undefined,
+            .system_nameservers = system,
+        };
+        self.resolver = resolver_mod.Resolver.init(allocator, &self.dns);
+    }
+
+    pub fn deinit(self: *DnsWatcher) void {
+        self.resolver.deinit();
+        for (self.system_nameservers) |nameserver| self.allocator.free(nameserver);
+        self.allocator.free(self.system_nameservers);
+    }
+
+    /// Thread entry point
+    pub fn run(self: *DnsWatcher) void {
+        std.debug.print("✓ Watching SRV records of {s} for upstream {s}\n", .{ self.config.name, self.group.name });
+
+        while (!signals.shouldShutdown()) {
+            self.refresh() catch |err| {
+                // Keep the last known servers until the records come back
+                std.debug.print("✗ DNS discovery for {s} failed: {any}\n", .{ self.group.name, err });
+            };
+            sleepUntilShutdown(self.config.refresh_interval_ms);
+        }
+    }
+
+    /// Look up the SRV records and replace the group's servers. Records
+    /// of the lowest priority are the primaries and all others backups;
+    /// each record's weight and port carry over to its server.
+    pub fn refresh(self: *DnsWatcher) !void {
+        var arena = std.heap.ArenaAllocator.init(self.allocator);
+        defer arena.deinit();
+        const allocator = arena.allocator();
+
+        const query = try message.buildQuery(allocator, self.config.name, .SRV);
+        const answer = try self.resolver.resolve(allocator, query);
+        const header = try message.Header.parse(answer);
+        if (header.rcode() != message.RCODE_NOERROR) return error.DnsLookupFailed;
+
+        var records: std.ArrayList(Srv) = .empty;
+        var it = try message.RecordIterator.init(answer);
+        while (try it.next()) |record| {
+            if (record.additional or record.rtype != @intFromEnum(message.RecordType.SRV)) continue;
+            if (record.rdata.len < 7) return error.InvalidSrvRecord;
+            var target_buf: [message.MAX_NAME_LEN]u8 = undefined;
+            const target = try message.readName(answer, record.rdata_offset + 6, &target_buf);
+            try records.append(allocator, .{
+                .priority = std.mem.readInt(u16, record.rdata[0..2], .big),
+                .weight = std.mem.readInt(u16, record.rdata[2..4], .big),
+                .port = std.mem.readInt(u16, record.rdata[4..6], .big),
+                .target = try allocator.dupe(u8, target.text),
+            });
+        }
+        if (records.items.len == 0) return error.NoSrvRecords;
+
+        var primary_priority: u16 = std.math.maxInt(u16);
+        for (records.items) |srv| primary_priority = @min(primary_priority, srv.priority);
+
+        var servers: std.ArrayList(manager.Server) = .empty;
+        for (records.items) |srv| {
+            // A target of "." means the service isn't offered there (RFC 2782)
+            if (srv.target.len == 0) continue;
+            const template = manager.Server{
+                .address = undefined,
+                // Weight 0 only makes a server unlikely to be picked, not excluded
+                .weight = @max(srv.weight, 1),
+                .backup = srv.priority != primary_priority,
+            };
+            const before = servers.items.len;
+            try appendAdditional(allocator, &servers, answer, srv, template);
+            if (servers.items.len > before) continue;
+
+            const list = std.net.getAddressList(allocator, srv.target, srv.port) catch |err| {
+                std.debug.print("✗ Resolving SRV target {s} for upstream {s} failed: {any}\n", .{ srv.target, self.group.name, err });
+                continue;
+            };
+            defer list.deinit();
+            for (list.addrs) |address| {
+                var server = template;
+                server.address = address;
+                try servers.append(allocator, server);
+            }
+        }
+
+        const previous = self.group.serverCount();
+        try self.group.replaceServers(servers.items);
+        if (previous != servers.items.len) {
+            std.debug.print("✓ Upstream {s} now has {d} servers from SRV records\n", .{ self.group.name, servers.items.len });
+        }
+    }
+};
+
+/// Servers for the addresses of `srv`'s target that came in the answer's
+/// additional section, which saves a lookup per target
+fn appendAdditional(
+    allocator: std.mem.Allocator,
+    servers: *std.ArrayList(manager.Server),
+    answer: []const u8,
+    srv: Srv,
+    template: manager.Server,
+) !void {
+    var it = try message.RecordIterator.init(answer);
+    while (try it.next()) |record| {
+        if (!record.additional) continue;
+        var owner_buf: [message.MAX_NAME_LEN]u8 = undefined;
+        const owner = try message.readName(answer, record.name_offset, &owner_buf);
+        if (!std.mem.eql(u8, owner.text, srv.target)) continue;
+
+        var server = template;
+        switch (record.rtype) {
+            @intFromEnum(message.RecordType.A) => {
+                if (record.rdata.len != 4) continue;
+                server.address = std.net.Address.initIp4(record.rdata[0..4].*, srv.port);
+            },
+            @intFromEnum(message.RecordType.AAAA) => {
+                if (record.rdata.len != 16) continue;
+                server.address = std.net.Address.initIp6(record.rdata[0..16].*, srv.port, 0, 0);
+            },
+            else => continue,
+        }
+        try servers.append(allocator, server);
+    }
+}
+
+/// "nameserver" lines of /etc/resolv.conf as ip:port; owned by the caller
+fn systemNameservers(allocator: std.mem.Allocator) ![]const []const u8 {
+    const text = std.fs.cwd().readFileAlloc(allocator, "/etc/resolv.conf", 64 * 1024) catch return error.NoNameservers;
+    defer allocator.free(text);
+
+    var list: std.ArrayList([]const u8) = .empty;
+    errdefer {
+        for (list.items) |nameserver| allocator.free(nameserver);
+        list.deinit(allocator);
+    }
+    var lines = std.mem.splitScalar(u8, text, '\n');
+    while (lines.next()) |line| {
+        var fields = std.mem.tokenizeAny(u8, line, " \t\r");
+        const keyword = fields.next() orelse continue;
+        if (!std.mem.eql(u8, keyword, "nameserver")) continue;
+        const address = std.net.Address.parseIp(fields.next() orelse continue, 53) catch continue;
+        try list.append(allocator, try std.fmt.allocPrint(allocator, "{f}", .{address}));
+    }
+    if (list.items.len == 0) return error.NoNameservers;
+    return list.toOwnedSlice(allocator);
+}
+
+fn sleepUntilShutdown(interval_ms: u32) void {
+    var remaining = interval_ms;
+    while (remaining > 0 and !signals.shouldShutdown()) {
+        const step = @min(remaining, 100);
+        std.Thread.sleep(@as(u64, step) * std.time.ns_per_ms);
+        remaining -= step;
+    }
+}
@@ -0,0 +1,232 @@
+const std = @import("std");
+const config = @import("../config/config.zig");
+const response = @import("../server/response.zig");
+const router = @import("../routing/router.zig");
+const signals = @import("../server/signals.zig");
+const events = @import("../server/events.zig");
+
+/// Largest container list accepted from the Docker API
+const MAX_RESPONSE_SIZE = 4 * 1024 * 1024;
+
+/// Container labels recognised by the provider
+pub const LABEL_ENABLE = "wraith.enable";
+pub const LABEL_HOST = "wraith.host";
+pub const LABEL_PORT = "wraith.port";
+pub const LABEL_PATH = "wraith.path";
+
+/// Route generated from a running container's labels
+pub const DockerRoute = struct {
+    container: []const u8,
+    host: []const u8,
+    path: []const u8,
+    address: std.net.Address,
+};
+
+/// Builds routes from the labels of running containers by polling the
+/// Docker Engine API over its Unix socket
+pub const DockerProvider = struct {
+    allocator: std.mem.Allocator,
+    config: *const config.DockerConfig,
+    mutex: std.Thread.Mutex,
+    /// Owns the current route snapshot
+    arena: std.heap.ArenaAllocator,
+    routes: []const DockerRoute,
+    /// Routes that appear or disappear are published here once set
+    events: ?*events.EventBus = null,
+
+    pub fn init(allocator: std.mem.Allocator, cfg: *const config.DockerConfig) DockerProvider {
+        return .{
+            .allocator = allocator,
+            .config = cfg,
+            .mutex = .{},
+            .arena = std.heap.ArenaAllocator.init(allocator),
+            .routes = &.{},
+        };
+    }
+
+    pub fn deinit(self: *DockerProvider) void {
+        self.arena.deinit();
+    }
+
+    /// Thread entry point
+    pub fn run(self: *DockerProvider) void {
+        std.debug.print("✓ Watching Docker containers via {s}\n", .{self.config.socket_path});
+
+        while (!signals.shouldShutdown()) {
+            self.refresh() catch |err| {
+                // Keep serving the last known routes until the daemon recovers
+                std.debug.print("✗ Docker discovery failed: {any}\n", .{err});
+            };
+
+            var remaining = self.config.refresh_interval_ms;
+            while (remaining > 0 and !signals.shouldShutdown()) {
+                const step = @min(remaining, 100);
+                std.Thread.sleep(@as(u64, step) * std.time.ns_per_ms);
+                remaining -= step;
+            }
+        }
+    }
+
+    /// Return the upstream address of the most specific container route
+    pub fn match(self: *DockerProvider, host: []const u8, path: []const u8) ?std.net.Address {
+        self.mutex.lock();
+        defer self.mutex.unlock();
+
+        var best: ?*const DockerRoute = null;
+        for (self.routes) |*route| {
+            if (!router.hostMatches(route.host, host) or !router.pathMatches(route.path, path)) continue;
+            if (best) |current| {
+                if (route.path.len <= current.path.len) continue;
+            }
+            best = route;
+        }
+        return if (best) |route| route.address else null;
+    }
+
+    /// List running containers and rebuild the route snapshot
+    pub fn refresh(self: *DockerProvider) !void {
+        var next = std.heap.ArenaAllocator.init(self.allocator);
+        errdefer next.deinit();
+        const allocator = next.allocator();
+
+        const body = try self.listContainers(allocator);
+        const parsed = try std.json.parseFromSliceLeaky(std.json.Value, allocator, body, .{});
+        if (parsed != .array) return error.InvalidDockerResponse;
+
+        var routes: std.ArrayList(DockerRoute) = .empty;
+        for (parsed.array.items) |container| {
+            const route = self.routeFor(container) catch |err| {
+                std.debug.print("✗ Ignoring container {s}: {any}\n", .{ containerName(container), err });
+                continue;
+            } orelse continue;
+            try routes.append(allocator, route);
+        }
+
+        // Only this thread replaces the snapshot, so it can be read unlocked
+        if (self.events) |bus| {
+            for (routes.items) |*route| {
+                if (!containsRoute(self.routes, route)) publishRoute(bus, .route_added, route);
+            }
+            for (self.routes) |*route| {
+                if (!containsRoute(routes.items, route)) publishRoute(bus, .route_removed, route);
+            }
+        }
+
+        self.mutex.lock();
+        const previous = self.routes.len;
+        self.arena.deinit();
+        self.arena = next;
+        self.routes = routes.items;
+        self.mutex.unlock();
+
+        if (previous != routes.items.len) {
+            std.debug.print("✓ Docker discovery: {d} container routes\n", .{routes.items.len});
+        }
+    }
+
+    /// Build a route from a container's labels; null when the container is not exposed
+    fn routeFor(self: *DockerProvider, container: std.json.Value) !?DockerRoute {
+        if (container != .object) return error.InvalidDockerResponse;
+        const labels = objectField(container, "Labels") orelse return null;
+
+        if (stringField(labels, LABEL_ENABLE)) |enable| {
+            if (std.mem.eql(u8, enable, "false")) return null;
+        }
+        const host = stringField(labels, LABEL_HOST) orelse return null;
+        const path = stringField(labels, LABEL_PATH) orelse "/";
+        const port_label = stringField(labels, LABEL_PORT) orelse "80";
+        const port = std.fmt.parseInt(u16, port_label, 10) catch return error.InvalidPortLabel;
+
+        const ip = self.containerIp(container) orelse return error.NoContainerAddress;
+        const address = std.net.Address.parseIp(ip, port) catch return error.NoContainerAddress;
+
+        return .{
+            .container = containerName(container),
+            .host = host,
+            .path = path,
+            .address = address,
+        };
+    }
+
+    /// Address on the configured network, or on the first attached network
+    fn containerIp(self: *DockerProvider, container: std.json.Value) ?[]const u8 {
+        const settings = objectField(container, "NetworkSettings") orelse return null;
+        const networks = objectField(settings, "Networks") orelse return null;
+
+        if (self.config.network.len > 0) {
+            const network = objectField(networks, self.config.network) orelse return null;
+            return nonEmpty(stringField(network, "IPAddress"));
+        }
+
+        var it = networks.object.iterator();
+        while (it.next()) |entry| {
+            if (entry.value_ptr.* != .object) continue;
+            if (nonEmpty(stringField(entry.value_ptr.*, "IPAddress"))) |ip| return ip;
+        }
+        return null;
+    }
+
+    fn listContainers(self: *DockerProvider, allocator: std.mem.Allocator) ![]const u8 {
+        const stream = try std.net.connectUnixSocket(self.config.socket_path);
+        defer stream.close();
+
+        // HTTP/1.0 keeps the daemon from chunking and closes after the body
+        try stream.writeAll("GET /containers/json HTTP/1.0\r\nHost: docker\r\n\r\n");
+
+        var data: std.ArrayList(u8) = .empty;
+        var chunk: [16 * 1024]u8 = undefined;
+        while (true) {
+            const n = try stream.read(&chunk);
+            if (n == 0) break;
+            if (data.items.len + n > MAX_RESPONSE_SIZE) return error.ResponseTooLarge;
+            try data.appendSlice(allocator, chunk[0..n]);
+        }
+
+        var headers: [64]response.Header = undefined;
+        const head = try response.parse(data.items, &headers);
+        if (head.status != 200) return error.DockerApiError;
+        return data.items[head.head_len..];
+    }
+};
+
+fn containsRoute(routes: []const DockerRoute, wanted: *const DockerRoute) bool {
+    for (routes) |*route| {
+        if (std.mem.eql(u8, route.container, wanted.container) and std.mem.eql(u8, route.host, wanted.host) and
+            std.mem.eql(u8, route.path, wanted.path) and route.address.eql(wanted.address)) return true;
+    }
+    return false;
+}
+
+fn publishRoute(bus: *events.EventBus, kind: config.EventKind, route: *const DockerRoute) void {
+    var subject_buf: [256]u8 = undefined;
+    const subject = std.fmt.bufPrint(&subject_buf, "{s}{s}", .{ route.host, route.path }) catch route.host;
+    const verb = if (kind == .route_added) "now routes" else "no longer routes";
+    bus.publish(kind, subject, "Container {s} {s} {s}{s} to {f}", .{ route.container, verb, route.host, route.path, route.address });
+}
+
+fn objectField(value: std.json.Value, name: []const u8) ?std.json.Value {
+    if (value != .object) return null;
+    const field = value.object.get(name) orelse return null;
+    return if (field == .object) field else null;
+}
+
+fn stringField(value: std.json.Value, name: []const u8) ?[]const u8 {
+    if (value != .object) return null;
+    const field = value.object.get(name) orelse return null;
+    return if (field == .string) field.string else null;
+}
+
+fn nonEmpty(value: ?[]const u8) ?[]const u8 {
+    const s = value orelse return null;
+    return if (s.len > 0) s else null;
+}
+
+/// First container name without Docker's leading slash
+fn containerName(container: std.json.Value) []const u8 {
+    if (container != .object) return "?";
+    const names = container.object.get("Names") orelse return "?";
+    if (names != .array or names.array.items.len == 0) return "?";
+    const name = names.array.items[0];
+    if (name != .string) return "?";
+    return std.mem.trimLeft(u8, name.string, "/");
+}
@@ -0,0 +1,151 @@
+const std = @import("std");
+const config = @import("../config/config.zig");
+const acl = @import("../security/acl.zig");
+const request = @import("../server/request.zig");
+const response = @import("../server/response.zig");
+const socket = @import("../server/socket.zig");
+const secret_mod = @import("../config/secret.zig");
+
+/// Open a TCP connection to `target`, tunnelled through the egress proxy
+/// when one is configured and the target is not exempt. With `local` the
+/// connection, to the proxy or the target, leaves from that address.
+pub fn connect(cfg: *const config.EgressProxyConfig, target: std.net.Address, local: ?std.net.Address) !std.net.Stream {
+    if (!cfg.enabled or exempt(cfg, target)) return dial(target, local);
+
+    const proxy_addr = try std.net.Address.parseIpAndPort(cfg.address);
+    const stream = try dial(proxy_addr, local);
+    errdefer stream.close();
+
+    try socket.setTimeouts(stream.handle, cfg.handshake_timeout_ms);
+    switch (cfg.kind) {
+        .http_connect => try httpConnect(stream, cfg, target),
+        .socks5 => try socks5Connect(stream, cfg, target),
+    }
+    // The caller applies its own timeouts to the tunnel
+    try socket.setTimeouts(stream.handle, 0);
+    return stream;
+}
+
+fn dial(target: std.net.Address, local: ?std.net.Address) !std.net.Stream {
+    const source = local orelse return std.net.tcpConnectToAddress(target);
+    const fd = try std.posix.socket(target.any.family, std.posix.SOCK.STREAM | std.posix.SOCK.CLOEXEC, std.posix.IPPROTO.TCP);
+    errdefer std.posix.close(fd);
+    // Port 0 lets the kernel pick the source port
+    try std.posix.bind(fd, &source.any, source.getOsSockLen());
+    try std.posix.connect(fd, &target.any, target.getOsSockLen());
+    return .{ .handle = fd };
+}
+
+/// Targets listed in no_proxy are dialled directly
+fn exempt(cfg: *const config.EgressProxyConfig, target: std.net.Address) bool {
+    const ip = acl.addressBytes(target) orelse return false;
+    return acl.listContains(cfg.no_proxy, ip);
+}
+
+fn httpConnect(stream: std.net.Stream, cfg: *const config.EgressProxyConfig, target: std.net.Address) !void {
+    var buf: [1024]u8 = undefined;
+    var head = response.HeadWriter{ .buf = &buf };
+    try head.print("CONNECT {f} HTTP/1.1\r\n", .{target});
+    try head.print("Host: {f}\r\n", .{target});
+    if (cfg.username.len > 0) {
+        var password_buf: [255]u8 = undefined;
+        const password = try secret_mod.resolveInto(cfg.password, &password_buf);
+        var credentials_buf: [512]u8 = undefined;
+        const credentials = try std.fmt.bufPrint(&credentials_buf, "{s}:{s}", .{ cfg.username, password });
+        var encoded_buf: [700]u8 = undefined;
+        const encoded = std.base64.standard.Encoder.encode(&encoded_buf, credentials);
+        try head.print("Proxy-Authorization: Basic {s}\r\n", .{encoded});
+    }
+    try head.print("\r\n", .{});
+    try stream.writeAll(head.slice());
+
+    // Read byte by byte so no tunnelled data is consumed past the head
+    var reply: [4096]u8 = undefined;
+    var len: usize = 0;
+    while (request.findHeadEnd(reply[0..len]) == null) {
+        if (len == reply.len) return error.ProxyReplyTooLarge;
+        const n = try stream.read(reply[len .. len + 1]);
+        if (n == 0) return error.ProxyClosedConnection;
+        len += n;
+    }
+    var headers: [request.MAX_HEADERS]request.Header = undefined;
+    const res = try response.parse(reply[0..len], &headers);
+    if (res.status == 407) return error.ProxyAuthenticationRequired;
+    if (res.status < 200 or res.status >= 300) return error.ProxyConnectRefused;
+}
+
+fn socks5Connect(stream: std.net.Stream, cfg: *const config.EgressProxyConfig, target: std.net.Address) !void {
+    const with_auth = cfg.username.len > 0;
+
+    // Greeting: offer no authentication, or username/password (RFC 1929)
+    try stream.writeAll(if (with_auth) &[_]u8{ 5, 1, 2 } else &[_]u8{ 5, 1, 0 });
+    var choice: [2]u8 = undefined;
+    try socket.readExact(stream, &choice);
+    if (choice[0] != 5) return error.InvalidSocksReply;
+    switch (choice[1]) {
+        0 => {},
+        2 => try socks5Authenticate(stream, cfg),
+        else => return error.SocksAuthenticationRejected,
+    }
+
+    // CONNECT to the target address
+    var req_buf: [22]u8 = undefined;
+    req_buf[0..3].* = .{ 5, 1, 0 };
+    var len: usize = 3;
+    switch (target.any.family) {
+        std.posix.AF.INET => {
+            req_buf[3] = 1;
+            @memcpy(req_buf[4..8], std.mem.asBytes(&target.in.sa.addr));
+            len = 8;
+        },
+        std.posix.AF.INET6 => {
+            req_buf[3] = 4;
+            @memcpy(req_buf[4..20], &target.in6.sa.addr);
+            len = 20;
+        },
+        else => return error.UnsupportedAddressFamily,
+    }
+    std.mem.writeInt(u16, req_buf[len..][0..2], target.getPort(), .big);
+    len += 2;
+    try stream.writeAll(req_buf[0..len]);
+
+    // Reply: version, status, reserved, then the bound address
+    var reply: [4]u8 = undefined;
+    try socket.readExact(stream, &reply);
+    if (reply[0] != 5) return error.InvalidSocksReply;
+    if (reply[1] != 0) return error.SocksConnectFailed;
+    var bound: [256 + 2]u8 = undefined;
+    const bound_len: usize = switch (reply[3]) {
+        1 => 4 + 2,
+        4 => 16 + 2,
+        3 => blk: {
+            var name_len: [1]u8 = undefined;
+            try socket.readExact(stream, &name_len);
+            break :blk @as(usize, name_len[0]) + 2;
+        },
+        else => return error.InvalidSocksReply,
+    };
+    try socket.readExact(stream, bound[0..bound_len]);
+}
+
+fn socks5Authenticate(stream: std.net.Stream, cfg: *const config.EgressProxyConfig) !void {
+    var password_buf: [255]u8 = undefined;
+    const password = secret_mod.resolveInto(cfg.password, &password_buf) catch |err| switch (err) {
+        error.SecretTooLong => return error.SocksCredentialsTooLong,
+        else => return err,
+    };
+    if (cfg.username.len > 255) return error.SocksCredentialsTooLong;
+
+    var buf: [3 + 255 + 255]u8 = undefined;
+    buf[0] = 1;
+    buf[1] = @intCast(cfg.username.len);
+    @memcpy(buf[2..][0..cfg.username.len], cfg.username);
+    const pass_at = 2 + cfg.username.len;
+    buf[pass_at] = @intCast(password.len);
+    @memcpy(buf[pass_at + 1 ..][0..password.len], password);
+    try stream.writeAll(buf[0 .. pass_at + 1 + password.len]);
+
+    var status: [2]u8 = undefined;
+    try socket.readExact(stream, &status);
+    if (status[1] != 0) return error.SocksAuthenticationRejected;
+}
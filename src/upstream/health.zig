@@ -0,0 +1,158 @@
+const std = @import("std");
+const config = @import("../config/config.zig");
+const signals = @import("../server/signals.zig");
+const socket = @import("../server/socket.zig");
+const request = @import("../server/request.zig");
+const response = @import("../server/response.zig");
+const body = @import("../proxy/body.zig");
+const manager = @import("manager.zig");
+const events = @import("../server/events.zig");
+
+/// Periodically probes every server in a group over HTTP and updates
+/// their health once results pass the rise/fall thresholds
+pub const HealthChecker = struct {
+    allocator: std.mem.Allocator,
+    group: *manager.UpstreamGroup,
+    /// Told when a server changes health
+    events: *events.EventBus,
+
+    pub fn init(allocator: std.mem.Allocator, group: *manager.UpstreamGroup, bus: *events.EventBus) HealthChecker {
+        return .{
+            .allocator = allocator,
+            .group = group,
+            .events = bus,
+        };
+    }
+
+    /// Thread entry point
+    pub fn run(self: *HealthChecker) void {
+        const cfg = self.group.config;
+        std.debug.print("✓ Health checking upstream {s} every {d}s\n", .{ self.group.name, cfg.health_check_interval });
+
+        while (!signals.shouldShutdown()) {
+            self.checkAll() catch |err| {
+                std.debug.print("✗ Health check of {s} failed: {any}\n", .{ self.group.name, err });
+            };
+            self.group.checked.store(true, .release);
+            // Jitter keeps checks from many instances from arriving in lockstep
+            const jitter = if (cfg.health_check.jitter_ms > 0) std.crypto.random.uintAtMost(u32, cfg.health_check.jitter_ms) else 0;
+            sleepUntilShutdown(cfg.health_check_interval * std.time.ms_per_s + jitter);
+        }
+    }
+
+    /// Probe each server once and record the results
+    pub fn checkAll(self: *HealthChecker) !void {
+        const addresses = try self.group.addresses(self.allocator);
+        defer self.allocator.free(addresses);
+
+        const cfg = self.group.config;
+        const timeout_ms: u32 = @intCast(@min(cfg.health_check_timeout * std.time.ms_per_s, std.math.maxInt(u32)));
+        for (addresses) |address| {
+            const passed = probe(self.allocator, self.group, address, timeout_ms) catch |err| blk: {
+                std.debug.print("✗ Health check of {s} server {any}: {any}\n", .{ self.group.name, address, err });
+                break :blk false;
+            };
+            if (self.group.recordCheck(address, passed)) |healthy| {
+                const state = if (healthy) "healthy" else "unhealthy";
+                std.debug.print("{s} Upstream {s} server {any} is now {s}\n", .{ if (healthy) "✓" else "✗", self.group.name, address, state });
+                var subject_buf: [128]u8 = undefined;
+                const subject = std.fmt.bufPrint(&subject_buf, "{s}/{f}", .{ self.group.name, address }) catch self.group.name;
+                self.events.publish(if (healthy) .upstream_up else .upstream_down, subject, "Upstream {s} server {f} is now {s}", .{ self.group.name, address, state });
+            }
+        }
+    }
+};
+
+/// Send the configured probe and judge the response by its status and,
+/// when configured, its body
+pub fn probe(allocator: std.mem.Allocator, group: *const manager.UpstreamGroup, address: std.net.Address, timeout_ms: u32) !bool {
+    const cfg = group.config;
+    const check = &cfg.health_check;
+
+    const stream = try group.pool.dial(address);
+    defer stream.close();
+    try socket.setTimeouts(stream.handle, timeout_ms);
+
+    var req_buf: [4096]u8 = undefined;
+    var head = response.HeadWriter{ .buf = &req_buf };
+    try head.print("{s} {s} HTTP/1.1\r\n", .{ check.method, cfg.health_check_path });
+    if (check.host.len > 0) {
+        try head.header("Host", check.host);
+    } else {
+        try head.print("Host: {f}\r\n", .{address});
+    }
+    try head.header("User-Agent", "wraith-health-check");
+    for (check.headers) |h| try head.header(h.name, h.value);
+    try head.print("Connection: close\r\n\r\n", .{});
+    try stream.writeAll(head.slice());
+
+    var buf: [request.MAX_HEAD_SIZE]u8 = undefined;
+    const n = try request.readHead(stream, &buf);
+    var headers: [request.MAX_HEADERS]request.Header = undefined;
+    const res = try response.parse(buf[0..n], &headers);
+
+    const status_ok = if (check.expected_status != 0) res.status == check.expected_status else res.status >= 200 and res.status < 400;
+    if (!status_ok) return false;
+    if (check.body_contains.len == 0 and check.json_field.len == 0) return true;
+
+    // Read as much of the body as matching needs
+    const framing: body.Framing = if (res.hasBody(check.method)) body.responseFraming(&res) else .{ .length = 0 };
+    var reader = body.BodyReader.init(stream, framing, buf[res.head_len..n]);
+    const content = try allocator.alloc(u8, check.max_body_size);
+    defer allocator.free(content);
+    var len: usize = 0;
+    while (len < content.len) {
+        const read = try reader.read(content[len..]);
+        if (read == 0) break;
+        len += read;
+    }
+
+    if (check.body_contains.len > 0 and std.mem.indexOf(u8, content[0..len], check.body_contains) == null) return false;
+    if (check.json_field.len > 0) return jsonFieldMatches(allocator, content[0..len], check.json_field, check.json_value);
+    return true;
+}
+
+/// Look up a dotted field path in a JSON document and compare it to `expected`.
+/// Strings compare by content, numbers and booleans by their JSON text.
+pub fn jsonFieldMatches(allocator: std.mem.Allocator, document: []const u8, field: []const u8, expected: []const u8) bool {
+    const parsed = std.json.parseFromSlice(std.json.Value, allocator, document, .{}) catch return false;
+    defer parsed.deinit();
+
+    var value = parsed.value;
+    var segments = std.mem.splitScalar(u8, field, '.');
+    while (segments.next()) |segment| {
+        switch (value) {
+            .object => |object| {
+                value = object.get(segment) orelse return false;
+            },
+            else => return false,
+        }
+    }
+
+    if (expected.len == 0) {
+        return switch (value) {
+            .null => false,
+            .bool => |b| b,
+            else => true,
+        };
+    }
+
+    var text_buf: [64]u8 = undefined;
+    const text = switch (value) {
+        .string, .number_string => |s| s,
+        .bool => |b| if (b) "true" else "false",
+        .integer => |i| std.fmt.bufPrint(&text_buf, "{d}", .{i}) catch return false,
+        .float => |f| std.fmt.bufPrint(&text_buf, "{d}", .{f}) catch return false,
+        else => return false,
+    };
+    return std.mem.eql(u8, text, expected);
+}
+
+fn sleepUntilShutdown(interval_ms: u64) void {
+    var remaining = interval_ms;
+    while (remaining > 0 and !signals.shouldShutdown()) {
+        const step = @min(remaining, 100);
+        std.Thread.sleep(step * std.time.ns_per_ms);
+        remaining -= step;
+    }
+}
@@ -0,0 +1,169 @@
+const std = @import("std");
+const config = @import("../config/config.zig");
+const manager = @import("manager.zig");
+const signals = @import("../server/signals.zig");
+
+/// Largest EndpointSlice list accepted from the API server
+const MAX_RESPONSE_SIZE = 4 * 1024 * 1024;
+
+const EndpointSliceList = struct {
+    items: []const EndpointSlice = &.{},
+};
+
+const EndpointSlice = struct {
+    addressType: []const u8 = "",
+    endpoints: ?[]const Endpoint = null,
+    ports: ?[]const EndpointPort = null,
+};
+
+const Endpoint = struct {
+    addresses: []const []const u8 = &.{},
+    conditions: ?Conditions = null,
+};
+
+const Conditions = struct {
+    ready: ?bool = null,
+};
+
+const EndpointPort = struct {
+    name: ?[]const u8 = null,
+    port: ?u16 = null,
+};
+
+/// Keeps an upstream group in sync with the ready endpoints of a
+/// Kubernetes Service by polling its EndpointSlices
+pub const KubernetesWatcher = struct {
+    allocator: std.mem.Allocator,
+    group: *manager.UpstreamGroup,
+    config: *const config.KubernetesDiscoveryConfig,
+
+    pub fn init(
+        allocator: std.mem.Allocator,
+        group: *manager.UpstreamGroup,
+        cfg: *const config.KubernetesDiscoveryConfig,
+    ) KubernetesWatcher {
+        return .{
+            .allocator = allocator,
+            .group = group,
+            .config = cfg,
+        };
+    }
+
+    /// Thread entry point
+    pub fn run(self: *KubernetesWatcher) void {
+        std.debug.print("✓ Watching Kubernetes service {s}/{s} for upstream {s}\n", .{
+            self.config.namespace,
+            self.config.service,
+            self.group.name,
+        });
+
+        while (!signals.shouldShutdown()) {
+            self.refresh() catch |err| {
+                // Keep the last known endpoints until the API server recovers
+                std.debug.print("✗ Kubernetes discovery for {s} failed: {any}\n", .{ self.group.name, err });
+            };
+            sleepUntilShutdown(self.config.refresh_interval_ms);
+        }
+    }
+
+    /// Fetch the service's EndpointSlices and replace the group's servers
+    pub fn refresh(self: *KubernetesWatcher) !void {
+        var arena = std.heap.ArenaAllocator.init(self.allocator);
+        defer arena.deinit();
+        const allocator = arena.allocator();
+
+        const body = try self.fetchEndpointSlices(allocator);
+        const slices = try std.json.parseFromSliceLeaky(EndpointSliceList, allocator, body, .{
+            .ignore_unknown_fields = true,
+        });
+
+        var servers: std.ArrayList(manager.Server) = .empty;
+        for (slices.items) |slice| {
+            const port = selectPort(slice.ports orelse continue, self.config.port_name) orelse continue;
+            for (slice.endpoints orelse continue) |endpoint| {
+                // Endpoints without a ready condition are treated as ready
+                if (endpoint.conditions) |conditions| {
+                    if (conditions.ready) |ready| {
+                        if (!ready) continue;
+                    }
+                }
+                for (endpoint.addresses) |ip| {
+                    // FQDN address types are not supported
+                    const address = std.net.Address.parseIp(ip, port) catch continue;
+                    try servers.append(allocator, .{ .address = address, .weight = 1 });
+                }
+            }
+        }
+
+        const previous = self.group.serverCount();
+        try self.group.replaceServers(servers.items);
+        if (previous != servers.items.len) {
+            std.debug.print("✓ Upstream {s} now has {d} ready endpoints\n", .{ self.group.name, servers.items.len });
+        }
+    }
+
+    fn fetchEndpointSlices(self: *KubernetesWatcher, allocator: std.mem.Allocator) ![]const u8 {
+        const token = try std.fs.cwd().readFileAlloc(allocator, self.config.token_path, 64 * 1024);
+        const authorization = try std.fmt.allocPrint(allocator, "Bearer {s}", .{std.mem.trim(u8, token, " \t\r\n")});
+
+        const api_server = self.config.api_server orelse try inClusterApiServer(allocator);
+        const url = try std.fmt.allocPrint(
+            allocator,
+            "{s}/apis/discovery.k8s.io/v1/namespaces/{s}/endpointslices?labelSelector=kubernetes.io%2Fservice-name%3D{s}",
+            .{ api_server, self.config.namespace, self.config.service },
+        );
+
+        var client: std.http.Client = .{ .allocator = allocator };
+        defer client.deinit();
+
+        // Trust only the cluster CA, not the system bundle
+        try client.ca_bundle.addCertsFromFilePathAbsolute(allocator, self.config.ca_path);
+        client.next_https_rescan_certs = false;
+
+        var body: std.Io.Writer.Allocating = .init(allocator);
+        const result = try client.fetch(.{
+            .location = .{ .url = url },
+            .method = .GET,
+            .extra_headers = &[_]std.http.Header{
+                .{ .name = "Authorization", .value = authorization },
+                .{ .name = "Accept", .value = "application/json" },
+            },
+            .response_writer = &body.writer,
+        });
+        if (result.status != .ok) return error.KubernetesApiError;
+
+        const written = body.written();
+        if (written.len > MAX_RESPONSE_SIZE) return error.ResponseTooLarge;
+        return written;
+    }
+};
+
+/// API server URL from the environment Kubernetes injects into every pod
+fn inClusterApiServer(allocator: std.mem.Allocator) ![]const u8 {
+    const host = std.posix.getenv("KUBERNETES_SERVICE_HOST") orelse return error.NotInCluster;
+    const port = std.posix.getenv("KUBERNETES_SERVICE_PORT") orelse "443";
+    if (std.mem.indexOfScalar(u8, host, ':') != null) {
+        return std.fmt.allocPrint(allocator, "https://[{s}]:{s}", .{ host, port });
+    }
+    return std.fmt.allocPrint(allocator, "https://{s}:{s}", .{ host, port });
+}
+
+/// The named port, or the slice's first port when no name is configured
+fn selectPort(ports: []const EndpointPort, name: []const u8) ?u16 {
+    for (ports) |port| {
+        if (name.len == 0) return port.port;
+        if (port.name) |port_name| {
+            if (std.mem.eql(u8, port_name, name)) return port.port;
+        }
+    }
+    return null;
+}
+
+fn sleepUntilShutdown(interval_ms: u32) void {
+    var remaining = interval_ms;
+    while (remaining > 0 and !signals.shouldShutdown()) {
+        const step = @min(remaining, 100);
+        std.Thread.sleep(@as(u64, step) * std.time.ns_per_ms);
+        remaining -= step;
+    }
+}
@@ -0,0 +1,76 @@
+const std = @import("std");
+const stats = @import("../routing/stats.zig");
+const metrics = @import("../server/metrics.zig");
+
+/// Bucket upper bounds in milliseconds, shared with route latency
+const bounds = stats.LatencyHistogram.bounds;
+
+/// Parts of an upstream exchange that are timed
+pub const Phase = enum {
+    /// Opening the connection, through the egress proxy if there is one
+    connect,
+    /// From connecting to the response head, including sending the request
+    ttfb,
+    /// From the first connection attempt to the last response byte relayed
+    total,
+};
+
+/// How long one exchange with an upstream took, per phase
+pub const Timing = struct {
+    connect_ms: u32 = 0,
+    ttfb_ms: u32 = 0,
+};
+
+/// Counts since startup, for Prometheus histograms
+const Cumulative = struct {
+    buckets: [bounds.len + 1]std.atomic.Value(u64) = [_]std.atomic.Value(u64){std.atomic.Value(u64).init(0)} ** (bounds.len + 1),
+    sum_ms: std.atomic.Value(u64) = std.atomic.Value(u64).init(0),
+
+    fn record(self: *Cumulative, ms: u32) void {
+        const bucket = for (bounds, 0..) |bound, i| {
+            if (ms <= bound) break i;
+        } else bounds.len;
+        _ = self.buckets[bucket].fetchAdd(1, .monotonic);
+        _ = self.sum_ms.fetchAdd(ms, .monotonic);
+    }
+};
+
+/// Connect, time-to-first-byte and total latency of an upstream group's
+/// exchanges, so slow backends can be told apart from failing ones
+pub const UpstreamLatency = struct {
+    cumulative: [3]Cumulative = [_]Cumulative{.{}} ** 3,
+    /// Rolling window behind the percentiles on /admin/upstreams
+    recent: [3]stats.LatencyHistogram = [_]stats.LatencyHistogram{.{}} ** 3,
+
+    pub fn record(self: *UpstreamLatency, timing: Timing, total_ms: u32) void {
+        const values = [3]u32{ timing.connect_ms, timing.ttfb_ms, total_ms };
+        for (&self.cumulative, &self.recent, values) |*cumulative, *recent, ms| {
+            cumulative.record(ms);
+            recent.record(ms);
+        }
+    }
+
+    pub const Percentiles = struct {
+        p50: ?u32,
+        p90: ?u32,
+        p99: ?u32,
+    };
+
+    pub fn percentiles(self: *UpstreamLatency, phase: Phase) Percentiles {
+        const recent = &self.recent[@intFromEnum(phase)];
+        return .{ .p50 = recent.percentile(50), .p90 = recent.percentile(90), .p99 = recent.percentile(99) };
+    }
+
+    /// Samples of `wraith_upstream_latency_ms` for one group; the family
+    /// line is written by the caller
+    pub fn writeMetrics(self: *const UpstreamLatency, m: *metrics.Exposition, upstream: []const u8) !void {
+        for (&self.cumulative, 0..) |*cumulative, i| {
+            var counts: [bounds.len + 1]u64 = undefined;
+            for (&counts, &cumulative.buckets) |*count, *bucket| count.* = bucket.load(.monotonic);
+            try m.histogram("wraith_upstream_latency_ms", &.{
+                .{ .name = "upstream", .value = upstream },
+                .{ .name = "phase", .value = @tagName(@as(Phase, @enumFromInt(i))) },
+            }, &bounds, &counts, cumulative.sum_ms.load(.monotonic));
+        }
+    }
+};
@@ -0,0 +1,635 @@
+const std = @import("std");
+const build_options = @import("build_options");
+const config = @import("../config/config.zig");
+const kubernetes = @import("kubernetes.zig");
+const health = @import("health.zig");
+const concurrency = @import("concurrency.zig");
+const egress = @import("egress.zig");
+const signing = @import("signing.zig");
+const acl = @import("../security/acl.zig");
+const metrics = @import("../server/metrics.zig");
+const latency = @import("latency.zig");
+const events = @import("../server/events.zig");
+const pool_mod = @import("pool.zig");
+const dns_discovery = @import("dns_discovery.zig");
+const retry_budget = @import("retry_budget.zig");
+const tls_client = @import("tls_client.zig");
+
+pub const Server = struct {
+    address: std.net.Address,
+    weight: u32,
+    /// Only used while every primary server is unhealthy
+    backup: bool = false,
+    healthy: bool = true,
+    /// Consecutive passing or failing health checks, whichever is current
+    streak: u32 = 0,
+    /// A cluster peer reported the server down; avoided until this time (ms)
+    peer_down_until: i64 = 0,
+    /// Smooth weighted round-robin state
+    current_weight: i64 = 0,
+    /// Requests and stream connections in flight, for least_conn
+    active: u32 = 0,
+
+    fn usable(self: *const Server, now_ms: i64) bool {
+        return self.healthy and now_ms >= self.peer_down_until;
+    }
+
+    /// In-flight work relative to the server's share of traffic
+    fn load(self: *const Server) f64 {
+        return @as(f64, @floatFromInt(self.active)) / @as(f64, @floatFromInt(self.weight));
+    }
+};
+
+/// Which servers a pick may choose from
+const Candidates = struct {
+    backup: bool,
+    healthy_only: bool,
+    except: ?std.net.Address,
+    now_ms: i64,
+
+    fn admits(self: Candidates, server: *const Server) bool {
+        if (server.weight == 0 or server.backup != self.backup) return false;
+        if (self.healthy_only and !server.usable(self.now_ms)) return false;
+        if (self.except) |address| return !server.address.eql(address);
+        return true;
+    }
+};
+
+/// A named pool of upstream servers. Membership may change at runtime
+/// when the group is backed by service discovery.
+pub const UpstreamGroup = struct {
+    allocator: std.mem.Allocator,
+    name: []const u8,
+    config: *const config.UpstreamConfig,
+    /// Outbound connections to the group's servers go through this proxy
+    egress: *const config.EgressProxyConfig,
+    mutex: std.Thread.Mutex,
+    servers: std.ArrayList(Server),
+    /// Set when adaptive concurrency is enabled for the group
+    limiter: ?concurrency.ConcurrencyLimiter = null,
+    /// Set when the group's requests are signed
+    signer: ?signing.Signer = null,
+    /// Whether a first round of health checks has finished
+    checked: std.atomic.Value(bool) = std.atomic.Value(bool).init(false),
+    /// Timing of proxied exchanges with the group's servers
+    latency: latency.UpstreamLatency = .{},
+    /// Source address of connections to the group's servers
+    local_address: ?std.net.Address = null,
+    /// Set when the group's servers speak TLS
+    tls: ?tls_client.TlsClient = null,
+    /// Connections to the group's servers for proxied requests and health
+    /// checks
+    pool: pool_mod.ConnectionPool,
+    /// Retries the group's traffic still allows
+    retry_budget: retry_budget.RetryBudget,
+
+    /// Pick a healthy primary server with the group's balancing method,
+    /// falling back to healthy backups. With every server down, primaries
+    /// are tried anyway. `client` keys ip_hash; the pick counts as in
+    /// flight until passed to `release`.
+    pub fn select(self: *UpstreamGroup, client: ?std.net.Address) ?std.net.Address {
+        self.mutex.lock();
+        defer self.mutex.unlock();
+
+        if (self.servers.items.len == 0) return null;
+        const server = self.pick(client, false, true, null) orelse
+            self.pick(client, true, true, null) orelse
+            self.pick(client, false, false, null) orelse return null;
+        server.active += 1;
+        return server.address;
+    }
+
+    /// Pick a healthy server other than `address` for a second attempt.
+    /// Second attempts are not counted as in flight.
+    pub fn selectOther(self: *UpstreamGroup, address: std.net.Address) ?std.net.Address {
+        self.mutex.lock();
+        defer self.mutex.unlock();
+
+        if (self.servers.items.len == 0) return null;
+        const server = self.pick(null, false, true, address) orelse
+            self.pick(null, true, true, address) orelse return null;
+        return server.address;
+    }
+
+    /// Finish a request or connection started with `select`
+    pub fn release(self: *UpstreamGroup, address: std.net.Address) void {
+        self.mutex.lock();
+        defer self.mutex.unlock();
+
+        for (self.servers.items) |*server| {
+            if (server.address.eql(address)) {
+                server.active -|= 1;
+                return;
+            }
+        }
+    }
+
+    fn pick(self: *UpstreamGroup, client: ?std.net.Address, backup: bool, healthy_only: bool, except: ?std.net.Address) ?*Server {
+        const candidates = Candidates{ .backup = backup, .healthy_only = healthy_only, .except = except, .now_ms = std.time.milliTimestamp() };
+        return switch (self.config.load_balancing) {
+            .round_robin => self.smoothWeighted(candidates, null),
+            .least_conn => self.smoothWeighted(candidates, self.leastLoad(candidates) orelse return null),
+            .ip_hash => {
+                const ip = (if (client) |c| acl.addressBytes(c) else null) orelse return self.smoothWeighted(candidates, null);
+                return self.weightedPoint(candidates, std.hash.Wyhash.hash(0, &ip));
+            },
+            .random => self.weightedPoint(candidates, std.crypto.random.int(u64)),
+        };
+    }
+
+    /// Smooth weighted round-robin, as in nginx: each candidate's current
+    /// weight grows by its weight, the largest wins and drops back by the
+    /// candidates' total. A 3:1 pair is picked a, a, b, a rather than in
+    /// bursts. With `only_load`, just the servers at that load take part.
+    fn smoothWeighted(self: *UpstreamGroup, candidates: Candidates, only_load: ?f64) ?*Server {
+        var total: i64 = 0;
+        var best: ?*Server = null;
+        for (self.servers.items) |*server| {
+            if (!candidates.admits(server)) continue;
+            if (only_load) |load| {
+                if (server.load() != load) continue;
+            }
+            server.current_weight += server.weight;
+            total += server.weight;
+            if (best == null or server.current_weight > best.?.current_weight) best = server;
+        }
+        const chosen = best orelse return null;
+        chosen.current_weight -= total;
+        return chosen;
+    }
+
+    /// Lowest in-flight load relative to weight among the candidates
+    fn leastLoad(self: *UpstreamGroup, candidates: Candidates) ?f64 {
+        var least: ?f64 = null;
+        for (self.servers.items) |*server| {
+            if (!candidates.admits(server)) continue;
+            const load = server.load();
+            if (least == null or load < least.?) least = load;
+        }
+        return least;
+    }
+
+    /// The candidate owning `seed` when their weights are laid end to end,
+    /// so each is chosen in proportion to its weight
+    fn weightedPoint(self: *UpstreamGroup, candidates: Candidates, seed: u64) ?*Server {
+        var total: u64 = 0;
+        for (self.servers.items) |*server| {
+            if (candidates.admits(server)) total += server.weight;
+        }
+        if (total == 0) return null;
+
+        var point = seed % total;
+        for (self.servers.items) |*server| {
+            if (!candidates.admits(server)) continue;
+            if (point < server.weight) return server;
+            point -= server.weight;
+        }
+        unreachable;
+    }
+
+    /// Record a health check result for the server at `address`. Health
+    /// flips after `rise` consecutive passes or `fall` consecutive failures;
+    /// returns the server's health when it changed.
+    pub fn recordCheck(self: *UpstreamGroup, address: std.net.Address, passed: bool) ?bool {
+        self.mutex.lock();
+        defer self.mutex.unlock();
+
+        const check = &self.config.health_check;
+        for (self.servers.items) |*server| {
+            if (!server.address.eql(address)) continue;
+            if (passed == server.healthy) {
+                server.streak = 0;
+                return null;
+            }
+            server.streak += 1;
+            if (server.streak < (if (passed) check.rise else check.fall)) return null;
+            server.healthy = passed;
+            server.streak = 0;
+            return passed;
+        }
+        return null;
+    }
+
+    /// Servers this instance's own health checks consider down
+    pub fn unhealthyServers(self: *UpstreamGroup, allocator: std.mem.Allocator) ![]std.net.Address {
+        self.mutex.lock();
+        defer self.mutex.unlock();
+
+        var list: std.ArrayList(std.net.Address) = .empty;
+        errdefer list.deinit(allocator);
+        for (self.servers.items) |server| {
+            if (!server.healthy) try list.append(allocator, server.address);
+        }
+        return list.toOwnedSlice(allocator);
+    }
+
+    /// Avoid the server at `address` until `until_ms` because a cluster
+    /// peer's health checks failed it
+    pub fn markPeerDown(self: *UpstreamGroup, address: std.net.Address, until_ms: i64) void {
+        self.mutex.lock();
+        defer self.mutex.unlock();
+
+        for (self.servers.items) |*server| {
+            if (server.address.eql(address)) server.peer_down_until = @max(server.peer_down_until, until_ms);
+        }
+    }
+
+    /// Copy of the current servers and their health
+    pub fn snapshot(self: *UpstreamGroup, allocator: std.mem.Allocator) ![]Server {
+        self.mutex.lock();
+        defer self.mutex.unlock();
+        return allocator.dupe(Server, self.servers.items);
+    }
+
+    /// Copy of the current server addresses
+    pub fn addresses(self: *UpstreamGroup, allocator: std.mem.Allocator) ![]std.net.Address {
+        self.mutex.lock();
+        defer self.mutex.unlock();
+
+        const list = try allocator.alloc(std.net.Address, self.servers.items.len);
+        for (self.servers.items, list) |server, *address| address.* = server.address;
+        return list;
+    }
+
+    /// Replace the group's membership
+    pub fn replaceServers(self: *UpstreamGroup, servers: []const Server) !void {
+        self.mutex.lock();
+        defer self.mutex.unlock();
+
+        // Work in flight on servers that stay keeps counting for least_conn
+        var next: std.ArrayList(Server) = .empty;
+        errdefer next.deinit(self.allocator);
+        try next.appendSlice(self.allocator, servers);
+        for (next.items) |*server| {
+            for (self.servers.items) |old| {
+                if (old.address.eql(server.address)) server.active = old.active;
+            }
+        }
+        self.servers.deinit(self.allocator);
+        self.servers = next;
+    }
+
+    /// Open a plain TCP connection to one of the group's servers, for
+    /// stream proxying; TLS, when configured, is only spoken by proxied
+    /// requests and health checks
+    pub fn connect(self: *const UpstreamGroup, address: std.net.Address) !std.net.Stream {
+        return egress.connect(self.egress, address, self.local_address);
+    }
+
+    /// Whether any server, primary or backup, could be picked as healthy
+    /// now; servers with weight 0 or reported down by a peer don't count
+    pub fn hasHealthy(self: *UpstreamGroup) bool {
+        self.mutex.lock();
+        defer self.mutex.unlock();
+
+        const now_ms = std.time.milliTimestamp();
+        for ([_]bool{ false, true }) |backup| {
+            const candidates = Candidates{ .backup = backup, .healthy_only = true, .except = null, .now_ms = now_ms };
+            for (self.servers.items) |*server| {
+                if (candidates.admits(server)) return true;
+            }
+        }
+        return false;
+    }
+
+    pub fn serverCount(self: *UpstreamGroup) usize {
+        self.mutex.lock();
+        defer self.mutex.unlock();
+        return self.servers.items.len;
+    }
+};
+
+/// Owns all upstream groups, their discovery watchers and health checkers
+pub const UpstreamManager = struct {
+    allocator: std.mem.Allocator,
+    groups: std.ArrayList(*UpstreamGroup),
+    watchers: std.ArrayList(*kubernetes.KubernetesWatcher),
+    dns_watchers: std.ArrayList(*dns_discovery.DnsWatcher),
+    checkers: std.ArrayList(*health.HealthChecker),
+    threads: std.ArrayList(std.Thread),
+
+    pub fn init(
+        allocator: std.mem.Allocator,
+        upstreams: []const config.UpstreamConfig,
+        egress_proxy: *const config.EgressProxyConfig,
+    ) !UpstreamManager {
+        var manager = UpstreamManager{
+            .allocator = allocator,
+            .groups = .empty,
+            .watchers = .empty,
+            .dns_watchers = .empty,
+            .checkers = .empty,
+            .threads = .empty,
+        };
+        errdefer manager.deinit();
+
+        for (upstreams) |*upstream| {
+            // Validated with the config
+            const local_address = if (upstream.local_address.len > 0) try std.net.Address.parseIp(upstream.local_address, 0) else null;
+            const group = try allocator.create(UpstreamGroup);
+            group.* = .{
+                .allocator = allocator,
+                .name = upstream.name,
+                .config = upstream,
+                .egress = egress_proxy,
+                .mutex = .{},
+                .servers = .empty,
+                .limiter = if (upstream.adaptive_concurrency.enabled)
+                    concurrency.ConcurrencyLimiter.init(allocator, &upstream.adaptive_concurrency)
+                else
+                    null,
+                .signer = if (upstream.signing.kind != .none)
+                    signing.Signer.init(allocator, &upstream.signing)
+                else
+                    null,
+                .local_address = local_address,
+                .pool = pool_mod.ConnectionPool.init(allocator, &upstream.pool, egress_proxy, local_address),
+                .retry_budget = retry_budget.RetryBudget.init(&upstream.retry_budget),
+            };
+            manager.groups.append(allocator, group) catch |err| {
+                allocator.destroy(group);
+                return err;
+            };
+            if (upstream.usesTls()) {
+                group.tls = tls_client.TlsClient.init(allocator, upstream) catch |err| {
+                    std.debug.print("✗ Loading CAs for upstream {s} failed: {any}\n", .{ upstream.name, err });
+                    return err;
+                };
+                group.pool.tls = &group.tls.?;
+            }
+
+            for (upstream.servers) |server| {
+                const address = parseServerAddress(allocator, server.host) catch |err| {
+                    std.debug.print("✗ Invalid upstream server {s}: {any}\n", .{ server.host, err });
+                    return err;
+                };
+                try group.servers.append(allocator, .{
+                    .address = address,
+                    .weight = server.weight,
+                    .backup = server.backup,
+                });
+            }
+        }
+
+        return manager;
+    }
+
+    pub fn deinit(self: *UpstreamManager) void {
+        for (self.threads.items) |thread| thread.join();
+        self.threads.deinit(self.allocator);
+
+        for (self.watchers.items) |watcher| self.allocator.destroy(watcher);
+        self.watchers.deinit(self.allocator);
+
+        for (self.dns_watchers.items) |watcher| {
+            watcher.deinit();
+            self.allocator.destroy(watcher);
+        }
+        self.dns_watchers.deinit(self.allocator);
+
+        for (self.checkers.items) |checker| self.allocator.destroy(checker);
+        self.checkers.deinit(self.allocator);
+
+        for (self.groups.items) |group| {
+            group.servers.deinit(self.allocator);
+            if (group.limiter) |*limiter| limiter.deinit();
+            if (group.signer) |*signer| signer.deinit();
+            group.pool.deinit();
+            if (group.tls) |*client| client.deinit();
+            self.allocator.destroy(group);
+        }
+        self.groups.deinit(self.allocator);
+    }
+
+    pub fn writeMetrics(self: *const UpstreamManager, m: *metrics.Exposition) !void {
+        const now_ms = std.time.milliTimestamp();
+        try m.family("wraith_upstream_server_healthy", .gauge, "1 while a server passes health checks and no cluster peer reports it down");
+        for (self.groups.items) |group| {
+            const servers = try group.snapshot(m.allocator);
+            defer m.allocator.free(servers);
+            for (servers) |server| {
+                var address_buf: [64]u8 = undefined;
+                const address = std.fmt.bufPrint(&address_buf, "{f}", .{server.address}) catch "";
+                try m.sample("wraith_upstream_server_healthy", &.{
+                    .{ .name = "upstream", .value = group.name },
+                    .{ .name = "server", .value = address },
+                }, @intFromBool(server.usable(now_ms)));
+            }
+        }
+        try m.family("wraith_upstream_server_active", .gauge, "Requests and stream connections in flight per server");
+        for (self.groups.items) |group| {
+            const servers = try group.snapshot(m.allocator);
+            defer m.allocator.free(servers);
+            for (servers) |server| {
+                var address_buf: [64]u8 = undefined;
+                const address = std.fmt.bufPrint(&address_buf, "{f}", .{server.address}) catch "";
+                try m.sample("wraith_upstream_server_active", &.{
+                    .{ .name = "upstream", .value = group.name },
+                    .{ .name = "server", .value = address },
+                }, server.active);
+            }
+        }
+        try m.family("wraith_upstream_latency_ms", .histogram, "Proxied exchange latency per upstream group by phase: connect, ttfb or total");
+        for (self.groups.items) |group| try group.latency.writeMetrics(m, group.name);
+    }
+
+    pub fn get(self: *const UpstreamManager, name: []const u8) ?*UpstreamGroup {
+        for (self.groups.items) |group| {
+            if (std.mem.eql(u8, group.name, name)) return group;
+        }
+        return null;
+    }
+
+    /// Start discovery watchers for groups backed by service discovery
+    pub fn startDiscovery(self: *UpstreamManager) !void {
+        for (self.groups.items) |group| {
+            if (group.config.dns.enabled) try self.startDnsWatcher(group);
+            if (!group.config.kubernetes.enabled) continue;
+            if (!build_options.kubernetes) return error.KubernetesSupportDisabled;
+
+            const watcher = try self.allocator.create(kubernetes.KubernetesWatcher);
+            watcher.* = kubernetes.KubernetesWatcher.init(self.allocator, group, &group.config.kubernetes);
+            self.watchers.append(self.allocator, watcher) catch |err| {
+                self.allocator.destroy(watcher);
+                return err;
+            };
+
+            try self.threads.ensureUnusedCapacity(self.allocator, 1);
+            const thread = try std.Thread.spawn(.{}, kubernetes.KubernetesWatcher.run, .{watcher});
+            self.threads.appendAssumeCapacity(thread);
+        }
+    }
+
+    fn startDnsWatcher(self: *UpstreamManager, group: *UpstreamGroup) !void {
+        const watcher = try self.allocator.create(dns_discovery.DnsWatcher);
+        watcher.init(self.allocator, group, &group.config.dns) catch |err| {
+            self.allocator.destroy(watcher);
+            return err;
+        };
+        self.dns_watchers.append(self.allocator, watcher) catch |err| {
+            watcher.deinit();
+            self.allocator.destroy(watcher);
+            return err;
+        };
+
+        try self.threads.ensureUnusedCapacity(self.allocator, 1);
+        const thread = try std.Thread.spawn(.{}, dns_discovery.DnsWatcher.run, .{watcher});
+        self.threads.appendAssumeCapacity(thread);
+    }
+
+    /// Start active health checks for groups with a check interval,
+    /// publishing servers that change health on `bus`
+    pub fn startHealthChecks(self: *UpstreamManager, bus: *events.EventBus) !void {
+        for (self.groups.items) |group| {
+            if (group.config.health_check_interval == 0) continue;
+
+            const checker = try self.allocator.create(health.HealthChecker);
+            checker.* = health.HealthChecker.init(self.allocator, group, bus);
+            self.checkers.append(self.allocator, checker) catch |err| {
+                self.allocator.destroy(checker);
+                return err;
+            };
+
+            try self.threads.ensureUnusedCapacity(self.allocator, 1);
+            const thread = try std.Thread.spawn(.{}, health.HealthChecker.run, .{checker});
+            self.threads.appendAssumeCapacity(thread);
+        }
+    }
+};
+
+/// Parse "http://host:port", "https://host:port" or "host:port",
+/// resolving hostnames once
+pub fn parseServerAddress(allocator: std.mem.Allocator, host: []const u8) !std.net.Address {
+    const without_scheme = if (std.mem.startsWith(u8, host, "http://"))
+        host[7..]
+    else if (std.mem.startsWith(u8, host, "https://"))
+        host[8..]
+    else
+        host;
+    const colon = std.mem.lastIndexOfScalar(u8, without_scheme, ':') orelse return error.MissingPort;
+    const port = try std.fmt.parseInt(u16, without_scheme[colon + 1 ..], 10);
+    const name = std.mem.trim(u8, without_scheme[0..colon], "[]");
+
+    if (std.net.Address.parseIp(name, port)) |address| {
+        return address;
+    } else |_| {}
+
+    const list = try std.net.getAddressList(allocator, name, port);
+    defer list.deinit();
+    if (list.addrs.len == 0) return error.UnknownHostName;
+    return list.addrs[0];
+}
+
+test "smooth weighted round-robin spreads picks by weight" {
+    const upstreams = [_]config.UpstreamConfig{.{
+        .name = "app",
+        .servers = &[_]config.UpstreamServer{
+            .{ .host = "10.0.0.1:80", .weight = 5 },
+            .{ .host = "10.0.0.2:80", .weight = 1 },
+            .{ .host = "10.0.0.3:80", .weight = 1 },
+        },
+    }};
+    const egress_proxy = config.EgressProxyConfig{};
+    var manager = try UpstreamManager.init(std.testing.allocator, &upstreams, &egress_proxy);
+    defer manager.deinit();
+    const group = manager.get("app").?;
+
+    // Interleaved rather than five in a row, and the cycle repeats every
+    // seven picks
+    const cycle = [_]usize{ 0, 0, 1, 0, 2, 0, 0 };
+    var counts = [_]usize{ 0, 0, 0 };
+    for (0..10) |_| {
+        for (cycle) |expected| {
+            const address = group.select(null).?;
+            group.release(address);
+            for (group.servers.items, &counts) |server, *count| {
+                if (server.address.eql(address)) count.* += 1;
+            }
+            try std.testing.expect(address.eql(group.servers.items[expected].address));
+        }
+    }
+    try std.testing.expectEqual([_]usize{ 50, 10, 10 }, counts);
+}
+
+/// Pick `picks` times from a one-group manager, returning how often each
+/// server was chosen
+fn countPicks(group: *UpstreamGroup, picks: usize) ![3]usize {
+    var counts = [_]usize{ 0, 0, 0 };
+    for (0..picks) |_| {
+        const address = group.select(null) orelse return error.NoServer;
+        group.release(address);
+        for (group.servers.items, counts[0..group.servers.items.len]) |server, *count| {
+            if (server.address.eql(address)) count.* += 1;
+        }
+    }
+    return counts;
+}
+
+test "servers with weight 0 are never picked" {
+    const upstreams = [_]config.UpstreamConfig{.{
+        .name = "app",
+        .servers = &[_]config.UpstreamServer{
+            .{ .host = "10.0.0.1:80", .weight = 0 },
+            .{ .host = "10.0.0.2:80", .weight = 2 },
+            .{ .host = "10.0.0.3:80", .weight = 1 },
+        },
+    }};
+    const egress_proxy = config.EgressProxyConfig{};
+    var manager = try UpstreamManager.init(std.testing.allocator, &upstreams, &egress_proxy);
+    defer manager.deinit();
+    const group = manager.get("app").?;
+
+    try std.testing.expectEqual([_]usize{ 0, 20, 10 }, try countPicks(group, 30));
+    // Even as the only healthy server left; the primaries that are down
+    // are tried instead
+    group.servers.items[1].healthy = false;
+    group.servers.items[2].healthy = false;
+    try std.testing.expectEqual([_]usize{ 0, 20, 10 }, try countPicks(group, 30));
+    try std.testing.expect(!group.hasHealthy());
+}
+
+test "down and backup servers" {
+    const upstreams = [_]config.UpstreamConfig{.{
+        .name = "app",
+        .servers = &[_]config.UpstreamServer{
+            .{ .host = "10.0.0.1:80" },
+            .{ .host = "10.0.0.2:80" },
+            .{ .host = "10.0.0.3:80", .backup = true },
+        },
+    }};
+    const egress_proxy = config.EgressProxyConfig{};
+    var manager = try UpstreamManager.init(std.testing.allocator, &upstreams, &egress_proxy);
+    defer manager.deinit();
+    const group = manager.get("app").?;
+
+    // The backup waits while any primary is up
+    try std.testing.expectEqual([_]usize{ 5, 5, 0 }, try countPicks(group, 10));
+    group.servers.items[0].healthy = false;
+    try std.testing.expectEqual([_]usize{ 0, 10, 0 }, try countPicks(group, 10));
+    group.servers.items[1].peer_down_until = std.math.maxInt(i64);
+    try std.testing.expectEqual([_]usize{ 0, 0, 10 }, try countPicks(group, 10));
+    try std.testing.expect(group.hasHealthy());
+
+    // With everything down, primaries are tried anyway
+    group.servers.items[2].healthy = false;
+    try std.testing.expect(!group.hasHealthy());
+    const counts = try countPicks(group, 10);
+    try std.testing.expectEqual(@as(usize, 0), counts[2]);
+}
+
+test "a group whose servers all have weight 0 picks none" {
+    const upstreams = [_]config.UpstreamConfig{.{
+        .name = "app",
+        .servers = &[_]config.UpstreamServer{
+            .{ .host = "10.0.0.1:80", .weight = 0 },
+            .{ .host = "10.0.0.2:80", .weight = 0, .backup = true },
+        },
+    }};
+    const egress_proxy = config.EgressProxyConfig{};
+    var manager = try UpstreamManager.init(std.testing.allocator, &upstreams, &egress_proxy);
+    defer manager.deinit();
+    const group = manager.get("app").?;
+
+    try std.testing.expectEqual(@as(?std.net.Address, null), group.select(null));
+    try std.testing.expect(!group.hasHealthy());
+}
@@ -0,0 +1,174 @@
+const std = @import("std");
+const config = @import("../config/config.zig");
+const egress = @import("egress.zig");
+const tls_client = @import("tls_client.zig");
+
+pub const PoolStats = struct {
+    /// New connections opened
+    opened: std.atomic.Value(u64) = std.atomic.Value(u64).init(0),
+    /// Requests sent over an idle connection
+    reused: std.atomic.Value(u64) = std.atomic.Value(u64).init(0),
+    /// Idle connections closed by the upstream or past the idle timeout
+    expired: std.atomic.Value(u64) = std.atomic.Value(u64).init(0),
+    /// Requests refused because max_connections were open
+    exhausted: std.atomic.Value(u64) = std.atomic.Value(u64).init(0),
+};
+
+/// A connection handed out by `ConnectionPool.acquire`
+pub const Connection = struct {
+    stream: std.net.Stream,
+    /// Taken from the idle list rather than opened for this request; the
+    /// upstream may have closed it just as the request went out
+    reused: bool,
+};
+
+const Idle = struct {
+    address: std.net.Address,
+    stream: std.net.Stream,
+    since_ms: i64,
+};
+
+/// Keep-alive connections to one group's servers. Connections that finish
+/// an exchange cleanly are kept idle and reused by later requests to the
+/// same server instead of a new connection each time.
+pub const ConnectionPool = struct {
+    allocator: std.mem.Allocator,
+    config: *const config.UpstreamPoolConfig,
+    egress: *const config.EgressProxyConfig,
+    /// Source address of the group's connections
+    local: ?std.net.Address,
+    /// Set when the group's servers speak TLS
+    tls: ?*tls_client.TlsClient = null,
+    mutex: std.Thread.Mutex = .{},
+    /// Most recently used last
+    idle: std.ArrayList(Idle) = .empty,
+    /// Connections open to the group's servers, idle or in use
+    open: usize = 0,
+    stats: PoolStats = .{},
+
+    pub fn init(allocator: std.mem.Allocator, cfg: *const config.UpstreamPoolConfig, egress_proxy: *const config.EgressProxyConfig, local: ?std.net.Address) ConnectionPool {
+        return .{ .allocator = allocator, .config = cfg, .egress = egress_proxy, .local = local };
+    }
+
+    pub fn deinit(self: *ConnectionPool) void {
+        for (self.idle.items) |conn| conn.stream.close();
+        self.idle.deinit(self.allocator);
+    }
+
+    /// Whether connections are kept after an exchange
+    pub fn enabled(self: *const ConnectionPool) bool {
+        return self.config.max_idle > 0;
+    }
+
+    /// An idle connection to `address`, or a new one. `fresh` skips the
+    /// idle list. Fails with `error.UpstreamPoolExhausted` when
+    /// max_connections are open.
+    pub fn acquire(self: *ConnectionPool, address: std.net.Address, fresh: bool) !Connection {
+        {
+            self.mutex.lock();
+            defer self.mutex.unlock();
+
+            self.pruneLocked(std.time.milliTimestamp());
+            if (!fresh) {
+                var i = self.idle.items.len;
+                while (i > 0) {
+                    i -= 1;
+                    if (!self.idle.items[i].address.eql(address)) continue;
+                    const conn = self.idle.orderedRemove(i);
+                    _ = self.stats.reused.fetchAdd(1, .monotonic);
+                    return .{ .stream = conn.stream, .reused = true };
+                }
+            }
+            if (self.config.max_connections > 0 and self.open >= self.config.max_connections) {
+                _ = self.stats.exhausted.fetchAdd(1, .monotonic);
+                return error.UpstreamPoolExhausted;
+            }
+            self.open += 1;
+        }
+
+        const stream = self.dial(address) catch |err| {
+            self.mutex.lock();
+            defer self.mutex.unlock();
+            self.open -= 1;
+            return err;
+        };
+        _ = self.stats.opened.fetchAdd(1, .monotonic);
+        return .{ .stream = stream, .reused = false };
+    }
+
+    /// Open a connection to `address` that the pool doesn't track: through
+    /// the egress proxy, from the group's source address and over TLS as
+    /// configured
+    pub fn dial(self: *const ConnectionPool, address: std.net.Address) !std.net.Stream {
+        const stream = try egress.connect(self.egress, address, self.local);
+        const client = self.tls orelse return stream;
+        errdefer stream.close();
+        return client.wrap(stream);
+    }
+
+    /// Hand back a connection from `acquire`. It is kept for reuse when the
+    /// exchange left it `reusable` and the idle list has room; otherwise it
+    /// is closed.
+    pub fn release(self: *ConnectionPool, address: std.net.Address, stream: std.net.Stream, reusable: bool) void {
+        self.mutex.lock();
+        defer self.mutex.unlock();
+
+        const now = std.time.milliTimestamp();
+        self.pruneLocked(now);
+        if (reusable and self.idle.items.len < self.config.max_idle) {
+            // Closed instead when there's no memory to track it
+            if (self.idle.append(self.allocator, .{ .address = address, .stream = stream, .since_ms = now })) |_| return else |_| {}
+        }
+        stream.close();
+        self.open -= 1;
+    }
+
+    /// Close idle connections past the idle timeout or that the upstream
+    /// has closed (readable with nothing asked means EOF or stray bytes)
+    fn pruneLocked(self: *ConnectionPool, now_ms: i64) void {
+        var i: usize = 0;
+        while (i < self.idle.items.len) {
+            const conn = self.idle.items[i];
+            const timed_out = self.config.idle_timeout_ms > 0 and now_ms - conn.since_ms >= self.config.idle_timeout_ms;
+            if (!timed_out and !readable(conn.stream)) {
+                i += 1;
+                continue;
+            }
+            conn.stream.close();
+            _ = self.idle.orderedRemove(i);
+            self.open -= 1;
+            _ = self.stats.expired.fetchAdd(1, .monotonic);
+        }
+    }
+
+    /// Counts for /admin/upstreams
+    pub fn summary(self: *ConnectionPool) Summary {
+        self.mutex.lock();
+        defer self.mutex.unlock();
+        return .{
+            .open = self.open,
+            .idle = self.idle.items.len,
+            .opened = self.stats.opened.load(.monotonic),
+            .reused = self.stats.reused.load(.monotonic),
+            .expired = self.stats.expired.load(.monotonic),
+            .exhausted = self.stats.exhausted.load(.monotonic),
+        };
+    }
+
+    pub const Summary = struct {
+        open: usize,
+        idle: usize,
+        opened: u64,
+        reused: u64,
+        expired: u64,
+        exhausted: u64,
+    };
+};
+
+fn readable(stream: std.net.Stream) bool {
+    var fds = [_]std.posix.pollfd{
+        .{ .fd = stream.handle, .events = std.posix.POLL.IN, .revents = 0 },
+    };
+    const ready = std.posix.poll(&fds, 0) catch return true;
+    return ready > 0;
+}
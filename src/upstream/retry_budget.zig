@@ -0,0 +1,64 @@
+const std = @import("std");
+const config = @import("../config/config.zig");
+
+/// Seconds of traffic the budget looks back over
+const WINDOW_S = 10;
+
+const Bucket = struct {
+    second: i64 = 0,
+    requests: u32 = 0,
+    retries: u32 = 0,
+};
+
+/// Requests and retries sent to one group, in one-second buckets. A retry
+/// is allowed while retries in the window stay under `ratio` times the
+/// requests plus `min_per_second` for each second of the window.
+pub const RetryBudget = struct {
+    config: *const config.RetryBudgetConfig,
+    mutex: std.Thread.Mutex = .{},
+    buckets: [WINDOW_S]Bucket = [_]Bucket{.{}} ** WINDOW_S,
+    /// Retries refused because the budget was spent
+    exhausted: std.atomic.Value(u64) = std.atomic.Value(u64).init(0),
+
+    pub fn init(cfg: *const config.RetryBudgetConfig) RetryBudget {
+        return .{ .config = cfg };
+    }
+
+    /// Count a request sent to the group
+    pub fn deposit(self: *RetryBudget) void {
+        if (self.config.ratio == 0) return;
+        self.mutex.lock();
+        defer self.mutex.unlock();
+        self.bucket(std.time.timestamp()).requests += 1;
+    }
+
+    /// Whether another retry fits the budget, counting it when it does
+    pub fn withdraw(self: *RetryBudget) bool {
+        if (self.config.ratio == 0) return true;
+        self.mutex.lock();
+        defer self.mutex.unlock();
+
+        const now_s = std.time.timestamp();
+        var requests: u64 = 0;
+        var retries: u64 = 0;
+        for (self.buckets) |b| {
+            if (now_s - b.second >= WINDOW_S) continue;
+            requests += b.requests;
+            retries += b.retries;
+        }
+        const allowed = @as(f64, @floatFromInt(requests)) * self.config.ratio +
+            @as(f64, @floatFromInt(@as(u64, self.config.min_per_second) * WINDOW_S));
+        if (@as(f64, @floatFromInt(retries + 1)) > allowed) {
+            _ = self.exhausted.fetchAdd(1, .monotonic);
+            return false;
+        }
+        self.bucket(now_s).retries += 1;
+        return true;
+    }
+
+    fn bucket(self: *RetryBudget, now_s: i64) *Bucket {
+        const b = &self.buckets[@intCast(@mod(now_s, WINDOW_S))];
+        if (b.second != now_s) b.* = .{ .second = now_s };
+        return b;
+    }
+};
@@ -0,0 +1,133 @@
+const std = @import("std");
+const config = @import("../config/config.zig");
+const socket = @import("../server/socket.zig");
+
+const tls = std.crypto.tls;
+const Certificate = std.crypto.Certificate;
+
+pub const TlsClientStats = struct {
+    handshakes: std.atomic.Value(u64) = std.atomic.Value(u64).init(0),
+    handshake_failures: std.atomic.Value(u64) = std.atomic.Value(u64).init(0),
+};
+
+/// Client side of TLS to one group's servers. The trusted CAs are loaded
+/// once; each connection's plaintext is relayed over a socket pair, so the
+/// proxy, pool and health checks keep working with plain streams.
+pub const TlsClient = struct {
+    allocator: std.mem.Allocator,
+    config: *const config.UpstreamTlsConfig,
+    /// Sent as SNI and matched against the certificates
+    server_name: []const u8,
+    /// Null when certificates aren't verified
+    bundle: ?Certificate.Bundle,
+    stats: TlsClientStats = .{},
+
+    pub fn init(allocator: std.mem.Allocator, upstream: *const config.UpstreamConfig) !TlsClient {
+        const cfg = &upstream.tls;
+        var bundle: ?Certificate.Bundle = null;
+        if (!cfg.insecure_skip_verify) {
+            var b: Certificate.Bundle = .{};
+            errdefer b.deinit(allocator);
+            if (cfg.ca_file.len > 0) {
+                try b.addCertsFromFilePath(allocator, std.fs.cwd(), cfg.ca_file);
+            } else {
+                try b.rescan(allocator);
+            }
+            bundle = b;
+        }
+        return .{
+            .allocator = allocator,
+            .config = cfg,
+            // Validated with the config
+            .server_name = upstream.tlsServerName() orelse "",
+            .bundle = bundle,
+        };
+    }
+
+    pub fn deinit(self: *TlsClient) void {
+        if (self.bundle) |*b| b.deinit(self.allocator);
+    }
+
+    /// Handshake over `stream` and return a stream carrying the session's
+    /// plaintext. The session takes `stream` over and ends once the
+    /// returned stream is closed or the server closes its side.
+    pub fn wrap(self: *TlsClient, stream: std.net.Stream) !std.net.Stream {
+        const tunnel = try self.allocator.create(Tunnel);
+        errdefer self.allocator.destroy(tunnel);
+        tunnel.allocator = self.allocator;
+        tunnel.stream = stream;
+        tunnel.stream_reader = stream.reader(&tunnel.socket_read_buf);
+        tunnel.stream_writer = stream.writer(&tunnel.socket_write_buf);
+
+        try socket.setTimeouts(stream.handle, self.config.handshake_timeout_ms);
+        tunnel.client = tls.Client.init(tunnel.stream_reader.interface(), &tunnel.stream_writer.interface, .{
+            // Without a host to verify, std's client sends no SNI either
+            .host = if (self.bundle == null) .no_verification else .{ .explicit = self.server_name },
+            .ca = if (self.bundle) |b| .{ .bundle = b } else .no_verification,
+            .read_buffer = &tunnel.read_buf,
+            .write_buffer = &tunnel.write_buf,
+        }) catch |err| {
+            _ = self.stats.handshake_failures.fetchAdd(1, .monotonic);
+            return err;
+        };
+        _ = self.stats.handshakes.fetchAdd(1, .monotonic);
+        try socket.setTimeouts(stream.handle, 0);
+
+        const pair = try socket.streamPair();
+        errdefer for (pair) |end| end.close();
+        tunnel.relay = pair[1];
+        const thread = try std.Thread.spawn(.{}, Tunnel.pump, .{tunnel});
+        thread.detach();
+        return pair[0];
+    }
+};
+
+/// One session and the relay end of its socket pair. Owns itself: the pump
+/// thread frees it once either side closes.
+const Tunnel = struct {
+    allocator: std.mem.Allocator,
+    stream: std.net.Stream,
+    relay: std.net.Stream,
+    stream_reader: std.net.Stream.Reader,
+    stream_writer: std.net.Stream.Writer,
+    client: tls.Client,
+    socket_read_buf: [tls.Client.min_buffer_len]u8,
+    socket_write_buf: [tls.Client.min_buffer_len]u8,
+    read_buf: [tls.Client.min_buffer_len]u8,
+    write_buf: [tls.Client.min_buffer_len]u8,
+
+    fn pump(self: *Tunnel) void {
+        self.relayUntilClosed() catch |err| switch (err) {
+            error.ConnectionResetByPeer, error.BrokenPipe, error.EndOfStream => {},
+            else => std.debug.print("✗ Upstream TLS relay stopped: {any}\n", .{err}),
+        };
+        self.client.end() catch {};
+        self.stream_writer.interface.flush() catch {};
+        self.stream.close();
+        self.relay.close();
+        self.allocator.destroy(self);
+    }
+
+    fn relayUntilClosed(self: *Tunnel) !void {
+        var buf: [16 * 1024]u8 = undefined;
+        const handles = [_]std.posix.socket_t{ self.stream.handle, self.relay.handle };
+        const reader = &self.client.reader;
+        while (true) {
+            // Bytes already read off the socket don't show up as readable
+            const pending = reader.bufferedLen() > 0 or self.stream_reader.interface().bufferedLen() > 0;
+            const ready = if (pending) 0 else try socket.firstReadable(&handles, 0) orelse continue;
+            if (ready == 0) {
+                if (reader.bufferedLen() == 0) try reader.fillMore();
+                const plaintext = reader.buffered();
+                try self.relay.writeAll(plaintext);
+                reader.toss(plaintext.len);
+            } else {
+                const n = try self.relay.read(&buf);
+                if (n == 0) return;
+                try self.client.writer.writeAll(buf[0..n]);
+                try self.client.writer.flush();
+                try self.stream_writer.interface.flush();
+            }
+        }
+    }
+};
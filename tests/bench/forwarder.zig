@@ -0,0 +1,185 @@
+//! Proxied GETs to a local upstream while many clients forward at once,
+//! with a new upstream connection per request and with pooled keep-alive
+//! connections. The upstream answers every request with `body_size` bytes.
+//!
+//!     zig build bench-forwarder -- [body size] [requests per client]
+
+const std = @import("std");
+const wraith = @import("wraith");
+
+const config = wraith.config;
+const proxy = wraith.proxy;
+const pool = wraith.pool;
+const request = wraith.request;
+const socket = wraith.socket;
+
+const CLIENT_COUNTS = [_]u32{ 8, 64, 256 };
+const MAX_IDLE = [_]u32{ 0, 256 };
+
+/// Answers each request head on a connection with a fixed response until
+/// the forwarder closes it
+const Upstream = struct {
+    server: std.net.Server,
+    response: []const u8,
+
+    fn run(self: *Upstream) void {
+        while (true) {
+            const conn = self.server.accept() catch return;
+            const thread = std.Thread.spawn(.{}, serve, .{ self, conn.stream }) catch {
+                conn.stream.close();
+                continue;
+            };
+            thread.detach();
+        }
+    }
+
+    fn serve(self: *Upstream, stream: std.net.Stream) void {
+        defer stream.close();
+        var buf: [request.MAX_HEAD_SIZE]u8 = undefined;
+        var len: usize = 0;
+        while (true) {
+            if (std.mem.indexOf(u8, buf[0..len], "\r\n\r\n")) |end| {
+                stream.writeAll(self.response) catch return;
+                const rest = len - (end + 4);
+                std.mem.copyForwards(u8, buf[0..rest], buf[end + 4 .. len]);
+                len = rest;
+                continue;
+            }
+            if (len == buf.len) return;
+            const n = stream.read(buf[len..]) catch return;
+            if (n == 0) return;
+            len += n;
+        }
+    }
+};
+
+/// State every client of one run shares, as connection threads do
+const Server = struct {
+    forwarder: *proxy.Forwarder,
+    pool: *pool.ConnectionPool,
+    upstream: std.net.Address,
+};
+
+const Client = struct {
+    server: *const Server,
+    /// Nanoseconds per request
+    latencies: []u64,
+    failed: u32 = 0,
+
+    fn run(self: *Client) void {
+        self.requests() catch |err| std.debug.print("✗ Client failed: {any}\n", .{err});
+    }
+
+    fn requests(self: *Client) !void {
+        const pair = try socket.streamPair();
+        defer pair[1].close();
+        const drain = try std.Thread.spawn(.{}, drainUntilClosed, .{pair[1]});
+        defer drain.join();
+        defer pair[0].close();
+
+        var arena = std.heap.ArenaAllocator.init(std.heap.page_allocator);
+        defer arena.deinit();
+        const head = "GET /bench HTTP/1.1\r\nHost: bench\r\n\r\n";
+        var headers: [4]request.Header = undefined;
+        const req = try request.parse(head, &headers);
+
+        for (self.latencies) |*latency| {
+            _ = arena.reset(.retain_capacity);
+            var timer = try std.time.Timer.start();
+            const outcome = self.server.forwarder.forward(arena.allocator(), pair[0], self.server.upstream, &req, head, &.{}, null, null, null, null, self.server.pool, null, true) catch {
+                self.failed += 1;
+                latency.* = timer.read();
+                continue;
+            };
+            latency.* = timer.read();
+            if (outcome.status != 200) self.failed += 1;
+        }
+    }
+};
+
+fn drainUntilClosed(stream: std.net.Stream) void {
+    var buf: [64 * 1024]u8 = undefined;
+    while (true) {
+        const n = stream.read(&buf) catch return;
+        if (n == 0) return;
+    }
+}
+
+pub fn main() !void {
+    var gpa = std.heap.GeneralPurposeAllocator(.{}){};
+    defer _ = gpa.deinit();
+    const allocator = gpa.allocator();
+
+    const args = try std.process.argsAlloc(allocator);
+    defer std.process.argsFree(allocator, args);
+    const body_size = if (args.len > 1) try std.fmt.parseInt(usize, args[1], 10) else 4096;
+    const per_client = if (args.len > 2) try std.fmt.parseInt(u32, args[2], 10) else 500;
+
+    const response = try std.fmt.allocPrint(allocator, "HTTP/1.1 200 OK\r\nContent-Length: {d}\r\n\r\n", .{body_size});
+    defer allocator.free(response);
+    const full = try allocator.alloc(u8, response.len + body_size);
+    defer allocator.free(full);
+    @memcpy(full[0..response.len], response);
+    @memset(full[response.len..], 'x');
+
+    var upstream = Upstream{
+        .server = try std.net.Address.initIp4(.{ 127, 0, 0, 1 }, 0).listen(.{ .reuse_address = true }),
+        .response = full,
+    };
+    // Left running: accept has no way to be woken, and the process exits
+    // when the runs are done
+    const upstream_thread = try std.Thread.spawn(.{}, Upstream.run, .{&upstream});
+    upstream_thread.detach();
+
+    std.debug.print("{s:>8} {s:>10} {s:>10} {s:>10} {s:>10} {s:>8} {s:>8}\n", .{ "clients", "max_idle", "req/s", "p50 ms", "p99 ms", "opened", "failed" });
+    for (CLIENT_COUNTS) |clients| {
+        for (MAX_IDLE) |max_idle| try run(allocator, upstream.server.listen_address, clients, per_client, max_idle);
+    }
+}
+
+fn run(allocator: std.mem.Allocator, upstream: std.net.Address, clients: u32, per_client: u32, max_idle: u32) !void {
+    const cfg = config.Config{ .server = .{ .listen = &.{} } };
+    var forwarder = proxy.Forwarder.init(allocator, &cfg);
+    const pool_config = config.UpstreamPoolConfig{ .max_idle = max_idle };
+    var connections = pool.ConnectionPool.init(allocator, &pool_config, &cfg.egress_proxy, null);
+    defer connections.deinit();
+    const server = Server{ .forwarder = &forwarder, .pool = &connections, .upstream = upstream };
+
+    const latencies = try allocator.alloc(u64, clients * per_client);
+    defer allocator.free(latencies);
+    const states = try allocator.alloc(Client, clients);
+    defer allocator.free(states);
+    const threads = try allocator.alloc(std.Thread, clients);
+    defer allocator.free(threads);
+
+    var timer = try std.time.Timer.start();
+    for (states, threads, 0..) |*state, *thread, i| {
+        state.* = .{
+            .server = &server,
+            .latencies = latencies[i * per_client ..][0..per_client],
+        };
+        thread.* = try std.Thread.spawn(.{}, Client.run, .{state});
+    }
+    for (threads) |thread| thread.join();
+    const elapsed_ns = timer.read();
+
+    var failed: u32 = 0;
+    for (states) |state| failed += state.failed;
+    std.mem.sort(u64, latencies, {}, std.sort.asc(u64));
+    const requests: f64 = @floatFromInt(latencies.len);
+    const seconds = @as(f64, @floatFromInt(elapsed_ns)) / std.time.ns_per_s;
+
+    std.debug.print("{d:>8} {d:>10} {d:>10.0} {d:>10.2} {d:>10.2} {d:>8} {d:>8}\n", .{
+        clients,
+        max_idle,
+        requests / seconds,
+        millis(latencies[latencies.len / 2]),
+        millis(latencies[latencies.len * 99 / 100]),
+        connections.stats.opened.load(.monotonic),
+        failed,
+    });
+}
+
+fn millis(ns: u64) f64 {
+    return @as(f64, @floatFromInt(ns)) / std.time.ns_per_ms;
+}
@@ -0,0 +1,160 @@
+//! Static file serving while many clients hit one large directory at once,
+//! with disk work unbounded and bounded by server.static_io. Every request
+//! looks a file up among `files` siblings and hashes a strong ETag; there
+//! are more files than the ETag cache holds, so hashing keeps going.
+//!
+//!     zig build bench-static-io -- [files] [requests per client]
+
+const std = @import("std");
+const wraith = @import("wraith");
+
+const config = wraith.config;
+const static = wraith.static;
+const static_io = wraith.static_io;
+const downloads = wraith.downloads;
+const request = wraith.request;
+const socket = wraith.socket;
+
+const ROOT = ".zig-cache/bench/static_io";
+const FILE_SIZE = 64 * 1024;
+const CLIENT_COUNTS = [_]u32{ 8, 64, 256 };
+
+/// State every client of one run shares, as connection threads do
+const Server = struct {
+    mount: *const config.StaticMountConfig,
+    etags: *static.EtagCache,
+    disk: *static_io.DiskGate,
+    downloads: *downloads.DownloadLimiter,
+};
+
+const Client = struct {
+    server: *const Server,
+    files: u32,
+    seed: u64,
+    /// Nanoseconds per request
+    latencies: []u64,
+    refused: u32 = 0,
+
+    fn run(self: *Client) void {
+        self.requests() catch |err| std.debug.print("✗ Client failed: {any}\n", .{err});
+    }
+
+    fn requests(self: *Client) !void {
+        const pair = try socket.streamPair();
+        defer pair[1].close();
+        const drain = try std.Thread.spawn(.{}, drainUntilClosed, .{pair[1]});
+        defer drain.join();
+        defer pair[0].close();
+
+        var prng = std.Random.DefaultPrng.init(self.seed);
+        const client = std.net.Address.initIp4(.{ 127, 0, 0, 1 }, 0);
+        for (self.latencies) |*latency| {
+            var head_buf: [64]u8 = undefined;
+            const head = try std.fmt.bufPrint(&head_buf, "GET /{d} HTTP/1.1\r\nHost: bench\r\n\r\n", .{prng.random().uintLessThan(u32, self.files)});
+            var headers: [4]request.Header = undefined;
+            const req = try request.parse(head, &headers);
+
+            var timer = try std.time.Timer.start();
+            const reused = try static.serve(pair[0], &req, self.server.mount, self.server.etags, self.server.disk, self.server.downloads, client, true);
+            latency.* = timer.read();
+            // Only a refusal closes the connection
+            if (!reused) self.refused += 1;
+        }
+    }
+};
+
+fn drainUntilClosed(stream: std.net.Stream) void {
+    var buf: [64 * 1024]u8 = undefined;
+    while (true) {
+        const n = stream.read(&buf) catch return;
+        if (n == 0) return;
+    }
+}
+
+pub fn main() !void {
+    var gpa = std.heap.GeneralPurposeAllocator(.{}){};
+    defer _ = gpa.deinit();
+    const allocator = gpa.allocator();
+
+    const args = try std.process.argsAlloc(allocator);
+    defer std.process.argsFree(allocator, args);
+    const files = if (args.len > 1) try std.fmt.parseInt(u32, args[1], 10) else 20_000;
+    const per_client = if (args.len > 2) try std.fmt.parseInt(u32, args[2], 10) else 200;
+
+    std.debug.print("Writing {d} files of {d} KiB to {s}\n", .{ files, FILE_SIZE / 1024, ROOT });
+    try writeFiles(files);
+    defer std.fs.cwd().deleteTree(ROOT) catch {};
+
+    const cpus: u32 = @intCast(try std.Thread.getCpuCount());
+    const bounds = [_]u32{ 0, 2 * cpus };
+    std.debug.print("{s:>8} {s:>10} {s:>10} {s:>10} {s:>10} {s:>8}\n", .{ "clients", "bound", "req/s", "p50 ms", "p99 ms", "refused" });
+    for (CLIENT_COUNTS) |clients| {
+        for (bounds) |bound| try run(allocator, files, clients, per_client, bound);
+    }
+}
+
+fn writeFiles(files: u32) !void {
+    try std.fs.cwd().makePath(ROOT);
+    var dir = try std.fs.cwd().openDir(ROOT, .{});
+    defer dir.close();
+
+    var contents: [FILE_SIZE]u8 = undefined;
+    var prng = std.Random.DefaultPrng.init(0);
+    for (0..files) |i| {
+        prng.random().bytes(&contents);
+        var name_buf: [16]u8 = undefined;
+        try dir.writeFile(.{ .sub_path = try std.fmt.bufPrint(&name_buf, "{d}", .{i}), .data = &contents });
+    }
+}
+
+fn run(allocator: std.mem.Allocator, files: u32, clients: u32, per_client: u32, bound: u32) !void {
+    const mount = config.StaticMountConfig{ .root = ROOT, .etag = .strong };
+    const io_config = config.StaticIoConfig{ .max_concurrent = bound, .queue_timeout_ms = 10_000 };
+    var etags = static.EtagCache.init(allocator);
+    defer etags.deinit();
+    var disk = static_io.DiskGate.init(&io_config);
+    var limiter = downloads.DownloadLimiter.init(allocator);
+    defer limiter.deinit();
+    const server = Server{ .mount = &mount, .etags = &etags, .disk = &disk, .downloads = &limiter };
+
+    const latencies = try allocator.alloc(u64, clients * per_client);
+    defer allocator.free(latencies);
+    const states = try allocator.alloc(Client, clients);
+    defer allocator.free(states);
+    const threads = try allocator.alloc(std.Thread, clients);
+    defer allocator.free(threads);
+
+    var timer = try std.time.Timer.start();
+    for (states, threads, 0..) |*state, *thread, i| {
+        state.* = .{
+            .server = &server,
+            .files = files,
+            .seed = i,
+            .latencies = latencies[i * per_client ..][0..per_client],
+        };
+        thread.* = try std.Thread.spawn(.{}, Client.run, .{state});
+    }
+    for (threads) |thread| thread.join();
+    const elapsed_ns = timer.read();
+
+    var refused: u32 = 0;
+    for (states) |state| refused += state.refused;
+    std.mem.sort(u64, latencies, {}, std.sort.asc(u64));
+    const requests: f64 = @floatFromInt(latencies.len);
+    const seconds = @as(f64, @floatFromInt(elapsed_ns)) / std.time.ns_per_s;
+
+    var bound_buf: [16]u8 = undefined;
+    const bound_text = if (bound == 0) "none" else try std.fmt.bufPrint(&bound_buf, "{d}", .{bound});
+    std.debug.print("{d:>8} {s:>10} {d:>10.0} {d:>10.2} {d:>10.2} {d:>8}\n", .{
+        clients,
+        bound_text,
+        requests / seconds,
+        millis(latencies[latencies.len / 2]),
+        millis(latencies[latencies.len * 99 / 100]),
+        refused,
+    });
+}
+
+fn millis(ns: u64) f64 {
+    return @as(f64, @floatFromInt(ns)) / std.time.ns_per_ms;
+}